@@ -1,10 +1,14 @@
+use std::collections::HashSet;
 use std::io::Write;
 
 use convert_case::{Case, Casing};
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 use substrait::text::simple_extensions::{
-    ArgumentsItem, ScalarFunction, ScalarFunctionImplsItem, SimpleExtensions, Type, ValueArg,
+    AggregateFunction, AggregateFunctionImplsItem, Arguments, ArgumentsItem, EnumerationArg,
+    NullabilityHandling, Options as ImplOptions, ReturnValue, ScalarFunction,
+    ScalarFunctionImplsItem, SimpleExtensions, Type, ValueArg, VariadicBehavior, WindowFunction,
+    WindowFunctionImplsItem,
 };
 use thiserror::Error;
 
@@ -25,6 +29,8 @@ pub enum FuncGenError {
 
 type Result<T> = std::result::Result<T, FuncGenError>;
 
+mod program;
+
 fn generate_type(fn_name: &str, type_name: &str) -> Option<TokenStream> {
     let (typ, nullability) = if type_name.ends_with('?') {
         (&type_name[0..type_name.len() - 1], true)
@@ -40,6 +46,19 @@ fn generate_type(fn_name: &str, type_name: &str) -> Option<TokenStream> {
         "fp32" => Some(quote!(types::fp32(#nullability))),
         "fp64" => Some(quote!(types::fp64(#nullability))),
         "boolean" => Some(quote!(types::bool(#nullability))),
+        "string" => Some(quote!(types::string(#nullability))),
+        "binary" => Some(quote!(types::binary(#nullability))),
+        "date" => Some(quote!(types::date(#nullability))),
+        "time" => Some(quote!(types::time(#nullability))),
+        "timestamp" => Some(quote!(types::timestamp(#nullability))),
+        "timestamp_tz" => Some(quote!(types::timestamp_tz(#nullability))),
+        "interval_year" => Some(quote!(types::interval_year(#nullability))),
+        // Without a precision parameter interval_day measures microseconds
+        "interval_day" => Some(quote!(types::interval_day(
+            types::MICROSECOND_PRECISION,
+            #nullability
+        ))),
+        "uuid" => Some(quote!(types::uuid(#nullability))),
         // Bleah, let's cleanup the yaml files!
         "BOOLEAN" => Some(quote!(types::bool(#nullability))),
         _ => {
@@ -62,6 +81,12 @@ fn generate_arg_type(fn_name: &str, typ: &Type) -> Option<TokenStream> {
     }
     if type_name.contains("any") || type_name == "T" {
         Some(quote!(ImplementationArgType::TemplateValue(#type_name.to_string())))
+    } else if program::is_parameterized_decimal(type_name) {
+        program::generate_decimal_arg(type_name)
+            .map_err(|err| {
+                println!("cargo:warning=Ignoring impl of {}: {}", fn_name, err);
+            })
+            .ok()
     } else {
         let typ = generate_type(fn_name, type_name)?;
         Some(quote!(ImplementationArgType::Value(#typ)))
@@ -78,23 +103,33 @@ fn generate_arg_return(fn_name: &str, typ: &Type) -> Option<TokenStream> {
     }
     if type_name.contains("any") || type_name == "T" {
         Some(quote!(FunctionReturn::Templated(#type_name.to_string())))
+    } else if type_name.contains('\n') || program::is_parameterized_decimal(type_name) {
+        program::generate_return_program(type_name)
+            .map_err(|err| {
+                println!("cargo:warning=Ignoring impl of {}: {}", fn_name, err);
+            })
+            .ok()
     } else {
         let typ = generate_type(fn_name, type_name)?;
         Some(quote!(FunctionReturn::Typed(#typ)))
     }
 }
 
-fn generate_arg_block(fn_name: &str, arg: &ArgumentsItem) -> Option<TokenStream> {
+fn generate_arg_block(fn_name: &str, arg_idx: usize, arg: &ArgumentsItem) -> Option<TokenStream> {
     match arg {
-        ArgumentsItem::EnumerationArg { .. } => {
-            println!(
-                "cargo:warning=Ignoring implementation of {} containing enumeration arg item",
-                fn_name
-            );
-            None
+        ArgumentsItem::EnumerationArg(EnumerationArg { name, options, .. }) => {
+            let name = name.clone().unwrap_or_else(|| format!("arg{}", arg_idx));
+            let options = options.iter();
+            Some(quote!(
+                ImplementationArg {
+                    name: #name.to_string(),
+                    arg_type: ImplementationArgType::Enum(vec![#(#options.to_string()),*])
+                }
+            ))
         }
         ArgumentsItem::ValueArg(ValueArg { name, value, .. }) => {
-            let name = name.as_ref()?;
+            // Some YAML files omit argument names so fall back to a positional name
+            let name = name.clone().unwrap_or_else(|| format!("arg{}", arg_idx));
             let typ = generate_arg_type(fn_name, value)?;
             Some(quote!(
                 ImplementationArg {
@@ -110,37 +145,231 @@ fn generate_arg_block(fn_name: &str, arg: &ArgumentsItem) -> Option<TokenStream>
     }
 }
 
-fn generate_implementation_block(
-    fn_name: &str,
-    imp: &ScalarFunctionImplsItem,
-) -> Option<TokenStream> {
-    let output_type = generate_arg_return(fn_name, &imp.return_.0)?;
-    let args = imp.args.as_ref()?;
+/// The parts of a YAML implementation that are shared by scalar and aggregate functions
+trait ImplsItem {
+    fn args(&self) -> Option<&Arguments>;
+    fn options(&self) -> Option<&ImplOptions>;
+    fn nullability(&self) -> Option<&NullabilityHandling>;
+    fn return_value(&self) -> &ReturnValue;
+    fn variadic(&self) -> Option<&VariadicBehavior>;
+}
+
+impl ImplsItem for ScalarFunctionImplsItem {
+    fn args(&self) -> Option<&Arguments> {
+        self.args.as_ref()
+    }
+    fn options(&self) -> Option<&ImplOptions> {
+        self.options.as_ref()
+    }
+    fn nullability(&self) -> Option<&NullabilityHandling> {
+        self.nullability.as_ref()
+    }
+    fn return_value(&self) -> &ReturnValue {
+        &self.return_
+    }
+    fn variadic(&self) -> Option<&VariadicBehavior> {
+        self.variadic.as_ref()
+    }
+}
+
+impl ImplsItem for AggregateFunctionImplsItem {
+    fn args(&self) -> Option<&Arguments> {
+        self.args.as_ref()
+    }
+    fn options(&self) -> Option<&ImplOptions> {
+        self.options.as_ref()
+    }
+    fn nullability(&self) -> Option<&NullabilityHandling> {
+        self.nullability.as_ref()
+    }
+    fn return_value(&self) -> &ReturnValue {
+        &self.return_
+    }
+    fn variadic(&self) -> Option<&VariadicBehavior> {
+        self.variadic.as_ref()
+    }
+}
+
+impl ImplsItem for WindowFunctionImplsItem {
+    fn args(&self) -> Option<&Arguments> {
+        self.args.as_ref()
+    }
+    fn options(&self) -> Option<&ImplOptions> {
+        self.options.as_ref()
+    }
+    fn nullability(&self) -> Option<&NullabilityHandling> {
+        self.nullability.as_ref()
+    }
+    fn return_value(&self) -> &ReturnValue {
+        &self.return_
+    }
+    fn variadic(&self) -> Option<&VariadicBehavior> {
+        self.variadic.as_ref()
+    }
+}
+
+/// The parts of a YAML function that are shared by scalar, aggregate and window functions
+trait Function {
+    type Impl: ImplsItem;
+    fn name(&self) -> &str;
+    fn description(&self) -> Option<&str>;
+    fn impls(&self) -> &[Self::Impl];
+}
+
+impl Function for ScalarFunction {
+    type Impl = ScalarFunctionImplsItem;
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+    fn impls(&self) -> &[Self::Impl] {
+        &self.impls
+    }
+}
+
+impl Function for AggregateFunction {
+    type Impl = AggregateFunctionImplsItem;
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+    fn impls(&self) -> &[Self::Impl] {
+        &self.impls
+    }
+}
+
+impl Function for WindowFunction {
+    type Impl = WindowFunctionImplsItem;
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+    fn impls(&self) -> &[Self::Impl] {
+        &self.impls
+    }
+}
+
+fn generate_implementation_block(fn_name: &str, imp: &impl ImplsItem) -> Option<TokenStream> {
+    let output_type = generate_arg_return(fn_name, &imp.return_value().0)?;
+    let args = imp.args()?;
     let args = args
         .iter()
-        .map(|arg| generate_arg_block(fn_name, arg))
+        .enumerate()
+        .map(|(arg_idx, arg)| generate_arg_block(fn_name, arg_idx, arg))
         .collect::<Option<Vec<_>>>()?;
 
+    let mut options = imp
+        .options()
+        .map(|options| {
+            options
+                .iter()
+                .map(|(name, option)| (name.clone(), option.values.clone()))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    // The YAML options are a hash map, sort them so the generated code is stable
+    options.sort();
+    let options = options
+        .iter()
+        .map(|(name, values)| quote!((#name.to_string(), vec![#(#values.to_string()),*])))
+        .collect::<Vec<_>>();
+
+    let nullability = match imp.nullability() {
+        None | Some(NullabilityHandling::Mirror) => quote!(NullabilityHandling::Mirror),
+        Some(NullabilityHandling::DeclaredOutput) => quote!(NullabilityHandling::DeclaredOutput),
+        Some(NullabilityHandling::Discrete) => quote!(NullabilityHandling::Discrete),
+    };
+
+    // If the YAML does not give a minimum then the last argument must be given at least once
+    let variadic = match imp.variadic() {
+        None => quote!(None),
+        Some(variadic) => {
+            let min = variadic.min.unwrap_or(1.0) as u32;
+            let max = match variadic.max {
+                Some(max) => {
+                    let max = max as u32;
+                    quote!(Some(#max))
+                }
+                None => quote!(None),
+            };
+            quote!(Some(Variadic { min: #min, max: #max }))
+        }
+    };
+
     Some(quote!(
         FunctionImplementation {
             output_type: #output_type,
             args: vec![#(#args),*],
+            options: std::collections::BTreeMap::from([#(#options),*]),
+            nullability: #nullability,
+            variadic: #variadic,
         }
     ))
 }
 
-fn generate_function_block(uri: &str, func: &ScalarFunction) -> Result<TokenStream> {
-    let func_name_caps: TokenStream = func.name.to_uppercase().parse()?;
-    let func_name = &func.name;
+/// Returns the width of the list marker (e.g. `* ` or `1. `) that starts a line, if any
+fn list_marker_width(line: &str) -> Option<usize> {
+    if line.starts_with("* ") || line.starts_with("- ") {
+        return Some(2);
+    }
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 && line[digits..].starts_with(". ") {
+        Some(digits + 2)
+    } else {
+        None
+    }
+}
+
+/// Converts a YAML description into `#[doc]` attributes, one per line
+///
+/// The YAML descriptions are not written with rustdoc in mind.  Leading whitespace is
+/// removed so that indented lines do not become code blocks (which rustdoc would run as
+/// doctests) and lines that continue a list item are then indented under that item.
+fn generate_doc_attrs(description: Option<&str>) -> TokenStream {
+    let mut list_indent = None;
+    let lines = description
+        .unwrap_or_default()
+        .trim()
+        .lines()
+        .map(|line| {
+            let line = line.trim();
+            if line.is_empty() {
+                list_indent = None;
+                return String::new();
+            }
+            if let Some(width) = list_marker_width(line) {
+                list_indent = Some(width);
+                format!(" {}", line)
+            } else {
+                let indent = " ".repeat(list_indent.unwrap_or(0));
+                format!(" {}{}", indent, line)
+            }
+        })
+        .collect::<Vec<_>>();
+    quote!(#(#[doc = #lines])*)
+}
+
+fn generate_function_block(uri: &str, func: &impl Function) -> Result<TokenStream> {
+    let func_name_caps: TokenStream = func.name().to_uppercase().parse()?;
+    let func_name = func.name();
 
     let implementations = func
-        .impls
+        .impls()
         .iter()
         .map(|imp| generate_implementation_block(func_name, imp))
         .filter(|imp| imp.is_some())
         .collect::<Vec<_>>();
 
+    let docs = generate_doc_attrs(func.description());
+
     Ok(quote!(
+        #docs
         pub static #func_name_caps: Lazy<FunctionDefinition> = Lazy::new(|| FunctionDefinition {
             uri: #uri.to_string(),
             name: #func_name.to_string(),
@@ -166,28 +395,114 @@ fn generate_function_block(uri: &str, func: &ScalarFunction) -> Result<TokenStre
 //     }
 // }
 
-fn generate_ext_impls(function: &ScalarFunction) -> Result<Vec<(TokenStream, TokenStream)>> {
+/// The builder type returned by the generated extension methods
+///
+/// Scalar functions return a `FunctionBuilder`, aggregate functions return an
+/// `AggregateBuilder`, and window functions return a `WindowBuilder`
+struct BuilderKind {
+    builder_type: TokenStream,
+    new_builder: TokenStream,
+}
+
+impl BuilderKind {
+    fn scalar() -> Self {
+        Self {
+            builder_type: quote!(FunctionBuilder),
+            new_builder: quote!(new_builder),
+        }
+    }
+
+    fn aggregate() -> Self {
+        Self {
+            builder_type: quote!(AggregateBuilder<'_>),
+            new_builder: quote!(new_aggregate_builder),
+        }
+    }
+
+    fn window() -> Self {
+        Self {
+            builder_type: quote!(WindowBuilder<'_>),
+            new_builder: quote!(new_window_builder),
+        }
+    }
+}
+
+/// Picks a method name that has not already been used in the trait being generated
+///
+/// Implementations with different argument counts get an arg count suffix (e.g. `substring3`)
+/// which could clash with another function in the same file.  If that happens an underscore
+/// and a counter is appended (e.g. `substring3_2`) instead of emitting a duplicate method.
+fn unique_method_name(name: String, used_names: &mut HashSet<String>) -> String {
+    let mut candidate = name.clone();
+    let mut counter = 2;
+    while used_names.contains(&candidate) {
+        candidate = format!("{}_{}", name, counter);
+        counter += 1;
+    }
+    if candidate != name {
+        println!(
+            "cargo:warning=Method name {} is already in use, generating {} instead",
+            name, candidate
+        );
+    }
+    used_names.insert(candidate.clone());
+    candidate
+}
+
+fn generate_ext_impls(
+    function: &impl Function,
+    kind: &BuilderKind,
+    used_names: &mut HashSet<String>,
+) -> Result<Vec<(TokenStream, TokenStream)>> {
     let mut num_args = function
-        .impls
+        .impls()
         .iter()
-        .map(|imp| imp.args.as_ref().map(|args| args.len()).unwrap_or(0))
-        .filter(|arg| *arg != 0)
+        // Implementations without an args list are skipped but an explicitly empty list
+        // (e.g. row_number) is a valid zero-argument implementation
+        .filter_map(|imp| imp.args().map(|args| args.len()))
         .collect::<Vec<_>>();
     num_args.sort();
     num_args.dedup();
 
-    let fn_name = function.name.to_case(Case::Snake);
-    let func_name_caps: TokenStream = function.name.to_uppercase().parse()?;
+    // An argument that is an enum in every implementation with the same number of
+    // arguments is accepted as a &str (e.g. the component of extract)
+    let is_enum_arg = |num_args: usize, arg_idx: usize| {
+        function
+            .impls()
+            .iter()
+            .filter_map(|imp| imp.args())
+            .filter(|args| args.len() == num_args)
+            .all(|args| matches!(args[arg_idx], ArgumentsItem::EnumerationArg(_)))
+    };
+    // If every implementation with the same number of arguments is variadic then the
+    // last argument is accepted as a Vec (e.g. concat)
+    let is_variadic = |num_args: usize| {
+        num_args > 0
+            && function
+                .impls()
+                .iter()
+                .filter(|imp| imp.args().is_some_and(|args| args.len() == num_args))
+                .all(|imp| imp.variadic().is_some())
+    };
+
+    let fn_name = function.name().to_case(Case::Snake);
+    let docs = generate_doc_attrs(function.description());
+    let func_name_caps: TokenStream = function.name().to_uppercase().parse()?;
+    let BuilderKind {
+        builder_type,
+        new_builder,
+    } = kind;
 
     Ok(num_args
         .iter()
         .enumerate()
         .map(|(idx, num_args)| {
-            let fn_name_token = if idx == 0 {
-                fn_name.parse::<TokenStream>().unwrap()
+            let method_name = if idx == 0 {
+                fn_name.clone()
             } else {
-                format!("{}{}", fn_name, num_args).as_str().parse().unwrap()
+                format!("{}{}", fn_name, num_args)
             };
+            let fn_name_token = format_ident!("{}", unique_method_name(method_name, used_names));
             let arg_name_tokens = (0..*num_args)
                 .map(|arg_idx| {
                     format!("arg{}", arg_idx)
@@ -196,10 +511,38 @@ fn generate_ext_impls(function: &ScalarFunction) -> Result<Vec<(TokenStream, Tok
                         .unwrap()
                 })
                 .collect::<Vec<_>>();
-            let prototype = quote!(fn #fn_name_token(&self, #(#arg_name_tokens: Expression),*) -> FunctionBuilder;);
+            let variadic = is_variadic(*num_args);
+            let (arg_types, arg_values): (Vec<_>, Vec<_>) = arg_name_tokens
+                .iter()
+                .enumerate()
+                .map(|(arg_idx, arg_name)| {
+                    if variadic && arg_idx == num_args - 1 {
+                        (quote!(Vec<Expression>), quote!())
+                    } else if is_enum_arg(*num_args, arg_idx) {
+                        (quote!(&str), quote!(literal(#arg_name)))
+                    } else {
+                        (quote!(Expression), quote!(#arg_name))
+                    }
+                })
+                .unzip();
+            let arg_values = arg_values
+                .into_iter()
+                .filter(|value| !value.is_empty())
+                .collect::<Vec<_>>();
+            let args = if variadic {
+                let repeated = &arg_name_tokens[num_args - 1];
+                quote!({
+                    let mut args = vec![#(#arg_values),*];
+                    args.extend(#repeated);
+                    args
+                })
+            } else {
+                quote!(vec![#(#arg_values),*])
+            };
+            let prototype = quote!(#docs fn #fn_name_token(&self, #(#arg_name_tokens: #arg_types),*) -> #builder_type;);
             let imp = quote!(
-                fn #fn_name_token(&self, #(#arg_name_tokens: Expression),*) -> FunctionBuilder {
-                    self.new_builder(&#func_name_caps, vec![#(#arg_name_tokens),*])
+                fn #fn_name_token(&self, #(#arg_name_tokens: #arg_types),*) -> #builder_type {
+                    self.#new_builder(&#func_name_caps, #args)
                 }
             );
             (prototype, imp)
@@ -207,24 +550,15 @@ fn generate_ext_impls(function: &ScalarFunction) -> Result<Vec<(TokenStream, Tok
         .collect::<Vec<_>>())
 }
 
-fn generate_function_blocks(
-    uri: &str,
-    mod_name: &str,
-    extensions: SimpleExtensions,
+fn generate_ext_trait(
+    trait_name: &proc_macro2::Ident,
+    functions: &[impl Function],
+    kind: &BuilderKind,
 ) -> Result<TokenStream> {
-    let statics = extensions
-        .scalar_functions
-        .iter()
-        .map(|ext| generate_function_block(uri, ext))
-        .collect::<Result<Vec<_>>>()?;
-
-    let ext_name = mod_name.to_case(Case::Pascal);
-    let trait_name = format_ident!("{}Ext", ext_name);
-
-    let prototypes_impls = extensions
-        .scalar_functions
+    let mut used_names = HashSet::new();
+    let prototypes_impls = functions
         .iter()
-        .map(|func| generate_ext_impls(func))
+        .map(|func| generate_ext_impls(func, kind, &mut used_names))
         .flat_map(|impls| match impls {
             Ok(impls) => impls.into_iter().map(Ok).collect(),
             Err(err) => vec![Err(err)],
@@ -241,8 +575,12 @@ fn generate_function_blocks(
         .collect::<Vec<_>>();
 
     Ok(quote!(
-        #(#statics)*
-
+        /// Builder methods for the functions in this module
+        ///
+        /// Other extension traits may define methods with the same name (e.g. `add` is
+        /// defined for both numbers and dates).  If both traits are in scope then call the
+        /// method with fully qualified syntax (e.g. `Trait::add(&functions, a, b)`) or only
+        /// import one of the traits in each scope.
         pub trait #trait_name {
             #(#prototypes)*
         }
@@ -253,9 +591,148 @@ fn generate_function_blocks(
     ))
 }
 
+fn generate_function_blocks(
+    uri: &str,
+    mod_name: &str,
+    extensions: SimpleExtensions,
+) -> Result<TokenStream> {
+    let statics = extensions
+        .scalar_functions
+        .iter()
+        .map(|ext| generate_function_block(uri, ext))
+        .chain(
+            extensions
+                .aggregate_functions
+                .iter()
+                .map(|ext| generate_function_block(uri, ext)),
+        )
+        .chain(
+            extensions
+                .window_functions
+                .iter()
+                .map(|ext| generate_function_block(uri, ext)),
+        )
+        .collect::<Result<Vec<_>>>()?;
+
+    let static_names = extensions
+        .scalar_functions
+        .iter()
+        .map(|func| func.name())
+        .chain(
+            extensions
+                .aggregate_functions
+                .iter()
+                .map(|func| func.name()),
+        )
+        .chain(extensions.window_functions.iter().map(|func| func.name()))
+        .map(|name| name.to_uppercase())
+        .collect::<Vec<_>>();
+    // Each function gets a static named after it so a scalar and an aggregate function with
+    // the same name would generate code that fails to compile
+    let mut seen_names = HashSet::new();
+    if let Some(duplicate) = static_names
+        .iter()
+        .find(|name| !seen_names.insert(name.as_str()))
+    {
+        return Err(FuncGenError::GeneralError(format!(
+            "The function {} is defined more than once in {}",
+            duplicate.to_lowercase(),
+            mod_name
+        )));
+    }
+    let static_names = static_names
+        .iter()
+        .map(|name| name.parse::<TokenStream>())
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    let ext_name = mod_name.to_case(Case::Pascal);
+    let trait_name = format_ident!("{}Ext", ext_name);
+    let scalar_trait = generate_ext_trait(
+        &trait_name,
+        &extensions.scalar_functions,
+        &BuilderKind::scalar(),
+    )?;
+    // Files without aggregate (or window) functions don't get an empty trait
+    let aggregate_trait = if extensions.aggregate_functions.is_empty() {
+        quote!()
+    } else {
+        let trait_name = format_ident!("{}AggregateExt", ext_name);
+        generate_ext_trait(
+            &trait_name,
+            &extensions.aggregate_functions,
+            &BuilderKind::aggregate(),
+        )?
+    };
+    let window_trait = if extensions.window_functions.is_empty() {
+        quote!()
+    } else {
+        let trait_name = format_ident!("{}WindowExt", ext_name);
+        generate_ext_trait(
+            &trait_name,
+            &extensions.window_functions,
+            &BuilderKind::window(),
+        )?
+    };
+
+    Ok(quote!(
+        #(#statics)*
+
+        /// All of the functions defined in this module
+        pub fn definitions() -> Vec<&'static FunctionDefinition> {
+            vec![#(&*#static_names),*]
+        }
+
+        #scalar_trait
+
+        #aggregate_trait
+
+        #window_trait
+    ))
+}
+
+/// Combines functions that are declared more than once in the same file
+///
+/// Some YAML files (e.g. functions_string.yaml) split the implementations of a single
+/// function across several entries.  The first entry's description is kept.
+fn merge_duplicates<F, I>(
+    functions: &mut Vec<F>,
+    name: impl Fn(&F) -> &str,
+    impls: impl Fn(&mut F) -> &mut Vec<I>,
+) {
+    let mut merged: Vec<F> = Vec::with_capacity(functions.len());
+    for mut func in functions.drain(..) {
+        match merged
+            .iter_mut()
+            .find(|existing| name(existing) == name(&func))
+        {
+            Some(existing) => {
+                let extra = std::mem::take(impls(&mut func));
+                impls(existing).extend(extra);
+            }
+            None => merged.push(func),
+        }
+    }
+    *functions = merged;
+}
+
 pub fn generate_functions_for_yaml(uri: &str, filepath: &str) -> Result<TokenStream> {
     let file = std::fs::File::open(filepath)?;
-    let extensions = serde_yaml::from_reader::<_, SimpleExtensions>(file)?;
+    let mut extensions = serde_yaml::from_reader::<_, SimpleExtensions>(file)?;
+    merge_duplicates(
+        &mut extensions.scalar_functions,
+        |func| &func.name,
+        |func| &mut func.impls,
+    );
+    merge_duplicates(
+        &mut extensions.aggregate_functions,
+        |func| &func.name,
+        |func| &mut func.impls,
+    );
+    merge_duplicates(
+        &mut extensions.window_functions,
+        |func| &func.name,
+        |func| &mut func.impls,
+    );
     let mod_name = std::path::Path::new(filepath)
         .file_stem()
         .unwrap()
@@ -301,16 +778,35 @@ pub fn generate_functions(entries: &[(&str, &str)], options: Options) -> Result<
         .iter()
         .map(|entry| generate_functions_for_yaml(entry.0, &entry.1))
         .collect::<Result<Vec<_>>>()?;
+    let mod_names = entries
+        .iter()
+        .map(|entry| {
+            std::path::Path::new(entry.1)
+                .file_stem()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .parse::<TokenStream>()
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
     let crate_name_token: TokenStream = options.get_crate_name().parse()?;
 
     let tokens = quote!(
         use once_cell::sync::Lazy;
         use substrait::proto::Expression;
         use #crate_name_token::builder::functions::{FunctionDefinition, FunctionImplementation,
-            ImplementationArg, ImplementationArgType, FunctionBuilder, FunctionsBuilder, FunctionReturn};
+            ImplementationArg, ImplementationArgType, FunctionBuilder, FunctionsBuilder, FunctionReturn,
+            NullabilityHandling, AggregateBuilder, WindowBuilder, Variadic, TypeParameter,
+            ProgramExpr, ProgramOp, ReturnProgram};
+        use #crate_name_token::helpers::literals::literal;
         use #crate_name_token::helpers::types;
 
         #(#yaml_modules)*
+
+        /// All of the functions defined in all of the modules
+        pub fn definitions() -> Vec<&'static FunctionDefinition> {
+            [#(#mod_names::definitions()),*].concat()
+        }
     );
 
     let syntax_tree = syn::parse2(tokens)?;
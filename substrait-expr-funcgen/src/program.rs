@@ -0,0 +1,317 @@
+//! Parsing for parameterized decimal types and the return type programs in YAML files
+//!
+//! A return program is a series of assignments followed by an output type:
+//!
+//! ```text
+//! init_scale = max(S1,S2)
+//! init_prec = init_scale + max(P1 - S1, P2 - S2) + 1
+//! ...
+//! scale = init_prec > 38 ? scale_after_borrow : init_scale
+//! DECIMAL<prec, scale>
+//! ```
+//!
+//! The program is parsed at build time and converted into code that constructs the
+//! equivalent `ReturnProgram` which is evaluated when a function is resolved.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::{FuncGenError, Result};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Integer(i64),
+    Symbol(&'static str),
+}
+
+// Longer symbols must come first so that `>=` is not read as `>` followed by `=`
+const SYMBOLS: [&str; 16] = [
+    "<=", ">=", "==", "!=", "+", "-", "*", "/", "(", ")", ",", "<", ">", "=", "?", ":",
+];
+
+fn parse_error(message: impl Into<String>, text: &str) -> FuncGenError {
+    FuncGenError::GeneralError(format!("{} in `{}`", message.into(), text))
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut remaining = text.trim_start();
+    while let Some(next) = remaining.chars().next() {
+        if next.is_ascii_alphabetic() || next == '_' {
+            let end = remaining
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(remaining.len());
+            tokens.push(Token::Ident(remaining[..end].to_string()));
+            remaining = &remaining[end..];
+        } else if next.is_ascii_digit() {
+            let end = remaining
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(remaining.len());
+            let value = remaining[..end]
+                .parse::<i64>()
+                .map_err(|_| parse_error("Invalid integer", text))?;
+            tokens.push(Token::Integer(value));
+            remaining = &remaining[end..];
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| remaining.starts_with(*symbol))
+                .ok_or_else(|| parse_error(format!("Unexpected character '{}'", next), text))?;
+            tokens.push(Token::Symbol(symbol));
+            remaining = &remaining[symbol.len()..];
+        }
+        remaining = remaining.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent parser over the tokens of a single line
+///
+/// Expressions are converted directly into tokens that construct a `ProgramExpr`
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Result<Self> {
+        Ok(Self {
+            text,
+            tokens: tokenize(text)?,
+            pos: 0,
+        })
+    }
+
+    fn error(&self, message: impl Into<String>) -> FuncGenError {
+        parse_error(message, self.text)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(next)) if *next == symbol) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<()> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(format!("Expected '{}'", symbol)))
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Token::Ident(name)) => Ok(name),
+            _ => Err(self.error("Expected a name")),
+        }
+    }
+
+    fn expect_end(&self) -> Result<()> {
+        if self.pos < self.tokens.len() {
+            Err(self.error("Unexpected trailing input"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn binary(op: TokenStream, left: TokenStream, right: TokenStream) -> TokenStream {
+        quote!(ProgramExpr::BinaryOp(ProgramOp::#op, Box::new(#left), Box::new(#right)))
+    }
+
+    // expr := comparison ('?' expr ':' expr)?
+    fn expr(&mut self) -> Result<TokenStream> {
+        let condition = self.comparison()?;
+        if self.eat("?") {
+            let if_true = self.expr()?;
+            self.expect(":")?;
+            let if_false = self.expr()?;
+            Ok(quote!(ProgramExpr::IfElse(
+                Box::new(#condition),
+                Box::new(#if_true),
+                Box::new(#if_false)
+            )))
+        } else {
+            Ok(condition)
+        }
+    }
+
+    // comparison := additive (comparison_op additive)?
+    fn comparison(&mut self) -> Result<TokenStream> {
+        let left = self.additive()?;
+        let op = match self.peek() {
+            Some(Token::Symbol("<")) => quote!(Lt),
+            Some(Token::Symbol("<=")) => quote!(Lte),
+            Some(Token::Symbol(">")) => quote!(Gt),
+            Some(Token::Symbol(">=")) => quote!(Gte),
+            Some(Token::Symbol("==")) => quote!(Eq),
+            Some(Token::Symbol("!=")) => quote!(NotEq),
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        let right = self.additive()?;
+        Ok(Self::binary(op, left, right))
+    }
+
+    // additive := multiplicative (('+' | '-') multiplicative)*
+    fn additive(&mut self) -> Result<TokenStream> {
+        let mut left = self.multiplicative()?;
+        loop {
+            let op = if self.eat("+") {
+                quote!(Add)
+            } else if self.eat("-") {
+                quote!(Subtract)
+            } else {
+                return Ok(left);
+            };
+            let right = self.multiplicative()?;
+            left = Self::binary(op, left, right);
+        }
+    }
+
+    // multiplicative := unary (('*' | '/') unary)*
+    fn multiplicative(&mut self) -> Result<TokenStream> {
+        let mut left = self.unary()?;
+        loop {
+            let op = if self.eat("*") {
+                quote!(Multiply)
+            } else if self.eat("/") {
+                quote!(Divide)
+            } else {
+                return Ok(left);
+            };
+            let right = self.unary()?;
+            left = Self::binary(op, left, right);
+        }
+    }
+
+    // unary := '-' unary | primary
+    fn unary(&mut self) -> Result<TokenStream> {
+        if self.eat("-") {
+            let inner = self.unary()?;
+            Ok(quote!(ProgramExpr::Negate(Box::new(#inner))))
+        } else {
+            self.primary()
+        }
+    }
+
+    // primary := integer | name | ('max' | 'min') '(' expr ',' expr ')' | '(' expr ')'
+    fn primary(&mut self) -> Result<TokenStream> {
+        match self.next() {
+            Some(Token::Integer(value)) => Ok(quote!(ProgramExpr::Literal(#value))),
+            Some(Token::Ident(name)) => {
+                if !self.eat("(") {
+                    return Ok(quote!(ProgramExpr::Variable(#name.to_string())));
+                }
+                let variant = match name.to_lowercase().as_str() {
+                    "max" => quote!(Max),
+                    "min" => quote!(Min),
+                    _ => return Err(self.error(format!("Unsupported function {}", name))),
+                };
+                let left = self.expr()?;
+                self.expect(",")?;
+                let right = self.expr()?;
+                self.expect(")")?;
+                Ok(quote!(ProgramExpr::#variant(Box::new(#left), Box::new(#right))))
+            }
+            Some(Token::Symbol("(")) => {
+                let inner = self.expr()?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            _ => Err(self.error("Expected an expression")),
+        }
+    }
+
+    /// Parses `DECIMAL<P, S>` (optionally `DECIMAL?<P, S>`) returning the nullability
+    ///
+    /// Parameters are parsed with `param` which must not consume the closing `>`
+    fn decimal<T>(
+        &mut self,
+        mut param: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<(bool, T, T)> {
+        let name = self.expect_ident()?;
+        if !name.eq_ignore_ascii_case("decimal") {
+            return Err(self.error(format!("Unsupported type {}", name)));
+        }
+        let nullable = self.eat("?");
+        self.expect("<")?;
+        let precision = param(self)?;
+        self.expect(",")?;
+        let scale = param(self)?;
+        self.expect(">")?;
+        self.expect_end()?;
+        Ok((nullable, precision, scale))
+    }
+}
+
+/// Returns true if a YAML type string is a decimal with type parameters (e.g. `decimal<P1,S1>`)
+pub(crate) fn is_parameterized_decimal(type_name: &str) -> bool {
+    type_name
+        .trim()
+        .get(..7)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("decimal"))
+        && type_name.contains('<')
+}
+
+/// Generates an `ImplementationArgType::Decimal` for an argument such as `decimal<P1,0>`
+pub(crate) fn generate_decimal_arg(type_name: &str) -> Result<TokenStream> {
+    let mut parser = Parser::new(type_name)?;
+    // Arguments match regardless of nullability so the marker is ignored
+    let (_, precision, scale) = parser.decimal(|parser| match parser.next() {
+        Some(Token::Ident(name)) => Ok(quote!(TypeParameter::Named(#name.to_string()))),
+        Some(Token::Integer(value)) => Ok(quote!(TypeParameter::Fixed(#value))),
+        _ => Err(parser.error("Expected a type parameter")),
+    })?;
+    Ok(quote!(ImplementationArgType::Decimal {
+        precision: #precision,
+        scale: #scale
+    }))
+}
+
+/// Generates a `FunctionReturn::Program` from a return program (or a parameterized type
+/// such as `DECIMAL?<38,S>` which is treated as a program without assignments)
+pub(crate) fn generate_return_program(program: &str) -> Result<TokenStream> {
+    let lines = program
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+    let (output, assignments) = lines
+        .split_last()
+        .ok_or_else(|| parse_error("Empty return program", program))?;
+    let assignments = assignments
+        .iter()
+        .map(|line| {
+            let mut parser = Parser::new(line)?;
+            let name = parser.expect_ident()?;
+            parser.expect("=")?;
+            let value = parser.expr()?;
+            parser.expect_end()?;
+            Ok(quote!((#name.to_string(), #value)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // Comparisons are not allowed in the type parameters since `>` closes the type
+    let (nullable, precision, scale) = Parser::new(output)?.decimal(|parser| parser.additive())?;
+    Ok(quote!(FunctionReturn::Program(ReturnProgram {
+        assignments: vec![#(#assignments),*],
+        precision: #precision,
+        scale: #scale,
+        nullable: #nullable,
+    })))
+}
@@ -110,6 +110,301 @@ impl Parse for NestedType {
     }
 }
 
+// Convert rust code to a builder call that adds a field to a types-only schema
+//
+// Example Input:
+//  a: i32?
+//
+// Example Output:
+//  .field(substrait_expr::helpers::types::i32(true))
+fn rust_field_to_types_field(field: &TypedField) -> proc_macro2::TokenStream {
+    let nullable = field.is_nullable();
+    match &field.ty {
+        TypedFieldType::Leaf(leaf) => {
+            let ty = rust_to_type(leaf, nullable);
+            quote! {
+                .field(#ty)
+            }
+        }
+        TypedFieldType::Nested(nested) => {
+            let children = rust_to_types_fields(nested);
+            quote! {
+                .nested(#nullable, |builder| builder #children)
+            }
+        }
+    }
+}
+
+// Convert rust code to a chain of builder calls that add fields to a types-only schema
+//
+// Example input:
+//  { a: i32, b: { c: fp32? } }
+//
+// Example Output:
+//  .field(substrait_expr::helpers::types::i32(false))
+//  .nested(false, |builder| builder.field(substrait_expr::helpers::types::fp32(true)))
+fn rust_to_types_fields(schema: &TypedNestedType) -> proc_macro2::TokenStream {
+    let parsed_fields = schema
+        .fields
+        .iter()
+        .map(rust_field_to_types_field)
+        .collect::<Vec<_>>();
+    quote! {#(#parsed_fields)*}
+}
+
+// Convert rust code to a types-only schema
+//
+// Example input:
+//  { a: i32, b: { c: fp32? } }
+//
+// Example Output:
+//  TypesOnlySchemaBuilder::new()
+//    .field(substrait_expr::helpers::types::i32(false))
+//    .nested(false, |builder| builder.field(substrait_expr::helpers::types::fp32(true)))
+//    .build()
+fn rust_to_types_schema(schema: &TypedNestedType) -> proc_macro2::TokenStream {
+    let children = rust_to_types_fields(schema);
+    quote! {
+        substrait_expr::builder::schema::TypesOnlySchemaBuilder::new() #children .build()
+    }
+}
+
+// Convert rust code to a builder call that adds a field to a full schema
+//
+// Example Input:
+//  a: { b: i32? }
+//
+// Example Output:
+//  .nested("a", false, |builder| builder.field("b", substrait_expr::helpers::types::i32(true)))
+fn rust_field_to_full_field(field: &TypedField) -> proc_macro2::TokenStream {
+    let name = field.name.to_string();
+    let nullable = field.is_nullable();
+    match &field.ty {
+        TypedFieldType::Leaf(leaf) => {
+            let ty = rust_to_type(leaf, nullable);
+            quote! {
+                .field(#name, #ty)
+            }
+        }
+        TypedFieldType::Nested(nested) => {
+            let children = rust_to_full_fields(nested);
+            quote! {
+                .nested(#name, #nullable, |builder| builder #children)
+            }
+        }
+    }
+}
+
+// Convert rust code to a chain of builder calls that add fields to a full schema
+fn rust_to_full_fields(schema: &TypedNestedType) -> proc_macro2::TokenStream {
+    let parsed_fields = schema
+        .fields
+        .iter()
+        .map(rust_field_to_full_field)
+        .collect::<Vec<_>>();
+    quote! {#(#parsed_fields)*}
+}
+
+// Convert rust code to a full schema
+//
+// Example input:
+//  { a: i32, b: { c: fp32? } }
+//
+// Example Output:
+//  SchemaInfo::new_full()
+//    .field("a", substrait_expr::helpers::types::i32(false))
+//    .nested("b", false, |builder| builder.field("c", substrait_expr::helpers::types::fp32(true)))
+//    .build()
+fn rust_to_full_schema(schema: &TypedNestedType) -> proc_macro2::TokenStream {
+    let children = rust_to_full_fields(schema);
+    quote! {
+        <substrait_expr::helpers::schema::SchemaInfo as substrait_expr::builder::schema::SchemaBuildersExt>::new_full() #children .build()
+    }
+}
+
+// Convert rust code to a call that creates a type
+//
+// Example Input:
+//  map<string, list<i32?>>
+//
+// Example Output:
+//  substrait_expr::helpers::types::map(
+//    false,
+//    substrait_expr::helpers::types::string(false),
+//    substrait_expr::helpers::types::list(false, substrait_expr::helpers::types::i32(true)))
+fn rust_to_type(leaf: &LeafType, nullable: bool) -> proc_macro2::TokenStream {
+    let name = &leaf.name;
+    let args = leaf
+        .args
+        .iter()
+        .map(|arg| rust_to_arg_type(&arg.ty, arg.nullable.is_some()))
+        .collect::<Vec<_>>();
+    quote! {
+        substrait_expr::helpers::types::#name(#nullable #(, #args)*)
+    }
+}
+
+// Convert rust code to a call that creates the type of a list item or map key / value
+//
+// Example Input:
+//  { x: fp32, y: fp64? }
+//
+// Example Output:
+//  substrait_expr::helpers::types::struct_(false, vec![
+//    substrait_expr::helpers::types::fp32(false),
+//    substrait_expr::helpers::types::fp64(true)
+//  ])
+fn rust_to_arg_type(ty: &TypedFieldType, nullable: bool) -> proc_macro2::TokenStream {
+    match ty {
+        TypedFieldType::Leaf(leaf) => rust_to_type(leaf, nullable),
+        TypedFieldType::Nested(nested) => {
+            let children = nested
+                .fields
+                .iter()
+                .map(|field| rust_to_arg_type(&field.ty, field.is_nullable()))
+                .collect::<Vec<_>>();
+            quote! {
+                substrait_expr::helpers::types::struct_(#nullable, vec![#(#children),*])
+            }
+        }
+    }
+}
+
+// The type of a field in a types-only or full schema, either a leaf type or a nested struct
+enum TypedFieldType {
+    Leaf(LeafType),
+    Nested(TypedNestedType),
+}
+
+impl Parse for TypedFieldType {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(syn::token::Brace) {
+            Ok(TypedFieldType::Nested(input.parse()?))
+        } else {
+            Ok(TypedFieldType::Leaf(input.parse()?))
+        }
+    }
+}
+
+// New rust syntax for a leaf type, the name of a function in substrait_expr::helpers::types
+// followed by any type arguments that the function accepts after the nullability flag
+//
+// Examples:
+//  i32
+//  list<string?>
+//  map<string, { x: fp32 }>
+struct LeafType {
+    name: syn::Ident,
+    args: syn::punctuated::Punctuated<TypeArg, syn::Token![,]>,
+}
+
+impl Parse for LeafType {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        let mut args = syn::punctuated::Punctuated::new();
+        if input.parse::<Option<syn::Token![<]>>()?.is_some() {
+            loop {
+                args.push_value(input.parse()?);
+                if input.peek(syn::Token![>]) {
+                    break;
+                }
+                args.push_punct(input.parse()?);
+            }
+            input.parse::<syn::Token![>]>()?;
+        }
+        Ok(LeafType { name, args })
+    }
+}
+
+// New rust syntax for a type argument (e.g. a list item type), a `?` marks it as nullable
+//
+// Examples:
+//  string
+//  fp64?
+//  { x: fp32, y: fp64? }
+struct TypeArg {
+    ty: TypedFieldType,
+    nullable: Option<syn::Token![?]>,
+}
+
+impl Parse for TypeArg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(TypeArg {
+            ty: input.parse()?,
+            nullable: input.parse()?,
+        })
+    }
+}
+
+// New rust syntax for a field in a types-only or full schema
+//
+// In a types-only schema the names only serve as documentation.  A `?` after the
+// name or after the type marks the field as nullable.
+//
+// Examples:
+//  foo: i32
+//  bar: string?
+//  tags: list<string>?
+//  blah?: { x: fp32, y: fp64? }
+//  blah: { x: fp32, y: fp64? }?
+struct TypedField {
+    name: syn::Ident,
+    name_nullable: Option<syn::Token![?]>,
+    _colon_token: syn::Token![:],
+    ty: TypedFieldType,
+    type_nullable: Option<syn::Token![?]>,
+}
+
+impl TypedField {
+    fn is_nullable(&self) -> bool {
+        self.name_nullable.is_some() || self.type_nullable.is_some()
+    }
+}
+
+impl Parse for TypedField {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        let name_nullable = input.parse()?;
+        Ok(TypedField {
+            name,
+            name_nullable,
+            _colon_token: input.parse()?,
+            ty: input.parse()?,
+            type_nullable: input.parse()?,
+        })
+    }
+}
+
+// New rust syntax for a nested type in a types-only or full schema ({field, field, field})
+//
+// Examples:
+//  { foo: i32 }
+//  { blah: { x: fp32, y: fp64? } }
+struct TypedNestedType {
+    _brace_token: syn::token::Brace,
+    fields: syn::punctuated::Punctuated<TypedField, syn::Token![,]>,
+}
+
+impl Parse for TypedNestedType {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let content;
+        Ok(Self {
+            _brace_token: syn::braced!(content in input),
+            fields: content.parse_terminated(TypedField::parse, syn::Token![,])?,
+        })
+    }
+}
+
+fn types_schema2(input: proc_macro2::TokenStream) -> syn::Result<proc_macro2::TokenStream> {
+    let struct_type: TypedNestedType = syn::parse2(input)?;
+    Ok(rust_to_types_schema(&struct_type))
+}
+
+fn full_schema2(input: proc_macro2::TokenStream) -> syn::Result<proc_macro2::TokenStream> {
+    let struct_type: TypedNestedType = syn::parse2(input)?;
+    Ok(rust_to_full_schema(&struct_type))
+}
+
 fn names_schema2(input: proc_macro2::TokenStream) -> syn::Result<proc_macro2::TokenStream> {
     let struct_type: NestedType = syn::parse2(input)?;
     Ok(rust_to_names_schema(&struct_type))
@@ -132,5 +427,62 @@ fn names_schema2(input: proc_macro2::TokenStream) -> syn::Result<proc_macro2::To
 #[proc_macro]
 pub fn names_schema(input: TokenStream) -> TokenStream {
     let input = proc_macro2::TokenStream::from(input);
-    names_schema2(input).unwrap().into()
+    names_schema2(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+/// A macro to create types-only schemas from a dictionary-like rust syntax
+///
+/// Each type is the name of a function in `substrait_expr::helpers::types` that
+/// accepts a nullability flag.  Types that accept further types (e.g. `list` and `map`)
+/// take them in angle brackets and a `{ ... }` inside angle brackets is a struct.  A `?`
+/// after the name or the type marks a field (or nested struct) as nullable.  The field
+/// names are not part of the schema and only serve as documentation.
+///
+/// # Examples
+/// ```ignore
+/// use substrait_expr::macros::types_schema;
+///
+/// let schema = types_schema!({
+///   score: i32,
+///   location: {
+///     x: fp32,
+///     y: fp64?
+///   },
+///   tags: list<string?>,
+///   attrs: map<string, { x: i32 }>
+/// });
+/// ```
+#[proc_macro]
+pub fn types_schema(input: TokenStream) -> TokenStream {
+    let input = proc_macro2::TokenStream::from(input);
+    types_schema2(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+/// A macro to create full schemas (names and types) from a dictionary-like rust syntax
+///
+/// The syntax is the same as [`types_schema!`] but the field names are kept.
+///
+/// # Examples
+/// ```ignore
+/// use substrait_expr::macros::full_schema;
+///
+/// let schema = full_schema!({
+///   score: i32,
+///   location?: {
+///     x: fp32,
+///     y: fp64?
+///   },
+///   tags: list<string>
+/// });
+/// ```
+#[proc_macro]
+pub fn full_schema(input: TokenStream) -> TokenStream {
+    let input = proc_macro2::TokenStream::from(input);
+    full_schema2(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
 }
@@ -17,6 +17,9 @@ fn main() {
     generate_functions(&[(
         "https://github.com/substrait-io/substrait/blob/main/extensions/functions_arithmetic.yaml",
         "substrait/extensions/functions_arithmetic.yaml",
+    ), (
+        "https://github.com/substrait-io/substrait/blob/main/extensions/functions_arithmetic_decimal.yaml",
+        "substrait/extensions/functions_arithmetic_decimal.yaml",
     ), (
         "https://github.com/substrait-io/substrait/blob/main/extensions/functions_boolean.yaml",
         "substrait/extensions/functions_boolean.yaml",
@@ -26,6 +29,12 @@ fn main() {
     ), (
         "https://github.com/substrait-io/substrait/blob/main/extensions/functions_datetime.yaml",
         "substrait/extensions/functions_datetime.yaml",
+    ), (
+        "https://github.com/substrait-io/substrait/blob/main/extensions/functions_rounding.yaml",
+        "substrait/extensions/functions_rounding.yaml",
+    ), (
+        "https://github.com/substrait-io/substrait/blob/main/extensions/functions_string.yaml",
+        "substrait/extensions/functions_string.yaml",
     )], options)
     .unwrap();
 }
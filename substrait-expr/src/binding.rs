@@ -0,0 +1,636 @@
+//! # Binding loose expressions to a schema
+//!
+//! An expression built against a schema that does not know names (e.g. an empty schema)
+//! will contain "name lookup" placeholders (see
+//! [`lookup_field_by_name`](crate::builder::functions::FunctionsBuilder::lookup_field_by_name))
+//! instead of field references.  Once the real schema is known the expression can be bound
+//! to it, replacing each placeholder with a proper field reference.
+//!
+//! ```
+//! use substrait_expr::binding::bind;
+//! use substrait_expr::builder::schema::SchemaBuildersExt;
+//! use substrait_expr::builder::{BuilderParams, ExpressionsBuilder};
+//! use substrait_expr::helpers::schema::{EmptySchema, SchemaInfo};
+//! use substrait_expr::helpers::types;
+//!
+//! // At the edge we don't know the schema
+//! let loose_schema = SchemaInfo::Empty(EmptySchema::default());
+//! let builder = ExpressionsBuilder::new(loose_schema, BuilderParams::new_loose());
+//! let loose = builder.fields().resolve_by_name("x").unwrap();
+//!
+//! // Later, the schema is looked up in a catalog
+//! let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+//! let bound = bind(&loose, builder.schema(), &schema).unwrap();
+//! ```
+
+use substrait::proto::expression::field_reference::ReferenceType;
+use substrait::proto::expression::mask_expression::select;
+use substrait::proto::expression::reference_segment::{self, StructField};
+use substrait::proto::expression::{
+    literal::LiteralType, Literal, MaskExpression, ReferenceSegment, RexType, ScalarFunction,
+};
+use substrait::proto::function_argument::ArgType;
+use substrait::proto::r#type::{Kind, UserDefined};
+use substrait::proto::{Expression, FunctionArgument, Type};
+
+use crate::builder::functions::{
+    FunctionsBuilder, LOOKUP_BY_NAME_FUNC_NAME, LOOKUP_BY_NAME_FUNC_URI,
+};
+use crate::builder::schema::RefBuilder;
+use crate::builder::BuilderParams;
+use crate::error::{Result, SubstraitExprError};
+use crate::helpers::registry::ExtensionsRegistry;
+use crate::helpers::schema::SchemaInfo;
+use crate::helpers::visitor::ExpressionRewriter;
+
+/// Moves the function and type anchors of an expression from one registry to another
+///
+/// Each function and user defined type that a node refers to is registered with `to` and
+/// the anchor is replaced with the anchor from `to`.  As an [`ExpressionRewriter`] this
+/// remaps every node in the expression.
+pub(crate) struct AnchorRemapper<'a> {
+    pub(crate) from: &'a ExtensionsRegistry,
+    pub(crate) to: &'a ExtensionsRegistry,
+}
+
+impl<'a> AnchorRemapper<'a> {
+    fn remap_function(&self, function_reference: u32) -> Result<u32> {
+        let name = self
+            .from
+            .lookup_function(function_reference)
+            .ok_or_else(|| {
+                SubstraitExprError::invalid_substrait(format!(
+                    "The function anchor {} is not registered",
+                    function_reference
+                ))
+            })?;
+        Ok(self.to.register_function_by_name(&name.uri, &name.name))
+    }
+
+    fn remap_type_reference(&self, type_reference: u32) -> Result<u32> {
+        let name = self.from.lookup_type(type_reference).ok_or_else(|| {
+            SubstraitExprError::invalid_substrait(format!(
+                "The type anchor {} is not registered",
+                type_reference
+            ))
+        })?;
+        Ok(self.to.register_type(name.uri, &name.name))
+    }
+
+    fn remap_type(&self, typ: &mut Type) -> Result<()> {
+        match typ.kind.as_mut() {
+            Some(Kind::UserDefined(UserDefined { type_reference, .. })) => {
+                *type_reference = self.remap_type_reference(*type_reference)?;
+            }
+            Some(Kind::Struct(strct)) => {
+                for child in strct.types.iter_mut() {
+                    self.remap_type(child)?;
+                }
+            }
+            Some(Kind::List(list)) => {
+                if let Some(item) = list.r#type.as_mut() {
+                    self.remap_type(item)?;
+                }
+            }
+            Some(Kind::Map(map)) => {
+                if let Some(key) = map.key.as_mut() {
+                    self.remap_type(key)?;
+                }
+                if let Some(value) = map.value.as_mut() {
+                    self.remap_type(value)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn remap_literal(&self, literal: &mut Literal) -> Result<()> {
+        match literal.literal_type.as_mut() {
+            Some(LiteralType::Null(typ)) => self.remap_type(typ)?,
+            Some(LiteralType::EmptyList(list)) => {
+                if let Some(item) = list.r#type.as_mut() {
+                    self.remap_type(item)?;
+                }
+            }
+            Some(LiteralType::EmptyMap(map)) => {
+                if let Some(key) = map.key.as_mut() {
+                    self.remap_type(key)?;
+                }
+                if let Some(value) = map.value.as_mut() {
+                    self.remap_type(value)?;
+                }
+            }
+            Some(LiteralType::UserDefined(user_defined)) => {
+                user_defined.type_reference =
+                    self.remap_type_reference(user_defined.type_reference)?;
+            }
+            Some(LiteralType::Struct(strct)) => {
+                for field in strct.fields.iter_mut() {
+                    self.remap_literal(field)?;
+                }
+            }
+            Some(LiteralType::List(list)) => {
+                for value in list.values.iter_mut() {
+                    self.remap_literal(value)?;
+                }
+            }
+            Some(LiteralType::Map(map)) => {
+                for key_value in map.key_values.iter_mut() {
+                    for child in key_value.key.iter_mut().chain(&mut key_value.value) {
+                        self.remap_literal(child)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn remap_arguments(&self, arguments: &mut [FunctionArgument]) -> Result<()> {
+        for arg in arguments {
+            if let Some(ArgType::Type(typ)) = arg.arg_type.as_mut() {
+                self.remap_type(typ)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remaps the anchors that `expr` itself refers to, leaving its children unchanged
+    pub(crate) fn remap_node(&self, mut expr: Expression) -> Result<Expression> {
+        match expr.rex_type.as_mut() {
+            Some(RexType::Literal(literal)) => self.remap_literal(literal)?,
+            Some(RexType::ScalarFunction(func)) => {
+                func.function_reference = self.remap_function(func.function_reference)?;
+                self.remap_arguments(&mut func.arguments)?;
+                if let Some(output_type) = func.output_type.as_mut() {
+                    self.remap_type(output_type)?;
+                }
+            }
+            Some(RexType::WindowFunction(func)) => {
+                func.function_reference = self.remap_function(func.function_reference)?;
+                self.remap_arguments(&mut func.arguments)?;
+                if let Some(output_type) = func.output_type.as_mut() {
+                    self.remap_type(output_type)?;
+                }
+            }
+            Some(RexType::Cast(cast)) => {
+                if let Some(typ) = cast.r#type.as_mut() {
+                    self.remap_type(typ)?;
+                }
+            }
+            Some(RexType::SwitchExpression(switch)) => {
+                for case in switch.ifs.iter_mut() {
+                    if let Some(case_literal) = case.r#if.as_mut() {
+                        self.remap_literal(case_literal)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(expr)
+    }
+}
+
+impl<'a> ExpressionRewriter for AnchorRemapper<'a> {
+    fn rewrite_node(&mut self, expr: Expression) -> Result<Expression> {
+        self.remap_node(expr)
+    }
+}
+
+struct Binder<'a> {
+    anchors: AnchorRemapper<'a>,
+    schema: &'a SchemaInfo,
+    refs: RefBuilder<'a>,
+}
+
+impl<'a> Binder<'a> {
+    /// Returns the field reference that replaces a name lookup placeholder
+    ///
+    /// Returns None if `func` is not a placeholder or the schema does not know names, in
+    /// which case the lookup is kept, like any other function
+    fn resolve_lookup(&self, func: &ScalarFunction) -> Result<Option<Expression>> {
+        let name = self
+            .anchors
+            .from
+            .lookup_function(func.function_reference)
+            .ok_or_else(|| {
+                SubstraitExprError::invalid_substrait(format!(
+                    "The function anchor {} is not registered",
+                    func.function_reference
+                ))
+            })?;
+        let knows_names = matches!(self.schema, SchemaInfo::Names(_) | SchemaInfo::Full(_));
+        if !knows_names
+            || name.uri != LOOKUP_BY_NAME_FUNC_URI
+            || name.name != LOOKUP_BY_NAME_FUNC_NAME
+        {
+            return Ok(None);
+        }
+        let field_name = match func.arguments.first().and_then(|arg| arg.arg_type.as_ref()) {
+            Some(ArgType::Enum(field_name)) => field_name,
+            _ => {
+                return Err(SubstraitExprError::invalid_substrait(
+                    "A name lookup placeholder did not have a field name argument",
+                ))
+            }
+        };
+        self.refs
+            .resolve_by_name(field_name)
+            .map(Some)
+            .map_err(|err| {
+                SubstraitExprError::InvalidInput(format!(
+                    "Could not bind the field {}: {}",
+                    field_name, err
+                ))
+            })
+    }
+}
+
+impl<'a> ExpressionRewriter for Binder<'a> {
+    fn rewrite_node(&mut self, mut expr: Expression) -> Result<Expression> {
+        match expr.rex_type.as_mut() {
+            Some(RexType::ScalarFunction(func)) => {
+                if let Some(reference) = self.resolve_lookup(func)? {
+                    return Ok(reference);
+                }
+            }
+            Some(RexType::Selection(selection)) => {
+                if let Some(ReferenceType::MaskedReference(mask)) = &selection.reference_type {
+                    if let Some(segment) = mask_to_direct(mask) {
+                        selection.reference_type = Some(ReferenceType::DirectReference(segment));
+                    }
+                }
+            }
+            _ => {}
+        }
+        self.anchors.remap_node(expr)
+    }
+}
+
+/// Converts a mask that selects exactly one (possibly nested) struct field into
+/// the equivalent direct reference
+///
+/// Returns None if the mask selects more than one field, selects list or map
+/// items, or wraps the selected field in a struct
+fn mask_to_direct(mask: &MaskExpression) -> Option<ReferenceSegment> {
+    if mask.maintain_singular_struct {
+        return None;
+    }
+    let mut fields = Vec::new();
+    let mut select = mask.select.as_ref()?;
+    loop {
+        let [item] = select.struct_items.as_slice() else {
+            return None;
+        };
+        fields.push(item.field);
+        match item.child.as_ref().map(|child| child.r#type.as_ref()) {
+            None => break,
+            Some(Some(select::Type::Struct(child))) => select = child,
+            Some(_) => return None,
+        }
+    }
+    fields.into_iter().rev().fold(None, |child, field| {
+        Some(ReferenceSegment {
+            reference_type: Some(reference_segment::ReferenceType::StructField(Box::new(
+                StructField {
+                    field,
+                    child: child.map(Box::new),
+                },
+            ))),
+        })
+    })
+}
+
+/// Binds a loose expression to a schema
+///
+/// Every name lookup placeholder in `expr` is resolved against `schema` and replaced with
+/// a field reference.  Masked references that select a single field are replaced with
+/// direct references (see [`normalize_references`]).  `loose_schema` is the schema that `expr` was originally built with.
+/// It is needed to interpret the extension anchors in `expr`.  Any functions and types
+/// referenced by `expr` are registered with `schema` so that the bound expression can be
+/// used with a builder for `schema`.
+///
+/// The output types of function calls are not recomputed and so a function call that
+/// returned the unknown type will still return the unknown type after binding.
+///
+/// If `schema` does not know the names of its fields (e.g. a types-only schema) then the
+/// name lookups are kept as-is, including any nullability hint (see
+/// [`resolve_by_name_with_nullability`](RefBuilder::resolve_by_name_with_nullability)).
+///
+/// Returns an error, naming the offending field, if a name cannot be resolved
+pub fn bind(
+    expr: &Expression,
+    loose_schema: &SchemaInfo,
+    schema: &SchemaInfo,
+) -> Result<Expression> {
+    bind_with_params(expr, loose_schema, schema, &default_bind_params())
+}
+
+/// Like [`bind`] but names are resolved with the given builder parameters
+///
+/// For example, [`case_insensitive_names`](BuilderParams::case_insensitive_names) can be
+/// set to bind names that differ from the schema's field names in case.  Binding to a
+/// names-only schema requires [`allow_unknown_types`](BuilderParams::allow_unknown_types).
+pub fn bind_with_params(
+    expr: &Expression,
+    loose_schema: &SchemaInfo,
+    schema: &SchemaInfo,
+    params: &BuilderParams,
+) -> Result<Expression> {
+    bind_with_registry(expr, loose_schema.extensions_registry(), schema, params)
+}
+
+/// The parameters used by [`bind`]
+pub(crate) fn default_bind_params() -> BuilderParams {
+    BuilderParams {
+        allow_unknown_types: true,
+        ..Default::default()
+    }
+}
+
+/// Like [`bind_with_params`] but only the registry of the loose schema is needed
+pub(crate) fn bind_with_registry(
+    expr: &Expression,
+    loose_registry: &ExtensionsRegistry,
+    schema: &SchemaInfo,
+    params: &BuilderParams,
+) -> Result<Expression> {
+    let mut binder = Binder {
+        anchors: AnchorRemapper {
+            from: loose_registry,
+            to: schema.extensions_registry(),
+        },
+        schema,
+        refs: RefBuilder::new(
+            schema,
+            params,
+            FunctionsBuilder::new_with_params(schema, params),
+        ),
+    };
+    binder.rewrite(expr.clone())
+}
+
+/// Rewrites the field references in an expression into a canonical form
+///
+/// Name lookup placeholders are resolved against `schema` and masked references that
+/// select a single field are converted to direct references, wherever they are nested.
+/// Afterwards, two references to the same field will be structurally equal, regardless
+/// of how they were created, and so normalized expressions can be compared with `==`.
+///
+/// `expr` must have been built with `schema`.  Returns an error if a name cannot be
+/// resolved (see [`bind`]).
+pub fn normalize_references(expr: &Expression, schema: &SchemaInfo) -> Result<Expression> {
+    bind(expr, schema, schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use substrait::proto::expression::field_reference::{RootReference, RootType};
+    use substrait::proto::expression::mask_expression::{Select, StructItem, StructSelect};
+    use substrait::proto::expression::FieldReference;
+
+    use crate::builder::schema::SchemaBuildersExt;
+    use crate::builder::ExpressionsBuilder;
+    use crate::functions::functions_arithmetic::{FunctionsArithmeticExt, ADD};
+    use crate::helpers::expr::ExpressionExt;
+    use crate::helpers::literals::literal;
+    use crate::helpers::schema::EmptySchema;
+    use crate::helpers::types::{self, TypeExt};
+
+    use super::*;
+
+    #[test]
+    fn test_bind() {
+        let loose_schema = SchemaInfo::Empty(EmptySchema::default());
+        let loose_builder = ExpressionsBuilder::new(loose_schema, BuilderParams::new_loose());
+        let loose = loose_builder
+            .functions()
+            .add(
+                loose_builder.fields().resolve_by_name("y").unwrap(),
+                literal(3_i32),
+            )
+            .build()
+            .unwrap();
+
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(false))
+            .field("y", types::i32(false))
+            .build();
+        let bound = bind(&loose, loose_builder.schema(), &schema).unwrap();
+
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let func = match bound.try_rex_type().unwrap() {
+            RexType::ScalarFunction(func) => func,
+            _ => panic!("Expected a function call"),
+        };
+        assert_eq!(
+            func.function_reference,
+            builder
+                .schema()
+                .extensions_registry()
+                .register_function(&ADD)
+        );
+        let arg = match &func.arguments[0].arg_type {
+            Some(ArgType::Value(arg)) => arg,
+            _ => panic!("Expected a value argument"),
+        };
+        assert_eq!(*arg, builder.fields().resolve_by_name("y").unwrap());
+    }
+
+    #[test]
+    fn test_bind_nested_lookups() {
+        let loose_schema = SchemaInfo::Empty(EmptySchema::default());
+        let loose_builder = ExpressionsBuilder::new(loose_schema, BuilderParams::new_loose());
+        let loose_field = |name: &str| loose_builder.fields().resolve_by_name(name).unwrap();
+        // if flag then (x in (y, 3)) else false
+        let in_list = loose_builder
+            .functions()
+            .in_list(loose_field("x"), vec![loose_field("y"), literal(3_i32)])
+            .unwrap();
+        let loose = loose_builder
+            .functions()
+            .if_then(vec![(loose_field("flag"), in_list)], Some(literal(false)))
+            .unwrap();
+
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(false))
+            .field("y", types::i32(false))
+            .field("flag", types::bool(false))
+            .build();
+        let bound = bind(&loose, loose_builder.schema(), &schema).unwrap();
+
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let field = |name: &str| builder.fields().resolve_by_name(name).unwrap();
+        let in_list = builder
+            .functions()
+            .in_list(field("x"), vec![field("y"), literal(3_i32)])
+            .unwrap();
+        let expected = builder
+            .functions()
+            .if_then(vec![(field("flag"), in_list)], Some(literal(false)))
+            .unwrap();
+        assert_eq!(bound, expected);
+        assert!(!bound
+            .contains_unknown(builder.schema().extensions_registry())
+            .unwrap());
+
+        // Names that cannot be bound are reported, no matter how deeply they are nested
+        let missing = loose_builder
+            .functions()
+            .in_list(literal(1_i32), vec![loose_field("z")])
+            .unwrap();
+        match bind(&missing, loose_builder.schema(), builder.schema()) {
+            Err(SubstraitExprError::InvalidInput(message)) => assert!(message.contains('z')),
+            _ => panic!("Expected binding to fail"),
+        }
+    }
+
+    #[test]
+    fn test_bind_missing_field() {
+        let loose_schema = SchemaInfo::Empty(EmptySchema::default());
+        let loose_builder = ExpressionsBuilder::new(loose_schema, BuilderParams::new_loose());
+        let loose = loose_builder.fields().resolve_by_name("z").unwrap();
+
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        match bind(&loose, loose_builder.schema(), &schema) {
+            Err(SubstraitExprError::InvalidInput(message)) => assert!(message.contains('z')),
+            _ => panic!("Expected binding to fail"),
+        }
+    }
+
+    #[test]
+    fn test_bind_with_params() {
+        let loose_schema = SchemaInfo::Empty(EmptySchema::default());
+        let loose_builder = ExpressionsBuilder::new(loose_schema, BuilderParams::new_loose());
+        let loose = loose_builder.fields().resolve_by_name("X").unwrap();
+
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        assert!(bind(&loose, loose_builder.schema(), &schema).is_err());
+        let params = BuilderParams {
+            case_insensitive_names: true,
+            ..default_bind_params()
+        };
+        let bound = bind_with_params(&loose, loose_builder.schema(), &schema, &params).unwrap();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        assert_eq!(bound, builder.fields().resolve_by_name("x").unwrap());
+    }
+
+    #[test]
+    fn test_bind_nullability_hint() {
+        let loose_schema = SchemaInfo::Empty(EmptySchema::default());
+        let loose_builder = ExpressionsBuilder::new(loose_schema, BuilderParams::new_loose());
+        let hinted = loose_builder
+            .fields()
+            .resolve_by_name_with_nullability("x", false)
+            .unwrap();
+        let unknown = |schema: &SchemaInfo, nullable: bool| {
+            crate::builder::types::unknown(schema.extensions_registry()).with_nullability(nullable)
+        };
+        assert_eq!(
+            hinted.output_type(loose_builder.schema()).unwrap(),
+            unknown(loose_builder.schema(), false)
+        );
+
+        let types_schema = SchemaInfo::new_types()
+            .field(types::i64(true))
+            .field(types::i32(false))
+            .build();
+        let bound = bind(&hinted, loose_builder.schema(), &types_schema).unwrap();
+        assert_eq!(
+            bound.output_type(&types_schema).unwrap(),
+            unknown(&types_schema, false)
+        );
+        let unhinted = loose_builder.fields().resolve_by_name("x").unwrap();
+        let bound = bind(&unhinted, loose_builder.schema(), &types_schema).unwrap();
+        assert_eq!(
+            bound.output_type(&types_schema).unwrap(),
+            unknown(&types_schema, true)
+        );
+
+        // Once the names are known the real type is used
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let bound = bind(&hinted, loose_builder.schema(), &schema).unwrap();
+        assert_eq!(bound.output_type(&schema).unwrap(), types::i32(false));
+    }
+
+    #[test]
+    fn test_normalize_references() {
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(false))
+            .nested("location", false, |builder| {
+                builder
+                    .field("lat", types::fp64(false))
+                    .field("lon", types::fp64(false))
+            })
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let by_index = builder.fields().resolve_by_index(&[1, 1]).unwrap();
+        let by_name = builder.functions().lookup_field_by_name("location.lon");
+        assert_ne!(by_name, by_index);
+
+        let mask = MaskExpression {
+            select: Some(StructSelect {
+                struct_items: vec![StructItem {
+                    field: 1,
+                    child: Some(Select {
+                        r#type: Some(select::Type::Struct(StructSelect {
+                            struct_items: vec![StructItem {
+                                field: 1,
+                                child: None,
+                            }],
+                        })),
+                    }),
+                }],
+            }),
+            maintain_singular_struct: false,
+        };
+        let by_mask = Expression {
+            rex_type: Some(RexType::Selection(Box::new(FieldReference {
+                reference_type: Some(ReferenceType::MaskedReference(mask.clone())),
+                root_type: Some(RootType::RootReference(RootReference {})),
+            }))),
+        };
+
+        let normalized = normalize_references(&by_index, builder.schema()).unwrap();
+        assert_eq!(normalized, by_index);
+        assert_eq!(
+            normalize_references(&by_name, builder.schema()).unwrap(),
+            normalized
+        );
+        assert_eq!(
+            normalize_references(&by_mask, builder.schema()).unwrap(),
+            normalized
+        );
+
+        // Masks that keep the struct wrapper are not simple field references
+        let wrapped = Expression {
+            rex_type: Some(RexType::Selection(Box::new(FieldReference {
+                reference_type: Some(ReferenceType::MaskedReference(MaskExpression {
+                    maintain_singular_struct: true,
+                    ..mask
+                })),
+                root_type: Some(RootType::RootReference(RootReference {})),
+            }))),
+        };
+        assert_eq!(
+            normalize_references(&wrapped, builder.schema()).unwrap(),
+            wrapped
+        );
+
+        // Masked references nested in other expressions are normalized too
+        let strct = builder
+            .functions()
+            .make_struct(vec![literal(1_i32), by_mask])
+            .unwrap();
+        let expected = builder
+            .functions()
+            .make_struct(vec![literal(1_i32), by_index])
+            .unwrap();
+        assert_ne!(strct, expected);
+        assert_eq!(
+            normalize_references(&strct, builder.schema()).unwrap(),
+            expected
+        );
+    }
+}
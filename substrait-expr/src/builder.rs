@@ -117,7 +117,7 @@
 //! compile time.
 //!
 //! ```
-//! use substrait_expr::macros::names_schema;
+//! use substrait_expr::macros::{full_schema, names_schema, types_schema};
 //!
 //! // Names only
 //! let schema = names_schema!({
@@ -128,11 +128,23 @@
 //!     }
 //! });
 //!
-//! // Types only
-//! // TODO
+//! // Types only (a trailing ? marks a field as nullable)
+//! let schema = types_schema!({
+//!     score: i32,
+//!     location: {
+//!        x: fp32,
+//!        y: fp64?
+//!     }
+//! });
 //!
 //! // Full
-//! // TODO
+//! let schema = full_schema!({
+//!     score: i32,
+//!     location?: {
+//!        x: fp32,
+//!        y: fp64?
+//!     }
+//! });
 //! ```
 //!
 //! ## Creating Expressions
@@ -190,25 +202,53 @@
 
 use std::cell::RefCell;
 
+use substrait::proto::expression::field_reference::{ReferenceType, RootType};
+use substrait::proto::expression::{reference_segment, RexType};
 use substrait::proto::expression_reference::ExprType;
-use substrait::proto::{Expression, ExpressionReference, ExtendedExpression};
+use substrait::proto::extensions::AdvancedExtension;
+use substrait::proto::{Expression, ExpressionReference, ExtendedExpression, Type, Version};
 
 use crate::error::{Result, SubstraitExprError};
 use crate::helpers::expr::ExpressionExt;
-use crate::helpers::schema::SchemaInfo;
+use crate::helpers::registry::ExtensionsRegistry;
+use crate::helpers::schema::{FullSchemaNode, NamesOnlySchemaNode, SchemaInfo};
 use crate::helpers::types::TypeExt;
+use crate::util::HasRequiredProperties;
 
 use self::functions::FunctionsBuilder;
 use self::schema::RefBuilder;
 
+pub mod dsl;
 pub mod functions;
+pub mod rpn;
 pub mod schema;
 pub mod types;
 
+/// The producer recorded in built messages unless [`BuilderParams::producer`] is set
+pub const DEFAULT_PRODUCER: &str = "substrait-expr";
+
 pub struct BuilderParams {
     pub allow_late_name_lookup: bool,
     pub allow_loose_types: bool,
     pub allow_unknown_types: bool,
+    /// If true, function arguments are promoted (e.g. i32 to i64) when no implementation
+    /// matches the arguments exactly.  The promoted arguments are wrapped in explicit casts.
+    pub allow_type_promotion: bool,
+    /// If true, it is an error if more than one function implementation matches the
+    /// arguments equally well (instead of picking the first match)
+    pub strict_function_resolution: bool,
+    /// If true, field names are matched ignoring ASCII case when resolving references by
+    /// name.  It is an error if a name matches more than one field.
+    pub case_insensitive_names: bool,
+    /// The producer recorded in the version of the built message
+    ///
+    /// If None then `substrait-expr` is used
+    pub producer: Option<String>,
+    /// The Substrait version recorded in the built message
+    ///
+    /// If None then the version of Substrait this crate was built against is used.  The
+    /// producer of this version is replaced by [`producer`](Self::producer).
+    pub substrait_version: Option<Version>,
 }
 
 impl Default for BuilderParams {
@@ -217,6 +257,11 @@ impl Default for BuilderParams {
             allow_late_name_lookup: false,
             allow_loose_types: false,
             allow_unknown_types: false,
+            allow_type_promotion: false,
+            strict_function_resolution: false,
+            case_insensitive_names: false,
+            producer: None,
+            substrait_version: None,
         }
     }
 }
@@ -227,12 +272,18 @@ impl BuilderParams {
             allow_late_name_lookup: true,
             allow_loose_types: true,
             allow_unknown_types: true,
+            allow_type_promotion: false,
+            strict_function_resolution: false,
+            case_insensitive_names: false,
+            producer: None,
+            substrait_version: None,
         }
     }
 }
 
 struct NamedExpression {
-    expr: Expression,
+    // Expressions loaded from an existing message may be measures
+    expr: ExprType,
     output_names: Vec<String>,
 }
 
@@ -247,7 +298,10 @@ impl NamedExpression {
                 num_types, num_names
             )))
         } else {
-            Ok(Self { expr, output_names })
+            Ok(Self {
+                expr: ExprType::Expression(expr),
+                output_names,
+            })
         }
     }
 }
@@ -263,6 +317,8 @@ pub struct ExpressionsBuilder {
     schema: SchemaInfo,
     params: BuilderParams,
     expressions: RefCell<Vec<NamedExpression>>,
+    advanced_extensions: RefCell<Option<AdvancedExtension>>,
+    expected_type_urls: RefCell<Vec<String>>,
 }
 
 pub trait IntoExprOutputNames {
@@ -287,13 +343,211 @@ impl IntoExprOutputNames for Vec<String> {
     }
 }
 
+fn push_names_dfs(node: &NamesOnlySchemaNode, names: &mut Vec<String>) {
+    for child in &node.children {
+        names.push(child.name.clone());
+        push_names_dfs(child, names);
+    }
+}
+
+fn push_full_names_dfs(node: &FullSchemaNode, names: &mut Vec<String>) {
+    for child in &node.children {
+        names.push(child.name.clone());
+        push_full_names_dfs(child, names);
+    }
+}
+
+/// Creates the output names for an expression that returns a struct
+///
+/// Substrait expects the names of a struct-returning expression to be flattened in
+/// depth-first order: the name of the expression itself followed by the name of each
+/// field (and the names of any nested fields).  `fields` describes the struct's fields
+/// and is typically created with the `names_schema!` macro.
+///
+/// ```
+/// # use substrait_expr::builder::nested_output_names;
+/// # use substrait_expr::macros::names_schema;
+/// let names = nested_output_names(
+///     "location",
+///     &names_schema!({
+///         x: {},
+///         y: {}
+///     }),
+/// )
+/// .unwrap();
+/// assert_eq!(names, vec!["location", "x", "y"]);
+/// ```
+///
+/// Returns an error if `fields` does not know the names of its fields
+pub fn nested_output_names(name: impl Into<String>, fields: &SchemaInfo) -> Result<Vec<String>> {
+    let mut names = vec![name.into()];
+    match fields {
+        SchemaInfo::Names(names_schema) => push_names_dfs(&names_schema.root, &mut names),
+        SchemaInfo::Full(full_schema) => push_full_names_dfs(&full_schema.root, &mut names),
+        SchemaInfo::Empty(_) | SchemaInfo::Types(_) => {
+            return Err(SubstraitExprError::invalid_input(
+                "Nested output names must be described by a schema that knows the field names",
+            ))
+        }
+    }
+    Ok(names)
+}
+
+/// Appends the dotted names of `node`'s descendants in DFS order (e.g. `expr_0.a.b`)
+fn push_dotted_names_dfs<N>(
+    node: &N,
+    prefix: &str,
+    children: &impl Fn(&N) -> &[N],
+    name: &impl Fn(&N) -> &str,
+    names: &mut Vec<String>,
+) {
+    for child in children(node) {
+        let child_name = format!("{}.{}", prefix, name(child));
+        names.push(child_name.clone());
+        push_dotted_names_dfs(child, &child_name, children, name, names);
+    }
+}
+
+/// Appends positional names (e.g. `expr_0.field_0`) for the fields of `typ` in DFS order
+fn push_positional_names_dfs(typ: &Type, prefix: &str, names: &mut Vec<String>) {
+    for (idx, child) in typ.children().into_iter().enumerate() {
+        let child_name = format!("{}.field_{}", prefix, idx);
+        names.push(child_name.clone());
+        push_positional_names_dfs(child, &child_name, names);
+    }
+}
+
+/// Follows a direct reference from the root of the schema to a (possibly nested) node
+fn referenced_node<'a, N>(
+    expression: &Expression,
+    root: &'a N,
+    children: impl Fn(&N) -> &[N],
+) -> Option<&'a N> {
+    let Some(RexType::Selection(field_ref)) = &expression.rex_type else {
+        return None;
+    };
+    let (Some(RootType::RootReference(_)), Some(ReferenceType::DirectReference(segment))) =
+        (&field_ref.root_type, &field_ref.reference_type)
+    else {
+        return None;
+    };
+    let mut node = root;
+    let mut segment = Some(segment);
+    while let Some(seg) = segment {
+        let Some(reference_segment::ReferenceType::StructField(field)) = &seg.reference_type else {
+            return None;
+        };
+        node = children(node).get(usize::try_from(field.field).ok()?)?;
+        segment = field.child.as_deref();
+    }
+    Some(node)
+}
+
+/// Generates output names for an expression (`expr_N` followed by the names of any struct
+/// fields)
+///
+/// If the expression is a direct reference to a struct field and the schema knows the names
+/// of that struct's fields then those names are used (e.g. `expr_0.x`).  Otherwise the fields
+/// are given positional names (e.g. `expr_0.field_0`).
+fn auto_output_names(
+    idx: usize,
+    expression: &Expression,
+    output_type: &Type,
+    schema: &SchemaInfo,
+) -> Vec<String> {
+    let prefix = format!("expr_{}", idx);
+    let mut names = vec![prefix.clone()];
+    match schema {
+        SchemaInfo::Names(names_schema) => {
+            if let Some(node) = referenced_node(expression, &names_schema.root, |node| {
+                node.children.as_slice()
+            }) {
+                push_dotted_names_dfs(
+                    node,
+                    &prefix,
+                    &|node: &NamesOnlySchemaNode| node.children.as_slice(),
+                    &|node: &NamesOnlySchemaNode| node.name.as_str(),
+                    &mut names,
+                );
+            }
+        }
+        SchemaInfo::Full(full_schema) => {
+            if let Some(node) = referenced_node(expression, &full_schema.root, |node| {
+                node.children.as_slice()
+            }) {
+                push_dotted_names_dfs(
+                    node,
+                    &prefix,
+                    &|node: &FullSchemaNode| node.children.as_slice(),
+                    &|node: &FullSchemaNode| node.name.as_str(),
+                    &mut names,
+                );
+            }
+        }
+        SchemaInfo::Empty(_) | SchemaInfo::Types(_) => {}
+    }
+    // Fall back to positional names if the names do not line up with the output type
+    if names.len() != output_type.num_types() as usize {
+        names.truncate(1);
+        push_positional_names_dfs(output_type, &prefix, &mut names);
+    }
+    names
+}
+
 impl ExpressionsBuilder {
     pub fn new(schema: SchemaInfo, params: BuilderParams) -> Self {
         Self {
             schema,
             params,
             expressions: RefCell::new(Vec::new()),
+            advanced_extensions: RefCell::new(None),
+            expected_type_urls: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Creates a builder that starts with the contents of an existing message
+    ///
+    /// The schema and extensions registry are recreated from the message and the message's
+    /// expressions are loaded as-is.  New expressions can then be added and [`build`](Self::build)
+    /// will create an equivalent message that also contains the new expressions.
+    ///
+    /// Unless `params` sets them, the message's Substrait version and producer are kept.
+    /// Returns an error if the message does not have a base schema or its extensions are not
+    /// valid.
+    pub fn from_extended(msg: ExtendedExpression, mut params: BuilderParams) -> Result<Self> {
+        if let Some(version) = msg.version {
+            if params.producer.is_none() {
+                params.producer = Some(version.producer.clone());
+            }
+            if params.substrait_version.is_none() {
+                params.substrait_version = Some(version);
+            }
         }
+        let registry = ExtensionsRegistry::from_substrait(&msg.extension_uris, &msg.extensions)?;
+        let base_schema = msg.base_schema.into_required("base_schema")?;
+        let schema = SchemaInfo::from_substrait_with_registry(base_schema, registry)?;
+        let expressions = msg
+            .referred_expr
+            .into_iter()
+            .map(|expr_ref| {
+                Ok(NamedExpression {
+                    expr: expr_ref.expr_type.into_required("expr_type")?,
+                    output_names: expr_ref.output_names,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            schema,
+            params,
+            expressions: RefCell::new(expressions),
+            advanced_extensions: RefCell::new(msg.advanced_extensions),
+            expected_type_urls: RefCell::new(msg.expected_type_urls),
+        })
+    }
+
+    /// The schema that expressions are built against
+    pub fn schema(&self) -> &SchemaInfo {
+        &self.schema
     }
 
     pub fn fields(&self) -> RefBuilder {
@@ -301,7 +555,7 @@ impl ExpressionsBuilder {
     }
 
     pub fn functions(&self) -> FunctionsBuilder {
-        FunctionsBuilder::new(&self.schema)
+        FunctionsBuilder::new_with_params(&self.schema, &self.params)
     }
 
     pub fn add_expression(
@@ -318,7 +572,82 @@ impl ExpressionsBuilder {
         Ok(self)
     }
 
+    /// Adds an expression with generated output names
+    ///
+    /// The expression is named `expr_N` where N is its position in the builder.  If the
+    /// expression returns a struct then each (nested) field is also given a name such as
+    /// `expr_N.x` (or `expr_N.field_0` if the schema does not know the field names) so the
+    /// number of names always matches the output type.
+    pub fn add_expression_auto(&self, expression: Expression) -> Result<&Self> {
+        let output_type = expression.output_type(&self.schema)?;
+        let idx = self.expressions.borrow().len();
+        let names = auto_output_names(idx, &expression, &output_type, &self.schema);
+        self.add_expression(names, expression)
+    }
+
+    /// Adds several named expressions at once, in iteration order
+    ///
+    /// This is convenient when translating a list of named columns (e.g. a SELECT list).
+    /// If any expression is invalid then an error naming it is returned and none of the
+    /// expressions are added.
+    pub fn add_expressions(
+        &self,
+        expressions: impl IntoIterator<Item = (String, Expression)>,
+    ) -> Result<&Self> {
+        let named_exprs = expressions
+            .into_iter()
+            .map(|(name, expression)| {
+                NamedExpression::try_new(expression, name.clone().into_names(), &self.schema)
+                    .map_err(|err| {
+                        SubstraitExprError::InvalidInput(format!(
+                            "Could not add the expression {}: {}",
+                            name, err
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.expressions.borrow_mut().extend(named_exprs);
+        Ok(self)
+    }
+
+    /// Sets the advanced extensions that will be attached to the built message
+    ///
+    /// This can be used to carry optimization hints or engine-specific metadata.  Calling
+    /// this again replaces any previously set extensions.
+    ///
+    /// Note that Substrait only supports advanced extensions on the ExtendedExpression as a
+    /// whole and not on the individual expressions it contains.
+    pub fn set_advanced_extensions(&self, advanced_extensions: AdvancedExtension) -> &Self {
+        self.advanced_extensions.replace(Some(advanced_extensions));
+        self
+    }
+
+    /// Adds a type URL to the `expected_type_urls` of the built message
+    ///
+    /// Consumers use this list to check, up front, that they understand the protobuf `Any`
+    /// messages (e.g. in advanced extensions) that the message contains.  A URL that has
+    /// already been added is not added again.
+    pub fn with_expected_type_url(&self, type_url: impl Into<String>) -> &Self {
+        let type_url = type_url.into();
+        let mut expected_type_urls = self.expected_type_urls.borrow_mut();
+        if !expected_type_urls.contains(&type_url) {
+            expected_type_urls.push(type_url);
+        }
+        self
+    }
+
     pub fn build(self) -> ExtendedExpression {
+        let producer = self
+            .params
+            .producer
+            .unwrap_or_else(|| DEFAULT_PRODUCER.to_string());
+        let version = Version {
+            producer,
+            ..self
+                .params
+                .substrait_version
+                .unwrap_or_else(substrait::version::version)
+        };
         let (extension_uris, extensions) = self.schema.extensions_registry().to_substrait();
         let referred_expr = self
             .expressions
@@ -326,15 +655,15 @@ impl ExpressionsBuilder {
             .into_iter()
             .map(|named_expr| ExpressionReference {
                 output_names: named_expr.output_names,
-                expr_type: Some(ExprType::Expression(named_expr.expr)),
+                expr_type: Some(named_expr.expr),
             })
             .collect::<Vec<_>>();
         ExtendedExpression {
-            version: Some(substrait::version::version_with_producer("substrait-expr")),
+            version: Some(version),
             extension_uris,
             extensions,
-            advanced_extensions: None,
-            expected_type_urls: Vec::new(),
+            advanced_extensions: self.advanced_extensions.into_inner(),
+            expected_type_urls: self.expected_type_urls.into_inner(),
             base_schema: Some(self.schema.to_substrait()),
             referred_expr,
         }
@@ -345,8 +674,11 @@ impl ExpressionsBuilder {
 mod tests {
     use substrait_expr_macros::names_schema;
 
+    use super::schema::SchemaBuildersExt;
     use super::*;
     use crate as substrait_expr;
+    use crate::helpers::literals::literal;
+    use crate::helpers::types;
 
     #[test]
     fn prevent_unknown_types_via_unknown_field_ref() {
@@ -361,4 +693,262 @@ mod tests {
         assert!(builder.fields().resolve_by_name("x").is_err());
         assert!(builder.fields().field_builder().field("x").is_err());
     }
+
+    #[test]
+    fn nested_output_names_for_struct() {
+        let schema = SchemaInfo::new_full()
+            .field("score", types::i32(false))
+            .nested("location", false, |builder| {
+                builder
+                    .field("x", types::fp32(false))
+                    .nested("z", true, |builder| builder.field("w", types::i8(true)))
+                    .field("y", types::fp64(true))
+            })
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let location = builder.fields().resolve_by_name("location").unwrap();
+
+        let names = nested_output_names(
+            "loc",
+            &names_schema!({
+                a: {},
+                b: {
+                    c: {}
+                },
+                d: {}
+            }),
+        )
+        .unwrap();
+        assert_eq!(names, vec!["loc", "a", "b", "c", "d"]);
+        builder.add_expression(names, location.clone()).unwrap();
+
+        // The names must match the shape of the struct
+        let too_few = nested_output_names(
+            "loc",
+            &names_schema!({
+                a: {},
+                b: {}
+            }),
+        )
+        .unwrap();
+        assert!(builder.add_expression(too_few, location).is_err());
+
+        let built = builder.build();
+        assert_eq!(
+            built.referred_expr[0].output_names,
+            vec!["loc", "a", "b", "c", "d"]
+        );
+
+        let types_only = SchemaInfo::new_types().field(types::i32(false)).build();
+        assert!(nested_output_names("loc", &types_only).is_err());
+    }
+
+    #[test]
+    fn add_many_expressions() {
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(false))
+            .field("y", types::fp64(false))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let y = builder.fields().resolve_by_name("y").unwrap();
+        builder
+            .add_expressions([
+                ("b".to_string(), x.clone()),
+                ("a".to_string(), y),
+                ("c".to_string(), literal(3_i32)),
+            ])
+            .unwrap();
+
+        // A failure adds nothing and names the offending expression
+        let invalid = Expression::default();
+        match builder.add_expressions([("d".to_string(), x), ("e".to_string(), invalid)]) {
+            Err(SubstraitExprError::InvalidInput(message)) => {
+                assert!(message.contains("expression e:"))
+            }
+            _ => panic!("Expected the invalid expression to be rejected"),
+        }
+
+        let built = builder.build();
+        let names = built
+            .referred_expr
+            .iter()
+            .map(|expr| expr.output_names.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![vec!["b"], vec!["a"], vec!["c"]]);
+    }
+
+    #[test]
+    fn advanced_extensions() {
+        let schema = names_schema!({
+            x: {}
+        });
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::new_loose());
+        let built = builder.build();
+        assert_eq!(built.advanced_extensions, None);
+
+        let schema = names_schema!({
+            x: {}
+        });
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::new_loose());
+        let mut advanced_extensions = AdvancedExtension {
+            optimization: vec![Default::default()],
+            enhancement: None,
+        };
+        advanced_extensions.optimization[0].type_url = "https://imaginary.com/hint".to_string();
+        advanced_extensions.optimization[0].value = vec![1, 2, 3];
+        builder.set_advanced_extensions(advanced_extensions.clone());
+        let built = builder.build();
+        assert_eq!(built.advanced_extensions, Some(advanced_extensions.clone()));
+        assert!(built.expected_type_urls.is_empty());
+
+        let schema = names_schema!({
+            x: {}
+        });
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::new_loose());
+        // Setting the extensions again replaces the earlier extensions
+        builder
+            .set_advanced_extensions(AdvancedExtension::default())
+            .set_advanced_extensions(advanced_extensions.clone())
+            .with_expected_type_url("https://imaginary.com/hint")
+            .with_expected_type_url("https://imaginary.com/hint");
+        let built = builder.build();
+        assert_eq!(built.advanced_extensions, Some(advanced_extensions));
+        assert_eq!(built.expected_type_urls, vec!["https://imaginary.com/hint"]);
+    }
+
+    #[test]
+    fn add_expression_auto_names() {
+        let schema = SchemaInfo::new_full()
+            .field("score", types::i32(false))
+            .nested("location", false, |builder| {
+                builder
+                    .field("x", types::fp32(false))
+                    .field("y", types::fp32(false))
+            })
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let score = builder.fields().resolve_by_name("score").unwrap();
+        let location = builder.fields().resolve_by_name("location").unwrap();
+        builder.add_expression_auto(score).unwrap();
+        builder.add_expression_auto(location).unwrap();
+        // The field names of a nested literal are not known
+        let point = builder
+            .functions()
+            .make_struct(vec![literal(1_i32), literal(2_i32)])
+            .unwrap();
+        builder.add_expression_auto(point).unwrap();
+
+        let names = builder
+            .build()
+            .referred_expr
+            .into_iter()
+            .map(|expr| expr.output_names)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                vec!["expr_0"],
+                vec!["expr_1", "expr_1.x", "expr_1.y"],
+                vec!["expr_2", "expr_2.field_0", "expr_2.field_1"],
+            ]
+        );
+    }
+
+    #[test]
+    fn producer_and_version() {
+        let builder = ExpressionsBuilder::new(names_schema!({}), BuilderParams::default());
+        let version = builder.build().version.unwrap();
+        assert_eq!(version.producer, DEFAULT_PRODUCER);
+        assert_eq!(
+            version.minor_number,
+            substrait::version::version().minor_number
+        );
+
+        let params = BuilderParams {
+            producer: Some("my-engine".to_string()),
+            substrait_version: Some(Version {
+                major_number: 0,
+                minor_number: 42,
+                patch_number: 1,
+                producer: "ignored".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let builder = ExpressionsBuilder::new(names_schema!({}), params);
+        let version = builder.build().version.unwrap();
+        assert_eq!(version.producer, "my-engine");
+        assert_eq!(
+            (
+                version.major_number,
+                version.minor_number,
+                version.patch_number
+            ),
+            (0, 42, 1)
+        );
+    }
+
+    #[test]
+    fn load_from_extended() {
+        use crate::functions::functions_arithmetic::FunctionsArithmeticExt;
+        use crate::functions::functions_comparison::FunctionsComparisonExt;
+
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(false))
+            .field("y", types::i32(true))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let sum = builder.functions().add(x, literal(1_i32)).build().unwrap();
+        builder.add_expression("sum", sum).unwrap();
+        let mut original = builder.build();
+        original.version = Some(Version {
+            minor_number: 3,
+            producer: "other-engine".to_string(),
+            ..Default::default()
+        });
+
+        let builder =
+            ExpressionsBuilder::from_extended(original.clone(), BuilderParams::default()).unwrap();
+        let rebuilt = builder.build();
+        assert_eq!(rebuilt.referred_expr, original.referred_expr);
+        // The message's version and producer are kept unless the params override them
+        assert_eq!(rebuilt.version, original.version);
+        let params = BuilderParams {
+            producer: Some("my-engine".to_string()),
+            ..Default::default()
+        };
+        let builder = ExpressionsBuilder::from_extended(original.clone(), params).unwrap();
+        let version = builder.build().version.unwrap();
+        assert_eq!(
+            (version.minor_number, version.producer.as_str()),
+            (3, "my-engine")
+        );
+
+        let builder =
+            ExpressionsBuilder::from_extended(original.clone(), BuilderParams::default()).unwrap();
+        let y = builder.fields().resolve_by_name("y").unwrap();
+        let filter = builder.functions().lt(y, literal(10_i32)).build().unwrap();
+        builder.add_expression("filter", filter).unwrap();
+        let extended = builder.build();
+
+        assert_eq!(extended.base_schema, original.base_schema);
+        assert_eq!(extended.referred_expr.len(), 2);
+        assert_eq!(extended.referred_expr[0], original.referred_expr[0]);
+        // Existing anchors are preserved and the new function gets a new anchor
+        assert!(original
+            .extensions
+            .iter()
+            .all(|ext| extended.extensions.contains(ext)));
+        assert_eq!(extended.extensions.len(), original.extensions.len() + 1);
+
+        let missing_schema = ExtendedExpression {
+            base_schema: None,
+            ..original
+        };
+        assert!(
+            ExpressionsBuilder::from_extended(missing_schema, BuilderParams::default()).is_err()
+        );
+    }
 }
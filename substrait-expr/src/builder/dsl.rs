@@ -0,0 +1,405 @@
+//! # Build expressions from rust closures
+//!
+//! [`build_expr`] hands a closure the fields of a full schema.  Fields are looked up by
+//! name with `[]` and combined with the arithmetic operators (`+`, `-`, `*`, `/`) or the
+//! comparison methods (e.g. [`lt`](ExprBuilder::lt)).
+//!
+//! ```
+//! use substrait_expr::builder::dsl::build_expr;
+//! use substrait_expr::builder::schema::SchemaBuildersExt;
+//! use substrait_expr::helpers::literals::literal;
+//! use substrait_expr::helpers::schema::SchemaInfo;
+//! use substrait_expr::helpers::types;
+//!
+//! let schema = SchemaInfo::new_full()
+//!     .field("score", types::fp64(false))
+//!     .nested("location", false, |builder| {
+//!         builder.field("x", types::fp64(false))
+//!     })
+//!     .build();
+//! // location.x * 2 < score
+//! let expr = build_expr(&schema, |fields| {
+//!     (&fields["location"]["x"] * literal(2.0)).lt(&fields["score"])
+//! })
+//! .unwrap();
+//! ```
+//!
+//! Looking up a field that does not exist panics (in the same way that indexing a
+//! `HashMap` would).  Use [`get`](Fields::get) to look up fields that may not exist.
+//! Errors building functions (e.g. adding a string to a number) are returned by
+//! [`build_expr`].
+//!
+//! ## Operators without a closure
+//!
+//! The operators are implemented by [`ExprBuilder`], which can also be created from any
+//! expression with [`FunctionsBuilder::expr`].  Call [`build`](ExprBuilder::build) to get
+//! the plain expression back.
+//!
+//! ```
+//! use substrait_expr::builder::schema::SchemaBuildersExt;
+//! use substrait_expr::builder::{BuilderParams, ExpressionsBuilder};
+//! use substrait_expr::helpers::literals::literal;
+//! use substrait_expr::helpers::schema::SchemaInfo;
+//! use substrait_expr::helpers::types;
+//!
+//! let schema = SchemaInfo::new_full().field("a", types::i32(false)).build();
+//! let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+//! let a = builder.fields().resolve_by_name("a").unwrap();
+//! // (a + 3) * 2
+//! let expr = ((builder.functions().expr(a) + literal(3)) * literal(2))
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! Note that the operators follow Rust's precedence and associativity rules, not SQL's.
+//! These agree for `+`, `-`, `*`, and `/` but the comparison methods are ordinary method
+//! calls and bind tighter than any operator.  For example, `a + b.lt(c)` is `a + (b < c)`.
+//! Use parentheses when in doubt.
+
+use std::ops::{Add, Div, Index, Mul, Sub};
+
+use substrait::proto::Expression;
+
+use crate::error::{Result, SubstraitExprError};
+use crate::helpers::literals::{literal, LiteralInference};
+use crate::helpers::schema::{FullSchemaNode, SchemaInfo};
+
+use super::functions::FunctionsBuilder;
+use super::rpn::Operator;
+use super::schema::RefBuilder;
+use super::BuilderParams;
+
+/// A field of the schema given to [`build_expr`]
+pub struct Field<'a> {
+    functions: FunctionsBuilder<'a>,
+    name: String,
+    reference: Expression,
+    children: Vec<Field<'a>>,
+}
+
+impl<'a> Field<'a> {
+    /// The name of the field
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The child field with the given name, if there is one
+    pub fn get(&self, name: &str) -> Option<&Field<'a>> {
+        find_field(&self.children, name)
+    }
+
+    /// A reference to this field
+    pub fn expr(&self) -> ExprBuilder<'a> {
+        ExprBuilder::new(self.functions.clone(), Ok(self.reference.clone()))
+    }
+}
+
+impl<'a> Index<&str> for Field<'a> {
+    type Output = Field<'a>;
+
+    fn index(&self, name: &str) -> &Field<'a> {
+        self.get(name)
+            .unwrap_or_else(|| panic!("The field {} has no child named {}", self.name, name))
+    }
+}
+
+/// The top-level fields of the schema given to [`build_expr`]
+pub struct Fields<'a> {
+    functions: FunctionsBuilder<'a>,
+    fields: Vec<Field<'a>>,
+}
+
+impl<'a> Fields<'a> {
+    /// The top-level field with the given name, if there is one
+    pub fn get(&self, name: &str) -> Option<&Field<'a>> {
+        find_field(&self.fields, name)
+    }
+
+    /// Creates a literal that can be used on the left side of an operator
+    ///
+    /// For example, `fields.literal(3) - &fields["x"]`
+    pub fn literal<T: LiteralInference>(&self, value: T) -> ExprBuilder<'a> {
+        ExprBuilder::new(self.functions.clone(), Ok(literal(value)))
+    }
+}
+
+impl<'a> Index<&str> for Fields<'a> {
+    type Output = Field<'a>;
+
+    fn index(&self, name: &str) -> &Field<'a> {
+        self.get(name)
+            .unwrap_or_else(|| panic!("The schema has no field named {}", name))
+    }
+}
+
+fn find_field<'b, 'a>(fields: &'b [Field<'a>], name: &str) -> Option<&'b Field<'a>> {
+    fields.iter().find(|field| field.name == name)
+}
+
+fn make_fields<'a>(
+    functions: &FunctionsBuilder<'a>,
+    refs: &RefBuilder,
+    path: &mut Vec<&'a str>,
+    nodes: &'a [FullSchemaNode],
+) -> Result<Vec<Field<'a>>> {
+    nodes
+        .iter()
+        .map(|node| {
+            path.push(&node.name);
+            let reference = refs.resolve_by_segments(path)?;
+            let children = make_fields(functions, refs, path, &node.children)?;
+            path.pop();
+            Ok(Field {
+                functions: functions.clone(),
+                name: node.name.clone(),
+                reference,
+                children,
+            })
+        })
+        .collect()
+}
+
+/// An expression that can be combined with other expressions using operators
+///
+/// Each operator delegates to the matching function builder (e.g. `+` calls `add`).
+/// Errors are carried along (so that operators can be chained) and reported by
+/// [`build`](Self::build) (or when a [`build_expr`] closure returns).  Functions are
+/// resolved with the parameters of the builder that created the expression (e.g.
+/// [`allow_type_promotion`](BuilderParams::allow_type_promotion)).
+pub struct ExprBuilder<'a> {
+    functions: FunctionsBuilder<'a>,
+    expr: Result<Expression>,
+}
+
+impl<'a> ExprBuilder<'a> {
+    pub(crate) fn new(functions: FunctionsBuilder<'a>, expr: Result<Expression>) -> Self {
+        Self { functions, expr }
+    }
+
+    /// Consume the builder and return the expression
+    pub fn build(self) -> Result<Expression> {
+        self.expr
+    }
+
+    fn binary(self, operator: Operator, rhs: impl IntoOperand) -> ExprBuilder<'a> {
+        let expr = self.expr.and_then(|lhs| {
+            self.functions
+                .new_builder(operator.function(), vec![lhs, rhs.into_operand()?])
+                .build()
+        });
+        ExprBuilder::new(self.functions, expr)
+    }
+
+    /// `self = rhs`
+    pub fn equal(self, rhs: impl IntoOperand) -> ExprBuilder<'a> {
+        self.binary(Operator::Equal, rhs)
+    }
+
+    /// `self != rhs`
+    pub fn not_equal(self, rhs: impl IntoOperand) -> ExprBuilder<'a> {
+        self.binary(Operator::NotEqual, rhs)
+    }
+
+    /// `self < rhs`
+    pub fn lt(self, rhs: impl IntoOperand) -> ExprBuilder<'a> {
+        self.binary(Operator::Lt, rhs)
+    }
+
+    /// `self <= rhs`
+    pub fn lte(self, rhs: impl IntoOperand) -> ExprBuilder<'a> {
+        self.binary(Operator::Lte, rhs)
+    }
+
+    /// `self > rhs`
+    pub fn gt(self, rhs: impl IntoOperand) -> ExprBuilder<'a> {
+        self.binary(Operator::Gt, rhs)
+    }
+
+    /// `self >= rhs`
+    pub fn gte(self, rhs: impl IntoOperand) -> ExprBuilder<'a> {
+        self.binary(Operator::Gte, rhs)
+    }
+}
+
+/// Something that can be used as an operand of an [`ExprBuilder`]
+///
+/// This is implemented for fields, expressions being built, and already-built expressions
+/// (e.g. literals)
+pub trait IntoOperand {
+    fn into_operand(self) -> Result<Expression>;
+}
+
+impl IntoOperand for Expression {
+    fn into_operand(self) -> Result<Expression> {
+        Ok(self)
+    }
+}
+
+impl<'a> IntoOperand for ExprBuilder<'a> {
+    fn into_operand(self) -> Result<Expression> {
+        self.expr
+    }
+}
+
+impl<'a> IntoOperand for &Field<'a> {
+    fn into_operand(self) -> Result<Expression> {
+        Ok(self.reference.clone())
+    }
+}
+
+macro_rules! impl_operator {
+    ($trait:ident, $method:ident, $operator:expr) => {
+        impl<'a, T: IntoOperand> $trait<T> for ExprBuilder<'a> {
+            type Output = ExprBuilder<'a>;
+
+            fn $method(self, rhs: T) -> ExprBuilder<'a> {
+                self.binary($operator, rhs)
+            }
+        }
+
+        impl<'a, T: IntoOperand> $trait<T> for &Field<'a> {
+            type Output = ExprBuilder<'a>;
+
+            fn $method(self, rhs: T) -> ExprBuilder<'a> {
+                self.expr().binary($operator, rhs)
+            }
+        }
+    };
+}
+
+impl_operator!(Add, add, Operator::Add);
+impl_operator!(Sub, sub, Operator::Subtract);
+impl_operator!(Mul, mul, Operator::Multiply);
+impl_operator!(Div, div, Operator::Divide);
+
+/// Builds an expression from a closure over the fields of a full schema
+///
+/// Returns an error if the schema is not a full schema (the field names and types must
+/// be known) or if any of the operations in the closure are invalid.
+pub fn build_expr<'a>(
+    schema: &'a SchemaInfo,
+    build_func: impl FnOnce(&Fields<'a>) -> ExprBuilder<'a>,
+) -> Result<Expression> {
+    build_expr_with_params(schema, &BuilderParams::default(), build_func)
+}
+
+/// Like [`build_expr`] but fields and functions are resolved with the given builder
+/// parameters
+///
+/// For example, [`allow_type_promotion`](BuilderParams::allow_type_promotion) can be set
+/// to add fields of different numeric types.
+pub fn build_expr_with_params<'a>(
+    schema: &'a SchemaInfo,
+    params: &BuilderParams,
+    build_func: impl FnOnce(&Fields<'a>) -> ExprBuilder<'a>,
+) -> Result<Expression> {
+    let SchemaInfo::Full(full_schema) = schema else {
+        return Err(SubstraitExprError::invalid_input(
+            "Expressions can only be built from closures when the schema is a full schema",
+        ));
+    };
+    let functions = FunctionsBuilder::new_with_params(schema, params);
+    let refs = RefBuilder::new(schema, params, functions.clone());
+    let fields = Fields {
+        fields: make_fields(
+            &functions,
+            &refs,
+            &mut Vec::new(),
+            &full_schema.root.children,
+        )?,
+        functions,
+    };
+    build_func(&fields).build()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::schema::SchemaBuildersExt;
+    use crate::builder::{BuilderParams, ExpressionsBuilder};
+    use crate::functions::functions_arithmetic::FunctionsArithmeticExt;
+    use crate::helpers::types;
+
+    use super::*;
+
+    #[test]
+    fn test_build_expr() {
+        let schema = SchemaInfo::new_full()
+            .field("score", types::i32(false))
+            .nested("location", false, |builder| {
+                builder
+                    .field("x", types::fp64(false))
+                    .field("y", types::fp64(true))
+            })
+            .build();
+
+        let expr = build_expr(&schema, |fields| &fields["location"]["x"] + literal(3.0)).unwrap();
+
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("location.x").unwrap();
+        let expected = builder.functions().add(x, literal(3.0)).build().unwrap();
+        assert_eq!(expr, expected);
+
+        let schema = builder.schema();
+        assert!(build_expr(schema, |fields| &fields["score"] + literal("hello")).is_err());
+        assert!(build_expr(schema, |fields| {
+            assert!(fields.get("missing").is_none());
+            assert!(fields["location"].get("z").is_none());
+            fields.literal(1)
+        })
+        .is_ok());
+        assert!(build_expr(&SchemaInfo::new_names().build(), |fields| fields.literal(1)).is_err());
+    }
+
+    #[test]
+    fn test_operators() {
+        let schema = SchemaInfo::new_full()
+            .field("a", types::i32(false))
+            .field("b", types::i32(false))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let a = builder.fields().resolve_by_name("a").unwrap();
+        let b = builder.fields().resolve_by_name("b").unwrap();
+        let functions = builder.functions();
+
+        // a + b * 3 follows rust precedence: a + (b * 3)
+        let expr = (functions.expr(a.clone()) + (functions.expr(b.clone()) * literal(3)))
+            .build()
+            .unwrap();
+        let product = functions.multiply(b, literal(3)).build().unwrap();
+        let expected = functions.add(a.clone(), product).build().unwrap();
+        assert_eq!(expr, expected);
+
+        let expr = (functions.expr(a.clone()) - literal(1)) / literal(2);
+        assert!(expr.build().is_ok());
+        assert!((functions.expr(a) + literal("hello")).build().is_err());
+    }
+
+    #[test]
+    fn test_builder_params() {
+        let schema = SchemaInfo::new_full()
+            .field("a", types::i32(false))
+            .field("b", types::i64(false))
+            .build();
+        assert!(build_expr(&schema, |fields| &fields["a"] + &fields["b"]).is_err());
+
+        // The operators resolve functions in the same way as the builder they came from
+        let params = BuilderParams {
+            allow_type_promotion: true,
+            ..Default::default()
+        };
+        let builder = ExpressionsBuilder::new(schema.clone(), params);
+        let a = builder.fields().resolve_by_name("a").unwrap();
+        let b = builder.fields().resolve_by_name("b").unwrap();
+        let functions = builder.functions();
+        let expected = functions.add(a.clone(), b.clone()).build().unwrap();
+        assert_eq!((functions.expr(a) + b).build().unwrap(), expected);
+
+        let params = BuilderParams {
+            allow_type_promotion: true,
+            ..Default::default()
+        };
+        let expr =
+            build_expr_with_params(&schema, &params, |fields| &fields["a"] + &fields["b"]).unwrap();
+        assert_eq!(expr, expected);
+    }
+}
@@ -1,22 +1,67 @@
 use std::collections::BTreeMap;
+use std::ops::Deref;
+use std::sync::Arc;
 
 use substrait::proto::{
-    expression::{RexType, ScalarFunction},
+    aggregate_function::AggregationInvocation,
+    expression::{
+        cast::FailureBehavior,
+        if_then::IfClause,
+        nested::{self, NestedType},
+        switch_expression::IfValue,
+        window_function::{bound, Bound, BoundsType},
+        Cast, IfThen, Nested, RexType, ScalarFunction, SingularOrList, SwitchExpression,
+        WindowFunction,
+    },
     function_argument::ArgType,
-    Expression, FunctionArgument, FunctionOption, Type,
+    r#type::Kind,
+    sort_field::{SortDirection, SortKind},
+    AggregateFunction, AggregationPhase, Expression, FunctionArgument, FunctionOption, SortField,
+    Type,
 };
 
 use crate::{
     error::{Result, SubstraitExprError},
+    functions::functions_comparison,
     helpers::{
+        literals::{literal, LiteralExt},
         registry::ExtensionsRegistry,
         schema::SchemaInfo,
         types::{self, TypeExt},
+        visitor::ExpressionRewriter,
     },
+    util::HasRequiredPropertiesRef,
 };
 
+use super::dsl::ExprBuilder;
+use super::BuilderParams;
 use super::ExpressionExt;
 
+/// Replaces parameter placeholders, wherever they are nested, for
+/// [`FunctionsBuilder::bind_parameters`]
+struct ParameterBinder<'a, 'b> {
+    functions: &'a FunctionsBuilder<'b>,
+    values: &'a [Expression],
+}
+
+impl<'a, 'b> ExpressionRewriter for ParameterBinder<'a, 'b> {
+    fn rewrite_node(&mut self, expr: Expression) -> Result<Expression> {
+        if let Some(RexType::ScalarFunction(func)) = &expr.rex_type {
+            let is_parameter = self
+                .functions
+                .schema
+                .extensions_registry()
+                .lookup_function(func.function_reference)
+                .map(|name| name.uri == PARAMETER_FUNC_URI && name.name == PARAMETER_FUNC_NAME)
+                .unwrap_or(false);
+            if is_parameter {
+                return self.functions.bind_parameter(func, self.values);
+            }
+        }
+        Ok(expr)
+    }
+}
+
 /// This is a rust equivalent of a YAML function definition
 ///
 /// We chose to use mirror types here as the YAML schema is still
@@ -47,6 +92,41 @@ pub enum ImplementationArgType {
     Enum(Vec<String>),
     /// A regular argument provided by an expression of the given type
     Value(Type),
+    /// A decimal argument whose precision and scale are type parameters (e.g. `decimal<P1,S1>`)
+    ///
+    /// Named parameters are bound when the function is resolved and can be referenced by
+    /// a [`ReturnProgram`].
+    Decimal {
+        precision: TypeParameter,
+        scale: TypeParameter,
+    },
+}
+
+/// A parameter of a parameterized argument type
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TypeParameter {
+    /// The parameter is bound to the value of the argument (e.g. `P1` in `decimal<P1,S1>`)
+    ///
+    /// Arguments that share a parameter name must have the same value for that parameter.
+    Named(String),
+    /// The argument must have exactly this value (e.g. the scale in `decimal<P1,0>`)
+    Fixed(i64),
+}
+
+impl TypeParameter {
+    fn accepts(&self, value: i64) -> bool {
+        match self {
+            TypeParameter::Named(_) => true,
+            TypeParameter::Fixed(expected) => *expected == value,
+        }
+    }
+
+    fn to_human_readable(&self) -> String {
+        match self {
+            TypeParameter::Named(name) => name.clone(),
+            TypeParameter::Fixed(value) => value.to_string(),
+        }
+    }
 }
 
 /// A named function argument
@@ -65,6 +145,9 @@ impl ImplementationArg {
     /// Returns true if an expression of the given type could be used as this argument
     ///
     /// There is no "enum" type so enum arguments will only recognize the string type
+    ///
+    /// Value arguments must match the expected type exactly (including type parameters
+    /// such as decimal precision and scale) except for nullability and type variation.
     pub fn matches(&self, arg_type: &Type, registry: &ExtensionsRegistry) -> Result<bool> {
         if arg_type.is_unknown(registry) {
             Ok(true)
@@ -73,12 +156,28 @@ impl ImplementationArg {
                 // At the moment we assume that templated values match anything
                 ImplementationArgType::TemplateValue(_) => Ok(true),
                 ImplementationArgType::Enum(_) => arg_type.same_kind(&types::string(true)),
-                ImplementationArgType::Value(expected_type) => arg_type.same_kind(expected_type),
+                ImplementationArgType::Value(expected_type) => Ok(arg_type
+                    .same_kind(expected_type)?
+                    && types::without_variation(arg_type)
+                        .structurally_eq(&types::without_variation(expected_type))),
+                ImplementationArgType::Decimal { precision, scale } => match &arg_type.kind {
+                    Some(Kind::Decimal(decimal)) => Ok(precision.accepts(decimal.precision as i64)
+                        && scale.accepts(decimal.scale as i64)),
+                    _ => Ok(false),
+                },
             }
         }
     }
 }
 
+/// The template name that matches any type without binding
+const ANY_TEMPLATE: &str = "any";
+
+/// Strips any nullability marker from a template name (e.g. `any1?` is bound as `any1`)
+fn template_name(name: &str) -> &str {
+    name.trim_end_matches('?')
+}
+
 #[derive(Clone, Debug)]
 pub enum FunctionReturn {
     /// The return value of the function is a templated type (e.g. add<T>(T, T) -> T)
@@ -86,7 +185,177 @@ pub enum FunctionReturn {
     /// The return value of the function is a fixed type (e.g. add(u32, u32) -> u32)
     Typed(Type),
     /// The return value of the function is a program (e.g. add(Decimal<P1,S1>, Decimal<P2,S2>) -> ...)
-    Program(),
+    Program(ReturnProgram),
+}
+
+/// An operator in a return type program
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgramOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Eq,
+    NotEq,
+}
+
+/// An integer expression in a return type program
+///
+/// Comparisons evaluate to 1 (true) or 0 (false)
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProgramExpr {
+    /// An integer constant
+    Literal(i64),
+    /// A type parameter bound by an argument (e.g. `P1`) or a variable assigned earlier in
+    /// the program
+    Variable(String),
+    /// Negates the inner expression
+    Negate(Box<ProgramExpr>),
+    BinaryOp(ProgramOp, Box<ProgramExpr>, Box<ProgramExpr>),
+    Max(Box<ProgramExpr>, Box<ProgramExpr>),
+    Min(Box<ProgramExpr>, Box<ProgramExpr>),
+    /// `condition ? if_true : if_false`
+    IfElse(Box<ProgramExpr>, Box<ProgramExpr>, Box<ProgramExpr>),
+}
+
+fn program_overflow() -> SubstraitExprError {
+    SubstraitExprError::invalid_input("Integer overflow in a return type program")
+}
+
+impl ProgramExpr {
+    /// Evaluates the expression given the values of the variables it references
+    ///
+    /// Returns an error if the arithmetic overflows
+    pub fn evaluate(&self, variables: &BTreeMap<String, i64>) -> Result<i64> {
+        match self {
+            ProgramExpr::Literal(value) => Ok(*value),
+            ProgramExpr::Variable(name) => variables.get(name).copied().ok_or_else(|| {
+                SubstraitExprError::invalid_input(format!(
+                    "The return type program references the unbound variable {}",
+                    name
+                ))
+            }),
+            ProgramExpr::Negate(inner) => inner
+                .evaluate(variables)?
+                .checked_neg()
+                .ok_or_else(program_overflow),
+            ProgramExpr::BinaryOp(op, left, right) => {
+                let left = left.evaluate(variables)?;
+                let right = right.evaluate(variables)?;
+                match op {
+                    ProgramOp::Add => left.checked_add(right).ok_or_else(program_overflow),
+                    ProgramOp::Subtract => left.checked_sub(right).ok_or_else(program_overflow),
+                    ProgramOp::Multiply => left.checked_mul(right).ok_or_else(program_overflow),
+                    ProgramOp::Divide => left.checked_div(right).ok_or_else(|| {
+                        if right == 0 {
+                            SubstraitExprError::invalid_input(
+                                "Division by zero in a return type program",
+                            )
+                        } else {
+                            program_overflow()
+                        }
+                    }),
+                    ProgramOp::Lt => Ok((left < right) as i64),
+                    ProgramOp::Lte => Ok((left <= right) as i64),
+                    ProgramOp::Gt => Ok((left > right) as i64),
+                    ProgramOp::Gte => Ok((left >= right) as i64),
+                    ProgramOp::Eq => Ok((left == right) as i64),
+                    ProgramOp::NotEq => Ok((left != right) as i64),
+                }
+            }
+            ProgramExpr::Max(left, right) => {
+                Ok(left.evaluate(variables)?.max(right.evaluate(variables)?))
+            }
+            ProgramExpr::Min(left, right) => {
+                Ok(left.evaluate(variables)?.min(right.evaluate(variables)?))
+            }
+            ProgramExpr::IfElse(condition, if_true, if_false) => {
+                if condition.evaluate(variables)? != 0 {
+                    if_true.evaluate(variables)
+                } else {
+                    if_false.evaluate(variables)
+                }
+            }
+        }
+    }
+}
+
+/// A program that computes the return type of a function from the parameters of its arguments
+///
+/// For example, the YAML for decimal addition is:
+///
+/// ```text
+/// init_scale = max(S1,S2)
+/// init_prec = init_scale + max(P1 - S1, P2 - S2) + 1
+/// ...
+/// DECIMAL<prec, scale>
+/// ```
+///
+/// At the moment only programs that return a decimal are supported
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReturnProgram {
+    /// Variables that are assigned, in order, before the output type is computed
+    pub assignments: Vec<(String, ProgramExpr)>,
+    /// The precision of the output type
+    pub precision: ProgramExpr,
+    /// The scale of the output type
+    pub scale: ProgramExpr,
+    /// True if the output type is always nullable (e.g. `DECIMAL?<38,S>`)
+    pub nullable: bool,
+}
+
+impl ReturnProgram {
+    /// Computes the output type given the type parameters bound by the arguments
+    pub fn evaluate(&self, parameters: BTreeMap<String, i64>) -> Result<Type> {
+        let mut variables = parameters;
+        for (name, expr) in &self.assignments {
+            let value = expr.evaluate(&variables)?;
+            variables.insert(name.clone(), value);
+        }
+        let precision = self.precision.evaluate(&variables)?;
+        let scale = self.scale.evaluate(&variables)?;
+        let to_u8 = |value: i64, what: &str| {
+            u8::try_from(value).map_err(|_| {
+                SubstraitExprError::invalid_input(format!(
+                    "The return type program computed an invalid decimal {} ({})",
+                    what, value
+                ))
+            })
+        };
+        types::decimal(
+            to_u8(precision, "precision")?,
+            to_u8(scale, "scale")?,
+            self.nullable,
+        )
+    }
+}
+
+/// Describes how the nullability of a function's output is determined
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NullabilityHandling {
+    /// The output is nullable if any of the (non-enum) arguments are nullable
+    #[default]
+    Mirror,
+    /// The output has exactly the nullability of the declared return type
+    ///
+    /// For example, `is_null` always returns a non-nullable boolean
+    DeclaredOutput,
+    /// Like [DeclaredOutput](Self::DeclaredOutput) but the nullability of the arguments is
+    /// part of the signature
+    Discrete,
+}
+
+/// Describes how many times the last argument of a variadic implementation may be repeated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Variadic {
+    /// The minimum number of times the argument must be given
+    pub min: u32,
+    /// The maximum number of times the argument may be given (None if there is no limit)
+    pub max: Option<u32>,
 }
 
 /// A potential implementation of a function
@@ -96,19 +365,124 @@ pub struct FunctionImplementation {
     pub args: Vec<ImplementationArg>,
     /// The type that should be output from the function
     pub output_type: FunctionReturn,
+    /// The options accepted by the implementation, mapped to their allowed values
+    ///
+    /// For example, `add` accepts an `overflow` option which can be `SILENT`, `SATURATE`,
+    /// or `ERROR`.
+    pub options: BTreeMap<String, Vec<String>>,
+    /// How the nullability of the output is determined
+    pub nullability: NullabilityHandling,
+    /// If set, the last argument may be repeated (e.g. `concat(string...)`)
+    pub variadic: Option<Variadic>,
 }
 
 impl FunctionImplementation {
+    /// Returns the declared argument that each of `num_args` given arguments corresponds to
+    ///
+    /// For a variadic implementation the last declared argument is repeated as needed.
+    /// Returns None if the implementation does not accept `num_args` arguments.
+    pub fn args_for(&self, num_args: usize) -> Option<Vec<&ImplementationArg>> {
+        match self.variadic {
+            None => (num_args == self.args.len()).then(|| self.args.iter().collect()),
+            Some(variadic) => {
+                let (last, fixed) = self.args.split_last()?;
+                let num_repeated = num_args.checked_sub(fixed.len())?;
+                if num_repeated < variadic.min as usize
+                    || variadic.max.is_some_and(|max| num_repeated > max as usize)
+                {
+                    return None;
+                }
+                Some(
+                    fixed
+                        .iter()
+                        .chain(std::iter::repeat_n(last, num_repeated))
+                        .collect(),
+                )
+            }
+        }
+    }
+
     /// Returns true if expressions with types specified by `arg_types` would match this implementation
+    ///
+    /// Template arguments that share a name (e.g. `T` in `max(T, T) -> T`) must all have the
+    /// same kind of type.  The template `any` is unconstrained.
     pub fn matches(&self, arg_types: &[Type], registry: &ExtensionsRegistry) -> bool {
-        if arg_types.len() != self.args.len() {
-            false
-        } else {
-            self.args
-                .iter()
-                .zip(arg_types)
-                .all(|(imp_arg, arg_type)| imp_arg.matches(arg_type, registry).unwrap_or(false))
+        match self.args_for(arg_types.len()) {
+            None => false,
+            Some(args) => {
+                args.iter()
+                    .zip(arg_types)
+                    .all(|(imp_arg, arg_type)| imp_arg.matches(arg_type, registry).unwrap_or(false))
+                    && self.bind_templates(arg_types, registry).is_some()
+                    && self.bind_parameters(arg_types).is_some()
+            }
+        }
+    }
+
+    /// The number of arguments that require a specific type (i.e. are not templates)
+    fn num_concrete_args(&self) -> usize {
+        self.args
+            .iter()
+            .filter(|arg| !matches!(arg.arg_type, ImplementationArgType::TemplateValue(_)))
+            .count()
+    }
+
+    /// Binds each template name to the type of the first argument that uses it
+    ///
+    /// Returns None if a template is used by arguments of different kinds.  Arguments of
+    /// the unknown type do not bind (or conflict with) a template.
+    fn bind_templates(
+        &self,
+        arg_types: &[Type],
+        registry: &ExtensionsRegistry,
+    ) -> Option<BTreeMap<String, Type>> {
+        let mut bindings: BTreeMap<String, Type> = BTreeMap::new();
+        for (imp_arg, arg_type) in self.args_for(arg_types.len())?.into_iter().zip(arg_types) {
+            if let ImplementationArgType::TemplateValue(name) = &imp_arg.arg_type {
+                let name = template_name(name);
+                if name == ANY_TEMPLATE || arg_type.is_unknown(registry) {
+                    continue;
+                }
+                match bindings.get(name) {
+                    Some(bound) => {
+                        if !bound.same_kind(arg_type).unwrap_or(false) {
+                            return None;
+                        }
+                    }
+                    None => {
+                        bindings.insert(name.to_string(), arg_type.clone());
+                    }
+                }
+            }
+        }
+        Some(bindings)
+    }
+
+    /// Binds each named type parameter (e.g. `P1` in `decimal<P1,S1>`) to the value from
+    /// the corresponding argument type
+    ///
+    /// Returns None if arguments that share a parameter name disagree on its value.
+    fn bind_parameters(&self, arg_types: &[Type]) -> Option<BTreeMap<String, i64>> {
+        let mut bindings: BTreeMap<String, i64> = BTreeMap::new();
+        for (imp_arg, arg_type) in self.args_for(arg_types.len())?.into_iter().zip(arg_types) {
+            if let (
+                ImplementationArgType::Decimal { precision, scale },
+                Some(Kind::Decimal(decimal)),
+            ) = (&imp_arg.arg_type, &arg_type.kind)
+            {
+                for (param, value) in [
+                    (precision, decimal.precision as i64),
+                    (scale, decimal.scale as i64),
+                ] {
+                    if let TypeParameter::Named(name) = param {
+                        if *bindings.entry(name.clone()).or_insert(value) != value {
+                            return None;
+                        }
+                    }
+                }
+            }
         }
+        Some(bindings)
     }
 
     fn relax(
@@ -116,15 +490,17 @@ impl FunctionImplementation {
         types: Vec<Type>,
         registry: &ExtensionsRegistry,
     ) -> Result<FunctionImplementation> {
-        if self.args.len() != types.len() {
-            Err(SubstraitExprError::InvalidInput(format!(
+        let Some(args) = self.args_for(types.len()) else {
+            return Err(SubstraitExprError::InvalidInput(format!(
                 "Attempt to relax implementation with {} args using {} types",
                 self.args.len(),
                 types.len()
-            )))
-        } else {
-            let relaxed_args = self
-                .args
+            )));
+        };
+        {
+            // The relaxed implementation is specific to this call so variadic arguments
+            // are expanded
+            let relaxed_args = args
                 .iter()
                 .zip(types.iter())
                 .map(|(arg, typ)| {
@@ -134,25 +510,234 @@ impl FunctionImplementation {
                             arg_type: ImplementationArgType::Value(typ.clone()),
                         }
                     } else {
-                        arg.clone()
+                        (*arg).clone()
                     }
                 })
                 .collect::<Vec<_>>();
             let has_unknown = types.iter().any(|typ| typ.is_unknown(registry));
+            // Unless the output nullability is declared, a function over a nullable value
+            // returns a nullable result
+            let mut has_nullable = false;
+            if self.nullability == NullabilityHandling::Mirror {
+                for (arg, typ) in args.iter().zip(types.iter()) {
+                    if !matches!(arg.arg_type, ImplementationArgType::Enum(_)) {
+                        has_nullable |= typ.is_nullable()?;
+                    }
+                }
+            }
             let output_type = if has_unknown {
                 FunctionReturn::Typed(super::types::unknown(registry))
             } else {
-                self.output_type.clone()
+                let output_type = match &self.output_type {
+                    FunctionReturn::Typed(typ) => Some(typ.clone()),
+                    FunctionReturn::Templated(name) => Some(
+                        self.bind_templates(&types, registry)
+                            .and_then(|mut bindings| bindings.remove(template_name(name)))
+                            .ok_or_else(|| {
+                                SubstraitExprError::invalid_input(format!(
+                                    "The return type template {} is not bound by any argument",
+                                    name
+                                ))
+                            })?,
+                    ),
+                    FunctionReturn::Program(program) => Some(program.evaluate(
+                        self.bind_parameters(&types).ok_or_else(|| {
+                            SubstraitExprError::invalid_input(
+                                "The arguments do not agree on the type parameters",
+                            )
+                        })?,
+                    )?),
+                };
+                match output_type {
+                    Some(typ) if has_nullable => FunctionReturn::Typed(typ.with_nullability(true)),
+                    Some(typ) => FunctionReturn::Typed(typ),
+                    None => self.output_type.clone(),
+                }
             };
             Ok(FunctionImplementation {
                 args: relaxed_args,
                 output_type,
+                options: self.options.clone(),
+                nullability: self.nullability,
+                variadic: None,
             })
         }
     }
 }
 
+/// A cast that would allow a function call to resolve to an implementation
+#[derive(Clone, Debug, PartialEq)]
+pub struct CastSuggestion {
+    /// The index of the argument that should be cast
+    pub arg_index: usize,
+    /// The current type of the argument
+    pub from: Type,
+    /// The type the argument should be cast to
+    pub to: Type,
+}
+
+/// Returns the numeric "family" and the relative width of a type
+///
+/// Only types that can be losslessly widened to another type are considered
+fn widening_rank(typ: &Type) -> Option<(u8, u8)> {
+    match typ.kind.as_ref()? {
+        Kind::I8(_) => Some((0, 0)),
+        Kind::I16(_) => Some((0, 1)),
+        Kind::I32(_) => Some((0, 2)),
+        Kind::I64(_) => Some((0, 3)),
+        Kind::Fp32(_) => Some((1, 0)),
+        Kind::Fp64(_) => Some((1, 1)),
+        _ => None,
+    }
+}
+
+/// Returns the number of decimal digits needed to hold any value of an integer type
+fn integer_digits(typ: &Type) -> Option<i32> {
+    match typ.kind.as_ref()? {
+        Kind::I8(_) => Some(3),
+        Kind::I16(_) => Some(5),
+        Kind::I32(_) => Some(10),
+        Kind::I64(_) => Some(19),
+        _ => None,
+    }
+}
+
+/// Lists types in human readable form (e.g. `i32, string?`) for error messages
+fn describe_types(types: &[Type], registry: &ExtensionsRegistry) -> String {
+    types
+        .iter()
+        .map(|typ| typ.to_human_readable(registry))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Returns true if `from` can be cast to `to` without losing information
+///
+/// Integers and floats can be widened (e.g. i32 to i64) and integers can be cast to
+/// a decimal that has enough digits before the decimal point.
+fn is_widening_cast(from: &Type, to: &Type) -> bool {
+    match (widening_rank(from), widening_rank(to)) {
+        (Some((from_family, from_width)), Some((to_family, to_width))) => {
+            from_family == to_family && from_width < to_width
+        }
+        _ => match (integer_digits(from), to.kind.as_ref()) {
+            (Some(digits), Some(Kind::Decimal(decimal))) => {
+                decimal.precision - decimal.scale >= digits
+            }
+            _ => false,
+        },
+    }
+}
+
+/// Returns the decimal type that an integer should be cast to for a decimal argument
+///
+/// Named parameters are bound by the cast and so the narrowest decimal that can hold
+/// every value of the integer type is used (e.g. decimal<10,0> for i32).
+fn decimal_cast_target(
+    from: &Type,
+    precision: &TypeParameter,
+    scale: &TypeParameter,
+) -> Option<Type> {
+    let digits = integer_digits(from)? as i64;
+    let scale = match scale {
+        TypeParameter::Named(_) => 0,
+        TypeParameter::Fixed(scale) => *scale,
+    };
+    let precision = match precision {
+        TypeParameter::Named(_) => digits + scale,
+        TypeParameter::Fixed(precision) => *precision,
+    };
+    let to = types::decimal(
+        u8::try_from(precision).ok()?,
+        u8::try_from(scale).ok()?,
+        false,
+    )
+    .ok()?;
+    is_widening_cast(from, &to).then_some(to)
+}
+
 impl FunctionDefinition {
+    /// Suggests casts that would allow a call with the given argument types to resolve
+    ///
+    /// Each entry in the returned vector is the set of casts needed to match one of the
+    /// function's implementations.  Only widening casts (e.g. i32 to i64 or i32 to
+    /// decimal<10,0>) are suggested.  An integer passed to a parameterized decimal
+    /// argument (e.g. `decimal<P1,S1>`) is cast to the narrowest decimal that holds it.
+    /// If the call already resolves without any casts then nothing is returned.  The
+    /// entries are sorted so that the suggestions requiring the fewest casts come first.
+    pub fn suggest_casts(
+        &self,
+        arg_types: &[Type],
+        registry: &ExtensionsRegistry,
+    ) -> Vec<Vec<CastSuggestion>> {
+        if self
+            .implementations
+            .iter()
+            .any(|imp| imp.matches(arg_types, registry))
+        {
+            return Vec::new();
+        }
+        self.cast_candidates(arg_types, registry)
+            .into_iter()
+            .map(|(_, casts)| casts)
+            .collect()
+    }
+
+    /// Returns each implementation that could be matched with widening casts and the casts
+    ///
+    /// The candidates requiring the fewest casts come first
+    fn cast_candidates(
+        &self,
+        arg_types: &[Type],
+        registry: &ExtensionsRegistry,
+    ) -> Vec<(&FunctionImplementation, Vec<CastSuggestion>)> {
+        let mut candidates = self
+            .implementations
+            .iter()
+            .filter_map(|imp| {
+                let casts = imp
+                    .args_for(arg_types.len())?
+                    .into_iter()
+                    .zip(arg_types)
+                    .enumerate()
+                    .filter(|(_, (imp_arg, arg_type))| {
+                        !imp_arg.matches(arg_type, registry).unwrap_or(false)
+                    })
+                    .map(|(arg_index, (imp_arg, arg_type))| match &imp_arg.arg_type {
+                        ImplementationArgType::Value(expected_type)
+                            if is_widening_cast(arg_type, expected_type) =>
+                        {
+                            Some(CastSuggestion {
+                                arg_index,
+                                from: arg_type.clone(),
+                                to: expected_type.clone(),
+                            })
+                        }
+                        ImplementationArgType::Decimal { precision, scale } => {
+                            decimal_cast_target(arg_type, precision, scale).map(|to| {
+                                CastSuggestion {
+                                    arg_index,
+                                    from: arg_type.clone(),
+                                    to,
+                                }
+                            })
+                        }
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                // The cast arguments must still agree on any shared type parameters
+                let mut cast_types = arg_types.to_vec();
+                for cast in &casts {
+                    cast_types[cast.arg_index] = cast.to.clone();
+                }
+                imp.bind_parameters(&cast_types)?;
+                Some((imp, casts))
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|(_, casts)| casts.len());
+        candidates
+    }
+
     /// Given input expressions this attempts to find a matching implementation
     ///
     /// This is still very experimental and the implementation resolution rules
@@ -178,6 +763,183 @@ impl FunctionDefinition {
             .map(|imp| imp.relax(types, registry))
             .transpose()
     }
+
+    /// Given input expressions this finds the best matching implementation
+    ///
+    /// This is the same as [pick_implementation_from_args](Self::pick_implementation_from_args)
+    /// except that every implementation is considered.  An implementation that matches more
+    /// arguments with a concrete (non-template) type is a better match.  If several
+    /// implementations match equally well then an error listing them is returned.
+    pub fn pick_implementation_strict(
+        &self,
+        args: &[Expression],
+        schema: &SchemaInfo,
+    ) -> Result<Option<FunctionImplementation>> {
+        let registry = schema.extensions_registry();
+        let arg_types = args
+            .iter()
+            .map(|arg| arg.output_type(schema))
+            .collect::<Result<Vec<_>>>()?;
+        let matching = self
+            .implementations
+            .iter()
+            .filter(|imp| imp.matches(&arg_types, registry))
+            .collect::<Vec<_>>();
+        let Some(best) = matching.iter().map(|imp| imp.num_concrete_args()).max() else {
+            return Ok(None);
+        };
+        let best_matches = matching
+            .into_iter()
+            .filter(|imp| imp.num_concrete_args() == best)
+            .collect::<Vec<_>>();
+        self.check_unambiguous(&best_matches, registry)?;
+        best_matches[0].relax(arg_types, registry).map(Some)
+    }
+
+    /// Returns an error listing the candidates if there is more than one
+    fn check_unambiguous(
+        &self,
+        candidates: &[&FunctionImplementation],
+        registry: &ExtensionsRegistry,
+    ) -> Result<()> {
+        if candidates.len() > 1 {
+            Err(SubstraitExprError::invalid_input(format!(
+                "The call to {} is ambiguous.  Candidates are: {}",
+                self.name,
+                candidates
+                    .iter()
+                    .map(|imp| self.signature(imp, registry))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Renders an implementation as a human readable signature (e.g. `add(i32, i32)`)
+    fn signature(&self, imp: &FunctionImplementation, registry: &ExtensionsRegistry) -> String {
+        let args = imp
+            .args
+            .iter()
+            .map(|arg| match &arg.arg_type {
+                ImplementationArgType::TemplateValue(name) => name.clone(),
+                ImplementationArgType::Enum(values) => values.join("|"),
+                ImplementationArgType::Value(typ) => typ.to_human_readable(registry),
+                ImplementationArgType::Decimal { precision, scale } => format!(
+                    "decimal<{},{}>",
+                    precision.to_human_readable(),
+                    scale.to_human_readable()
+                ),
+            })
+            .collect::<Vec<_>>();
+        let variadic = if imp.variadic.is_some() { "..." } else { "" };
+        format!("{}({}{})", self.name, args.join(", "), variadic)
+    }
+
+    /// Returns a message explaining that no implementation accepts the given argument types
+    ///
+    /// The message lists the argument types and the signature of every implementation
+    pub(crate) fn no_match_message(
+        &self,
+        arg_types: &[Type],
+        registry: &ExtensionsRegistry,
+    ) -> String {
+        let provided = describe_types(arg_types, registry);
+        let candidates = self
+            .implementations
+            .iter()
+            .map(|imp| self.signature(imp, registry))
+            .collect::<Vec<_>>();
+        format!(
+            "No implementation of {} accepts the arguments ({}).  Candidates are: {}",
+            self.name,
+            provided,
+            if candidates.is_empty() {
+                "<none>".to_string()
+            } else {
+                candidates.join(", ")
+            }
+        )
+    }
+
+    /// Creates an error with the [`no_match_message`](Self::no_match_message)
+    fn no_match_error(
+        &self,
+        arg_types: &[Type],
+        registry: &ExtensionsRegistry,
+    ) -> SubstraitExprError {
+        SubstraitExprError::invalid_input(self.no_match_message(arg_types, registry))
+    }
+
+    /// Finds a matching implementation, promoting numeric arguments if needed
+    ///
+    /// If no implementation matches the arguments exactly then the arguments are promoted
+    /// using the casts from [suggest_casts](Self::suggest_casts) that require the fewest
+    /// casts.  The returned arguments contain an explicit cast for each promoted argument
+    /// so that the resulting call is unambiguous.
+    pub fn pick_implementation_with_promotion(
+        &self,
+        args: Vec<Expression>,
+        schema: &SchemaInfo,
+    ) -> Result<Option<(FunctionImplementation, Vec<Expression>)>> {
+        self.pick_with_promotion(args, schema, false)
+    }
+
+    /// Finds a matching implementation, promoting numeric arguments if needed
+    ///
+    /// If `strict` is true then exact matches are resolved with
+    /// [pick_implementation_strict](Self::pick_implementation_strict) and it is an error
+    /// if several implementations can be reached with the fewest casts.  Exact matches
+    /// always win over promoted matches.
+    fn pick_with_promotion(
+        &self,
+        mut args: Vec<Expression>,
+        schema: &SchemaInfo,
+        strict: bool,
+    ) -> Result<Option<(FunctionImplementation, Vec<Expression>)>> {
+        let pick = |args: &[Expression]| {
+            if strict {
+                self.pick_implementation_strict(args, schema)
+            } else {
+                self.pick_implementation_from_args(args, schema)
+            }
+        };
+        if let Some(implementation) = pick(&args)? {
+            return Ok(Some((implementation, args)));
+        }
+        let registry = schema.extensions_registry();
+        let arg_types = args
+            .iter()
+            .map(|arg| arg.output_type(schema))
+            .collect::<Result<Vec<_>>>()?;
+        let candidates = self.cast_candidates(&arg_types, registry);
+        let Some((_, fewest_casts)) = candidates.first() else {
+            return Ok(None);
+        };
+        if strict {
+            let best = candidates
+                .iter()
+                .filter(|(_, casts)| casts.len() == fewest_casts.len())
+                .map(|(imp, _)| *imp)
+                .collect::<Vec<_>>();
+            self.check_unambiguous(&best, registry)?;
+        }
+        let casts = fewest_casts.clone();
+        for cast in casts {
+            let arg = &mut args[cast.arg_index];
+            // Promotion should not change whether or not an argument can be null
+            let to = cast.to.with_nullability(cast.from.is_nullable()?);
+            *arg = Expression {
+                rex_type: Some(RexType::Cast(Box::new(Cast {
+                    r#type: Some(to),
+                    input: Some(Box::new(std::mem::take(arg))),
+                    failure_behavior: FailureBehavior::ThrowException as i32,
+                }))),
+            };
+        }
+        Ok(pick(&args)?.map(|implementation| (implementation, args)))
+    }
 }
 
 /// The URI of the special function we use to indicate a late lookup
@@ -189,14 +951,63 @@ pub const LOOKUP_BY_NAME_FUNC_URI: &'static str = "https://substrait.io/function
 /// The name of the special function we use to indicate a late lookup
 pub const LOOKUP_BY_NAME_FUNC_NAME: &'static str = "lookup_by_name";
 
+/// The URI of the special function we use to indicate a bind parameter
+///
+/// See [`parameter`](crate::builder::functions::FunctionsBuilder::parameter)
+pub const PARAMETER_FUNC_URI: &str = "https://substrait.io/functions";
+/// The name of the special function we use to indicate a bind parameter
+pub const PARAMETER_FUNC_NAME: &str = "parameter";
+
+/// What a cast should do if the input value cannot be converted to the target type
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CastFailureBehavior {
+    /// The cast returns null (this makes the output type nullable)
+    ReturnNull,
+    /// The cast raises an error
+    #[default]
+    ThrowException,
+}
+
+impl From<CastFailureBehavior> for FailureBehavior {
+    fn from(value: CastFailureBehavior) -> Self {
+        match value {
+            CastFailureBehavior::ReturnNull => FailureBehavior::ReturnNull,
+            CastFailureBehavior::ThrowException => FailureBehavior::ThrowException,
+        }
+    }
+}
+
+/// Returns true if the type is a nested (struct, list, or map) type
+fn is_nested(typ: &Type) -> bool {
+    matches!(
+        typ.kind,
+        Some(Kind::Struct(_)) | Some(Kind::List(_)) | Some(Kind::Map(_))
+    )
+}
+
 /// A builder that can create scalar function expressions
+#[derive(Clone)]
 pub struct FunctionsBuilder<'a> {
     schema: &'a SchemaInfo,
+    allow_type_promotion: bool,
+    strict_function_resolution: bool,
 }
 
 impl<'a> FunctionsBuilder<'a> {
     pub(crate) fn new(schema: &'a SchemaInfo) -> Self {
-        Self { schema }
+        Self {
+            schema,
+            allow_type_promotion: false,
+            strict_function_resolution: false,
+        }
+    }
+
+    pub(crate) fn new_with_params(schema: &'a SchemaInfo, params: &BuilderParams) -> Self {
+        Self {
+            schema,
+            allow_type_promotion: params.allow_type_promotion,
+            strict_function_resolution: params.strict_function_resolution,
+        }
     }
 
     /// Creates a new [FunctionBuilder] based on a given function definition.
@@ -211,72 +1022,686 @@ impl<'a> FunctionsBuilder<'a> {
         func: &'static FunctionDefinition,
         args: Vec<Expression>,
     ) -> FunctionBuilder {
-        let func_reference = self.schema.extensions_registry().register_function(func);
+        self.new_builder_from_ref(FunctionDefinitionRef::Static(func), args)
+    }
+
+    /// Creates a new [FunctionBuilder] based on a function definition created at runtime
+    ///
+    /// This is the same as [new_builder](Self::new_builder) but does not require the
+    /// function definition to be a `static`.  This is useful for UDFs that are loaded
+    /// from a catalog.
+    pub fn new_builder_owned(
+        &self,
+        func: Arc<FunctionDefinition>,
+        args: Vec<Expression>,
+    ) -> FunctionBuilder<'_> {
+        self.new_builder_from_ref(FunctionDefinitionRef::Owned(func), args)
+    }
+
+    fn new_builder_from_ref(
+        &self,
+        func: FunctionDefinitionRef,
+        args: Vec<Expression>,
+    ) -> FunctionBuilder<'_> {
+        let func_reference = self.schema.extensions_registry().register_function(&func);
         FunctionBuilder {
-            func: func,
+            func,
             func_reference,
             args,
             options: BTreeMap::new(),
             schema: self.schema,
+            impl_index: None,
+            allow_type_promotion: self.allow_type_promotion,
+            strict_function_resolution: self.strict_function_resolution,
         }
     }
 
-    /// Creates a "late lookup" function expression
+    /// Creates a new [FunctionBuilder] that will use a specific implementation
     ///
-    /// This is not really a function call.  It's a placeholder we are currently
-    /// using to indicate an unresolved field reference.  This is created whenever
-    /// a user creates a field reference by name but the schema is unknown or does
-    /// not know names.
-    pub fn lookup_field_by_name(&self, name: impl Into<String>) -> Expression {
-        let arg = FunctionArgument {
-            arg_type: Some(ArgType::Enum(name.into())),
-        };
-        let registry = self.schema.extensions_registry();
-        let function_reference =
-            registry.register_function_by_name(LOOKUP_BY_NAME_FUNC_URI, LOOKUP_BY_NAME_FUNC_NAME);
-        Expression {
-            rex_type: Some(RexType::ScalarFunction(ScalarFunction {
-                arguments: vec![arg],
-                function_reference,
-                // TODO: Use the proper unknown type
-                output_type: Some(super::types::unknown(registry)),
-                options: vec![],
-                ..Default::default()
-            })),
-        }
-    }
-}
+    /// Normally the implementation is picked by matching the argument types against
+    /// each of the function's implementations.  If the caller already knows which
+    /// implementation applies (e.g. a code generator creating many identical calls)
+    /// then this can be used to skip that matching step.
+    ///
+    /// Returns an error if `impl_index` is out of range or if the number of arguments
+    /// does not match the chosen implementation.
+    pub fn new_builder_with_impl(
+        &self,
+        func: &'static FunctionDefinition,
+        impl_index: usize,
+        args: Vec<Expression>,
+    ) -> Result<FunctionBuilder<'_>> {
+        let implementation = func.implementations.get(impl_index).ok_or_else(|| {
+            SubstraitExprError::invalid_input(format!(
+                "The function {} has {} implementations but implementation {} was requested",
+                func.name,
+                func.implementations.len(),
+                impl_index
+            ))
+        })?;
+        if implementation.args_for(args.len()).is_none() {
+            return Err(SubstraitExprError::invalid_input(format!(
+                "Implementation {} of function {} takes {} arguments but {} were given",
+                impl_index,
+                func.name,
+                implementation.args.len(),
+                args.len()
+            )));
+        }
+        let mut builder = self.new_builder(func, args);
+        builder.impl_index = Some(impl_index);
+        Ok(builder)
+    }
+
+    /// Creates a new [AggregateBuilder] based on a given aggregate function definition.
+    ///
+    /// This method is not typically used directly.  Instead, extension functions
+    /// like `sum` or `avg` are used which call this function.
+    pub fn new_aggregate_builder(
+        &self,
+        func: &'static FunctionDefinition,
+        args: Vec<Expression>,
+    ) -> AggregateBuilder<'_> {
+        AggregateBuilder::new(self.new_builder(func, args))
+    }
+
+    /// Creates a new [WindowBuilder] based on a given window function definition.
+    ///
+    /// This method is not typically used directly.  Instead, extension functions
+    /// like `row_number` or `lag` are used which call this function.
+    pub fn new_window_builder(
+        &self,
+        func: &'static FunctionDefinition,
+        args: Vec<Expression>,
+    ) -> WindowBuilder<'_> {
+        WindowBuilder::new(self.new_builder(func, args))
+    }
+
+    /// Creates a new [AggregateBuilder] based on a function definition created at runtime
+    ///
+    /// See [new_builder_owned](Self::new_builder_owned)
+    pub fn new_aggregate_builder_owned(
+        &self,
+        func: Arc<FunctionDefinition>,
+        args: Vec<Expression>,
+    ) -> AggregateBuilder<'_> {
+        AggregateBuilder::new(self.new_builder_owned(func, args))
+    }
+
+    /// Creates a cast expression that converts `input` to the type `to`
+    ///
+    /// Returns an error if the input can clearly not be cast to the target type.  At
+    /// the moment this only rejects casts between nested types (struct, list, map) and
+    /// other kinds of types.  Inputs of the unknown type can be cast to anything.
+    pub fn cast(
+        &self,
+        input: Expression,
+        to: Type,
+        on_failure: CastFailureBehavior,
+    ) -> Result<Expression> {
+        let input_type = input.output_type(self.schema)?;
+        let registry = self.schema.extensions_registry();
+        if !input_type.is_unknown(registry)
+            && (is_nested(&input_type) || is_nested(&to))
+            && !input_type.same_kind(&to)?
+        {
+            return Err(SubstraitExprError::invalid_input(format!(
+                "Cannot cast an expression of type {} to {}",
+                input_type.to_human_readable(registry),
+                to.to_human_readable(registry)
+            )));
+        }
+        Ok(Expression {
+            rex_type: Some(RexType::Cast(Box::new(Cast {
+                r#type: Some(to),
+                input: Some(Box::new(input)),
+                failure_behavior: FailureBehavior::from(on_failure) as i32,
+            }))),
+        })
+    }
+
+    /// Creates a coalesce expression which returns the first non-null argument
+    ///
+    /// This shadows the generated
+    /// [`coalesce`](functions_comparison::FunctionsComparisonExt::coalesce) and builds the
+    /// call with it, so the arguments are resolved and checked in the same way.  The only
+    /// difference is the output nullability.  The leading arguments are only returned when
+    /// they are not null and so the result is only nullable if the last argument is nullable
+    /// (see [branch_result_type](crate::helpers::types::branch_result_type)).
+    pub fn coalesce(&self, args: Vec<Expression>) -> Result<Expression> {
+        let arg_types = args
+            .iter()
+            .map(|arg| arg.output_type(self.schema))
+            .collect::<Result<Vec<_>>>()?;
+        let mut func =
+            functions_comparison::FunctionsComparisonExt::coalesce(self, args).build_function()?;
+        let last_idx = arg_types.len() - 1;
+        let branch_types = arg_types
+            .into_iter()
+            .enumerate()
+            .map(|(idx, typ)| {
+                if idx != last_idx {
+                    typ.with_nullability(false)
+                } else {
+                    typ
+                }
+            })
+            .collect::<Vec<_>>();
+        let nullable = types::branch_result_type(&branch_types, true)?.is_nullable()?;
+        func.output_type = Some(
+            func.output_type
+                .required("output_type")?
+                .with_nullability(nullable),
+        );
+        Ok(Expression {
+            rex_type: Some(RexType::ScalarFunction(func)),
+        })
+    }
+
+    /// Creates an if-then expression which returns the result of the first clause whose
+    /// condition is true
+    ///
+    /// Each clause is a (condition, result) pair.  The conditions must be boolean and the
+    /// results, including `else_result`, must have the same kind of type.  Without an
+    /// `else_result` the expression is null when no condition is true and so the result
+    /// is nullable (see [branch_result_type](crate::helpers::types::branch_result_type)).
+    pub fn if_then(
+        &self,
+        clauses: Vec<(Expression, Expression)>,
+        else_result: Option<Expression>,
+    ) -> Result<Expression> {
+        if clauses.is_empty() {
+            return Err(SubstraitExprError::invalid_input(
+                "An if-then expression requires at least one clause",
+            ));
+        }
+        let registry = self.schema.extensions_registry();
+        for (condition, _) in &clauses {
+            let condition_type = condition.output_type(self.schema)?;
+            if !condition_type.is_unknown(registry)
+                && !condition_type.same_kind(&types::bool(false))?
+            {
+                return Err(SubstraitExprError::invalid_input(format!(
+                    "The conditions of an if-then expression must be boolean but received {}",
+                    condition_type.to_human_readable(registry)
+                )));
+            }
+        }
+        let result_types = clauses
+            .iter()
+            .map(|(_, result)| result)
+            .chain(else_result.as_ref())
+            .map(|result| result.output_type(self.schema))
+            .collect::<Result<Vec<_>>>()?;
+        self.check_same_kind("results of an if-then expression", &result_types)?;
+        Ok(Expression {
+            rex_type: Some(RexType::IfThen(Box::new(IfThen {
+                ifs: clauses
+                    .into_iter()
+                    .map(|(condition, result)| IfClause {
+                        r#if: Some(condition),
+                        then: Some(result),
+                    })
+                    .collect(),
+                r#else: else_result.map(Box::new),
+            }))),
+        })
+    }
+
+    /// Creates a switch expression which returns the result of the first case whose
+    /// literal is equal to `value`
+    ///
+    /// Each case is a (literal, result) pair where the literal is created with one of the
+    /// [literals](crate::helpers::literals) helpers.  The case literals must have the same
+    /// kind of type as `value` and the results,
+    /// including `else_result`, must have the same kind of type.  Without an `else_result`
+    /// the expression is null when no case matches and so the result is nullable (see
+    /// [branch_result_type](crate::helpers::types::branch_result_type)).
+    pub fn switch(
+        &self,
+        value: Expression,
+        cases: Vec<(Expression, Expression)>,
+        else_result: Option<Expression>,
+    ) -> Result<Expression> {
+        if cases.is_empty() {
+            return Err(SubstraitExprError::invalid_input(
+                "A switch expression requires at least one case",
+            ));
+        }
+        let registry = self.schema.extensions_registry();
+        let value_type = value.output_type(self.schema)?;
+        let cases = cases
+            .into_iter()
+            .map(|(case, result)| Ok((case.try_as_literal()?.clone(), result)))
+            .collect::<Result<Vec<_>>>()?;
+        if !value_type.is_unknown(registry) {
+            for (case, _) in &cases {
+                let case_type = case.data_type()?;
+                if !case_type.same_kind(&value_type)? {
+                    return Err(SubstraitExprError::invalid_input(format!(
+                        "Cannot compare a switch value of type {} with a case of type {}",
+                        value_type.to_human_readable(registry),
+                        case_type.to_human_readable(registry)
+                    )));
+                }
+            }
+        }
+        let result_types = cases
+            .iter()
+            .map(|(_, result)| result)
+            .chain(else_result.as_ref())
+            .map(|result| result.output_type(self.schema))
+            .collect::<Result<Vec<_>>>()?;
+        self.check_same_kind("results of a switch expression", &result_types)?;
+        Ok(Expression {
+            rex_type: Some(RexType::SwitchExpression(Box::new(SwitchExpression {
+                r#match: Some(Box::new(value)),
+                ifs: cases
+                    .into_iter()
+                    .map(|(case, result)| IfValue {
+                        r#if: Some(case),
+                        then: Some(result),
+                    })
+                    .collect(),
+                r#else: else_result.map(Box::new),
+            }))),
+        })
+    }
+
+    /// Returns an error if the known types in `types` are not all of the same kind
+    ///
+    /// `what` describes the expressions that produced the types (e.g. "arguments to coalesce")
+    fn check_same_kind(&self, what: &str, types: &[Type]) -> Result<()> {
+        let registry = self.schema.extensions_registry();
+        let known_types = types
+            .iter()
+            .filter(|typ| !typ.is_unknown(registry))
+            .collect::<Vec<_>>();
+        for typ in &known_types {
+            if !typ.same_kind(known_types[0])? {
+                return Err(SubstraitExprError::invalid_input(format!(
+                    "All {} must have the same type but received {} and {}",
+                    what,
+                    known_types[0].to_human_readable(registry),
+                    typ.to_human_readable(registry)
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn build_comparison(
+        &self,
+        func: &'static FunctionDefinition,
+        lhs: Expression,
+        rhs: Expression,
+    ) -> Result<Expression> {
+        self.new_builder(func, vec![lhs, rhs]).build()
+    }
+
+    /// Builds `lhs = rhs`
+    ///
+    /// This is a shortcut for `equal(lhs, rhs).build()` (see
+    /// [FunctionsComparisonExt](crate::functions::functions_comparison::FunctionsComparisonExt))
+    /// for the common case where no options are needed.  The other `build_` comparison
+    /// methods are similar.
+    pub fn build_eq(&self, lhs: Expression, rhs: Expression) -> Result<Expression> {
+        self.build_comparison(&functions_comparison::EQUAL, lhs, rhs)
+    }
+
+    /// Builds `lhs != rhs`
+    pub fn build_ne(&self, lhs: Expression, rhs: Expression) -> Result<Expression> {
+        self.build_comparison(&functions_comparison::NOT_EQUAL, lhs, rhs)
+    }
+
+    /// Builds `lhs < rhs`
+    pub fn build_lt(&self, lhs: Expression, rhs: Expression) -> Result<Expression> {
+        self.build_comparison(&functions_comparison::LT, lhs, rhs)
+    }
+
+    /// Builds `lhs <= rhs`
+    pub fn build_lte(&self, lhs: Expression, rhs: Expression) -> Result<Expression> {
+        self.build_comparison(&functions_comparison::LTE, lhs, rhs)
+    }
+
+    /// Builds `lhs > rhs`
+    pub fn build_gt(&self, lhs: Expression, rhs: Expression) -> Result<Expression> {
+        self.build_comparison(&functions_comparison::GT, lhs, rhs)
+    }
+
+    /// Builds `lhs >= rhs`
+    pub fn build_gte(&self, lhs: Expression, rhs: Expression) -> Result<Expression> {
+        self.build_comparison(&functions_comparison::GTE, lhs, rhs)
+    }
+
+    /// Wraps an expression so it can be combined with operators (e.g. `+`)
+    ///
+    /// See [ExprBuilder] for details
+    pub fn expr(&self, expr: Expression) -> ExprBuilder<'a> {
+        ExprBuilder::new(self.clone(), Ok(expr))
+    }
+
+    /// Creates an IN-list expression (e.g. `x IN (1, 2, 3)`)
+    ///
+    /// Every option must have the same kind of type as `value` (arguments of the unknown
+    /// type are not checked).  The result is a boolean which is nullable if `value` or any
+    /// of the options are nullable.
+    ///
+    /// Returns an error if there are no options
+    pub fn in_list(&self, value: Expression, options: Vec<Expression>) -> Result<Expression> {
+        if options.is_empty() {
+            return Err(SubstraitExprError::invalid_input(
+                "An IN-list requires at least one option",
+            ));
+        }
+        let registry = self.schema.extensions_registry();
+        let value_type = value.output_type(self.schema)?;
+        if !value_type.is_unknown(registry) {
+            for option in &options {
+                let option_type = option.output_type(self.schema)?;
+                if !option_type.is_unknown(registry) && !option_type.same_kind(&value_type)? {
+                    return Err(SubstraitExprError::invalid_input(format!(
+                        "The options of an IN-list must have the same type as the value ({}) but received {}",
+                        value_type.to_human_readable(registry),
+                        option_type.to_human_readable(registry)
+                    )));
+                }
+            }
+        }
+        Ok(Expression {
+            rex_type: Some(RexType::SingularOrList(Box::new(SingularOrList {
+                value: Some(Box::new(value)),
+                options,
+            }))),
+        })
+    }
+
+    /// Creates an expression that assembles a (non-nullable) struct from its children
+    ///
+    /// The output type is a struct whose fields are the output types of the children.
+    /// Since a struct counts as a type in addition to its fields, an expression with two
+    /// children needs three output names.
+    ///
+    /// Returns an error if there are no children
+    pub fn make_struct(&self, children: Vec<Expression>) -> Result<Expression> {
+        if children.is_empty() {
+            return Err(SubstraitExprError::invalid_input(
+                "A struct requires at least one child",
+            ));
+        }
+        // Resolve the child types up front so invalid children are reported here
+        for child in &children {
+            child.output_type(self.schema)?;
+        }
+        Ok(Expression {
+            rex_type: Some(RexType::Nested(Nested {
+                nullable: false,
+                type_variation_reference: 0,
+                nested_type: Some(NestedType::Struct(nested::Struct { fields: children })),
+            })),
+        })
+    }
+
+    /// Creates a placeholder for a bind parameter
+    ///
+    /// This is not really a function call.  It's a placeholder for a value that will be
+    /// provided later (e.g. a prepared statement parameter).  The placeholder has a known
+    /// type so that function calls using it can be resolved.  Use
+    /// [`bind_parameters`](Self::bind_parameters) to replace the placeholders with literals.
+    pub fn parameter(&self, index: u32, typ: Type) -> Expression {
+        let arg = FunctionArgument {
+            arg_type: Some(ArgType::Value(literal(index as i64))),
+        };
+        let function_reference = self
+            .schema
+            .extensions_registry()
+            .register_function_by_name(PARAMETER_FUNC_URI, PARAMETER_FUNC_NAME);
+        Expression {
+            rex_type: Some(RexType::ScalarFunction(ScalarFunction {
+                arguments: vec![arg],
+                function_reference,
+                output_type: Some(typ),
+                options: vec![],
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Replaces all parameter placeholders in `expr` with the given values
+    ///
+    /// The placeholder created by `parameter(index, ..)` is replaced by `values[index]`,
+    /// wherever it is nested (e.g. in an IN-list or the branch of an if-then).
+    /// Returns an error if a placeholder's index is out of range or if the value's type
+    /// is a different kind than the placeholder's type.
+    pub fn bind_parameters(&self, expr: Expression, values: &[Expression]) -> Result<Expression> {
+        ParameterBinder {
+            functions: self,
+            values,
+        }
+        .rewrite(expr)
+    }
+
+    fn bind_parameter(&self, func: &ScalarFunction, values: &[Expression]) -> Result<Expression> {
+        let index = match func.arguments.first().and_then(|arg| arg.arg_type.as_ref()) {
+            Some(ArgType::Value(index)) => index.try_as_rust_literal::<i64>()?,
+            _ => {
+                return Err(SubstraitExprError::invalid_substrait(
+                    "A parameter placeholder did not have an index argument",
+                ))
+            }
+        };
+        let value = usize::try_from(index)
+            .ok()
+            .and_then(|index| values.get(index))
+            .ok_or_else(|| {
+                SubstraitExprError::invalid_input(format!(
+                    "No value was given for parameter {} ({} values were given)",
+                    index,
+                    values.len()
+                ))
+            })?;
+        let expected_type = func.output_type.required("output_type")?;
+        let value_type = value.output_type(self.schema)?;
+        let registry = self.schema.extensions_registry();
+        if !value_type.is_unknown(registry)
+            && !expected_type.is_unknown(registry)
+            && !value_type.same_kind(expected_type)?
+        {
+            return Err(SubstraitExprError::invalid_input(format!(
+                "Parameter {} has type {} but the given value has type {}",
+                index,
+                expected_type.to_human_readable(registry),
+                value_type.to_human_readable(registry)
+            )));
+        }
+        Ok(value.clone())
+    }
+
+    /// Creates a "late lookup" function expression
+    ///
+    /// This is not really a function call.  It's a placeholder we are currently
+    /// using to indicate an unresolved field reference.  This is created whenever
+    /// a user creates a field reference by name but the schema is unknown or does
+    /// not know names.
+    pub fn lookup_field_by_name(&self, name: impl Into<String>) -> Expression {
+        self.lookup_field_by_name_with_nullability(name, true)
+    }
+
+    /// Creates a "late lookup" function expression for a field with known nullability
+    ///
+    /// The output type is still the unknown type but it has the given nullability
+    /// instead of always being nullable.
+    pub fn lookup_field_by_name_with_nullability(
+        &self,
+        name: impl Into<String>,
+        nullable: bool,
+    ) -> Expression {
+        let arg = FunctionArgument {
+            arg_type: Some(ArgType::Enum(name.into())),
+        };
+        let registry = self.schema.extensions_registry();
+        let function_reference =
+            registry.register_function_by_name(LOOKUP_BY_NAME_FUNC_URI, LOOKUP_BY_NAME_FUNC_NAME);
+        let output_type = super::types::unknown(registry).with_nullability(nullable);
+        Expression {
+            rex_type: Some(RexType::ScalarFunction(ScalarFunction {
+                arguments: vec![arg],
+                function_reference,
+                // TODO: Use the proper unknown type
+                output_type: Some(output_type),
+                options: vec![],
+                ..Default::default()
+            })),
+        }
+    }
+}
+
+/// A function definition that is either a `static` or was created at runtime
+enum FunctionDefinitionRef {
+    Static(&'static FunctionDefinition),
+    Owned(Arc<FunctionDefinition>),
+}
+
+impl Deref for FunctionDefinitionRef {
+    type Target = FunctionDefinition;
+
+    fn deref(&self) -> &FunctionDefinition {
+        match self {
+            FunctionDefinitionRef::Static(func) => func,
+            FunctionDefinitionRef::Owned(func) => func,
+        }
+    }
+}
 
 /// A builder object to create a scalar function expression
 ///
 /// This can be used to parameterize the function call with options
 pub struct FunctionBuilder<'a> {
-    func: &'static FunctionDefinition,
+    func: FunctionDefinitionRef,
     func_reference: u32,
     args: Vec<Expression>,
     options: BTreeMap<String, Vec<String>>,
     schema: &'a SchemaInfo,
+    impl_index: Option<usize>,
+    allow_type_promotion: bool,
+    strict_function_resolution: bool,
 }
 
 impl<'a> FunctionBuilder<'a> {
+    /// Adds a preferred value for an option (e.g. `overflow` = `ERROR` for `add`)
+    ///
+    /// Calling this multiple times with the same option name adds additional (less
+    /// preferred) values.  The order of the preferences is significant and is preserved but
+    /// a value that is already preferred is not added again.  The option is validated when
+    /// the function is built.
+    ///
+    /// Options are always emitted sorted by name so the order in which different options
+    /// are set does not change the built (or serialized) expression.
+    pub fn with_option(mut self, name: impl Into<String>, preference: impl Into<String>) -> Self {
+        let preferences = self.options.entry(name.into()).or_default();
+        let preference = preference.into();
+        if !preferences.contains(&preference) {
+            preferences.push(preference);
+        }
+        self
+    }
+
+    /// Adds several option preferences at once (see [with_option](Self::with_option))
+    pub fn with_options<N: Into<String>, P: Into<String>>(
+        self,
+        options: impl IntoIterator<Item = (N, P)>,
+    ) -> Self {
+        options
+            .into_iter()
+            .fold(self, |builder, (name, preference)| {
+                builder.with_option(name, preference)
+            })
+    }
+
+    fn validate_options(&self, implementation: &FunctionImplementation) -> Result<()> {
+        for (name, preferences) in &self.options {
+            let allowed = implementation.options.get(name).ok_or_else(|| {
+                SubstraitExprError::InvalidInput(format!(
+                    "The function {} does not have an option named {} (accepted options: {:?})",
+                    self.func.name,
+                    name,
+                    implementation.options.keys().collect::<Vec<_>>()
+                ))
+            })?;
+            if let Some(invalid) = preferences.iter().find(|pref| !allowed.contains(pref)) {
+                return Err(SubstraitExprError::InvalidInput(format!(
+                    "The value {} is not valid for the option {} of function {} (accepted values: {:?})",
+                    invalid, name, self.func.name, allowed
+                )));
+            }
+        }
+        Ok(())
+    }
+
     /// Consume the builder and create a function expression
     pub fn build(self) -> Result<Expression> {
-        let implementation = self
-            .func
-            .pick_implementation_from_args(&self.args, self.schema)?
-            .ok_or_else(|| {
-                SubstraitExprError::invalid_input(format!(
-                    "Cannot find matching call to function {:?} that takes the given arguments",
+        Ok(Expression {
+            rex_type: Some(RexType::ScalarFunction(self.build_function()?)),
+        })
+    }
+
+    fn build_function(mut self) -> Result<ScalarFunction> {
+        let args = std::mem::take(&mut self.args);
+        let registry = self.schema.extensions_registry();
+        let arg_types = args
+            .iter()
+            .map(|arg| arg.output_type(self.schema))
+            .collect::<Result<Vec<_>>>()?;
+        let (implementation, args) = match self.impl_index {
+            // The implementation was picked by the caller but it must still accept the
+            // arguments and its output type depends on them
+            Some(impl_index) => {
+                let implementation = &self.func.implementations[impl_index];
+                if !implementation.matches(&arg_types, registry) {
+                    return Err(SubstraitExprError::invalid_input(format!(
+                        "Implementation {} of {} ({}) does not accept the arguments ({})",
+                        impl_index,
+                        self.func.name,
+                        self.func.signature(implementation, registry),
+                        describe_types(&arg_types, registry)
+                    )));
+                }
+                (implementation.relax(arg_types, registry)?, args)
+            }
+            None => {
+                let no_match = || self.func.no_match_error(&arg_types, registry);
+                if self.allow_type_promotion {
                     self.func
-                ))
-            })?;
-        let arguments = self
-            .args
+                        .pick_with_promotion(args, self.schema, self.strict_function_resolution)?
+                        .ok_or_else(no_match)?
+                } else if self.strict_function_resolution {
+                    let implementation = self
+                        .func
+                        .pick_implementation_strict(&args, self.schema)?
+                        .ok_or_else(no_match)?;
+                    (implementation, args)
+                } else {
+                    let implementation = self
+                        .func
+                        .pick_implementation_from_args(&args, self.schema)?
+                        .ok_or_else(no_match)?;
+                    (implementation, args)
+                }
+            }
+        };
+        let implementation_args = implementation.args_for(args.len()).ok_or_else(|| {
+            SubstraitExprError::invalid_input(format!(
+                "The implementation of {} does not accept {} arguments",
+                self.func.name,
+                args.len()
+            ))
+        })?;
+        let arguments = args
             .iter()
-            .zip(implementation.args.iter())
+            .zip(implementation_args)
             .map(|(arg, imp_arg)| match &imp_arg.arg_type {
                 ImplementationArgType::Enum(vals) => {
-                    let value = arg.try_as_rust_literal::<&str>()?.to_string();
+                    let value = arg.try_as_rust_literal::<String>()?;
                     if vals.contains(&value) {
                         Ok(FunctionArgument {
                             arg_type: Some(ArgType::Enum(value)),
@@ -288,15 +1713,22 @@ impl<'a> FunctionBuilder<'a> {
                         )))
                     }
                 }
-                ImplementationArgType::Value(_) => Ok(FunctionArgument {
-                    arg_type: Some(ArgType::Value(arg.clone())),
-                }),
-                ImplementationArgType::TemplateValue(_) => Ok(FunctionArgument {
+                ImplementationArgType::Value(_)
+                | ImplementationArgType::TemplateValue(_)
+                | ImplementationArgType::Decimal { .. } => Ok(FunctionArgument {
                     arg_type: Some(ArgType::Value(arg.clone())),
                 }),
             })
             .collect::<Result<Vec<_>>>()?;
-        let output_type = &implementation.output_type;
+        self.validate_options(&implementation)?;
+        // Resolved implementations are relaxed to the argument types and so have a fixed
+        // output type
+        let FunctionReturn::Typed(output_type) = &implementation.output_type else {
+            return Err(SubstraitExprError::invalid_input(format!(
+                "The output type of {} could not be resolved",
+                self.func.name
+            )));
+        };
         let options = self
             .options
             .into_iter()
@@ -306,23 +1738,1715 @@ impl<'a> FunctionBuilder<'a> {
             })
             .collect::<Vec<_>>();
 
-        let output_type = match output_type {
-            FunctionReturn::Program() => todo!(),
-            FunctionReturn::Typed(typ) => typ.clone(),
-            // TODO: This is a hack.  We need to find which input argument to base the return type on
-            // by matching the template names (e.g. if it is foo<T1,T2>(T1,T2) => T2 then this would
-            // do the wrong thing)
-            FunctionReturn::Templated(_) => self.args.first().unwrap().output_type(&self.schema)?,
+        Ok(ScalarFunction {
+            arguments,
+            function_reference: self.func_reference,
+            output_type: Some(output_type.clone()),
+            options,
+            ..Default::default()
+        })
+    }
+}
+
+/// A builder object to create an aggregate function
+///
+/// Implementations are resolved in the same way as [FunctionBuilder].  By default the
+/// function aggregates all values (not just distinct values) and the entire aggregation
+/// (initial input to result) is performed.
+pub struct AggregateBuilder<'a> {
+    inner: FunctionBuilder<'a>,
+    invocation: AggregationInvocation,
+    phase: AggregationPhase,
+}
+
+impl<'a> AggregateBuilder<'a> {
+    fn new(inner: FunctionBuilder<'a>) -> Self {
+        Self {
+            inner,
+            invocation: AggregationInvocation::All,
+            phase: AggregationPhase::InitialToResult,
+        }
+    }
+
+    /// Adds a preferred value for an option (see [FunctionBuilder::with_option])
+    pub fn with_option(mut self, name: impl Into<String>, preference: impl Into<String>) -> Self {
+        self.inner = self.inner.with_option(name, preference);
+        self
+    }
+
+    /// Adds several option preferences at once (see [FunctionBuilder::with_options])
+    pub fn with_options<N: Into<String>, P: Into<String>>(
+        mut self,
+        options: impl IntoIterator<Item = (N, P)>,
+    ) -> Self {
+        self.inner = self.inner.with_options(options);
+        self
+    }
+
+    /// Sets whether all values or only distinct values are aggregated
+    pub fn with_invocation(mut self, invocation: AggregationInvocation) -> Self {
+        self.invocation = invocation;
+        self
+    }
+
+    /// Only aggregate distinct values (e.g. `SUM(DISTINCT x)`)
+    pub fn distinct(self) -> Self {
+        self.with_invocation(AggregationInvocation::Distinct)
+    }
+
+    /// Sets which part of a (distributed) aggregation this function performs
+    pub fn with_phase(mut self, phase: AggregationPhase) -> Self {
+        self.phase = phase;
+        self
+    }
+
+    /// Consume the builder and create an aggregate function
+    pub fn build(self) -> Result<AggregateFunction> {
+        let ScalarFunction {
+            function_reference,
+            arguments,
+            options,
+            output_type,
+            ..
+        } = self.inner.build_function()?;
+        Ok(AggregateFunction {
+            function_reference,
+            arguments,
+            options,
+            output_type,
+            phase: self.phase as i32,
+            invocation: self.invocation as i32,
+            ..Default::default()
+        })
+    }
+}
+
+/// One end of a window frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowBound {
+    /// The given number of rows (or range units) before the current row
+    Preceding(i64),
+    /// The given number of rows (or range units) after the current row
+    Following(i64),
+    /// The current row (and, for range frames, its peers)
+    CurrentRow,
+    /// The start or end of the partition
+    Unbounded,
+}
+
+impl From<WindowBound> for Bound {
+    fn from(value: WindowBound) -> Self {
+        let kind = match value {
+            WindowBound::Preceding(offset) => bound::Kind::Preceding(bound::Preceding { offset }),
+            WindowBound::Following(offset) => bound::Kind::Following(bound::Following { offset }),
+            WindowBound::CurrentRow => bound::Kind::CurrentRow(bound::CurrentRow {}),
+            WindowBound::Unbounded => bound::Kind::Unbounded(bound::Unbounded {}),
         };
+        Bound { kind: Some(kind) }
+    }
+}
+
+/// A builder object to create a window function expression
+///
+/// Implementations are resolved in the same way as [FunctionBuilder].  If no frame is
+/// given then the frame is left unspecified, which consumers interpret as the default
+/// frame (typically everything from the start of the partition to the current row).
+pub struct WindowBuilder<'a> {
+    inner: FunctionBuilder<'a>,
+    partitions: Vec<Expression>,
+    sorts: Vec<SortField>,
+    bounds_type: BoundsType,
+    lower_bound: Option<WindowBound>,
+    upper_bound: Option<WindowBound>,
+    invocation: AggregationInvocation,
+}
+
+impl<'a> WindowBuilder<'a> {
+    fn new(inner: FunctionBuilder<'a>) -> Self {
+        Self {
+            inner,
+            partitions: Vec::new(),
+            sorts: Vec::new(),
+            bounds_type: BoundsType::Unspecified,
+            lower_bound: None,
+            upper_bound: None,
+            invocation: AggregationInvocation::All,
+        }
+    }
+
+    /// Adds a preferred value for an option (see [FunctionBuilder::with_option])
+    pub fn with_option(mut self, name: impl Into<String>, preference: impl Into<String>) -> Self {
+        self.inner = self.inner.with_option(name, preference);
+        self
+    }
+
+    /// Adds several option preferences at once (see [FunctionBuilder::with_options])
+    pub fn with_options<N: Into<String>, P: Into<String>>(
+        mut self,
+        options: impl IntoIterator<Item = (N, P)>,
+    ) -> Self {
+        self.inner = self.inner.with_options(options);
+        self
+    }
+
+    /// Adds an expression to partition the input by
+    ///
+    /// Calling this multiple times partitions by all of the given expressions
+    pub fn partition_by(mut self, expr: Expression) -> Self {
+        self.partitions.push(expr);
+        self
+    }
+
+    /// Adds an expression to sort each partition by
+    ///
+    /// Calling this multiple times adds less significant sort keys
+    pub fn order_by(mut self, expr: Expression, direction: SortDirection) -> Self {
+        self.sorts.push(SortField {
+            expr: Some(expr),
+            sort_kind: Some(SortKind::Direction(direction as i32)),
+        });
+        self
+    }
+
+    /// Sets the frame, relative to the current row, that the function is applied to
+    pub fn with_frame(
+        mut self,
+        bounds_type: BoundsType,
+        lower_bound: WindowBound,
+        upper_bound: WindowBound,
+    ) -> Self {
+        self.bounds_type = bounds_type;
+        self.lower_bound = Some(lower_bound);
+        self.upper_bound = Some(upper_bound);
+        self
+    }
+
+    /// Sets a frame measured in rows (e.g. `ROWS BETWEEN 2 PRECEDING AND CURRENT ROW`)
+    pub fn rows(self, lower_bound: WindowBound, upper_bound: WindowBound) -> Self {
+        self.with_frame(BoundsType::Rows, lower_bound, upper_bound)
+    }
 
+    /// Sets a frame measured in values of the sort key (e.g. `RANGE BETWEEN ...`)
+    pub fn range(self, lower_bound: WindowBound, upper_bound: WindowBound) -> Self {
+        self.with_frame(BoundsType::Range, lower_bound, upper_bound)
+    }
+
+    /// Only consider distinct values (e.g. `COUNT(DISTINCT x) OVER (...)`)
+    pub fn distinct(mut self) -> Self {
+        self.invocation = AggregationInvocation::Distinct;
+        self
+    }
+
+    /// Consume the builder and create a window function expression
+    pub fn build(self) -> Result<Expression> {
+        if self.bounds_type == BoundsType::Range
+            && self.sorts.len() != 1
+            && [self.lower_bound, self.upper_bound].iter().any(|bound| {
+                matches!(
+                    bound,
+                    Some(WindowBound::Preceding(_)) | Some(WindowBound::Following(_))
+                )
+            })
+        {
+            return Err(SubstraitExprError::invalid_input(
+                "A range frame with an offset requires exactly one sort expression",
+            ));
+        }
+        let ScalarFunction {
+            function_reference,
+            arguments,
+            options,
+            output_type,
+            ..
+        } = self.inner.build_function()?;
         Ok(Expression {
-            rex_type: Some(RexType::ScalarFunction(ScalarFunction {
+            rex_type: Some(RexType::WindowFunction(WindowFunction {
+                function_reference,
                 arguments,
-                function_reference: self.func_reference,
-                output_type: Some(output_type.clone()),
                 options,
+                output_type,
+                phase: AggregationPhase::InitialToResult as i32,
+                sorts: self.sorts,
+                invocation: self.invocation as i32,
+                partitions: self.partitions,
+                bounds_type: self.bounds_type as i32,
+                lower_bound: self.lower_bound.map(Bound::from),
+                upper_bound: self.upper_bound.map(Bound::from),
                 ..Default::default()
             })),
         })
     }
 }
+
+/// Helpers for tests that define their own functions (e.g. a UDF loaded from a catalog)
+#[cfg(test)]
+pub(crate) mod test_udfs {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    use substrait::proto::Type;
+
+    use super::{
+        FunctionDefinition, FunctionImplementation, FunctionReturn, ImplementationArg,
+        ImplementationArgType, NullabilityHandling,
+    };
+
+    /// The URI of the extension that test functions are defined in
+    pub(crate) const UDF_URI: &str = "https://example.com/udfs";
+
+    /// Creates a function named `name` in the [`UDF_URI`] extension
+    pub(crate) fn udf(
+        name: &str,
+        implementations: Vec<FunctionImplementation>,
+    ) -> Arc<FunctionDefinition> {
+        Arc::new(FunctionDefinition {
+            uri: UDF_URI.to_string(),
+            name: name.to_string(),
+            implementations,
+        })
+    }
+
+    /// Creates a non-variadic implementation without options
+    ///
+    /// The arguments are named `arg0`, `arg1`, ...
+    pub(crate) fn implementation(
+        arg_types: Vec<ImplementationArgType>,
+        output_type: FunctionReturn,
+    ) -> FunctionImplementation {
+        FunctionImplementation {
+            args: arg_types
+                .into_iter()
+                .enumerate()
+                .map(|(index, arg_type)| ImplementationArg {
+                    name: format!("arg{}", index),
+                    arg_type,
+                })
+                .collect(),
+            output_type,
+            options: BTreeMap::new(),
+            nullability: NullabilityHandling::Mirror,
+            variadic: None,
+        }
+    }
+
+    /// Creates an implementation that only accepts and returns values of the given types
+    pub(crate) fn typed_implementation(
+        arg_types: Vec<Type>,
+        output_type: Type,
+    ) -> FunctionImplementation {
+        implementation(
+            arg_types
+                .into_iter()
+                .map(ImplementationArgType::Value)
+                .collect(),
+            FunctionReturn::Typed(output_type),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+
+    use crate::builder::schema::SchemaBuildersExt;
+    use crate::builder::{BuilderParams, ExpressionsBuilder};
+    use crate::functions::functions_arithmetic::FunctionsArithmeticWindowExt;
+    use crate::functions::functions_arithmetic::{
+        FunctionsArithmeticAggregateExt, FunctionsArithmeticExt, ADD, BITWISE_AND, BITWISE_OR,
+        BITWISE_XOR,
+    };
+    use crate::functions::functions_comparison::FunctionsComparisonExt;
+    use crate::helpers::types;
+
+    use super::test_udfs::{implementation, typed_implementation, udf, UDF_URI};
+    use super::*;
+
+    #[test]
+    fn test_pre_resolved_implementation() {
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(false))
+            .field("y", types::i32(true))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let y = builder.fields().resolve_by_name("y").unwrap();
+
+        let auto_resolved = builder
+            .functions()
+            .add(x.clone(), literal(3_i32))
+            .build()
+            .unwrap();
+
+        let impl_index = ADD
+            .implementations
+            .iter()
+            .position(|imp| {
+                imp.matches(
+                    &[types::i32(false), types::i32(false)],
+                    builder.schema.extensions_registry(),
+                )
+            })
+            .unwrap();
+        let pre_resolved = builder
+            .functions()
+            .new_builder_with_impl(&ADD, impl_index, vec![x.clone(), literal(3_i32)])
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(auto_resolved, pre_resolved);
+
+        // The output nullability still depends on the arguments
+        let nullable_sum = builder
+            .functions()
+            .new_builder_with_impl(&ADD, impl_index, vec![y, literal(3_i32)])
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(
+            nullable_sum.output_type(builder.schema()).unwrap(),
+            types::i32(true)
+        );
+
+        // The arguments must match the chosen implementation
+        assert!(builder
+            .functions()
+            .new_builder_with_impl(&ADD, impl_index, vec![x.clone(), literal("hello")])
+            .unwrap()
+            .build()
+            .is_err());
+        assert!(builder
+            .functions()
+            .new_builder_with_impl(&ADD, impl_index, vec![x.clone()])
+            .is_err());
+        assert!(builder
+            .functions()
+            .new_builder_with_impl(&ADD, ADD.implementations.len(), vec![x, literal(3_i32)])
+            .is_err());
+    }
+
+    #[test]
+    fn test_cast() {
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(false))
+            .nested("point", false, |builder| {
+                builder
+                    .field("x", types::fp64(false))
+                    .field("y", types::fp64(false))
+            })
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let point = builder.fields().resolve_by_name("point").unwrap();
+
+        let cast = builder
+            .functions()
+            .cast(x.clone(), types::i64(false), CastFailureBehavior::default())
+            .unwrap();
+        assert_eq!(
+            cast.output_type(&builder.schema).unwrap(),
+            types::i64(false)
+        );
+        let cast = builder
+            .functions()
+            .cast(x, types::i64(false), CastFailureBehavior::ReturnNull)
+            .unwrap();
+        assert_eq!(cast.output_type(&builder.schema).unwrap(), types::i64(true));
+
+        assert!(builder
+            .functions()
+            .cast(point, types::i64(false), CastFailureBehavior::default())
+            .is_err());
+    }
+
+    #[test]
+    fn test_bind_parameters() {
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let functions = builder.functions();
+
+        let parameterized = functions
+            .lt(x.clone(), functions.parameter(0, types::i32(false)))
+            .build()
+            .unwrap();
+        assert_eq!(
+            parameterized.output_type(&builder.schema).unwrap(),
+            types::bool(false)
+        );
+
+        let bound = functions
+            .bind_parameters(parameterized.clone(), &[literal(5_i32)])
+            .unwrap();
+        let expected = functions.lt(x.clone(), literal(5_i32)).build().unwrap();
+        assert_eq!(bound, expected);
+
+        // Missing value
+        assert!(functions
+            .bind_parameters(parameterized.clone(), &[])
+            .is_err());
+        // Wrong type
+        assert!(functions
+            .bind_parameters(parameterized, &[literal("hello")])
+            .is_err());
+
+        // Placeholders in other kinds of expressions are bound too
+        let in_list = functions
+            .in_list(
+                x.clone(),
+                vec![
+                    functions.parameter(0, types::i32(false)),
+                    functions.parameter(1, types::i32(false)),
+                ],
+            )
+            .unwrap();
+        let if_then = functions
+            .if_then(
+                vec![(in_list, functions.parameter(2, types::string(false)))],
+                None,
+            )
+            .unwrap();
+        let values = [literal(1_i32), literal(2_i32), literal("found")];
+        let expected = functions
+            .if_then(
+                vec![(
+                    functions
+                        .in_list(x.clone(), vec![literal(1_i32), literal(2_i32)])
+                        .unwrap(),
+                    literal("found"),
+                )],
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            functions.bind_parameters(if_then.clone(), &values).unwrap(),
+            expected
+        );
+        assert!(functions
+            .bind_parameters(if_then.clone(), &values[..2])
+            .is_err());
+        assert!(functions
+            .bind_parameters(if_then, &[literal(1_i32), literal("two"), literal("found")])
+            .is_err());
+
+        let window = functions
+            .row_number()
+            .partition_by(
+                functions
+                    .add(x, functions.parameter(0, types::i32(false)))
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        assert!(functions.bind_parameters(window, &[]).is_err());
+    }
+
+    #[test]
+    fn test_function_options() {
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+
+        let sum = builder
+            .functions()
+            .add(x.clone(), literal(1_i32))
+            .with_option("overflow", "ERROR")
+            .build()
+            .unwrap();
+        let func = match sum.rex_type {
+            Some(RexType::ScalarFunction(func)) => func,
+            _ => panic!("Expected a scalar function"),
+        };
+        assert_eq!(
+            func.options,
+            vec![FunctionOption {
+                name: "overflow".to_string(),
+                preference: vec!["ERROR".to_string()],
+            }]
+        );
+
+        assert!(builder
+            .functions()
+            .add(x.clone(), literal(1_i32))
+            .with_options([("overflow", "SATURATE"), ("overflow", "SILENT")])
+            .build()
+            .is_ok());
+        let bad_value = builder
+            .functions()
+            .add(x.clone(), literal(1_i32))
+            .with_option("overflow", "WRAP")
+            .build();
+        match bad_value {
+            Err(SubstraitExprError::InvalidInput(message)) => assert!(message.contains("SATURATE")),
+            _ => panic!("Expected an invalid option value to be rejected"),
+        }
+        assert!(builder
+            .functions()
+            .add(x, literal(1_i32))
+            .with_option("rounding", "TIE_TO_EVEN")
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_option_ordering() {
+        let schema = SchemaInfo::new_full()
+            .field("x", types::fp64(false))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+
+        let first = builder
+            .functions()
+            .sqrt(x.clone())
+            .with_option("rounding", "TRUNCATE")
+            .with_option("on_domain_error", "NAN")
+            .with_option("rounding", "FLOOR")
+            .build()
+            .unwrap();
+        let second = builder
+            .functions()
+            .sqrt(x)
+            .with_options([
+                ("on_domain_error", "NAN"),
+                ("rounding", "TRUNCATE"),
+                ("on_domain_error", "NAN"),
+                ("rounding", "FLOOR"),
+            ])
+            .build()
+            .unwrap();
+        assert_eq!(first.encode_to_vec(), second.encode_to_vec());
+        let func = match first.rex_type {
+            Some(RexType::ScalarFunction(func)) => func,
+            _ => panic!("Expected a scalar function"),
+        };
+        assert_eq!(
+            func.options,
+            vec![
+                FunctionOption {
+                    name: "on_domain_error".to_string(),
+                    preference: vec!["NAN".to_string()],
+                },
+                FunctionOption {
+                    name: "rounding".to_string(),
+                    preference: vec!["TRUNCATE".to_string(), "FLOOR".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_generated_options() {
+        let allowed = ["SILENT", "SATURATE", "ERROR"].map(String::from).to_vec();
+        assert!(ADD
+            .implementations
+            .iter()
+            .any(|imp| imp.options.get("overflow") == Some(&allowed)));
+        let float_add = ADD
+            .implementations
+            .iter()
+            .find(|imp| imp.options.contains_key("rounding"))
+            .unwrap();
+        assert!(float_add.options["rounding"].contains(&"TIE_TO_EVEN".to_string()));
+    }
+
+    #[test]
+    fn test_templated_functions() {
+        let schema = SchemaInfo::new_full()
+            .field("a", types::i64(false))
+            .field("b", types::i64(false))
+            .field("c", types::i32(false))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let template_arg = || ImplementationArgType::TemplateValue("T".to_string());
+        let max = udf(
+            "max",
+            vec![implementation(
+                vec![template_arg(), template_arg()],
+                FunctionReturn::Templated("T".to_string()),
+            )],
+        );
+        let a = builder.fields().resolve_by_name("a").unwrap();
+        let b = builder.fields().resolve_by_name("b").unwrap();
+        let c = builder.fields().resolve_by_name("c").unwrap();
+
+        let both_i64 = builder
+            .functions()
+            .new_builder_owned(max.clone(), vec![a.clone(), b])
+            .build()
+            .unwrap();
+        assert_eq!(
+            both_i64.output_type(&builder.schema).unwrap(),
+            types::i64(false)
+        );
+
+        // T cannot be both i64 and i32
+        assert!(builder
+            .functions()
+            .new_builder_owned(max, vec![a.clone(), c.clone()])
+            .build()
+            .is_err());
+        assert!(builder.functions().equal(a, c).build().is_err());
+    }
+
+    #[test]
+    fn test_output_nullability() {
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(true))
+            .field("y", types::i32(false))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let y = builder.fields().resolve_by_name("y").unwrap();
+
+        let nullable_sum = builder.functions().add(x, y.clone()).build().unwrap();
+        assert_eq!(
+            nullable_sum.output_type(&builder.schema).unwrap(),
+            types::i32(true)
+        );
+        let sum = builder.functions().add(y.clone(), y).build().unwrap();
+        assert_eq!(sum.output_type(&builder.schema).unwrap(), types::i32(false));
+    }
+
+    #[test]
+    fn test_integer_functions() {
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(false))
+            .field("y", types::i32(true))
+            .field("z", types::i64(false))
+            .field("f", types::fp64(false))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let field = |name: &str| builder.fields().resolve_by_name(name).unwrap();
+
+        let remainder = builder
+            .functions()
+            .modulus(field("x"), field("y"))
+            .build()
+            .unwrap();
+        assert_eq!(
+            remainder.output_type(&builder.schema).unwrap(),
+            types::i32(true)
+        );
+        let remainder = builder
+            .functions()
+            .modulus(field("z"), literal(3_i64))
+            .build()
+            .unwrap();
+        assert_eq!(
+            remainder.output_type(&builder.schema).unwrap(),
+            types::i64(false)
+        );
+        // modulus is only defined for integers
+        assert!(builder
+            .functions()
+            .modulus(field("f"), literal(3.0_f64))
+            .build()
+            .is_err());
+
+        let masked = builder
+            .functions()
+            .bitwise_and(field("z"), literal(0xFF_i64))
+            .build()
+            .unwrap();
+        assert_eq!(
+            masked.output_type(&builder.schema).unwrap(),
+            types::i64(false)
+        );
+        for func in [&*BITWISE_AND, &*BITWISE_OR, &*BITWISE_XOR] {
+            assert!(builder
+                .functions()
+                .new_builder(func, vec![field("f"), field("f")])
+                .build()
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_declared_output_nullability() {
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(true))
+            .field("y", types::i32(true))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let y = builder.fields().resolve_by_name("y").unwrap();
+
+        let distinct = builder
+            .functions()
+            .is_distinct_from(x.clone(), y.clone())
+            .build()
+            .unwrap();
+        assert_eq!(
+            distinct.output_type(&builder.schema).unwrap(),
+            types::bool(false)
+        );
+        let not_distinct = builder
+            .functions()
+            .is_not_distinct_from(x.clone(), y.clone())
+            .build()
+            .unwrap();
+        assert_eq!(
+            not_distinct.output_type(&builder.schema).unwrap(),
+            types::bool(false)
+        );
+        // Functions that mirror their inputs are still nullable
+        let equal = builder.functions().equal(x, y).build().unwrap();
+        assert_eq!(
+            equal.output_type(&builder.schema).unwrap(),
+            types::bool(true)
+        );
+    }
+
+    #[test]
+    fn test_runtime_function_definition() {
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        // e.g. loaded from a catalog
+        let double = udf(
+            "double",
+            vec![typed_implementation(
+                vec![types::i32(false)],
+                types::i64(false),
+            )],
+        );
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let doubled = builder
+            .functions()
+            .new_builder_owned(double.clone(), vec![x.clone()])
+            .build()
+            .unwrap();
+        assert_eq!(
+            doubled.output_type(&builder.schema).unwrap(),
+            types::i64(false)
+        );
+        let func = match doubled.rex_type {
+            Some(RexType::ScalarFunction(func)) => func,
+            _ => panic!("Expected a scalar function"),
+        };
+        assert_eq!(
+            func.function_reference,
+            builder
+                .schema
+                .extensions_registry()
+                .register_function_by_name(UDF_URI, "double")
+        );
+
+        assert!(builder
+            .functions()
+            .new_builder_owned(double, vec![literal("hello")])
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_parameterized_arg_matching() {
+        let registry = ExtensionsRegistry::default();
+        let arg = ImplementationArg {
+            name: "x".to_string(),
+            arg_type: ImplementationArgType::Value(types::decimal(38, 6, false).unwrap()),
+        };
+        let matches = |typ: Type| arg.matches(&typ, &registry).unwrap();
+        assert!(matches(types::decimal(38, 6, false).unwrap()));
+        assert!(matches(types::decimal(38, 6, true).unwrap()));
+        assert!(!matches(types::decimal(38, 2, false).unwrap()));
+        assert!(!matches(types::decimal(10, 6, false).unwrap()));
+        assert!(matches(crate::builder::types::unknown(&registry)));
+
+        let template = ImplementationArg {
+            name: "x".to_string(),
+            arg_type: ImplementationArgType::TemplateValue("any1".to_string()),
+        };
+        assert!(template
+            .matches(&types::decimal(10, 2, false).unwrap(), &registry)
+            .unwrap());
+    }
+
+    #[test]
+    fn test_enum_argument_round_trip() {
+        let schema = SchemaInfo::new_full().field("d", types::i32(false)).build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let truncate_to = udf(
+            "truncate_to",
+            vec![implementation(
+                vec![
+                    ImplementationArgType::Enum(vec!["YEAR".to_string(), "MONTH".to_string()]),
+                    ImplementationArgType::Value(types::i32(false)),
+                ],
+                FunctionReturn::Typed(types::i32(false)),
+            )],
+        );
+        let d = builder.fields().resolve_by_name("d").unwrap();
+        let truncated = builder
+            .functions()
+            .new_builder_owned(truncate_to.clone(), vec![literal("YEAR"), d.clone()])
+            .build()
+            .unwrap();
+        let decoded = Expression::decode(truncated.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded, truncated);
+        let Some(RexType::ScalarFunction(func)) = decoded.rex_type else {
+            panic!("Expected a scalar function");
+        };
+        assert_eq!(
+            func.arguments[0].arg_type,
+            Some(ArgType::Enum("YEAR".to_string()))
+        );
+
+        assert!(builder
+            .functions()
+            .new_builder_owned(truncate_to, vec![literal("DAY"), d])
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_coalesce_nullability() {
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(true))
+            .field("y", types::i32(false))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let y = builder.fields().resolve_by_name("y").unwrap();
+
+        let non_null_last = builder
+            .functions()
+            .coalesce(vec![x.clone(), y.clone()])
+            .unwrap();
+        assert_eq!(
+            non_null_last.output_type(&builder.schema).unwrap(),
+            types::i32(false)
+        );
+
+        let nullable_last = builder
+            .functions()
+            .coalesce(vec![y.clone(), x.clone()])
+            .unwrap();
+        assert_eq!(
+            nullable_last.output_type(&builder.schema).unwrap(),
+            types::i32(true)
+        );
+
+        assert!(builder.functions().coalesce(vec![x.clone()]).is_err());
+        assert!(builder
+            .functions()
+            .coalesce(vec![x.clone(), literal("hello")])
+            .is_err());
+
+        // Only the nullability differs from the generated builder
+        let mut generated = FunctionsComparisonExt::coalesce(&builder.functions(), vec![x, y])
+            .build()
+            .unwrap();
+        assert_eq!(
+            generated.output_type(&builder.schema).unwrap(),
+            types::i32(true)
+        );
+        if let Some(RexType::ScalarFunction(func)) = &mut generated.rex_type {
+            func.output_type = Some(types::i32(false));
+        }
+        assert_eq!(generated, non_null_last);
+    }
+
+    #[test]
+    fn test_if_then_and_switch() {
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(true))
+            .field("y", types::i32(false))
+            .field("flag", types::bool(false))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let y = builder.fields().resolve_by_name("y").unwrap();
+        let flag = builder.fields().resolve_by_name("flag").unwrap();
+
+        let with_else = builder
+            .functions()
+            .if_then(vec![(flag.clone(), y.clone())], Some(literal(0_i32)))
+            .unwrap();
+        assert_eq!(
+            with_else.output_type(&builder.schema).unwrap(),
+            types::i32(false)
+        );
+        // Without an else branch the result is null if no condition is true
+        let without_else = builder
+            .functions()
+            .if_then(vec![(flag.clone(), y.clone())], None)
+            .unwrap();
+        assert_eq!(
+            without_else.output_type(&builder.schema).unwrap(),
+            types::i32(true)
+        );
+        let nullable_branch = builder
+            .functions()
+            .if_then(vec![(flag.clone(), x.clone())], Some(y.clone()))
+            .unwrap();
+        assert_eq!(
+            nullable_branch.output_type(&builder.schema).unwrap(),
+            types::i32(true)
+        );
+        assert!(builder
+            .functions()
+            .if_then(vec![], Some(y.clone()))
+            .is_err());
+        assert!(builder
+            .functions()
+            .if_then(vec![(y.clone(), y.clone())], None)
+            .is_err());
+        assert!(builder
+            .functions()
+            .if_then(vec![(flag, y.clone())], Some(literal("hello")))
+            .is_err());
+
+        let switch = builder
+            .functions()
+            .switch(
+                x.clone(),
+                vec![
+                    (literal(1_i32), y.clone()),
+                    (literal(2_i32), literal(7_i32)),
+                ],
+                Some(literal(0_i32)),
+            )
+            .unwrap();
+        assert_eq!(
+            switch.output_type(&builder.schema).unwrap(),
+            types::i32(false)
+        );
+        let switch = builder
+            .functions()
+            .switch(x.clone(), vec![(literal(1_i32), y.clone())], None)
+            .unwrap();
+        assert_eq!(
+            switch.output_type(&builder.schema).unwrap(),
+            types::i32(true)
+        );
+        assert!(builder
+            .functions()
+            .switch(x.clone(), vec![(literal("one"), y.clone())], None)
+            .is_err());
+        assert!(builder
+            .functions()
+            .switch(x, vec![(y.clone(), y)], None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_suggest_casts() {
+        let registry = ExtensionsRegistry::default();
+        let suggestions = ADD.suggest_casts(&[types::i32(false), types::i64(false)], &registry);
+        assert_eq!(
+            suggestions,
+            vec![vec![CastSuggestion {
+                arg_index: 0,
+                from: types::i32(false),
+                to: types::i64(false),
+            }]]
+        );
+
+        // No suggestions if the call already resolves
+        assert!(ADD
+            .suggest_casts(&[types::i32(false), types::i32(false)], &registry)
+            .is_empty());
+        // No suggestions if there is no lossless cast
+        assert!(ADD
+            .suggest_casts(&[types::i64(false), types::string(false)], &registry)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_no_match_error() {
+        let schema = SchemaInfo::new_full()
+            .field("s", types::string(false))
+            .field("x", types::i32(true))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let s = builder.fields().resolve_by_name("s").unwrap();
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let message = builder
+            .functions()
+            .add(s, x)
+            .build()
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("No implementation of add accepts the arguments (string, i32?)"));
+        assert!(message.contains("add(i32, i32)"));
+        assert!(message.contains("add(fp64, fp64)"));
+    }
+
+    #[test]
+    fn test_strict_function_resolution() {
+        let templated = |template: &str| {
+            implementation(
+                vec![ImplementationArgType::TemplateValue(template.to_string())],
+                FunctionReturn::Typed(types::string(false)),
+            )
+        };
+        let unary = |typ: Type| typed_implementation(vec![typ.clone()], typ);
+        let widen = udf(
+            "widen",
+            vec![
+                templated("any1"),
+                unary(types::i64(false)),
+                unary(types::fp32(false)),
+                unary(types::fp64(false)),
+            ],
+        );
+        let schema = || {
+            SchemaInfo::new_full()
+                .field("i", types::i64(false))
+                .field("f", types::fp32(false))
+                .build()
+        };
+        let params = BuilderParams {
+            strict_function_resolution: true,
+            ..Default::default()
+        };
+        let builder = ExpressionsBuilder::new(schema(), params);
+        let i = builder.fields().resolve_by_name("i").unwrap();
+        // The concrete implementation is a better match than the template
+        let call = builder
+            .functions()
+            .new_builder_owned(widen.clone(), vec![i])
+            .build()
+            .unwrap();
+        assert_eq!(
+            call.output_type(builder.schema()).unwrap(),
+            types::i64(false)
+        );
+
+        // Two implementations that differ only in the name of the template are ambiguous
+        let ambiguous = udf("identity", vec![templated("any1"), templated("T")]);
+        let message = builder
+            .functions()
+            .new_builder_owned(ambiguous.clone(), vec![literal(1_i32)])
+            .build()
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("The call to identity is ambiguous"));
+        assert!(message.contains("identity(any1), identity(T)"));
+
+        // Without strict resolution the first match is used
+        let loose = ExpressionsBuilder::new(schema(), BuilderParams::default());
+        assert!(loose
+            .functions()
+            .new_builder_owned(ambiguous, vec![literal(1_i32)])
+            .build()
+            .is_ok());
+
+        // An exact match wins over a promotion
+        let params = BuilderParams {
+            strict_function_resolution: true,
+            allow_type_promotion: true,
+            ..Default::default()
+        };
+        let promoting = ExpressionsBuilder::new(schema(), params);
+        let f = promoting.fields().resolve_by_name("f").unwrap();
+        let fp_only = udf("fp_only", widen.implementations[2..].to_vec());
+        let call = promoting
+            .functions()
+            .new_builder_owned(fp_only, vec![f])
+            .build()
+            .unwrap();
+        assert_eq!(
+            call.output_type(promoting.schema()).unwrap(),
+            types::fp32(false)
+        );
+
+        // Promotions that need the same number of casts are ambiguous
+        let money = types::decimal(38, 0, false).unwrap();
+        let int_or_money = udf("int_or_money", vec![unary(types::i64(false)), unary(money)]);
+        let message = promoting
+            .functions()
+            .new_builder_owned(int_or_money, vec![literal(1_i32)])
+            .build()
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("int_or_money(i64), int_or_money(decimal<38,0>)"));
+    }
+
+    #[test]
+    fn test_type_promotion() {
+        let schema = || {
+            SchemaInfo::new_full()
+                .field("a", types::i32(true))
+                .field("b", types::i64(false))
+                .build()
+        };
+        let strict = ExpressionsBuilder::new(schema(), BuilderParams::default());
+        let a = strict.fields().resolve_by_name("a").unwrap();
+        let b = strict.fields().resolve_by_name("b").unwrap();
+        assert!(strict
+            .functions()
+            .add(a.clone(), b.clone())
+            .build()
+            .is_err());
+
+        let params = BuilderParams {
+            allow_type_promotion: true,
+            ..Default::default()
+        };
+        let promoting = ExpressionsBuilder::new(schema(), params);
+        let sum = promoting
+            .functions()
+            .add(a.clone(), b.clone())
+            .build()
+            .unwrap();
+        assert_eq!(
+            sum.output_type(promoting.schema()).unwrap(),
+            types::i64(true)
+        );
+        let Some(RexType::ScalarFunction(func)) = &sum.rex_type else {
+            panic!("Expected a scalar function");
+        };
+        // The narrower argument is wrapped in a cast that keeps its nullability
+        let Some(ArgType::Value(promoted)) = &func.arguments[0].arg_type else {
+            panic!("Expected a value argument");
+        };
+        assert_eq!(
+            promoted,
+            &promoting
+                .functions()
+                .cast(
+                    a.clone(),
+                    types::i64(true),
+                    CastFailureBehavior::ThrowException
+                )
+                .unwrap()
+        );
+        assert_eq!(func.arguments[1].arg_type, Some(ArgType::Value(b)));
+
+        // Integers can be promoted to a wide enough decimal
+        let money = types::decimal(38, 6, false).unwrap();
+        let round_money = udf(
+            "round_money",
+            vec![typed_implementation(vec![money.clone()], money)],
+        );
+        assert!(promoting
+            .functions()
+            .new_builder_owned(round_money, vec![a])
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_enum_arguments() {
+        // The datetime extension shares names (e.g. add) with the arithmetic extension
+        use crate::functions::functions_datetime::FunctionsDatetimeExt;
+
+        let schema = SchemaInfo::new_full().field("d", types::date(true)).build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let d = builder.fields().resolve_by_name("d").unwrap();
+        let year = builder
+            .functions()
+            .extract("YEAR", d.clone())
+            .build()
+            .unwrap();
+        assert_eq!(
+            year.output_type(builder.schema()).unwrap(),
+            types::i64(true)
+        );
+        let Some(RexType::ScalarFunction(func)) = &year.rex_type else {
+            panic!("Expected a scalar function");
+        };
+        assert_eq!(
+            func.arguments[0].arg_type,
+            Some(ArgType::Enum("YEAR".to_string()))
+        );
+
+        let month = builder
+            .functions()
+            // The second argument is only an enum in some of the 3-argument implementations
+            .extract3("MONTH", literal("ONE"), d.clone())
+            .build()
+            .unwrap();
+        assert_eq!(
+            month.output_type(builder.schema()).unwrap(),
+            types::i64(true)
+        );
+
+        // HOUR is not a valid component of a date
+        assert!(builder.functions().extract("HOUR", d).build().is_err());
+    }
+
+    #[test]
+    fn test_temporal_functions() {
+        use crate::functions::functions_datetime::FunctionsDatetimeExt;
+
+        let schema = SchemaInfo::new_full()
+            .field("ts", types::timestamp(false))
+            .field("tstz", types::timestamp_tz(true))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let ts = builder.fields().resolve_by_name("ts").unwrap();
+        let tstz = builder.fields().resolve_by_name("tstz").unwrap();
+        let hour = builder.functions().extract("HOUR", ts).build().unwrap();
+        assert_eq!(
+            hour.output_type(builder.schema()).unwrap(),
+            types::i64(false)
+        );
+        let hour = builder
+            .functions()
+            .extract3("HOUR", tstz, literal("UTC"))
+            .build()
+            .unwrap();
+        assert_eq!(
+            hour.output_type(builder.schema()).unwrap(),
+            types::i64(true)
+        );
+    }
+
+    #[test]
+    fn test_variadic_functions() {
+        use crate::functions::functions_string::FunctionsStringExt;
+
+        let schema = SchemaInfo::new_full()
+            .field("first", types::string(false))
+            .field("last", types::string(true))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let first = builder.fields().resolve_by_name("first").unwrap();
+        let last = builder.fields().resolve_by_name("last").unwrap();
+
+        let full_name = builder
+            .functions()
+            .concat(vec![first.clone(), literal(" ".to_string()), last])
+            .build()
+            .unwrap();
+        assert_eq!(
+            full_name.output_type(builder.schema()).unwrap(),
+            types::string(true)
+        );
+        // concat requires at least one argument
+        assert!(builder.functions().concat(vec![]).build().is_err());
+        // Every repeated argument must match the declared type
+        assert!(builder
+            .functions()
+            .concat(vec![first, literal(1_i32)])
+            .build()
+            .is_err());
+
+        let implementation = FunctionImplementation {
+            args: vec![
+                ImplementationArg {
+                    name: "separator".to_string(),
+                    arg_type: ImplementationArgType::Value(types::string(false)),
+                },
+                ImplementationArg {
+                    name: "value".to_string(),
+                    arg_type: ImplementationArgType::Value(types::string(false)),
+                },
+            ],
+            output_type: FunctionReturn::Typed(types::string(false)),
+            options: BTreeMap::new(),
+            nullability: NullabilityHandling::Mirror,
+            variadic: Some(Variadic {
+                min: 1,
+                max: Some(2),
+            }),
+        };
+        assert!(implementation.args_for(1).is_none());
+        assert_eq!(implementation.args_for(2).unwrap().len(), 2);
+        let args = implementation.args_for(3).unwrap();
+        assert_eq!(args[0].name, "separator");
+        assert_eq!(args[2].name, "value");
+        assert!(implementation.args_for(4).is_none());
+    }
+
+    #[test]
+    fn test_decimal_return_programs() {
+        // The decimal extension shares names (e.g. add) with the arithmetic extension
+        use crate::functions::functions_arithmetic_decimal::FunctionsArithmeticDecimalExt as Decimal;
+
+        let schema = SchemaInfo::new_full()
+            .field("price", types::decimal(10, 2, true).unwrap())
+            .field("rate", types::decimal(5, 3, false).unwrap())
+            .field("big", types::decimal(38, 10, false).unwrap())
+            .field("count", types::decimal(8, 0, false).unwrap())
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let price = builder.fields().resolve_by_name("price").unwrap();
+        let rate = builder.fields().resolve_by_name("rate").unwrap();
+        let big = builder.fields().resolve_by_name("big").unwrap();
+        let count = builder.fields().resolve_by_name("count").unwrap();
+
+        // init_scale = 3, init_prec = 3 + max(8, 2) + 1
+        let sum = Decimal::add(&builder.functions(), price.clone(), rate.clone())
+            .build()
+            .unwrap();
+        assert_eq!(
+            sum.output_type(builder.schema()).unwrap(),
+            types::decimal(12, 3, true).unwrap()
+        );
+        // The precision is capped at 38 and the scale is reduced to make room
+        let sum = Decimal::add(&builder.functions(), big.clone(), big.clone())
+            .build()
+            .unwrap();
+        assert_eq!(
+            sum.output_type(builder.schema()).unwrap(),
+            types::decimal(38, 9, false).unwrap()
+        );
+        let product = Decimal::multiply(&builder.functions(), price.clone(), rate)
+            .build()
+            .unwrap();
+        assert_eq!(
+            product.output_type(builder.schema()).unwrap(),
+            types::decimal(16, 5, true).unwrap()
+        );
+
+        // bitwise_and only accepts decimals with a scale of 0
+        let bits = Decimal::bitwise_and(&builder.functions(), count.clone(), count)
+            .build()
+            .unwrap();
+        assert_eq!(
+            bits.output_type(builder.schema()).unwrap(),
+            types::decimal(8, 0, false).unwrap()
+        );
+        assert!(Decimal::bitwise_and(&builder.functions(), price, big)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_decimal_promotion() {
+        use crate::functions::functions_arithmetic_decimal::FunctionsArithmeticDecimalExt as Decimal;
+
+        let schema = || {
+            SchemaInfo::new_full()
+                .field("price", types::decimal(10, 2, true).unwrap())
+                .field("qty", types::i32(false))
+                .build()
+        };
+        let strict = ExpressionsBuilder::new(schema(), BuilderParams::default());
+        let price = strict.fields().resolve_by_name("price").unwrap();
+        let qty = strict.fields().resolve_by_name("qty").unwrap();
+        assert!(
+            Decimal::add(&strict.functions(), price.clone(), qty.clone())
+                .build()
+                .is_err()
+        );
+
+        let params = BuilderParams {
+            allow_type_promotion: true,
+            ..Default::default()
+        };
+        let promoting = ExpressionsBuilder::new(schema(), params);
+        // The integer binds P2 and S2 as decimal<10,0> so init_prec = 2 + max(8, 10) + 1
+        let sum = Decimal::add(&promoting.functions(), price, qty.clone())
+            .build()
+            .unwrap();
+        assert_eq!(
+            sum.output_type(promoting.schema()).unwrap(),
+            types::decimal(13, 2, true).unwrap()
+        );
+        let Some(RexType::ScalarFunction(func)) = &sum.rex_type else {
+            panic!("Expected a scalar function");
+        };
+        let Some(ArgType::Value(promoted)) = &func.arguments[1].arg_type else {
+            panic!("Expected a value argument");
+        };
+        assert_eq!(
+            promoted,
+            &promoting
+                .functions()
+                .cast(
+                    qty,
+                    types::decimal(10, 0, false).unwrap(),
+                    CastFailureBehavior::ThrowException
+                )
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_return_program_errors() {
+        let program = ReturnProgram {
+            assignments: vec![(
+                "scale".to_string(),
+                ProgramExpr::BinaryOp(
+                    ProgramOp::Divide,
+                    Box::new(ProgramExpr::Variable("S".to_string())),
+                    Box::new(ProgramExpr::Literal(0)),
+                ),
+            )],
+            precision: ProgramExpr::Variable("P".to_string()),
+            scale: ProgramExpr::Variable("scale".to_string()),
+            nullable: false,
+        };
+        let params = BTreeMap::from([("P".to_string(), 10), ("S".to_string(), 2)]);
+        assert!(program.evaluate(params).is_err());
+        // P is never bound
+        assert!(program.evaluate(BTreeMap::new()).is_err());
+
+        let program = ReturnProgram {
+            assignments: vec![],
+            precision: ProgramExpr::Literal(50),
+            scale: ProgramExpr::Literal(0),
+            nullable: true,
+        };
+        assert!(program.evaluate(BTreeMap::new()).is_err());
+
+        // Overflow is reported rather than panicking
+        let program = ReturnProgram {
+            assignments: vec![],
+            precision: ProgramExpr::BinaryOp(
+                ProgramOp::Multiply,
+                Box::new(ProgramExpr::Variable("P".to_string())),
+                Box::new(ProgramExpr::Variable("P".to_string())),
+            ),
+            scale: ProgramExpr::Literal(0),
+            nullable: false,
+        };
+        let params = BTreeMap::from([("P".to_string(), i64::MAX)]);
+        assert!(program.evaluate(params).is_err());
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let schema = SchemaInfo::new_full().field("x", types::i32(true)).build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+
+        // SUM(DISTINCT x)
+        let sum = builder
+            .functions()
+            .sum(x.clone())
+            .with_option("overflow", "ERROR")
+            .distinct()
+            .build()
+            .unwrap();
+        assert_eq!(sum.invocation, AggregationInvocation::Distinct as i32);
+        assert_eq!(sum.phase, AggregationPhase::InitialToResult as i32);
+        assert_eq!(sum.output_type, Some(types::i64(true)));
+        assert_eq!(sum.arguments.len(), 1);
+        assert_eq!(sum.options.len(), 1);
+        let registry = builder.schema().extensions_registry();
+        assert_eq!(
+            registry
+                .lookup_function(sum.function_reference)
+                .unwrap()
+                .name,
+            "sum"
+        );
+
+        let partial = builder
+            .functions()
+            .sum(x.clone())
+            .with_phase(AggregationPhase::InitialToIntermediate)
+            .build()
+            .unwrap();
+        assert_eq!(partial.invocation, AggregationInvocation::All as i32);
+        assert_eq!(
+            partial.phase,
+            AggregationPhase::InitialToIntermediate as i32
+        );
+
+        // Implementations are resolved like scalar functions
+        assert!(builder.functions().sum(literal("hello")).build().is_err());
+    }
+
+    #[test]
+    fn test_enum_aggregate_arguments() {
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+
+        // The precision of median is an enum argument
+        let median = builder
+            .functions()
+            .median("EXACT", x.clone())
+            .build()
+            .unwrap();
+        assert_eq!(
+            median.arguments[0].arg_type,
+            Some(ArgType::Enum("EXACT".to_string()))
+        );
+        assert_eq!(median.output_type, Some(types::i32(true)));
+
+        assert!(builder.functions().median("ROUGHLY", x).build().is_err());
+    }
+
+    #[test]
+    fn test_window() {
+        let schema = SchemaInfo::new_full()
+            .field("region", types::string(false))
+            .field("x", types::i32(false))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let region = builder.fields().resolve_by_name("region").unwrap();
+        let x = builder.fields().resolve_by_name("x").unwrap();
+
+        // ROW_NUMBER() OVER (PARTITION BY region ORDER BY x DESC)
+        let row_number = builder
+            .functions()
+            .row_number()
+            .partition_by(region.clone())
+            .order_by(x.clone(), SortDirection::DescNullsLast)
+            .build()
+            .unwrap();
+        assert_eq!(
+            row_number.output_type(builder.schema()).unwrap(),
+            types::i64(true)
+        );
+        let Some(RexType::WindowFunction(func)) = &row_number.rex_type else {
+            panic!("Expected a window function");
+        };
+        assert_eq!(func.partitions, vec![region.clone()]);
+        assert_eq!(func.sorts.len(), 1);
+        assert_eq!(func.bounds_type, BoundsType::Unspecified as i32);
+
+        // LAG(x) OVER (ORDER BY x ROWS BETWEEN 2 PRECEDING AND CURRENT ROW)
+        let lag = builder
+            .functions()
+            .lag(x.clone())
+            .order_by(x.clone(), SortDirection::AscNullsFirst)
+            .rows(WindowBound::Preceding(2), WindowBound::CurrentRow)
+            .build()
+            .unwrap();
+        assert_eq!(
+            lag.output_type(builder.schema()).unwrap(),
+            types::i32(false)
+        );
+        let Some(RexType::WindowFunction(func)) = &lag.rex_type else {
+            panic!("Expected a window function");
+        };
+        assert_eq!(func.bounds_type, BoundsType::Rows as i32);
+        assert_eq!(func.lower_bound, Some(WindowBound::Preceding(2).into()));
+        assert_eq!(func.upper_bound, Some(WindowBound::CurrentRow.into()));
+
+        // NTH_VALUE(x, 2) OVER (ORDER BY x) with options
+        let nth_value = builder
+            .functions()
+            .nth_value(x.clone(), literal(2_i32))
+            .order_by(x.clone(), SortDirection::AscNullsFirst)
+            .with_options([("on_domain_error", "NAN"), ("on_domain_error", "ERROR")])
+            .build()
+            .unwrap();
+        let Some(RexType::WindowFunction(func)) = &nth_value.rex_type else {
+            panic!("Expected a window function");
+        };
+        assert_eq!(
+            func.options,
+            vec![FunctionOption {
+                name: "on_domain_error".to_string(),
+                preference: vec!["NAN".to_string(), "ERROR".to_string()],
+            }]
+        );
+        assert!(builder
+            .functions()
+            .nth_value(x.clone(), literal(2_i32))
+            .with_options([("on_domain_error", "SILENT")])
+            .build()
+            .is_err());
+
+        // A range offset needs a single sort key
+        assert!(builder
+            .functions()
+            .lag(x.clone())
+            .range(WindowBound::Preceding(2), WindowBound::CurrentRow)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_in_list() {
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(false))
+            .field("y", types::i32(true))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let y = builder.fields().resolve_by_name("y").unwrap();
+
+        // x IN (1, 2, 3)
+        let in_list = builder
+            .functions()
+            .in_list(
+                x.clone(),
+                vec![literal(1_i32), literal(2_i32), literal(3_i32)],
+            )
+            .unwrap();
+        assert_eq!(
+            in_list.output_type(builder.schema()).unwrap(),
+            types::bool(false)
+        );
+        // A nullable option makes the result nullable
+        let nullable = builder
+            .functions()
+            .in_list(x.clone(), vec![literal(1_i32), y])
+            .unwrap();
+        assert_eq!(
+            nullable.output_type(builder.schema()).unwrap(),
+            types::bool(true)
+        );
+
+        assert!(builder
+            .functions()
+            .in_list(x.clone(), vec![literal(1_i32), literal("hello")])
+            .is_err());
+        assert!(builder.functions().in_list(x, vec![]).is_err());
+    }
+
+    #[test]
+    fn test_make_struct() {
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(false))
+            .field("y", types::string(true))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let y = builder.fields().resolve_by_name("y").unwrap();
+
+        let strct = builder
+            .functions()
+            .make_struct(vec![x, y, literal(1.0_f64)])
+            .unwrap();
+        let output_type = strct.output_type(builder.schema()).unwrap();
+        assert_eq!(
+            output_type,
+            types::struct_(
+                false,
+                vec![types::i32(false), types::string(true), types::fp64(false)]
+            )
+        );
+        assert_eq!(output_type.num_types(), 4);
+
+        assert!(builder.add_expression("point", strct.clone()).is_err());
+        let names = ["point", "x", "y", "z"].map(String::from).to_vec();
+        assert!(builder.add_expression(names, strct).is_ok());
+
+        assert!(builder.functions().make_struct(vec![]).is_err());
+    }
+
+    #[test]
+    fn test_build_comparisons() {
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(false))
+            .field("y", types::i32(true))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let y = builder.fields().resolve_by_name("y").unwrap();
+        let functions = builder.functions();
+
+        let lt = functions.build_lt(x.clone(), literal(3_i32)).unwrap();
+        assert_eq!(lt, functions.lt(x.clone(), literal(3_i32)).build().unwrap());
+        assert_eq!(
+            lt.output_type(builder.schema()).unwrap(),
+            types::bool(false)
+        );
+        let comparisons = [
+            functions.build_eq(x.clone(), y.clone()).unwrap(),
+            functions.build_ne(x.clone(), y.clone()).unwrap(),
+            functions.build_lte(x.clone(), y.clone()).unwrap(),
+            functions.build_gt(x.clone(), y.clone()).unwrap(),
+            functions.build_gte(x.clone(), y).unwrap(),
+        ];
+        for comparison in comparisons {
+            assert_eq!(
+                comparison.output_type(builder.schema()).unwrap(),
+                types::bool(true)
+            );
+        }
+        assert!(functions.build_eq(x, literal("hello")).is_err());
+    }
+}
@@ -0,0 +1,224 @@
+//! # Build expressions from reverse-Polish (postfix) token streams
+//!
+//! This is a lightweight alternative to a full parser for calculator-style front ends.
+//! Operands are pushed onto a stack and each operator pops its arguments and pushes
+//! the resulting function call.
+//!
+//! ```
+//! use substrait_expr::builder::rpn::{parse_rpn, Operator, Token};
+//! use substrait_expr::builder::schema::SchemaBuildersExt;
+//! use substrait_expr::builder::BuilderParams;
+//! use substrait_expr::helpers::literals::literal;
+//! use substrait_expr::helpers::schema::SchemaInfo;
+//! use substrait_expr::helpers::types;
+//!
+//! let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+//! // x 3 +
+//! let tokens = vec![
+//!     Token::Field("x".to_string()),
+//!     Token::Literal(literal(3_i32)),
+//!     Token::Operator(Operator::Add),
+//! ];
+//! let expr = parse_rpn(&tokens, &schema, &BuilderParams::default()).unwrap();
+//! ```
+
+use std::str::FromStr;
+
+use substrait::proto::Expression;
+
+use crate::error::{Result, SubstraitExprError};
+use crate::functions::{functions_arithmetic, functions_comparison};
+use crate::helpers::schema::SchemaInfo;
+
+use super::functions::{FunctionDefinition, FunctionsBuilder};
+use super::schema::RefBuilder;
+use super::BuilderParams;
+
+/// A binary operator that can appear in a token stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Equal,
+    NotEqual,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+}
+
+impl Operator {
+    /// The standard function that this operator maps to
+    pub fn function(&self) -> &'static FunctionDefinition {
+        match self {
+            Operator::Add => &functions_arithmetic::ADD,
+            Operator::Subtract => &functions_arithmetic::SUBTRACT,
+            Operator::Multiply => &functions_arithmetic::MULTIPLY,
+            Operator::Divide => &functions_arithmetic::DIVIDE,
+            Operator::Equal => &functions_comparison::EQUAL,
+            Operator::NotEqual => &functions_comparison::NOT_EQUAL,
+            Operator::Lt => &functions_comparison::LT,
+            Operator::Lte => &functions_comparison::LTE,
+            Operator::Gt => &functions_comparison::GT,
+            Operator::Gte => &functions_comparison::GTE,
+        }
+    }
+}
+
+impl FromStr for Operator {
+    type Err = SubstraitExprError;
+
+    /// Parses an operator from its symbol (e.g. `+` or `<=`)
+    fn from_str(symbol: &str) -> Result<Self> {
+        match symbol {
+            "+" => Ok(Operator::Add),
+            "-" => Ok(Operator::Subtract),
+            "*" => Ok(Operator::Multiply),
+            "/" => Ok(Operator::Divide),
+            "=" => Ok(Operator::Equal),
+            "!=" => Ok(Operator::NotEqual),
+            "<" => Ok(Operator::Lt),
+            "<=" => Ok(Operator::Lte),
+            ">" => Ok(Operator::Gt),
+            ">=" => Ok(Operator::Gte),
+            _ => Err(SubstraitExprError::invalid_input(format!(
+                "Unrecognized operator {}",
+                symbol
+            ))),
+        }
+    }
+}
+
+/// A single token in a reverse-Polish token stream
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    /// A field reference, using the same syntax as
+    /// [`resolve_by_name`](crate::builder::schema::RefBuilder::resolve_by_name)
+    Field(String),
+    /// A literal (or any other already-built expression)
+    Literal(Expression),
+    /// An operator that pops two operands and pushes the result
+    Operator(Operator),
+}
+
+/// Builds an expression from a reverse-Polish token stream
+///
+/// Returns an error if an operator does not have enough operands or if the
+/// token stream does not reduce to exactly one expression.
+pub fn parse_rpn(
+    tokens: &[Token],
+    schema: &SchemaInfo,
+    params: &BuilderParams,
+) -> Result<Expression> {
+    let functions = FunctionsBuilder::new_with_params(schema, params);
+    let fields = RefBuilder::new(
+        schema,
+        params,
+        FunctionsBuilder::new_with_params(schema, params),
+    );
+    let mut stack = Vec::new();
+    for (position, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Field(name) => stack.push(fields.resolve_by_name(name)?),
+            Token::Literal(expr) => stack.push(expr.clone()),
+            Token::Operator(operator) => {
+                if stack.len() < 2 {
+                    return Err(SubstraitExprError::invalid_input(format!(
+                        "Stack underflow: the operator {:?} at position {} needs 2 operands but only {} are available",
+                        operator,
+                        position,
+                        stack.len()
+                    )));
+                }
+                let rhs = stack.pop().unwrap();
+                let lhs = stack.pop().unwrap();
+                stack.push(
+                    functions
+                        .new_builder(operator.function(), vec![lhs, rhs])
+                        .build()?,
+                );
+            }
+        }
+    }
+    if stack.len() != 1 {
+        return Err(SubstraitExprError::invalid_input(format!(
+            "The token stream should reduce to a single expression but {} expressions remain",
+            stack.len()
+        )));
+    }
+    Ok(stack.pop().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::schema::SchemaBuildersExt;
+    use crate::functions::functions_arithmetic::FunctionsArithmeticExt;
+    use crate::helpers::expr::ExpressionExt;
+    use crate::helpers::literals::literal;
+    use crate::helpers::types;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_rpn() {
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let params = BuilderParams::default();
+        let tokens = vec![
+            Token::Field("x".to_string()),
+            Token::Literal(literal(3_i32)),
+            Token::Operator("+".parse().unwrap()),
+        ];
+        let parsed = parse_rpn(&tokens, &schema, &params).unwrap();
+
+        let functions = FunctionsBuilder::new(&schema);
+        let fields = RefBuilder::new(&schema, &params, FunctionsBuilder::new(&schema));
+        let expected = functions
+            .add(fields.resolve_by_name("x").unwrap(), literal(3_i32))
+            .build()
+            .unwrap();
+        assert_eq!(parsed, expected);
+    }
+
+    #[test]
+    fn test_parse_rpn_stack_errors() {
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let params = BuilderParams::default();
+        // x +
+        let underflow = vec![
+            Token::Field("x".to_string()),
+            Token::Operator(Operator::Add),
+        ];
+        assert!(parse_rpn(&underflow, &schema, &params).is_err());
+        // x 3
+        let leftover = vec![
+            Token::Field("x".to_string()),
+            Token::Literal(literal(3_i32)),
+        ];
+        assert!(parse_rpn(&leftover, &schema, &params).is_err());
+        assert!(parse_rpn(&[], &schema, &params).is_err());
+    }
+
+    #[test]
+    fn test_parse_rpn_params() {
+        let schema = SchemaInfo::new_full()
+            .field("a", types::i32(false))
+            .field("b", types::i64(false))
+            .build();
+        // a b +
+        let tokens = vec![
+            Token::Field("a".to_string()),
+            Token::Field("b".to_string()),
+            Token::Operator(Operator::Add),
+        ];
+        assert!(parse_rpn(&tokens, &schema, &BuilderParams::default()).is_err());
+
+        let params = BuilderParams {
+            allow_type_promotion: true,
+            ..Default::default()
+        };
+        let parsed = parse_rpn(&tokens, &schema, &params).unwrap();
+        assert_eq!(parsed.output_type(&schema).unwrap(), types::i64(false));
+    }
+}
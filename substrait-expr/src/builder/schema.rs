@@ -1,22 +1,25 @@
 use std::iter::Peekable;
 use std::str::Chars;
 
-use substrait::proto::expression::field_reference::{RootReference, RootType};
+use substrait::proto::expression::field_reference::{OuterReference, RootReference, RootType};
+use substrait::proto::expression::mask_expression::{select, Select, StructItem, StructSelect};
 use substrait::proto::expression::reference_segment::{
     ListElement, MapKey, ReferenceType, StructField,
 };
-use substrait::proto::expression::{FieldReference, ReferenceSegment, RexType};
+use substrait::proto::expression::{FieldReference, MaskExpression, ReferenceSegment, RexType};
 use substrait::proto::r#type::{Kind, Struct, UserDefined};
 use substrait::proto::{Expression, Type};
 
 use crate::error::{Result, SubstraitExprError};
 use crate::helpers::expr::ExpressionExt;
-use crate::helpers::literals::literal;
+use crate::helpers::literals::{literal, LiteralInference};
 use crate::helpers::registry::ExtensionsRegistry;
 use crate::helpers::schema::{
     FullSchema, FullSchemaNode, NamesOnlySchema, NamesOnlySchemaNode, SchemaInfo, TypesOnlySchema,
 };
-use crate::helpers::types::{nullability, NO_VARIATION, UNKNOWN_TYPE_NAME, UNKNOWN_TYPE_URI};
+use crate::helpers::types::{
+    nullability, set_variation, TypeExt, NO_VARIATION, UNKNOWN_TYPE_NAME, UNKNOWN_TYPE_URI,
+};
 
 use super::functions::FunctionsBuilder;
 use super::BuilderParams;
@@ -82,6 +85,30 @@ impl<'a> TypeBuilder<'a> {
         let type_reference = self.registry.register_type(uri.into(), name.as_ref());
         UserDefinedTypeBuilder { type_reference }
     }
+
+    /// Attaches a type variation to a type
+    ///
+    /// The variation is registered with the schema's registry.  This works for both simple
+    /// types and user defined types.
+    ///
+    /// ```
+    /// use substrait_expr::builder::schema::SchemaBuildersExt;
+    /// use substrait_expr::helpers::schema::SchemaInfo;
+    /// use substrait_expr::helpers::types;
+    ///
+    /// // Substrait has no unsigned types so a u8 column is an i8 with a "u8" variation
+    /// let builder = SchemaInfo::new_types();
+    /// let u8_type = builder
+    ///     .types()
+    ///     .with_variation(types::i8(false), "https://example.com/variations", "u8");
+    /// let schema = builder.field(u8_type).build();
+    /// ```
+    pub fn with_variation(&self, typ: Type, uri: impl Into<String>, name: impl AsRef<str>) -> Type {
+        let mut typ = typ;
+        let variation = self.registry.register_variation(uri.into(), name.as_ref());
+        set_variation(&mut typ, variation);
+        typ
+    }
 }
 
 /// A builder for creating a types-only schema
@@ -333,10 +360,28 @@ pub trait ReferenceBuilder {
     /// Can also be called again to reference a nested field with the chosen
     /// field
     fn field(&mut self, name: &str) -> Result<&mut dyn ReferenceBuilder>;
+    /// References a field within the schema by its position
+    ///
+    /// This is the only way to navigate a schema that does not know the
+    /// field names.  Like [`field`](ReferenceBuilder::field) it can be
+    /// called again to reference a nested field.
+    fn field_index(&mut self, idx: u32) -> Result<&mut dyn ReferenceBuilder>;
     /// Assuming the current node is a list column this will reference
     /// the idx'th item in the list.  If the list doesn't have enough
     /// items then NULL is returned (TODO: does substrait mandate this?)
+    ///
+    /// `idx` must be at most 2^31 - 1 (the largest offset Substrait can
+    /// represent).
     fn list_item(&mut self, idx: u32) -> Result<&mut dyn ReferenceBuilder>;
+    /// Assuming the current node is a list column this will reference
+    /// the idx'th item from the end of the list (`list_item_from_end(1)`
+    /// is the last item).
+    ///
+    /// This is encoded as a negative list offset.  The Substrait spec only
+    /// describes offsets as zero-indexed positions so negative offsets are
+    /// a convention that consumers may not honour.  `idx` must be between
+    /// 1 and 2^31 (inclusive).
+    fn list_item_from_end(&mut self, idx: u32) -> Result<&mut dyn ReferenceBuilder>;
     /// Assuming the current node is a map column this will reference
     /// an item in the map with the given key.  If the map doesn't have
     /// an item matching this key then NULL is returned.
@@ -347,6 +392,98 @@ pub trait ReferenceBuilder {
     fn build(&mut self) -> Result<Expression>;
 }
 
+/// Combines a path of reference segments into a field reference expression
+fn build_reference(parts: &[ReferenceSegment]) -> Result<Expression> {
+    let root_segment = parts
+        .iter()
+        .rev()
+        .cloned()
+        .reduce(|acc, mut el| {
+            match el.reference_type.as_mut().unwrap() {
+                ReferenceType::StructField(struct_field) => {
+                    struct_field.child = Some(Box::new(acc));
+                }
+                ReferenceType::ListElement(list_elem) => {
+                    list_elem.child = Some(Box::new(acc));
+                }
+                ReferenceType::MapKey(map_key) => {
+                    map_key.child = Some(Box::new(acc));
+                }
+            };
+            el
+        })
+        .ok_or_else(|| {
+            SubstraitExprError::invalid_input("Attempt to create an empty field reference")
+        })?;
+    Ok(Expression {
+        rex_type: Some(RexType::Selection(Box::new(FieldReference {
+            reference_type: Some(
+                substrait::proto::expression::field_reference::ReferenceType::DirectReference(
+                    root_segment,
+                ),
+            ),
+            root_type: Some(RootType::RootReference(RootReference {})),
+        }))),
+    })
+}
+
+fn struct_field_segment(field_index: u32) -> ReferenceSegment {
+    ReferenceSegment {
+        reference_type: Some(ReferenceType::StructField(Box::new(StructField {
+            field: field_index as i32,
+            child: None,
+        }))),
+    }
+}
+
+fn list_element_segment(index: u32) -> Result<ReferenceSegment> {
+    let offset = i32::try_from(index).map_err(|_| {
+        SubstraitExprError::InvalidInput(format!(
+            "Invalid list index {} (the largest list index is {})",
+            index,
+            i32::MAX
+        ))
+    })?;
+    Ok(ReferenceSegment {
+        reference_type: Some(ReferenceType::ListElement(Box::new(ListElement {
+            offset,
+            child: None,
+        }))),
+    })
+}
+
+fn list_element_from_end_segment(index: u32) -> Result<ReferenceSegment> {
+    if index == 0 || index > i32::MAX as u32 + 1 {
+        return Err(SubstraitExprError::InvalidInput(format!(
+            "Invalid list index -{} (-1 is the last item and the smallest index is -{})",
+            index,
+            i32::MAX as u32 + 1
+        )));
+    }
+    Ok(ReferenceSegment {
+        reference_type: Some(ReferenceType::ListElement(Box::new(ListElement {
+            offset: (-(index as i64)) as i32,
+            child: None,
+        }))),
+    })
+}
+
+fn map_key_segment(key: Expression) -> Result<ReferenceSegment> {
+    Ok(ReferenceSegment {
+        reference_type: Some(ReferenceType::MapKey(Box::new(MapKey {
+            map_key: Some(key.try_as_literal()?.clone()),
+            child: None,
+        }))),
+    })
+}
+
+fn index_out_of_bounds(idx: u32, num_children: usize, path: &str) -> SubstraitExprError {
+    SubstraitExprError::InvalidInput(format!(
+        "field index {} does not exist at {} (there are only {} children)",
+        idx, path, num_children
+    ))
+}
+
 struct AlwaysFaillingReferenceBuilder {
     reason: String,
 }
@@ -356,10 +493,18 @@ impl ReferenceBuilder for AlwaysFaillingReferenceBuilder {
         Err(SubstraitExprError::InvalidInput(self.reason.clone()))
     }
 
+    fn field_index(&mut self, _: u32) -> Result<&mut dyn ReferenceBuilder> {
+        Err(SubstraitExprError::InvalidInput(self.reason.clone()))
+    }
+
     fn list_item(&mut self, _: u32) -> Result<&mut dyn ReferenceBuilder> {
         Err(SubstraitExprError::InvalidInput(self.reason.clone()))
     }
 
+    fn list_item_from_end(&mut self, _: u32) -> Result<&mut dyn ReferenceBuilder> {
+        Err(SubstraitExprError::InvalidInput(self.reason.clone()))
+    }
+
     fn map_item(&mut self, _: Expression) -> Result<&mut dyn ReferenceBuilder> {
         Err(SubstraitExprError::InvalidInput(self.reason.clone()))
     }
@@ -369,18 +514,54 @@ impl ReferenceBuilder for AlwaysFaillingReferenceBuilder {
     }
 }
 
+/// Finds the index of the child named `name`
+///
+/// If `case_insensitive` is true then names are compared ignoring ASCII case and it is an
+/// error if more than one child matches.
+fn find_child_index<'c>(
+    mut child_names: impl Iterator<Item = &'c str>,
+    name: &str,
+    case_insensitive: bool,
+    cur_path: &str,
+) -> Result<usize> {
+    let field_index = if case_insensitive {
+        let mut matches = child_names
+            .enumerate()
+            .filter(|(_, child_name)| child_name.eq_ignore_ascii_case(name))
+            .map(|(idx, _)| idx);
+        let first = matches.next();
+        if first.is_some() && matches.next().is_some() {
+            return Err(SubstraitExprError::InvalidInput(format!(
+                "field {} is ambiguous at {} (more than one child matches when ignoring case)",
+                name, cur_path
+            )));
+        }
+        first
+    } else {
+        child_names.position(|child_name| child_name == name)
+    };
+    field_index.ok_or_else(|| {
+        SubstraitExprError::InvalidInput(format!(
+            "field {} does not exist at {} (no matching child)",
+            name, cur_path
+        ))
+    })
+}
+
 struct FullSchemaReferenceBuilder<'a> {
     cur_children: &'a Vec<FullSchemaNode>,
     parts: Vec<ReferenceSegment>,
     cur_path: String,
+    case_insensitive: bool,
 }
 
 impl<'a> FullSchemaReferenceBuilder<'a> {
-    fn new(schema: &'a FullSchema) -> Self {
+    fn new(schema: &'a FullSchema, case_insensitive: bool) -> Self {
         Self {
             cur_children: &schema.root.children,
             parts: Vec::new(),
             cur_path: String::new(),
+            case_insensitive,
         }
     }
 }
@@ -388,82 +569,46 @@ impl<'a> FullSchemaReferenceBuilder<'a> {
 // TODO: This is identical to the one used for the names schema.  Combine them somehow for DRY
 impl<'a> ReferenceBuilder for FullSchemaReferenceBuilder<'a> {
     fn field(&mut self, name: &str) -> Result<&mut dyn ReferenceBuilder> {
-        let name = name.to_string();
-        let field_index = self
+        let field_index = find_child_index(
+            self.cur_children.iter().map(|child| child.name.as_str()),
+            name,
+            self.case_insensitive,
+            &self.cur_path,
+        )?;
+        self.cur_path.push_str(&self.cur_children[field_index].name);
+        self.cur_children = &self.cur_children[field_index].children;
+        self.parts.push(struct_field_segment(field_index as u32));
+        Ok(self)
+    }
+
+    fn field_index(&mut self, idx: u32) -> Result<&mut dyn ReferenceBuilder> {
+        let child = self
             .cur_children
-            .iter()
-            .position(|child| child.name == name);
-        if let Some(field_index) = field_index {
-            self.cur_path.push_str(&name);
-            self.cur_children = &self.cur_children[field_index].children;
-            self.parts.push(ReferenceSegment {
-                reference_type: Some(ReferenceType::StructField(Box::new(StructField {
-                    field: field_index as i32,
-                    child: None,
-                }))),
-            });
-            Ok(self)
-        } else {
-            Err(SubstraitExprError::InvalidInput(format!(
-                "field {} does not exist at {} (no matching child)",
-                name, self.cur_path
-            )))
-        }
+            .get(idx as usize)
+            .ok_or_else(|| index_out_of_bounds(idx, self.cur_children.len(), &self.cur_path))?;
+        self.cur_path.push_str(&child.name);
+        self.cur_children = &child.children;
+        self.parts.push(struct_field_segment(idx));
+        Ok(self)
     }
 
     fn list_item(&mut self, index: u32) -> Result<&mut dyn ReferenceBuilder> {
-        self.parts.push(ReferenceSegment {
-            reference_type: Some(ReferenceType::ListElement(Box::new(ListElement {
-                offset: index as i32,
-                child: None,
-            }))),
-        });
+        self.parts.push(list_element_segment(index)?);
+        Ok(self)
+    }
+
+    fn list_item_from_end(&mut self, index: u32) -> Result<&mut dyn ReferenceBuilder> {
+        self.parts.push(list_element_from_end_segment(index)?);
         Ok(self)
     }
 
     fn map_item(&mut self, key: Expression) -> Result<&mut dyn ReferenceBuilder> {
-        self.parts.push(ReferenceSegment {
-            reference_type: Some(ReferenceType::MapKey(Box::new(MapKey {
-                map_key: Some(key.try_as_literal()?.clone()),
-                child: None,
-            }))),
-        });
+        self.parts.push(map_key_segment(key)?);
         Ok(self)
     }
 
     fn build(&mut self) -> Result<Expression> {
-        let root_segment = self
-            .parts
-            .iter()
-            .rev()
-            .cloned()
-            .reduce(|acc, mut el| {
-                match el.reference_type.as_mut().unwrap() {
-                    ReferenceType::StructField(struct_field) => {
-                        struct_field.child = Some(Box::new(acc));
-                    }
-                    ReferenceType::ListElement(list_elem) => {
-                        list_elem.child = Some(Box::new(acc));
-                    }
-                    ReferenceType::MapKey(map_key) => {
-                        map_key.child = Some(Box::new(acc));
-                    }
-                };
-                el
-            })
-            .ok_or_else(|| {
-                SubstraitExprError::invalid_input("Attempt to create an empty field reference")
-            })?;
-        Ok(Expression {
-            rex_type: Some(RexType::Selection(Box::new(FieldReference {
-                reference_type: Some(
-                    substrait::proto::expression::field_reference::ReferenceType::DirectReference(
-                        root_segment,
-                    ),
-                ),
-                root_type: Some(RootType::RootReference(RootReference {})),
-            }))),
-        })
+        build_reference(&self.parts)
     }
 }
 
@@ -471,102 +616,158 @@ struct NamesOnlyReferenceBuilder<'a> {
     cur_children: &'a Vec<NamesOnlySchemaNode>,
     parts: Vec<ReferenceSegment>,
     cur_path: String,
+    case_insensitive: bool,
 }
 
 impl<'a> NamesOnlyReferenceBuilder<'a> {
-    fn new(schema: &'a NamesOnlySchema) -> Self {
+    fn new(schema: &'a NamesOnlySchema, case_insensitive: bool) -> Self {
         Self {
             cur_children: &schema.root.children,
             parts: Vec::new(),
             cur_path: String::new(),
+            case_insensitive,
         }
     }
 }
 
 impl<'a> ReferenceBuilder for NamesOnlyReferenceBuilder<'a> {
     fn field(&mut self, name: &str) -> Result<&mut dyn ReferenceBuilder> {
-        let name = name.to_string();
-        let field_index = self
+        let field_index = find_child_index(
+            self.cur_children.iter().map(|child| child.name.as_str()),
+            name,
+            self.case_insensitive,
+            &self.cur_path,
+        )?;
+        self.cur_path.push_str(&self.cur_children[field_index].name);
+        self.cur_children = &self.cur_children[field_index].children;
+        self.parts.push(struct_field_segment(field_index as u32));
+        Ok(self)
+    }
+
+    fn field_index(&mut self, idx: u32) -> Result<&mut dyn ReferenceBuilder> {
+        let child = self
             .cur_children
-            .iter()
-            .position(|child| child.name == name);
-        if let Some(field_index) = field_index {
-            self.cur_path.push_str(&name);
-            self.cur_children = &self.cur_children[field_index].children;
-            self.parts.push(ReferenceSegment {
-                reference_type: Some(ReferenceType::StructField(Box::new(StructField {
-                    field: field_index as i32,
-                    child: None,
-                }))),
-            });
-            Ok(self)
-        } else {
-            Err(SubstraitExprError::InvalidInput(format!(
-                "field {} does not exist at {} (no matching child)",
-                name, self.cur_path
-            )))
+            .get(idx as usize)
+            .ok_or_else(|| index_out_of_bounds(idx, self.cur_children.len(), &self.cur_path))?;
+        self.cur_path.push_str(&child.name);
+        self.cur_children = &child.children;
+        self.parts.push(struct_field_segment(idx));
+        Ok(self)
+    }
+
+    fn list_item(&mut self, index: u32) -> Result<&mut dyn ReferenceBuilder> {
+        self.parts.push(list_element_segment(index)?);
+        Ok(self)
+    }
+
+    fn list_item_from_end(&mut self, index: u32) -> Result<&mut dyn ReferenceBuilder> {
+        self.parts.push(list_element_from_end_segment(index)?);
+        Ok(self)
+    }
+
+    fn map_item(&mut self, key: Expression) -> Result<&mut dyn ReferenceBuilder> {
+        self.parts.push(map_key_segment(key)?);
+        Ok(self)
+    }
+
+    fn build(&mut self) -> Result<Expression> {
+        build_reference(&self.parts)
+    }
+}
+
+struct TypesOnlyReferenceBuilder<'a> {
+    cur_fields: Option<&'a [Type]>,
+    cur_type: Option<&'a Type>,
+    parts: Vec<ReferenceSegment>,
+    cur_path: String,
+}
+
+impl<'a> TypesOnlyReferenceBuilder<'a> {
+    fn new(schema: &'a TypesOnlySchema) -> Self {
+        Self {
+            cur_fields: Some(&schema.root.types),
+            cur_type: None,
+            parts: Vec::new(),
+            cur_path: String::new(),
         }
     }
 
+    fn descend(&mut self, typ: Option<&'a Type>) {
+        self.cur_type = typ;
+        self.cur_fields = match typ.and_then(|typ| typ.kind.as_ref()) {
+            Some(Kind::Struct(strct)) => Some(&strct.types),
+            _ => None,
+        };
+    }
+}
+
+impl<'a> ReferenceBuilder for TypesOnlyReferenceBuilder<'a> {
+    fn field(&mut self, name: &str) -> Result<&mut dyn ReferenceBuilder> {
+        Err(SubstraitExprError::InvalidInput(format!(
+            "Cannot reference the field {} by name because the schema does not know the names (use field_index instead)",
+            name
+        )))
+    }
+
+    fn field_index(&mut self, idx: u32) -> Result<&mut dyn ReferenceBuilder> {
+        let fields = self.cur_fields.ok_or_else(|| {
+            SubstraitExprError::InvalidInput(format!(
+                "field index {} does not exist at {} (not a struct)",
+                idx, self.cur_path
+            ))
+        })?;
+        let child = fields
+            .get(idx as usize)
+            .ok_or_else(|| index_out_of_bounds(idx, fields.len(), &self.cur_path))?;
+        self.cur_path.push_str(&format!(".{}", idx));
+        self.descend(Some(child));
+        self.parts.push(struct_field_segment(idx));
+        Ok(self)
+    }
+
     fn list_item(&mut self, index: u32) -> Result<&mut dyn ReferenceBuilder> {
-        self.parts.push(ReferenceSegment {
-            reference_type: Some(ReferenceType::ListElement(Box::new(ListElement {
-                offset: index as i32,
-                child: None,
-            }))),
-        });
+        let item_type = match self.cur_type.and_then(|typ| typ.kind.as_ref()) {
+            Some(Kind::List(list)) => list.r#type.as_deref(),
+            _ => None,
+        };
+        self.cur_path.push_str(&format!("[{}]", index));
+        self.descend(item_type);
+        self.parts.push(list_element_segment(index)?);
+        Ok(self)
+    }
+
+    fn list_item_from_end(&mut self, index: u32) -> Result<&mut dyn ReferenceBuilder> {
+        let segment = list_element_from_end_segment(index)?;
+        let item_type = match self.cur_type.and_then(|typ| typ.kind.as_ref()) {
+            Some(Kind::List(list)) => list.r#type.as_deref(),
+            _ => None,
+        };
+        self.cur_path.push_str(&format!("[-{}]", index));
+        self.descend(item_type);
+        self.parts.push(segment);
         Ok(self)
     }
 
     fn map_item(&mut self, key: Expression) -> Result<&mut dyn ReferenceBuilder> {
-        self.parts.push(ReferenceSegment {
-            reference_type: Some(ReferenceType::MapKey(Box::new(MapKey {
-                map_key: Some(key.try_as_literal()?.clone()),
-                child: None,
-            }))),
-        });
+        let value_type = match self.cur_type.and_then(|typ| typ.kind.as_ref()) {
+            Some(Kind::Map(map)) => map.value.as_deref(),
+            _ => None,
+        };
+        self.parts.push(map_key_segment(key)?);
+        self.cur_path.push_str("[?]");
+        self.descend(value_type);
         Ok(self)
     }
 
     fn build(&mut self) -> Result<Expression> {
-        let root_segment = self
-            .parts
-            .iter()
-            .rev()
-            .cloned()
-            .reduce(|acc, mut el| {
-                match el.reference_type.as_mut().unwrap() {
-                    ReferenceType::StructField(struct_field) => {
-                        struct_field.child = Some(Box::new(acc));
-                    }
-                    ReferenceType::ListElement(list_elem) => {
-                        list_elem.child = Some(Box::new(acc));
-                    }
-                    ReferenceType::MapKey(map_key) => {
-                        map_key.child = Some(Box::new(acc));
-                    }
-                };
-                el
-            })
-            .ok_or_else(|| {
-                SubstraitExprError::invalid_input("Attempt to create an empty field reference")
-            })?;
-        Ok(Expression {
-            rex_type: Some(RexType::Selection(Box::new(FieldReference {
-                reference_type: Some(
-                    substrait::proto::expression::field_reference::ReferenceType::DirectReference(
-                        root_segment,
-                    ),
-                ),
-                root_type: Some(RootType::RootReference(RootReference {})),
-            }))),
-        })
+        build_reference(&self.parts)
     }
 }
 
 enum NamedRefElement {
     Name(String),
     ListIndex(u32),
+    ListIndexFromEnd(u32),
     MapLookup(String),
 }
 
@@ -591,6 +792,61 @@ impl<'a> NamedRefIter<'a> {
     fn invalid(&self) -> SubstraitExprError {
         SubstraitExprError::InvalidInput(format!("Invalid field reference: {}", self.val))
     }
+
+    // Reads a quoted segment, the opening quote has already been consumed.  Within quotes
+    // all characters are literal and "" is an escaped quote
+    fn read_quoted(&mut self) -> Result<String> {
+        let mut part = String::new();
+        while let Some(chr) = self.chars.next() {
+            if chr == '"' {
+                if self.chars.peek() == Some(&'"') {
+                    self.chars.next();
+                    part.push('"');
+                } else {
+                    return Ok(part);
+                }
+            } else {
+                part.push(chr);
+            }
+        }
+        // "foo <-- No closing quote
+        Err(self.invalid())
+    }
+
+    // Called after the closing ] of a bracketed element
+    fn end_brackets(&mut self) -> Result<()> {
+        // E.g. if x[3].y then consume both ] and .
+        if let Some(chr) = self.chars.peek() {
+            if *chr == '.' {
+                self.chars.next();
+            } else {
+                // x[3]y is invalid
+                return Err(self.invalid());
+            }
+        }
+        self.in_brackets = false;
+        Ok(())
+    }
+
+    fn next_quoted(&mut self) -> Result<NamedRefElement> {
+        let part = self.read_quoted()?;
+        if self.in_brackets {
+            // A quoted key is always a map key, even if it looks like a number
+            if self.chars.next() != Some(']') {
+                return Err(self.invalid());
+            }
+            self.end_brackets()?;
+            return Ok(NamedRefElement::MapLookup(part));
+        }
+        match self.chars.next() {
+            None => self.exhausted = true,
+            Some('.') => {}
+            Some('[') => self.in_brackets = true,
+            // "x"y is invalid
+            Some(_) => return Err(self.invalid()),
+        }
+        Ok(NamedRefElement::Name(part))
+    }
 }
 
 impl<'a> Iterator for NamedRefIter<'a> {
@@ -601,6 +857,11 @@ impl<'a> Iterator for NamedRefIter<'a> {
             return None;
         }
         let mut part = String::new();
+        // Quotes are only special at the start of a segment
+        if self.chars.peek() == Some(&'"') {
+            self.chars.next();
+            return Some(self.next_quoted());
+        }
         if self.in_brackets {
             while let Some(chr) = self.chars.next() {
                 if chr == ']' {
@@ -608,18 +869,15 @@ impl<'a> Iterator for NamedRefIter<'a> {
                         // x[] <-- empty brackets
                         return Some(Err(self.invalid()));
                     }
-                    // E.g. if x[3].y then consume both ] and .
-                    if let Some(chr) = self.chars.peek() {
-                        if *chr == '.' {
-                            self.chars.next();
-                        } else {
-                            // x[3]y is invalid
-                            return Some(Err(self.invalid()));
-                        }
+                    if let Err(err) = self.end_brackets() {
+                        return Some(Err(err));
                     }
-                    self.in_brackets = false;
                     return if let Ok(idx) = part.parse::<u32>() {
                         Some(Ok(NamedRefElement::ListIndex(idx)))
+                    } else if let Some(Ok(idx)) =
+                        part.strip_prefix('-').map(|idx| idx.parse::<u32>())
+                    {
+                        Some(Ok(NamedRefElement::ListIndexFromEnd(idx)))
                     } else {
                         Some(Ok(NamedRefElement::MapLookup(part)))
                     };
@@ -662,6 +920,172 @@ impl<'a> Iterator for NamedRefIter<'a> {
     }
 }
 
+/// Creates a masked reference which selects a subset of the fields in the schema
+///
+/// Created with [`RefBuilder::mask`].  The output type of the reference is a struct
+/// containing only the selected fields, in the order they were selected.
+pub struct MaskBuilder<'a> {
+    schema: &'a SchemaInfo,
+    items: Vec<StructItem>,
+    maintain_singular_struct: bool,
+}
+
+impl<'a> MaskBuilder<'a> {
+    fn new(schema: &'a SchemaInfo) -> Self {
+        Self {
+            schema,
+            items: Vec::new(),
+            maintain_singular_struct: false,
+        }
+    }
+
+    /// Select the field at `index`, keeping all of its children
+    pub fn field(mut self, index: u32) -> Self {
+        self.items.push(StructItem {
+            field: index as i32,
+            child: None,
+        });
+        self
+    }
+
+    /// Select the struct field at `index`, keeping only the children selected by `build_child`
+    ///
+    /// Indices given to the child builder are indices into the struct field.
+    pub fn nested(
+        mut self,
+        index: u32,
+        build_child: impl FnOnce(MaskBuilder<'a>) -> MaskBuilder<'a>,
+    ) -> Self {
+        let child = build_child(MaskBuilder::new(self.schema));
+        self.items.push(StructItem {
+            field: index as i32,
+            child: Some(Select {
+                r#type: Some(select::Type::Struct(child.struct_select())),
+            }),
+        });
+        self
+    }
+
+    /// If false (the default) then a mask selecting a single field returns that field
+    /// instead of a struct with one field
+    ///
+    /// This only applies to the top level of the mask.
+    pub fn maintain_singular_struct(mut self, maintain_singular_struct: bool) -> Self {
+        self.maintain_singular_struct = maintain_singular_struct;
+        self
+    }
+
+    fn struct_select(self) -> StructSelect {
+        StructSelect {
+            struct_items: self.items,
+        }
+    }
+
+    /// Create the masked reference
+    ///
+    /// Returns an error if a selected field does not exist in the schema (if the schema
+    /// knows the shape of its fields)
+    pub fn build(self) -> Result<Expression> {
+        let maintain_singular_struct = self.maintain_singular_struct;
+        let schema = self.schema;
+        let reference = Expression {
+            rex_type: Some(RexType::Selection(Box::new(FieldReference {
+                reference_type: Some(
+                    substrait::proto::expression::field_reference::ReferenceType::MaskedReference(
+                        MaskExpression {
+                            select: Some(self.struct_select()),
+                            maintain_singular_struct,
+                        },
+                    ),
+                ),
+                root_type: Some(RootType::RootReference(RootReference {})),
+            }))),
+        };
+        // Checks that the selected fields exist
+        reference.output_type(schema)?;
+        Ok(reference)
+    }
+}
+
+/// Creates field references into the result of another expression
+///
+/// Created with [`RefBuilder::from_expression`].  The root expression must return a
+/// struct, for example a function call that returns several values.
+pub struct ExpressionRefBuilder<'a> {
+    schema: &'a SchemaInfo,
+    root: Expression,
+}
+
+impl<'a> ExpressionRefBuilder<'a> {
+    /// Create a reference from a path of field indices into the root expression's output
+    ///
+    /// Each index selects a field within the struct referenced by the previous index.  An
+    /// error is returned if the path does not exist in the root expression's output type
+    /// (unless that type is unknown).
+    pub fn resolve_by_index(&self, path: &[u32]) -> Result<Expression> {
+        let parts = path
+            .iter()
+            .map(|idx| struct_field_segment(*idx))
+            .collect::<Vec<_>>();
+        let mut reference = build_reference(&parts)?;
+        if let Some(RexType::Selection(selection)) = reference.rex_type.as_mut() {
+            selection.root_type = Some(RootType::Expression(Box::new(self.root.clone())));
+        }
+        // Checks that the path exists
+        reference.output_type(self.schema)?;
+        Ok(reference)
+    }
+}
+
+/// Creates field references whose root is an enclosing query (`OuterReference`)
+///
+/// Created with [`RefBuilder::outer`].  The fields are resolved against the schema of
+/// the [`RefBuilder`], which should be the schema of the enclosing query.
+pub struct OuterRefBuilder<'b, 'a> {
+    refs: &'b RefBuilder<'a>,
+    steps_out: u32,
+}
+
+impl<'b, 'a> OuterRefBuilder<'b, 'a> {
+    fn make_outer(&self, reference: Result<Expression>, what: &str) -> Result<Expression> {
+        if self.steps_out == 0 {
+            return Err(SubstraitExprError::invalid_input(
+                "An outer reference must step out at least 1 level",
+            ));
+        }
+        let mut reference = reference?;
+        match reference.rex_type.as_mut() {
+            Some(RexType::Selection(selection)) => {
+                selection.root_type = Some(RootType::OuterReference(OuterReference {
+                    steps_out: self.steps_out,
+                }));
+                Ok(reference)
+            }
+            _ => Err(SubstraitExprError::InvalidInput(format!(
+                "Cannot create an outer reference to {} because the enclosing schema does not know the names",
+                what
+            ))),
+        }
+    }
+
+    /// Create an outer reference from a "path string" (see [`RefBuilder::resolve_by_name`])
+    pub fn resolve_by_name(&self, name: &str) -> Result<Expression> {
+        self.make_outer(self.refs.resolve_by_name(name), name)
+    }
+
+    /// Create an outer reference from a path of field names (see
+    /// [`RefBuilder::resolve_by_segments`])
+    pub fn resolve_by_segments(&self, segments: &[&str]) -> Result<Expression> {
+        self.make_outer(self.refs.resolve_by_segments(segments), &segments.join("."))
+    }
+
+    /// Create an outer reference from a path of field indices (see
+    /// [`RefBuilder::resolve_by_index`])
+    pub fn resolve_by_index(&self, path: &[u32]) -> Result<Expression> {
+        self.make_outer(self.refs.resolve_by_index(path), &format!("{:?}", path))
+    }
+}
+
 /// Creates field reference expressions that reference a field in a schema
 pub struct RefBuilder<'a> {
     schema: &'a SchemaInfo,
@@ -699,21 +1123,7 @@ impl<'a> RefBuilder<'a> {
                 if !self.params.allow_unknown_types {
                     SubstraitExprError::invalid_input("Cannot reference fields when unknown types are disallowed and the schema is not type-aware");
                 }
-                let mut builder = self.field_builder();
-                for path_part in NamedRefIter::new(name) {
-                    match path_part? {
-                        NamedRefElement::Name(name) => {
-                            builder.field(&name)?;
-                        }
-                        NamedRefElement::ListIndex(idx) => {
-                            builder.list_item(idx)?;
-                        }
-                        NamedRefElement::MapLookup(map_key) => {
-                            builder.map_item(literal(map_key))?;
-                        }
-                    }
-                }
-                builder.build()
+                self.resolve_path(name)
             }
             SchemaInfo::Types(_) => {
                 if self.params.allow_late_name_lookup {
@@ -723,50 +1133,245 @@ impl<'a> RefBuilder<'a> {
                     Err(SubstraitExprError::InvalidInput(format!("Cannot lookup a field named {} because the input schema does not know the names", name)))
                 }
             }
-            SchemaInfo::Full(_) => {
-                // TODO: This is identical to the path for names, fix for DRY
-                let mut builder = self.field_builder();
-                for path_part in NamedRefIter::new(name) {
-                    match path_part? {
-                        NamedRefElement::Name(name) => {
-                            builder.field(&name)?;
-                        }
-                        NamedRefElement::ListIndex(idx) => {
-                            builder.list_item(idx)?;
+            SchemaInfo::Full(_) => self.resolve_path(name),
+        }
+    }
+
+    fn resolve_path(&self, name: &str) -> Result<Expression> {
+        let mut builder = self.field_builder();
+        for path_part in NamedRefIter::new(name) {
+            match path_part? {
+                NamedRefElement::Name(name) => {
+                    builder.field(&name)?;
+                }
+                NamedRefElement::ListIndex(idx) => match self.map_key_type(builder.as_mut())? {
+                    Some(key_type) => {
+                        builder.map_item(self.map_key(&idx.to_string(), &key_type)?)?;
+                    }
+                    None => {
+                        builder.list_item(idx)?;
+                    }
+                },
+                NamedRefElement::ListIndexFromEnd(idx) => {
+                    match self.map_key_type(builder.as_mut())? {
+                        Some(key_type) => {
+                            builder.map_item(self.map_key(&format!("-{}", idx), &key_type)?)?;
                         }
-                        NamedRefElement::MapLookup(map_key) => {
-                            builder.map_item(literal(map_key))?;
+                        None => {
+                            builder.list_item_from_end(idx)?;
                         }
                     }
                 }
-                builder.build()
+                NamedRefElement::MapLookup(map_key) => {
+                    let key = match self.map_key_type(builder.as_mut())? {
+                        Some(key_type) => self.map_key(&map_key, &key_type)?,
+                        None => literal(map_key),
+                    };
+                    builder.map_item(key)?;
+                }
             }
         }
+        builder.build()
     }
 
-    /// Create a builder that can be used to programmatically create a field reference
-    pub fn field_builder(&self) -> Box<dyn ReferenceBuilder + 'a> {
-        match &self.schema {
-            SchemaInfo::Empty(_) => todo!(),
-            SchemaInfo::Full(full) => Box::new(FullSchemaReferenceBuilder::new(full)),
-            SchemaInfo::Names(names) => {
-                if self.params.allow_unknown_types {
-                    Box::new(NamesOnlyReferenceBuilder::new(names))
-                } else {
-                    Box::new(AlwaysFaillingReferenceBuilder { reason: "Cannot create field references when unknown types are disallowed and the schema is not type-aware".to_string() })
-                }
-            }
-            SchemaInfo::Types(_) => todo!(),
+    // The key type of the map referenced so far by `builder`, if the schema knows it
+    fn map_key_type(&self, builder: &mut dyn ReferenceBuilder) -> Result<Option<Type>> {
+        // The root is a struct so an empty path (which fails to build) is never a map
+        let Ok(reference) = builder.build() else {
+            return Ok(None);
+        };
+        match reference.rex_type {
+            Some(RexType::Selection(selection)) => match selection.reference_type {
+                Some(
+                    substrait::proto::expression::field_reference::ReferenceType::DirectReference(
+                        root_segment,
+                    ),
+                ) => self.schema.resolve_map_key_type(&root_segment),
+                _ => Ok(None),
+            },
+            _ => Ok(None),
         }
     }
-}
 
-#[cfg(test)]
+    // Converts the text of a bracketed path segment into a map key of type `key_type`
+    fn map_key(&self, key: &str, key_type: &Type) -> Result<Expression> {
+        fn parse<T: std::str::FromStr + LiteralInference>(
+            key: &str,
+            key_type: &str,
+        ) -> Result<Expression> {
+            key.parse::<T>().map(literal).map_err(|_| {
+                SubstraitExprError::InvalidInput(format!(
+                    "The map key {} is not a valid {}",
+                    key, key_type
+                ))
+            })
+        }
+        let type_name = || key_type.to_human_readable(self.schema.extensions_registry());
+        match &key_type.kind {
+            Some(Kind::String(_)) | Some(Kind::Varchar(_)) | Some(Kind::FixedChar(_)) => {
+                Ok(literal(key))
+            }
+            Some(Kind::Bool(_)) => parse::<bool>(key, &type_name()),
+            Some(Kind::I8(_)) => parse::<i8>(key, &type_name()),
+            Some(Kind::I16(_)) => parse::<i16>(key, &type_name()),
+            Some(Kind::I32(_)) => parse::<i32>(key, &type_name()),
+            Some(Kind::I64(_)) => parse::<i64>(key, &type_name()),
+            Some(Kind::Fp32(_)) => parse::<f32>(key, &type_name()),
+            Some(Kind::Fp64(_)) => parse::<f64>(key, &type_name()),
+            // The key type is not known
+            _ if key_type.is_unknown(self.schema.extensions_registry()) => Ok(literal(key)),
+            _ => Err(SubstraitExprError::not_implemented(format!(
+                "map keys of type {} in a field reference path",
+                type_name()
+            ))),
+        }
+    }
+
+    /// Create a field reference from a "path string" with a known nullability
+    ///
+    /// This is useful when the schema does not know types but the caller knows whether
+    /// the field is nullable.  If the schema knows types then this is the same as
+    /// [`resolve_by_name`](Self::resolve_by_name) and the hint is ignored.  Otherwise a late
+    /// lookup (see
+    /// [`lookup_field_by_name_with_nullability`](FunctionsBuilder::lookup_field_by_name_with_nullability))
+    /// is created whose unknown type has the given nullability.  The hint is preserved when
+    /// the expression is [bound](crate::binding::bind) to a schema that does not know names.
+    pub fn resolve_by_name_with_nullability(
+        &self,
+        name: &str,
+        nullable: bool,
+    ) -> Result<Expression> {
+        // Resolve the name first so that missing fields are reported
+        let resolved = self.resolve_by_name(name)?;
+        match &self.schema {
+            SchemaInfo::Full(_) => Ok(resolved),
+            _ => Ok(self
+                .functions
+                .lookup_field_by_name_with_nullability(name, nullable)),
+        }
+    }
+
+    /// Create a field reference from a path that has already been split into field names
+    ///
+    /// Each segment is treated as a field name, as-is, so names containing `.` or `[` do
+    /// not need to be escaped.  For example, `resolve_by_segments(&["location", "x"])` is
+    /// the same as `resolve_by_name("location.x")`.
+    ///
+    /// If the schema does not know the names of its fields and late name lookup is allowed
+    /// then the segments are joined with `.` and looked up later.
+    pub fn resolve_by_segments(&self, segments: &[&str]) -> Result<Expression> {
+        match &self.schema {
+            SchemaInfo::Empty(_) | SchemaInfo::Types(_) => {
+                let name = segments.join(".");
+                if self.params.allow_late_name_lookup {
+                    Ok(self.functions.lookup_field_by_name(&name))
+                } else {
+                    Err(SubstraitExprError::InvalidInput(format!("Cannot lookup a field named {} because the input schema does not know the names", name)))
+                }
+            }
+            SchemaInfo::Names(_) | SchemaInfo::Full(_) => {
+                let mut builder = self.field_builder();
+                for segment in segments {
+                    builder.field(segment)?;
+                }
+                builder.build()
+            }
+        }
+    }
+
+    /// Create a reference to a field in an enclosing query
+    ///
+    /// This is the same as `self.outer(steps_out).resolve_by_name(name)`, see
+    /// [`outer`](Self::outer)
+    pub fn resolve_outer_by_name(&self, name: &str, steps_out: u32) -> Result<Expression> {
+        self.outer(steps_out).resolve_by_name(name)
+    }
+
+    /// Create a builder for references into the result of another expression
+    ///
+    /// The expression is evaluated against this builder's schema.
+    pub fn from_expression(&self, expr: Expression) -> ExpressionRefBuilder<'a> {
+        ExpressionRefBuilder {
+            schema: self.schema,
+            root: expr,
+        }
+    }
+
+    /// Create a builder for references to fields in an enclosing query (i.e. correlated
+    /// references from a subquery)
+    ///
+    /// This builder's schema must be the schema of the enclosing query and `steps_out`
+    /// is the number of subquery boundaries between the expression and that query (it
+    /// must be at least 1).  The type of the reference can be determined with
+    /// [`output_type_in_stack`](crate::helpers::expr::ExpressionExt::output_type_in_stack).
+    pub fn outer<'b>(&'b self, steps_out: u32) -> OuterRefBuilder<'b, 'a> {
+        OuterRefBuilder {
+            refs: self,
+            steps_out,
+        }
+    }
+
+    /// Create a builder for a masked reference that selects a subset of the schema's fields
+    pub fn mask(&self) -> MaskBuilder<'a> {
+        MaskBuilder::new(self.schema)
+    }
+
+    /// Create a field reference from a path of field indices
+    ///
+    /// Each index selects a field within the struct referenced by the previous index
+    /// (e.g. `[1, 0]` is the first child of the second field).  If the schema knows the
+    /// shape of its fields then each index is checked and an error is returned if it is
+    /// out of bounds.
+    pub fn resolve_by_index(&self, path: &[u32]) -> Result<Expression> {
+        if let SchemaInfo::Empty(_) = &self.schema {
+            // Nothing to validate against
+            let parts = path
+                .iter()
+                .map(|idx| struct_field_segment(*idx))
+                .collect::<Vec<_>>();
+            return build_reference(&parts);
+        }
+        let mut builder = self.field_builder();
+        for idx in path {
+            builder.field_index(*idx)?;
+        }
+        builder.build()
+    }
+
+    /// Create a builder that can be used to programmatically create a field reference
+    pub fn field_builder(&self) -> Box<dyn ReferenceBuilder + 'a> {
+        match &self.schema {
+            SchemaInfo::Empty(_) => Box::new(AlwaysFaillingReferenceBuilder {
+                reason: "Cannot create field references against an empty schema".to_string(),
+            }),
+            SchemaInfo::Full(full) => Box::new(FullSchemaReferenceBuilder::new(
+                full,
+                self.params.case_insensitive_names,
+            )),
+            SchemaInfo::Names(names) => {
+                if self.params.allow_unknown_types {
+                    Box::new(NamesOnlyReferenceBuilder::new(
+                        names,
+                        self.params.case_insensitive_names,
+                    ))
+                } else {
+                    Box::new(AlwaysFaillingReferenceBuilder { reason: "Cannot create field references when unknown types are disallowed and the schema is not type-aware".to_string() })
+                }
+            }
+            SchemaInfo::Types(types) => Box::new(TypesOnlyReferenceBuilder::new(types)),
+        }
+    }
+}
+
+#[cfg(test)]
 mod tests {
+    use crate::helpers::schema::SchemaStack;
+    use crate::helpers::types::TypeExt;
     use crate::{self as substrait_expr, helpers::types};
     use substrait_expr_macros::names_schema;
 
     use super::*;
+    use crate::builder::ExpressionsBuilder;
 
     #[test]
     fn test_literals() {
@@ -837,6 +1442,322 @@ mod tests {
         assert_eq!(by_name, by_builder);
     }
 
+    #[test]
+    fn test_case_insensitive_names() {
+        let schema = SchemaInfo::new_full()
+            .field("Score", types::i32(false))
+            .nested("location", false, |builder| {
+                builder
+                    .field("x", types::fp64(false))
+                    .field("X", types::fp64(false))
+                    .field("y", types::fp64(false))
+            })
+            .build();
+        let params = BuilderParams {
+            case_insensitive_names: true,
+            ..Default::default()
+        };
+        let ref_builder = RefBuilder::new(&schema, &params, FunctionsBuilder::new(&schema));
+        assert_eq!(
+            ref_builder.resolve_by_name("SCORE").unwrap(),
+            ref_builder.resolve_by_index(&[0]).unwrap()
+        );
+        assert_eq!(
+            ref_builder.resolve_by_name("LOCATION.Y").unwrap(),
+            ref_builder.resolve_by_index(&[1, 2]).unwrap()
+        );
+        // Ambiguous names are rejected
+        assert!(ref_builder.resolve_by_name("location.x").is_err());
+        assert!(ref_builder.resolve_by_name("missing").is_err());
+
+        // The default is case-sensitive
+        let params = BuilderParams::default();
+        let ref_builder = RefBuilder::new(&schema, &params, FunctionsBuilder::new(&schema));
+        assert!(ref_builder.resolve_by_name("score").is_err());
+        assert_eq!(
+            ref_builder.resolve_by_name("location.X").unwrap(),
+            ref_builder.resolve_by_index(&[1, 1]).unwrap()
+        );
+
+        let names = names_schema!({
+            Score: {},
+            location: {}
+        });
+        let params = BuilderParams {
+            allow_unknown_types: true,
+            case_insensitive_names: true,
+            ..Default::default()
+        };
+        let ref_builder = RefBuilder::new(&names, &params, FunctionsBuilder::new(&names));
+        assert_eq!(
+            ref_builder.resolve_by_name("score").unwrap(),
+            ref_builder.resolve_by_name("Score").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_quoted_names() {
+        let schema = SchemaInfo::new_full()
+            .nested("first.last", false, |builder| {
+                builder.field("value", types::i32(false))
+            })
+            .field("a[b]", types::list(false, types::string(false)))
+            .field("say \"hi\"", types::i32(false))
+            .field(
+                "attrs",
+                types::map(false, types::string(false), types::i32(false)),
+            )
+            .build();
+        let params = BuilderParams::default();
+        let ref_builder = RefBuilder::new(&schema, &params, FunctionsBuilder::new(&schema));
+
+        assert_eq!(
+            ref_builder.resolve_by_name("\"first.last\".value").unwrap(),
+            ref_builder.resolve_by_index(&[0, 0]).unwrap()
+        );
+        assert_eq!(
+            ref_builder.resolve_by_name("\"a[b]\"").unwrap(),
+            ref_builder.resolve_by_index(&[1]).unwrap()
+        );
+        assert_eq!(
+            ref_builder.resolve_by_name("\"a[b]\"[2]").unwrap(),
+            ref_builder
+                .field_builder()
+                .field("a[b]")
+                .unwrap()
+                .list_item(2)
+                .unwrap()
+                .build()
+                .unwrap()
+        );
+        assert_eq!(
+            ref_builder.resolve_by_name("\"say \"\"hi\"\"\"").unwrap(),
+            ref_builder.resolve_by_index(&[2]).unwrap()
+        );
+        // Quoted keys may contain brackets and are always map keys
+        assert_eq!(
+            ref_builder.resolve_by_name("attrs[\"x]\"]").unwrap(),
+            ref_builder
+                .field_builder()
+                .field("attrs")
+                .unwrap()
+                .map_item(literal("x]"))
+                .unwrap()
+                .build()
+                .unwrap()
+        );
+
+        // Without quotes the name is split
+        assert!(ref_builder.resolve_by_name("first.last.value").is_err());
+        // Unterminated quotes and trailing characters are invalid
+        assert!(ref_builder.resolve_by_name("\"first.last.value").is_err());
+        assert!(ref_builder.resolve_by_name("\"first.last\"value").is_err());
+        assert!(ref_builder.resolve_by_name("attrs[\"x\"y]").is_err());
+    }
+
+    #[test]
+    fn test_typed_map_keys() {
+        let schema = SchemaInfo::new_full()
+            .field(
+                "by_id",
+                types::map(false, types::i32(false), types::string(true)),
+            )
+            .field(
+                "flags",
+                types::map(false, types::bool(false), types::i64(true)),
+            )
+            .field(
+                "attrs",
+                types::map(false, types::string(false), types::i32(true)),
+            )
+            .field("tags", types::list(false, types::string(true)))
+            .build();
+        let params = BuilderParams::default();
+        let ref_builder = RefBuilder::new(&schema, &params, FunctionsBuilder::new(&schema));
+
+        let map_ref = |field: &str, key: Expression| {
+            ref_builder
+                .field_builder()
+                .field(field)
+                .unwrap()
+                .map_item(key)
+                .unwrap()
+                .build()
+                .unwrap()
+        };
+        assert_eq!(
+            ref_builder.resolve_by_name("by_id[42]").unwrap(),
+            map_ref("by_id", literal(42_i32))
+        );
+        assert_eq!(
+            ref_builder.resolve_by_name("flags[true]").unwrap(),
+            map_ref("flags", literal(true))
+        );
+        // Numeric keys of a string-keyed map are strings
+        assert_eq!(
+            ref_builder.resolve_by_name("attrs[42]").unwrap(),
+            map_ref("attrs", literal("42"))
+        );
+        assert_eq!(
+            ref_builder
+                .resolve_by_name("tags[3]")
+                .unwrap()
+                .output_type(&schema)
+                .unwrap(),
+            types::string(true)
+        );
+        assert!(ref_builder.resolve_by_name("by_id[abc]").is_err());
+        assert!(ref_builder.resolve_by_name("flags[2]").is_err());
+
+        // Without types the key is a string
+        let names = names_schema!({
+            by_id: {}
+        });
+        let params = BuilderParams {
+            allow_unknown_types: true,
+            ..Default::default()
+        };
+        let ref_builder = RefBuilder::new(&names, &params, FunctionsBuilder::new(&names));
+        assert_eq!(
+            ref_builder.resolve_by_name("by_id[abc]").unwrap(),
+            ref_builder
+                .field_builder()
+                .field("by_id")
+                .unwrap()
+                .map_item(literal("abc"))
+                .unwrap()
+                .build()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_list_item_from_end() {
+        let schema = SchemaInfo::new_full()
+            .field("genres", types::list(false, types::string(false)))
+            .field(
+                "attrs",
+                types::map(false, types::i32(false), types::i32(false)),
+            )
+            .build();
+        let params = BuilderParams::default();
+        let ref_builder = RefBuilder::new(&schema, &params, FunctionsBuilder::new(&schema));
+
+        let last = ref_builder.resolve_by_name("genres[-1]").unwrap();
+        assert_eq!(
+            last,
+            ref_builder
+                .field_builder()
+                .field("genres")
+                .unwrap()
+                .list_item_from_end(1)
+                .unwrap()
+                .build()
+                .unwrap()
+        );
+        assert_eq!(last.to_human_readable(&schema).unwrap(), "genres[-1]");
+        // The list may be too short so the item is nullable
+        assert_eq!(last.output_type(&schema).unwrap(), types::string(true));
+        assert!(ref_builder.resolve_by_name("genres[-0]").is_err());
+
+        // Offsets are i32 so large indices must not wrap around to negative offsets
+        assert!(ref_builder.resolve_by_name("genres[2147483647]").is_ok());
+        assert!(ref_builder.resolve_by_name("genres[4294967295]").is_err());
+        assert!(ref_builder
+            .field_builder()
+            .field("genres")
+            .unwrap()
+            .list_item(i32::MAX as u32 + 1)
+            .is_err());
+        assert!(ref_builder.resolve_by_name("genres[-2147483648]").is_ok());
+        assert!(ref_builder.resolve_by_name("genres[-2147483649]").is_err());
+
+        // A negative key of an integer-keyed map is still a map key
+        assert_eq!(
+            ref_builder.resolve_by_name("attrs[-1]").unwrap(),
+            ref_builder
+                .field_builder()
+                .field("attrs")
+                .unwrap()
+                .map_item(literal(-1_i32))
+                .unwrap()
+                .build()
+                .unwrap()
+        );
+
+        let types_schema = SchemaInfo::new_types()
+            .field(types::list(false, types::string(false)))
+            .build();
+        let ref_builder =
+            RefBuilder::new(&types_schema, &params, FunctionsBuilder::new(&types_schema));
+        let last = ref_builder
+            .field_builder()
+            .field_index(0)
+            .unwrap()
+            .list_item_from_end(1)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(
+            last.output_type(&types_schema).unwrap(),
+            types::string(true)
+        );
+    }
+
+    #[test]
+    fn test_resolve_type_out_of_bounds() {
+        let full_schema = SchemaInfo::new_full()
+            .nested("location", false, |builder| {
+                builder.field("x", types::fp64(false))
+            })
+            .build();
+        let types_schema = SchemaInfo::new_types()
+            .nested(false, |builder| builder.field(types::fp64(false)))
+            .build();
+        let top_level = struct_field_segment(3);
+        let mut nested = struct_field_segment(0);
+        if let Some(ReferenceType::StructField(field)) = nested.reference_type.as_mut() {
+            field.child = Some(Box::new(struct_field_segment(1)));
+        }
+        for schema in [&full_schema, &types_schema] {
+            let err = schema.resolve_type(&top_level).unwrap_err();
+            assert!(err.to_string().contains("field 3 does not exist"));
+            assert!(err.to_string().contains("has 1 field)"));
+            let err = schema.resolve_type(&nested).unwrap_err();
+            assert!(err.to_string().contains("field 1 does not exist"));
+            assert!(err.to_string().contains("has 1 field)"));
+        }
+    }
+
+    #[test]
+    fn test_resolve_by_segments() {
+        let schema = SchemaInfo::new_full()
+            .field("score", types::i32(false))
+            .nested("location", false, |builder| {
+                builder
+                    .field("x", types::fp64(false))
+                    .field("a.b", types::fp64(false))
+            })
+            .build();
+        let params = BuilderParams::default();
+        let ref_builder = RefBuilder::new(&schema, &params, FunctionsBuilder::new(&schema));
+
+        assert_eq!(
+            ref_builder.resolve_by_segments(&["location", "x"]).unwrap(),
+            ref_builder.resolve_by_name("location.x").unwrap()
+        );
+        // Segments are not parsed so they may contain special characters
+        assert_eq!(
+            ref_builder
+                .resolve_by_segments(&["location", "a.b"])
+                .unwrap()
+                .output_type(&schema)
+                .unwrap(),
+            types::fp64(false)
+        );
+        assert!(ref_builder.resolve_by_segments(&["location", "z"]).is_err());
+    }
+
     #[test]
     fn test_types_builder() {
         let schema = SchemaInfo::new_types()
@@ -891,4 +1812,438 @@ mod tests {
         let expected = vec![types::i32(false), types::fp32(false), types::fp64(true)];
         assert_eq!(expected, types);
     }
+
+    #[test]
+    fn test_from_substrait() {
+        let full = || {
+            SchemaInfo::new_full()
+                .field("score", types::i32(false))
+                .nested("location", true, |builder| {
+                    builder
+                        .field("x", types::fp64(false))
+                        .nested("extra", false, |builder| {
+                            builder.field("label", types::string(true))
+                        })
+                })
+                .field("genres", types::list(false, types::string(true)))
+                .build()
+        };
+        let named = full().to_substrait();
+        assert_eq!(
+            named.names,
+            vec!["score", "location", "x", "extra", "label", "genres"]
+        );
+        assert_eq!(named.r#struct.as_ref().unwrap().types.len(), 3);
+        assert_eq!(SchemaInfo::from_substrait(named).unwrap(), full());
+
+        let types_only = || {
+            SchemaInfo::new_types()
+                .field(types::i32(false))
+                .nested(true, |builder| builder.field(types::fp64(false)))
+                .build()
+        };
+        let named = types_only().to_substrait();
+        assert_eq!(named.names, vec!["field_0", "field_1", "field_2"]);
+        assert_eq!(SchemaInfo::from_substrait(named).unwrap(), types_only());
+
+        let mut too_few = full().to_substrait();
+        too_few.names.pop();
+        assert!(SchemaInfo::from_substrait(too_few).is_err());
+        let mut too_many = full().to_substrait();
+        too_many.names.push("extra".to_string());
+        assert!(SchemaInfo::from_substrait(too_many).is_err());
+    }
+
+    #[test]
+    fn test_collect_types() {
+        let schema = SchemaInfo::new_full()
+            .field("a", types::i32(false))
+            .field("b", types::fp64(true))
+            .field("c", types::list(false, types::string(true)))
+            .field("d", types::i32(true))
+            .build();
+        assert_eq!(
+            schema.collect_types(),
+            vec![
+                types::i32(false),
+                types::fp64(false),
+                types::list(false, types::string(false)),
+                types::string(false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_variations() {
+        let uri = "https://example.com/variations";
+        let builder = SchemaInfo::new_types();
+        let u8_type = builder.types().with_variation(types::i8(false), uri, "u8");
+        let point = builder
+            .types()
+            .user_defined("https://example.com/types", "point")
+            .with_nullability(true);
+        let projected = builder.types().with_variation(point, uri, "projected");
+        let schema = builder
+            .field(u8_type.clone())
+            .field(projected.clone())
+            .build();
+
+        let registry = schema.extensions_registry();
+        let variation_name = |typ: &Type| {
+            let variation = match typ.kind.as_ref() {
+                Some(Kind::I8(t)) => t.type_variation_reference,
+                Some(Kind::UserDefined(t)) => t.type_variation_reference,
+                _ => panic!("Unexpected type"),
+            };
+            registry.lookup_variation(variation).unwrap().name
+        };
+        assert_eq!(variation_name(&u8_type), "u8");
+        assert_eq!(variation_name(&projected), "projected");
+        assert!(projected.is_nullable().unwrap());
+    }
+
+    #[test]
+    fn test_types_only_field_builder() {
+        let types_schema = SchemaInfo::new_types()
+            .field(types::list(false, types::string(true)))
+            .nested(false, |builder| {
+                builder.field(types::fp32(false)).field(types::fp64(true))
+            })
+            .build();
+        let full_schema = SchemaInfo::new_full()
+            .field("genres", types::list(false, types::string(true)))
+            .nested("location", false, |builder| {
+                builder
+                    .field("x", types::fp32(false))
+                    .field("y", types::fp64(true))
+            })
+            .build();
+        let params = BuilderParams::default();
+        let types_refs =
+            RefBuilder::new(&types_schema, &params, FunctionsBuilder::new(&types_schema));
+        let full_refs = RefBuilder::new(&full_schema, &params, FunctionsBuilder::new(&full_schema));
+
+        let by_index = types_refs
+            .field_builder()
+            .field_index(1)
+            .unwrap()
+            .field_index(1)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(by_index, full_refs.resolve_by_name("location.y").unwrap());
+        assert_eq!(
+            by_index.output_type(&types_schema).unwrap(),
+            types::fp64(true)
+        );
+
+        let by_index = types_refs
+            .field_builder()
+            .field_index(0)
+            .unwrap()
+            .list_item(2)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(by_index, full_refs.resolve_by_name("genres[2]").unwrap());
+
+        assert!(types_refs.field_builder().field_index(2).is_err());
+        assert!(types_refs.field_builder().field("location").is_err());
+        assert!(types_refs
+            .field_builder()
+            .field_index(0)
+            .unwrap()
+            .field_index(0)
+            .is_err());
+        assert!(full_refs.field_builder().field_index(2).is_err());
+    }
+
+    #[test]
+    fn test_resolve_by_index() {
+        let params = BuilderParams {
+            allow_unknown_types: true,
+            ..Default::default()
+        };
+        let names = names_schema!({
+            a: {
+                b: {},
+                c: {},
+            },
+            d: {}
+        });
+        let types = SchemaInfo::new_types()
+            .nested(false, |builder| {
+                builder.field(types::i32(false)).field(types::i64(false))
+            })
+            .field(types::fp32(true))
+            .build();
+        let empty = SchemaInfo::Empty(Default::default());
+        let names_refs = RefBuilder::new(&names, &params, FunctionsBuilder::new(&names));
+        let types_refs = RefBuilder::new(&types, &params, FunctionsBuilder::new(&types));
+        let empty_refs = RefBuilder::new(&empty, &params, FunctionsBuilder::new(&empty));
+
+        let expected = names_refs.resolve_by_name("a.c").unwrap();
+        assert_eq!(names_refs.resolve_by_index(&[0, 1]).unwrap(), expected);
+        assert_eq!(types_refs.resolve_by_index(&[0, 1]).unwrap(), expected);
+        assert_eq!(empty_refs.resolve_by_index(&[0, 1]).unwrap(), expected);
+        assert_eq!(
+            types_refs
+                .resolve_by_index(&[0, 1])
+                .unwrap()
+                .output_type(&types)
+                .unwrap(),
+            types::i64(false)
+        );
+
+        assert!(names_refs.resolve_by_index(&[2]).is_err());
+        assert!(types_refs.resolve_by_index(&[0, 2]).is_err());
+        assert!(types_refs.resolve_by_index(&[1, 0]).is_err());
+        assert!(types_refs.resolve_by_index(&[]).is_err());
+    }
+
+    #[test]
+    fn test_completions() {
+        let schema = SchemaInfo::new_full()
+            .field("score", types::i32(false))
+            .nested("location", false, |builder| {
+                builder
+                    .field("x", types::fp32(false))
+                    .field("y", types::fp64(true))
+            })
+            .field("locale", types::string(false))
+            .build();
+
+        assert_eq!(schema.completions("loc"), vec!["location", "locale"]);
+        assert_eq!(
+            schema.completions("location."),
+            vec!["location.x", "location.y"]
+        );
+        assert_eq!(schema.completions("location.y"), vec!["location.y"]);
+        assert_eq!(schema.completions(""), vec!["score", "location", "locale"]);
+        assert!(schema.completions("location.z").is_empty());
+        assert!(schema.completions("missing.").is_empty());
+        assert!(schema.completions("score.").is_empty());
+
+        let names = names_schema!({
+            location: {
+                x: {}
+            }
+        });
+        assert_eq!(names.completions("location."), vec!["location.x"]);
+
+        let types_only = SchemaInfo::new_types().field(types::i32(false)).build();
+        assert!(types_only.completions("").is_empty());
+    }
+
+    #[test]
+    fn test_outer_reference() {
+        let outer = SchemaInfo::new_full()
+            .field("region", types::string(false))
+            .field("sales", types::fp64(true))
+            .build();
+        let inner = SchemaInfo::new_full()
+            .field("region", types::i32(false))
+            .build();
+        let params = BuilderParams::default();
+        let outer_refs = RefBuilder::new(&outer, &params, FunctionsBuilder::new(&outer));
+
+        // e.g. a window partitioned by the enclosing query's region
+        let partition_key = outer_refs.resolve_outer_by_name("region", 1).unwrap();
+        let mut stack = SchemaStack::new(&outer);
+        stack.push(&inner);
+        assert_eq!(
+            partition_key.output_type_in_stack(&stack).unwrap(),
+            types::string(false)
+        );
+        assert_eq!(stack.current(), &inner);
+        // Without the stack the outer reference can't be resolved
+        assert!(partition_key.output_type(&inner).is_err());
+        assert!(inner.extensions_registry().to_substrait().1.is_empty());
+
+        let too_far = outer_refs.resolve_outer_by_name("sales", 2).unwrap();
+        assert!(too_far.output_type_in_stack(&stack).is_err());
+        assert!(outer_refs.resolve_outer_by_name("sales", 0).is_err());
+
+        stack.pop();
+        assert!(partition_key.output_type_in_stack(&stack).is_err());
+        assert!(stack.pop().is_none());
+
+        let by_index = outer_refs.outer(1).resolve_by_index(&[1]).unwrap();
+        let by_segments = outer_refs.outer(1).resolve_by_segments(&["sales"]).unwrap();
+        assert_eq!(by_index, by_segments);
+        let mut stack = SchemaStack::new(&outer);
+        stack.push(&inner);
+        assert_eq!(
+            by_index.output_type_in_stack(&stack).unwrap(),
+            types::fp64(true)
+        );
+        assert!(outer_refs.outer(0).resolve_by_index(&[1]).is_err());
+    }
+
+    #[test]
+    fn test_expression_reference() {
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(false))
+            .field("y", types::fp64(true))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let y = builder.fields().resolve_by_name("y").unwrap();
+        let point = builder
+            .functions()
+            .make_struct(vec![x, literal(1_i32), y])
+            .unwrap();
+
+        let refs = builder.fields();
+        let second = refs
+            .from_expression(point.clone())
+            .resolve_by_index(&[1])
+            .unwrap();
+        assert_eq!(
+            second.output_type(builder.schema()).unwrap(),
+            types::i32(false)
+        );
+        let third = refs
+            .from_expression(point.clone())
+            .resolve_by_index(&[2])
+            .unwrap();
+        assert_eq!(
+            third.output_type(builder.schema()).unwrap(),
+            types::fp64(true)
+        );
+        assert!(refs.from_expression(point).resolve_by_index(&[3]).is_err());
+        // Only struct results have fields
+        assert!(refs
+            .from_expression(literal(3_i32))
+            .resolve_by_index(&[0])
+            .is_err());
+    }
+
+    #[test]
+    fn test_masked_reference() {
+        let schema = SchemaInfo::new_full()
+            .field("score", types::i32(false))
+            .nested("location", true, |builder| {
+                builder
+                    .field("x", types::fp32(false))
+                    .field("y", types::fp64(false))
+            })
+            .field("name", types::string(true))
+            .build();
+        let params = BuilderParams::default();
+        let refs = RefBuilder::new(&schema, &params, FunctionsBuilder::new(&schema));
+
+        let subset = refs.mask().field(2).field(0).build().unwrap();
+        assert_eq!(
+            subset.output_type(&schema).unwrap(),
+            types::struct_(false, vec![types::string(true), types::i32(false)])
+        );
+
+        let nested = refs
+            .mask()
+            .field(0)
+            .nested(1, |child| child.field(1))
+            .build()
+            .unwrap();
+        assert_eq!(
+            nested.output_type(&schema).unwrap(),
+            types::struct_(
+                false,
+                vec![
+                    types::i32(false),
+                    types::struct_(true, vec![types::fp64(false)])
+                ]
+            )
+        );
+
+        // A single field is unwrapped unless maintain_singular_struct is set
+        let single = refs.mask().field(2).build().unwrap();
+        assert_eq!(single.output_type(&schema).unwrap(), types::string(true));
+        let single = refs
+            .mask()
+            .field(2)
+            .maintain_singular_struct(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            single.output_type(&schema).unwrap(),
+            types::struct_(false, vec![types::string(true)])
+        );
+
+        assert!(refs.mask().field(3).build().is_err());
+        assert!(refs
+            .mask()
+            .nested(0, |child| child.field(0))
+            .build()
+            .is_err());
+        assert!(refs
+            .mask()
+            .nested(1, |child| child.field(2))
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_resolve_nested_types() {
+        let full_schema = SchemaInfo::new_full()
+            .field("score", types::i32(false))
+            .field("genres", types::list(false, types::string(true)))
+            .field(
+                "attrs",
+                types::map(false, types::string(false), types::i32(true)),
+            )
+            .build();
+        let types_schema = SchemaInfo::new_types()
+            .field(types::i32(false))
+            .field(types::list(false, types::string(true)))
+            .field(types::map(false, types::string(false), types::i32(true)))
+            .build();
+        let params = BuilderParams::default();
+        let ref_builder =
+            RefBuilder::new(&full_schema, &params, FunctionsBuilder::new(&full_schema));
+
+        let list_item = ref_builder.resolve_by_name("genres[3]").unwrap();
+        let map_item = ref_builder.resolve_by_name("attrs[size]").unwrap();
+        for schema in [&full_schema, &types_schema] {
+            assert_eq!(list_item.output_type(schema).unwrap(), types::string(true));
+            assert_eq!(map_item.output_type(schema).unwrap(), types::i32(true));
+        }
+
+        // A missing list element or map key is NULL even if the items are non-nullable
+        let strict_schema = SchemaInfo::new_full()
+            .field("genres", types::list(false, types::string(false)))
+            .field(
+                "attrs",
+                types::map(false, types::string(false), types::i32(false)),
+            )
+            .build();
+        let strict_refs = RefBuilder::new(
+            &strict_schema,
+            &params,
+            FunctionsBuilder::new(&strict_schema),
+        );
+        let list_item = strict_refs.resolve_by_name("genres[5]").unwrap();
+        let map_item = strict_refs.resolve_by_name("attrs[size]").unwrap();
+        let genres = strict_refs.resolve_by_name("genres").unwrap();
+        assert_eq!(
+            list_item.output_type(&strict_schema).unwrap(),
+            types::string(true)
+        );
+        assert_eq!(
+            map_item.output_type(&strict_schema).unwrap(),
+            types::i32(true)
+        );
+        assert_eq!(
+            genres.output_type(&strict_schema).unwrap(),
+            types::list(false, types::string(false))
+        );
+
+        let invalid = ref_builder.resolve_by_name("score[1]").unwrap();
+        for schema in [&full_schema, &types_schema] {
+            assert!(matches!(
+                invalid.output_type(schema),
+                Err(SubstraitExprError::InvalidInput(_))
+            ));
+        }
+    }
 }
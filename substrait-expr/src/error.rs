@@ -10,6 +10,12 @@ pub enum SubstraitExprError {
     /// This indicates that a user is trying to do something with the library that is invalid
     #[error("Invalid input: {0}")]
     InvalidInput(String),
+    /// This indicates that a substrait message could not be decoded from bytes
+    #[error("Serialization error: {0}")]
+    SerializationError(#[from] prost::DecodeError),
+    /// This indicates that the library does not yet support the requested construct
+    #[error("Not implemented: {0}")]
+    NotImplemented(String),
 }
 
 impl SubstraitExprError {
@@ -22,6 +28,11 @@ impl SubstraitExprError {
     pub fn invalid_substrait(message: impl Into<String>) -> Self {
         SubstraitExprError::InvalidSubstrait(message.into())
     }
+
+    /// Shortcut for creating NotImplemented from &str
+    pub fn not_implemented(message: impl Into<String>) -> Self {
+        SubstraitExprError::NotImplemented(message.into())
+    }
 }
 
 pub(crate) type Result<T> = std::result::Result<T, SubstraitExprError>;
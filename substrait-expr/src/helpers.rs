@@ -12,8 +12,12 @@
 //! extension traits for [expressions](crate::helpers::expr::ExpressionExt),
 //! [types](crate::helpers::expr::TypeExt), and [literals](crate::helpers::literals::LiteralExt)
 
+#[cfg(feature = "arrow")]
+pub mod arrow;
 pub mod expr;
 pub mod literals;
 pub mod registry;
 pub mod schema;
+pub mod serde;
 pub mod types;
+pub mod visitor;
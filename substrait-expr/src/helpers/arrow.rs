@@ -0,0 +1,219 @@
+//! # Create schemas from Apache Arrow schemas
+//!
+//! This module requires the `arrow` feature.  Arrow schemas can be converted directly
+//! with [`from_arrow`] or decoded from Arrow IPC metadata (e.g. the schema returned by
+//! an Arrow Flight service) with [`from_arrow_ipc`].
+//!
+//! Dictionary encoded fields are converted to their value type since Substrait does not
+//! have a notion of dictionary encoding.
+
+use arrow_schema::{DataType, Field, IntervalUnit, Schema, TimeUnit};
+use substrait::proto::{
+    r#type::{
+        FixedBinary, IntervalCompound, IntervalDay, IntervalYear, Kind, PrecisionTimestampTz,
+    },
+    Type,
+};
+
+use crate::error::{Result, SubstraitExprError};
+
+use super::schema::{FullSchema, FullSchemaNode, SchemaInfo};
+use super::types::{self, nullability, NO_VARIATION};
+
+fn unsupported(field: &Field) -> SubstraitExprError {
+    SubstraitExprError::invalid_input(format!(
+        "The field {} has the type {} which has no Substrait equivalent",
+        field.name(),
+        field.data_type()
+    ))
+}
+
+fn time_unit_precision(unit: &TimeUnit) -> u8 {
+    match unit {
+        TimeUnit::Second => 0,
+        TimeUnit::Millisecond => 3,
+        TimeUnit::Microsecond => types::MICROSECOND_PRECISION,
+        TimeUnit::Nanosecond => 9,
+    }
+}
+
+fn value_type(data_type: &DataType) -> &DataType {
+    match data_type {
+        DataType::Dictionary(_, value_type) => self::value_type(value_type),
+        _ => data_type,
+    }
+}
+
+fn field_type(field: &Field) -> Result<Type> {
+    let nullable = field.is_nullable();
+    let typ = match value_type(field.data_type()) {
+        DataType::Boolean => types::bool(nullable),
+        DataType::Int8 => types::i8(nullable),
+        DataType::Int16 => types::i16(nullable),
+        DataType::Int32 => types::i32(nullable),
+        DataType::Int64 => types::i64(nullable),
+        DataType::Float32 => types::fp32(nullable),
+        DataType::Float64 => types::fp64(nullable),
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => types::string(nullable),
+        DataType::Binary | DataType::LargeBinary | DataType::BinaryView => types::binary(nullable),
+        DataType::FixedSizeBinary(length) => Type {
+            kind: Some(Kind::FixedBinary(FixedBinary {
+                length: *length,
+                nullability: nullability(nullable),
+                type_variation_reference: NO_VARIATION,
+            })),
+        },
+        DataType::Date32 | DataType::Date64 => types::date(nullable),
+        DataType::Time32(_) | DataType::Time64(_) => types::time(nullable),
+        DataType::Timestamp(unit, None) => {
+            types::precision_timestamp(time_unit_precision(unit), nullable)
+        }
+        DataType::Timestamp(unit, Some(_)) => Type {
+            kind: Some(Kind::PrecisionTimestampTz(PrecisionTimestampTz {
+                precision: time_unit_precision(unit) as i32,
+                nullability: nullability(nullable),
+                type_variation_reference: NO_VARIATION,
+            })),
+        },
+        DataType::Interval(IntervalUnit::YearMonth) => Type {
+            kind: Some(Kind::IntervalYear(IntervalYear {
+                nullability: nullability(nullable),
+                type_variation_reference: NO_VARIATION,
+            })),
+        },
+        DataType::Interval(IntervalUnit::DayTime) => Type {
+            kind: Some(Kind::IntervalDay(IntervalDay {
+                precision: Some(3),
+                nullability: nullability(nullable),
+                type_variation_reference: NO_VARIATION,
+            })),
+        },
+        DataType::Interval(IntervalUnit::MonthDayNano) => Type {
+            kind: Some(Kind::IntervalCompound(IntervalCompound {
+                precision: 9,
+                nullability: nullability(nullable),
+                type_variation_reference: NO_VARIATION,
+            })),
+        },
+        DataType::Decimal128(precision, scale) => {
+            let scale = u8::try_from(*scale).map_err(|_| unsupported(field))?;
+            types::decimal(*precision, scale, nullable)?
+        }
+        DataType::List(item) | DataType::LargeList(item) | DataType::FixedSizeList(item, _) => {
+            types::list(nullable, field_type(item)?)
+        }
+        DataType::Map(entries, _) => match entries.data_type() {
+            DataType::Struct(kv) if kv.len() == 2 => {
+                types::map(nullable, field_type(&kv[0])?, field_type(&kv[1])?)
+            }
+            _ => return Err(unsupported(field)),
+        },
+        DataType::Struct(children) => types::struct_(
+            nullable,
+            children
+                .iter()
+                .map(|child| field_type(child))
+                .collect::<Result<Vec<_>>>()?,
+        ),
+        _ => return Err(unsupported(field)),
+    };
+    Ok(typ)
+}
+
+fn field_node(field: &Field) -> Result<FullSchemaNode> {
+    let children = match value_type(field.data_type()) {
+        DataType::Struct(children) => children
+            .iter()
+            .map(|child| field_node(child))
+            .collect::<Result<Vec<_>>>()?,
+        _ => Vec::new(),
+    };
+    Ok(FullSchemaNode {
+        name: field.name().clone(),
+        r#type: field_type(field)?,
+        children,
+    })
+}
+
+/// Creates a full schema from an Arrow schema
+///
+/// Returns an error if any field has a type that cannot be represented in Substrait
+/// (e.g. unsigned integers)
+pub fn from_arrow(schema: &Schema) -> Result<SchemaInfo> {
+    let children = schema
+        .fields()
+        .iter()
+        .map(|field| field_node(field))
+        .collect::<Result<Vec<_>>>()?;
+    let root_type = types::struct_(
+        false,
+        children.iter().map(|child| child.r#type.clone()).collect(),
+    );
+    Ok(SchemaInfo::Full(FullSchema::new(FullSchemaNode {
+        name: String::new(),
+        r#type: root_type,
+        children,
+    })))
+}
+
+/// Creates a full schema from an encapsulated Arrow IPC schema message
+///
+/// This is the format used for the schema in Arrow Flight messages such as `SchemaResult`
+/// and `FlightInfo`.
+pub fn from_arrow_ipc(bytes: &[u8]) -> Result<SchemaInfo> {
+    let schema = arrow_ipc::convert::try_schema_from_ipc_buffer(bytes).map_err(|err| {
+        SubstraitExprError::invalid_input(format!("Could not decode the Arrow schema: {}", err))
+    })?;
+    from_arrow(&schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_ipc::writer::{IpcWriteOptions, StreamWriter};
+
+    use crate::builder::schema::SchemaBuildersExt;
+
+    use super::*;
+
+    #[test]
+    fn test_from_arrow_ipc() {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new_dictionary("category", DataType::Int32, DataType::Utf8, true),
+            Field::new_list(
+                "scores",
+                Field::new_list_field(DataType::Float64, true),
+                false,
+            ),
+            Field::new_struct(
+                "location",
+                vec![
+                    Field::new("x", DataType::Float32, false),
+                    Field::new("y", DataType::Float32, false),
+                ],
+                true,
+            ),
+        ]);
+        let mut bytes = Vec::new();
+        StreamWriter::try_new_with_options(&mut bytes, &schema, IpcWriteOptions::default())
+            .unwrap()
+            .finish()
+            .unwrap();
+
+        let expected = SchemaInfo::new_full()
+            .field("id", types::i64(false))
+            .field("category", types::string(true))
+            .field("scores", types::list(false, types::fp64(true)))
+            .nested("location", true, |builder| {
+                builder
+                    .field("x", types::fp32(false))
+                    .field("y", types::fp32(false))
+            })
+            .build();
+        assert_eq!(from_arrow_ipc(&bytes).unwrap(), expected);
+
+        let unsigned = Schema::new(vec![Field::new("count", DataType::UInt32, false)]);
+        assert!(from_arrow(&unsigned).is_err());
+        assert!(from_arrow_ipc(&[1, 2, 3]).is_err());
+    }
+}
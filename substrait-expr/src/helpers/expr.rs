@@ -1,18 +1,74 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+
+use prost::Message;
 use substrait::proto::{
-    expression::{field_reference::ReferenceType, Literal, RexType},
-    Expression, Type,
+    expression::{
+        cast::FailureBehavior,
+        field_reference::{ReferenceType, RootType},
+        literal::LiteralType,
+        nested::NestedType,
+        reference_segment, Cast, FieldReference, Literal, Nested, ReferenceSegment, RexType,
+        ScalarFunction, WindowFunction,
+    },
+    function_argument::ArgType,
+    r#type::Kind,
+    Expression, FunctionArgument, Type,
 };
 
 use crate::{
+    binding::AnchorRemapper,
+    builder::functions::{FunctionDefinition, LOOKUP_BY_NAME_FUNC_NAME, LOOKUP_BY_NAME_FUNC_URI},
     error::{Result, SubstraitExprError},
+    functions::functions_comparison,
     util::HasRequiredPropertiesRef,
+    validation::type_check,
 };
 
 use super::{
     literals::{LiteralExt, LiteralInference},
-    schema::SchemaInfo,
+    registry::{ExtensionsRegistry, QualifiedName},
+    schema::{
+        resolve_masked_type, resolve_nested_type, FullSchemaNode, NamesOnlySchemaNode, SchemaInfo,
+        SchemaStack,
+    },
+    types::{self, TypeExt},
+    visitor::{walk, ExpressionRewriter, ExpressionVisitor},
 };
 
+fn nested_output_type(nested: &Nested, schema: &SchemaInfo) -> Result<Type> {
+    let mut output_type = match nested.nested_type.as_ref().required("nested_type")? {
+        NestedType::Struct(strct) => types::struct_(
+            nested.nullable,
+            strct
+                .fields
+                .iter()
+                .map(|field| field.output_type(schema))
+                .collect::<Result<Vec<_>>>()?,
+        ),
+        // The element types of lists and maps are taken from the first element
+        NestedType::List(list) => {
+            let first = list.values.first().ok_or_else(|| {
+                SubstraitExprError::invalid_substrait("A nested list must have at least one value")
+            })?;
+            types::list(nested.nullable, first.output_type(schema)?)
+        }
+        NestedType::Map(map) => {
+            let first = map.key_values.first().ok_or_else(|| {
+                SubstraitExprError::invalid_substrait("A nested map must have at least one entry")
+            })?;
+            types::map(
+                nested.nullable,
+                first.key.required("key")?.output_type(schema)?,
+                first.value.required("value")?.output_type(schema)?,
+            )
+        }
+    };
+    types::set_variation(&mut output_type, nested.type_variation_reference);
+    Ok(output_type)
+}
+
 /// Extends the protobuf Expression object with useful helper methods
 pub trait ExpressionExt {
     /// The rex_type is a required property
@@ -26,10 +82,552 @@ pub trait ExpressionExt {
     fn try_as_rust_literal<T: LiteralInference>(&self) -> Result<T>;
     /// Tries to decode the expression as a Substrait literal
     fn try_as_literal(&self) -> Result<&Literal>;
+    /// Returns the value of the expression if it is a (non-null) boolean literal
+    ///
+    /// This is useful for detecting predicates that are always true or always false
+    fn as_const_bool(&self) -> Option<bool>;
     /// Determines the output type of the expression
     ///
     /// TODO: Explain this more
+    ///
+    /// Returns an error for an outer reference since it refers to an enclosing schema (use
+    /// [`output_type_in_stack`](Self::output_type_in_stack) to resolve it)
     fn output_type(&self, schema: &SchemaInfo) -> Result<Type>;
+    /// Determines the output type of an expression that may contain outer references
+    ///
+    /// Outer references are resolved against the enclosing schemas in `schemas` and
+    /// everything else is resolved against the current schema.
+    fn output_type_in_stack(&self, schemas: &SchemaStack) -> Result<Type>;
+    /// Renders the expression as a short human readable string (e.g. `x + 3`)
+    ///
+    /// Common binary operators (e.g. add or lt) are rendered infix and other functions
+    /// are rendered as calls (e.g. `sqrt(x)`).  Field references are rendered as dotted
+    /// names if the schema knows the names and as positions (e.g. `#1.#0`) otherwise.
+    /// Late name lookups (in loose expressions) are rendered as the plain name.
+    fn to_human_readable(&self, schema: &SchemaInfo) -> Result<String>;
+    /// Renders the expression as a SQL-like string (e.g. `location.x + 3.0`)
+    ///
+    /// This is the same as [`to_human_readable`](Self::to_human_readable) except that
+    /// function anchors are resolved with `registry` instead of the schema's registry.
+    /// This is useful when the expression came from a message (e.g. an ExtendedExpression)
+    /// whose extensions were not registered with the schema.  IN-lists are rendered as
+    /// `x IN (1, 2)`.
+    fn to_sql_string(&self, schema: &SchemaInfo, registry: &ExtensionsRegistry) -> Result<String>;
+    /// Renders the expression as an indented tree with one node per line
+    ///
+    /// Each line shows the kind of node and its details (function name, literal value,
+    /// reference segments, etc.) along with its type, if known.  Nodes of the unknown
+    /// type are marked with `[UNKNOWN]`.  Invalid or unsupported nodes are rendered
+    /// rather than failing so this can be used on any expression.
+    fn to_tree_string(&self, registry: &ExtensionsRegistry) -> String;
+    /// Returns true if the output type of any node is (or contains) the unknown type
+    ///
+    /// This can be used to make sure an expression has been fully bound (see
+    /// [`bind`](crate::binding::bind)) before it is sent to a consumer.  Field references do
+    /// not have their own types (they are resolved against a schema) but a reference to an
+    /// unknown field will make the output types of its parents unknown.
+    fn contains_unknown(&self, registry: &ExtensionsRegistry) -> Result<bool>;
+    /// Checks that the expression is well typed
+    ///
+    /// Every scalar or window function call to a known function (see
+    /// [`functions::definitions`](crate::functions::definitions)) must match one of the
+    /// function's implementations and enum arguments must be one of the accepted values.
+    /// Field references must be valid for the schema and literals must be valid for their
+    /// types.  Calls to unknown functions (e.g. UDFs) are not checked but their arguments
+    /// are.  The children of nested expressions (e.g. struct fields) and the partitions
+    /// and sorts of window functions are checked too.  The conditions of if-then
+    /// expressions must be boolean, the cases of switch expressions must match the switch
+    /// value, and the results of either must have the same kind of type.
+    ///
+    /// The first problem is reported along with where it is (e.g.
+    /// `argument 1 of add in argument 0 of multiply`).  See
+    /// [`validation::validate`](crate::validation::validate) for restricting which
+    /// functions are allowed.
+    fn validate(&self, schema: &SchemaInfo) -> Result<()>;
+    /// Renders the expression as a Graphviz digraph (in the DOT language)
+    ///
+    /// Each expression is a box labeled like the lines of
+    /// [`to_tree_string`](Self::to_tree_string) with edges from parents to children.  The
+    /// segments of a field reference are drawn as a chain of ellipses and arguments that
+    /// are not expressions (e.g. enums) are drawn as plain text.
+    fn to_dot(&self, registry: &ExtensionsRegistry) -> String;
+    /// Recognizes a call to one of the standard binary comparison functions
+    ///
+    /// Returns the operator and the two operands or None if the expression is not a
+    /// comparison.  The registry is used to interpret the function anchor.
+    fn as_comparison(
+        &self,
+        registry: &ExtensionsRegistry,
+    ) -> Option<(ComparisonOp, &Expression, &Expression)>;
+    /// Returns every distinct type used by the expression, including nested types
+    ///
+    /// This includes the output type of every node (e.g. literals, field references, and
+    /// the results of functions and casts).  See [`types::collect_types`] for details.
+    fn collect_types(&self, schema: &SchemaInfo) -> Result<Vec<Type>>;
+    /// Returns the indices of the top-level fields referenced by the expression
+    ///
+    /// Only the first segment of each direct reference is considered, so a reference to
+    /// `location.x` only reports the index of `location`.  This is useful for pruning
+    /// columns that an expression does not need.  Outer references and references into
+    /// the result of another expression are not references to the input schema and are
+    /// ignored.
+    ///
+    /// Returns an error if the expression contains a masked reference
+    fn referenced_fields(&self) -> Result<BTreeSet<u32>>;
+    /// Changes the top-level field that each reference starts at
+    ///
+    /// This is typically used after the input schema has been pruned (see
+    /// [`referenced_fields`](Self::referenced_fields)).  `mapping` maps old field indices to
+    /// new field indices.  Only the first segment of each reference is changed.
+    ///
+    /// Returns an error if a referenced field has no mapping
+    fn remap_fields(&self, mapping: &HashMap<u32, u32>) -> Result<Expression>;
+    /// Returns true if two expressions are the same, even if they were built with
+    /// different registries
+    ///
+    /// The derived `==` compares the numeric function and type anchors, which depend on
+    /// the order things were registered.  This compares the qualified names (URI and name)
+    /// that the anchors refer to instead.  `self_registry` and `other_registry` are the
+    /// registries the expressions were built with.
+    ///
+    /// Returns false if either expression refers to an anchor that is not registered
+    fn semantic_eq(
+        &self,
+        other: &Expression,
+        self_registry: &ExtensionsRegistry,
+        other_registry: &ExtensionsRegistry,
+    ) -> bool;
+    /// Returns a hash of the expression that does not depend on the anchors in `registry`
+    ///
+    /// Expressions that are [`semantic_eq`](Self::semantic_eq) have the same hash, even if
+    /// they were built with different registries, so the two can be used together to find
+    /// common subexpressions.  `registry` is the registry the expression was built with.
+    ///
+    /// Returns an error if the expression refers to an anchor that is not registered
+    fn semantic_hash(&self, registry: &ExtensionsRegistry) -> Result<u64>;
+}
+
+/// A standard binary comparison
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComparisonOp {
+    Equal,
+    NotEqual,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+}
+
+impl ComparisonOp {
+    /// The standard function that this comparison maps to
+    pub fn function(&self) -> &'static FunctionDefinition {
+        match self {
+            ComparisonOp::Equal => &functions_comparison::EQUAL,
+            ComparisonOp::NotEqual => &functions_comparison::NOT_EQUAL,
+            ComparisonOp::Lt => &functions_comparison::LT,
+            ComparisonOp::Lte => &functions_comparison::LTE,
+            ComparisonOp::Gt => &functions_comparison::GT,
+            ComparisonOp::Gte => &functions_comparison::GTE,
+        }
+    }
+
+    fn from_qualified_name(name: &QualifiedName) -> Option<Self> {
+        [
+            ComparisonOp::Equal,
+            ComparisonOp::NotEqual,
+            ComparisonOp::Lt,
+            ComparisonOp::Lte,
+            ComparisonOp::Gt,
+            ComparisonOp::Gte,
+        ]
+        .into_iter()
+        .find(|op| {
+            let func = op.function();
+            func.uri == name.uri && func.name == name.name
+        })
+    }
+}
+
+/// The infix operator used to render common binary functions
+fn infix_operator(function_name: &str) -> Option<&str> {
+    match function_name {
+        "add" => Some("+"),
+        "subtract" => Some("-"),
+        "multiply" => Some("*"),
+        "divide" => Some("/"),
+        "equal" => Some("="),
+        "not_equal" => Some("!="),
+        "lt" => Some("<"),
+        "lte" => Some("<="),
+        "gt" => Some(">"),
+        "gte" => Some(">="),
+        "and" => Some("AND"),
+        "or" => Some("OR"),
+        _ => None,
+    }
+}
+
+fn render_literal(literal: &Literal, registry: &ExtensionsRegistry) -> Result<String> {
+    Ok(
+        match literal.literal_type.as_ref().required("literal_type")? {
+            LiteralType::Boolean(value) => value.to_string(),
+            LiteralType::I8(value) | LiteralType::I16(value) | LiteralType::I32(value) => {
+                value.to_string()
+            }
+            LiteralType::I64(value) => value.to_string(),
+            LiteralType::Fp32(value) => format!("{:?}", value),
+            LiteralType::Fp64(value) => format!("{:?}", value),
+            LiteralType::String(value) | LiteralType::FixedChar(value) => format!("'{}'", value),
+            LiteralType::VarChar(varchar) => format!("'{}'", varchar.value),
+            LiteralType::Null(typ) => format!("null::{}", typ.to_human_readable(registry)),
+            LiteralType::Decimal(decimal) => {
+                let value: [u8; 16] = decimal.value.as_slice().try_into().map_err(|_| {
+                    SubstraitExprError::invalid_substrait("A decimal literal must have 16 bytes")
+                })?;
+                let digits = i128::from_le_bytes(value).unsigned_abs().to_string();
+                let scale = decimal.scale.max(0) as usize;
+                let digits = format!("{:0>width$}", digits, width = scale + 1);
+                let (whole, fraction) = digits.split_at(digits.len() - scale);
+                let sign = if i128::from_le_bytes(value) < 0 {
+                    "-"
+                } else {
+                    ""
+                };
+                if fraction.is_empty() {
+                    format!("{}{}", sign, whole)
+                } else {
+                    format!("{}{}.{}", sign, whole, fraction)
+                }
+            }
+            LiteralType::Struct(strct) => format!(
+                "{{{}}}",
+                strct
+                    .fields
+                    .iter()
+                    .map(|field| render_literal(field, registry))
+                    .collect::<Result<Vec<_>>>()?
+                    .join(", ")
+            ),
+            other => format!("{:?}", other),
+        },
+    )
+}
+
+/// The names of the fields at the current level of a field reference, if known
+enum FieldNames<'a> {
+    Full(&'a [FullSchemaNode]),
+    Names(&'a [NamesOnlySchemaNode]),
+    Unknown,
+}
+
+impl<'a> FieldNames<'a> {
+    fn new(schema: &'a SchemaInfo) -> Self {
+        match schema {
+            SchemaInfo::Full(full) => FieldNames::Full(&full.root.children),
+            SchemaInfo::Names(names) => FieldNames::Names(&names.root.children),
+            _ => FieldNames::Unknown,
+        }
+    }
+
+    /// Returns the name of the field and the names of that field's children
+    fn child(&self, index: i32) -> (Option<&'a str>, FieldNames<'a>) {
+        let index = index as usize;
+        match self {
+            FieldNames::Full(nodes) => match nodes.get(index) {
+                Some(node) => (Some(&node.name), FieldNames::Full(&node.children)),
+                None => (None, FieldNames::Unknown),
+            },
+            FieldNames::Names(nodes) => match nodes.get(index) {
+                Some(node) => (Some(&node.name), FieldNames::Names(&node.children)),
+                None => (None, FieldNames::Unknown),
+            },
+            FieldNames::Unknown => (None, FieldNames::Unknown),
+        }
+    }
+}
+
+fn render_reference(
+    root_segment: &ReferenceSegment,
+    schema: &SchemaInfo,
+    registry: &ExtensionsRegistry,
+) -> Result<String> {
+    let mut rendered = String::new();
+    let mut names = FieldNames::new(schema);
+    let mut segment = Some(root_segment);
+    while let Some(seg) = segment {
+        segment = match seg.reference_type.as_ref().required("reference_type")? {
+            reference_segment::ReferenceType::StructField(field) => {
+                let (name, children) = names.child(field.field);
+                if !rendered.is_empty() {
+                    rendered.push('.');
+                }
+                match name {
+                    Some(name) => rendered.push_str(name),
+                    None => rendered.push_str(&format!("#{}", field.field)),
+                }
+                names = children;
+                field.child.as_deref()
+            }
+            reference_segment::ReferenceType::ListElement(element) => {
+                rendered.push_str(&format!("[{}]", element.offset));
+                names = FieldNames::Unknown;
+                element.child.as_deref()
+            }
+            reference_segment::ReferenceType::MapKey(map_key) => {
+                let key = render_literal(map_key.map_key.as_ref().required("map_key")?, registry)?;
+                rendered.push_str(&format!("[{}]", key));
+                names = FieldNames::Unknown;
+                map_key.child.as_deref()
+            }
+        };
+    }
+    Ok(rendered)
+}
+
+fn render_function(
+    func: &ScalarFunction,
+    schema: &SchemaInfo,
+    registry: &ExtensionsRegistry,
+) -> Result<String> {
+    let name = registry
+        .lookup_function(func.function_reference)
+        .ok_or_else(|| {
+            SubstraitExprError::invalid_substrait(format!(
+                "The function anchor {} is not registered",
+                func.function_reference
+            ))
+        })?;
+    let args = func
+        .arguments
+        .iter()
+        .map(|arg| match arg.arg_type.as_ref().required("arg_type")? {
+            ArgType::Value(value) => {
+                let rendered = render_expression(value, schema, registry)?;
+                // Parenthesize nested infix operations so precedence is clear
+                Ok(match &value.rex_type {
+                    Some(RexType::ScalarFunction(inner))
+                        if inner.arguments.len() == 2
+                            && registry
+                                .lookup_function(inner.function_reference)
+                                .map(|inner_name| infix_operator(&inner_name.name).is_some())
+                                .unwrap_or(false) =>
+                    {
+                        format!("({})", rendered)
+                    }
+                    _ => rendered,
+                })
+            }
+            ArgType::Enum(value) => Ok(value.clone()),
+            ArgType::Type(typ) => Ok(typ.to_human_readable(registry)),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if name.uri == LOOKUP_BY_NAME_FUNC_URI && name.name == LOOKUP_BY_NAME_FUNC_NAME {
+        // A late lookup is rendered as the (unbound) name it refers to
+        return args.into_iter().next().ok_or_else(|| {
+            SubstraitExprError::invalid_substrait(
+                "A name lookup placeholder did not have a field name argument",
+            )
+        });
+    }
+    match infix_operator(&name.name) {
+        Some(operator) if args.len() == 2 => Ok(format!("{} {} {}", args[0], operator, args[1])),
+        _ => Ok(format!("{}({})", name.name, args.join(", "))),
+    }
+}
+
+#[derive(Default)]
+struct ReferencedFieldsCollector {
+    fields: BTreeSet<u32>,
+    error: Option<SubstraitExprError>,
+}
+
+/// Returns the index of the top-level field in the input schema that a reference starts at
+///
+/// Returns None for outer references and references into the result of an expression
+fn top_level_field(reference: &FieldReference) -> Result<Option<u32>> {
+    if !matches!(reference.root_type, None | Some(RootType::RootReference(_))) {
+        return Ok(None);
+    }
+    match reference
+        .reference_type
+        .as_ref()
+        .required("reference_type")?
+    {
+        ReferenceType::DirectReference(segment) => {
+            match segment.reference_type.as_ref().required("reference_type")? {
+                reference_segment::ReferenceType::StructField(field) => {
+                    u32::try_from(field.field).map(Some).map_err(|_| {
+                        SubstraitExprError::invalid_substrait(format!(
+                            "A field reference had a negative field index {}",
+                            field.field
+                        ))
+                    })
+                }
+                _ => Err(SubstraitExprError::invalid_substrait(
+                    "The first segment of a field reference was not a struct field",
+                )),
+            }
+        }
+        ReferenceType::MaskedReference(_) => Err(SubstraitExprError::invalid_input(
+            "Masked references are not supported",
+        )),
+    }
+}
+
+impl ExpressionVisitor for ReferencedFieldsCollector {
+    fn visit_field_reference(&mut self, reference: &FieldReference) {
+        if self.error.is_some() {
+            return;
+        }
+        match top_level_field(reference) {
+            Ok(Some(field)) => {
+                self.fields.insert(field);
+            }
+            Ok(None) => {}
+            Err(err) => self.error = Some(err),
+        }
+    }
+}
+
+/// Checks the output type of each node for [`ExpressionExt::contains_unknown`]
+struct UnknownFinder<'a> {
+    registry: &'a ExtensionsRegistry,
+    found: bool,
+    error: Option<SubstraitExprError>,
+}
+
+impl UnknownFinder<'_> {
+    fn check(&mut self, typ: Result<&Type>) {
+        if self.found || self.error.is_some() {
+            return;
+        }
+        match typ {
+            Ok(typ) => self.found = typ.contains_unknown(self.registry),
+            Err(err) => self.error = Some(err),
+        }
+    }
+}
+
+impl ExpressionVisitor for UnknownFinder<'_> {
+    fn visit_literal(&mut self, literal: &Literal) {
+        let data_type = literal.data_type();
+        match data_type {
+            Ok(typ) => self.check(Ok(&typ)),
+            Err(err) => self.check(Err(err)),
+        }
+    }
+
+    fn visit_scalar_function(&mut self, func: &ScalarFunction) {
+        self.check(func.output_type.required("output_type"));
+    }
+
+    fn visit_window_function(&mut self, func: &WindowFunction) {
+        self.check(func.output_type.required("output_type"));
+    }
+
+    fn visit_cast(&mut self, cast: &Cast) {
+        self.check(cast.r#type.required("type"));
+    }
+}
+
+struct FieldRemapper<'a> {
+    mapping: &'a HashMap<u32, u32>,
+}
+
+impl ExpressionRewriter for FieldRemapper<'_> {
+    fn rewrite_node(&mut self, mut expr: Expression) -> Result<Expression> {
+        let Some(RexType::Selection(reference)) = expr.rex_type.as_mut() else {
+            return Ok(expr);
+        };
+        let Some(field) = top_level_field(reference)? else {
+            return Ok(expr);
+        };
+        let remapped = *self.mapping.get(&field).ok_or_else(|| {
+            SubstraitExprError::invalid_input(format!(
+                "The field {} is referenced but there is no mapping for it",
+                field
+            ))
+        })?;
+        if let Some(ReferenceType::DirectReference(segment)) = reference.reference_type.as_mut() {
+            if let Some(reference_segment::ReferenceType::StructField(struct_field)) =
+                segment.reference_type.as_mut()
+            {
+                struct_field.field = remapped as i32;
+            }
+        }
+        Ok(expr)
+    }
+}
+
+/// Collects the output type of every node for [`ExpressionExt::collect_types`]
+struct NodeTypeCollector<'a> {
+    schema: &'a SchemaInfo,
+    node_types: Vec<Type>,
+    error: Option<SubstraitExprError>,
+}
+
+impl ExpressionVisitor for NodeTypeCollector<'_> {
+    fn visit_expression(&mut self, expr: &Expression) {
+        if self.error.is_some() {
+            return;
+        }
+        match expr.output_type(self.schema) {
+            Ok(typ) => self.node_types.push(typ),
+            Err(err) => self.error = Some(err),
+        }
+    }
+}
+
+/// Moves the anchors in `expr` from `registry` to `canonical`
+fn canonical_anchors(
+    expr: &Expression,
+    registry: &ExtensionsRegistry,
+    canonical: &ExtensionsRegistry,
+) -> Result<Expression> {
+    AnchorRemapper {
+        from: registry,
+        to: canonical,
+    }
+    .rewrite(expr.clone())
+}
+
+/// Replaces negative zeros in floating point literals with positive zeros
+struct PositiveZeros;
+
+impl PositiveZeros {
+    fn normalize(literal: &mut Literal) {
+        match literal.literal_type.as_mut() {
+            Some(LiteralType::Fp32(value)) if *value == 0.0 => *value = 0.0,
+            Some(LiteralType::Fp64(value)) if *value == 0.0 => *value = 0.0,
+            Some(LiteralType::Struct(strct)) => strct.fields.iter_mut().for_each(Self::normalize),
+            Some(LiteralType::List(list)) => list.values.iter_mut().for_each(Self::normalize),
+            Some(LiteralType::Map(map)) => {
+                for key_value in map.key_values.iter_mut() {
+                    key_value
+                        .key
+                        .iter_mut()
+                        .chain(&mut key_value.value)
+                        .for_each(Self::normalize);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl ExpressionRewriter for PositiveZeros {
+    fn rewrite_node(&mut self, mut expr: Expression) -> Result<Expression> {
+        match expr.rex_type.as_mut() {
+            Some(RexType::Literal(literal)) => Self::normalize(literal),
+            Some(RexType::SwitchExpression(switch)) => {
+                for case in switch.ifs.iter_mut() {
+                    case.r#if.iter_mut().for_each(Self::normalize);
+                }
+            }
+            _ => {}
+        }
+        Ok(expr)
+    }
 }
 
 impl ExpressionExt for Expression {
@@ -41,6 +639,84 @@ impl ExpressionExt for Expression {
         })
     }
 
+    fn as_comparison(
+        &self,
+        registry: &ExtensionsRegistry,
+    ) -> Option<(ComparisonOp, &Expression, &Expression)> {
+        let Some(RexType::ScalarFunction(func)) = &self.rex_type else {
+            return None;
+        };
+        let op =
+            ComparisonOp::from_qualified_name(&registry.lookup_function(func.function_reference)?)?;
+        match func.arguments.as_slice() {
+            [FunctionArgument {
+                arg_type: Some(ArgType::Value(lhs)),
+            }, FunctionArgument {
+                arg_type: Some(ArgType::Value(rhs)),
+            }] => Some((op, lhs, rhs)),
+            _ => None,
+        }
+    }
+
+    fn collect_types(&self, schema: &SchemaInfo) -> Result<Vec<Type>> {
+        let mut collector = NodeTypeCollector {
+            schema,
+            node_types: Vec::new(),
+            error: None,
+        };
+        walk(self, &mut collector);
+        match collector.error {
+            Some(err) => Err(err),
+            None => Ok(types::collect_types(collector.node_types)),
+        }
+    }
+
+    fn referenced_fields(&self) -> Result<BTreeSet<u32>> {
+        let mut collector = ReferencedFieldsCollector::default();
+        walk(self, &mut collector);
+        match collector.error {
+            Some(err) => Err(err),
+            None => Ok(collector.fields),
+        }
+    }
+
+    fn remap_fields(&self, mapping: &HashMap<u32, u32>) -> Result<Expression> {
+        FieldRemapper { mapping }.rewrite(self.clone())
+    }
+
+    fn semantic_eq(
+        &self,
+        other: &Expression,
+        self_registry: &ExtensionsRegistry,
+        other_registry: &ExtensionsRegistry,
+    ) -> bool {
+        // Remapping both expressions to a shared registry gives equal names equal anchors
+        let canonical = ExtensionsRegistry::default();
+        let lhs = canonical_anchors(self, self_registry, &canonical);
+        let rhs = canonical_anchors(other, other_registry, &canonical);
+        matches!((lhs, rhs), (Ok(lhs), Ok(rhs)) if lhs == rhs)
+    }
+
+    fn semantic_hash(&self, registry: &ExtensionsRegistry) -> Result<u64> {
+        // Anchors are assigned in the order they are first seen and so expressions that are
+        // semantic_eq get the same anchors, even in separate registries
+        let canonical_registry = ExtensionsRegistry::default();
+        let canonical = canonical_anchors(self, registry, &canonical_registry)?;
+        // -0.0 == 0.0 but the two are encoded differently
+        let canonical = PositiveZeros.rewrite(canonical)?;
+        let mut hasher = DefaultHasher::new();
+        canonical.encode_to_vec().hash(&mut hasher);
+        // The names that the anchors refer to
+        let (uris, extensions) = canonical_registry.to_substrait();
+        for uri in uris {
+            uri.encode_to_vec().hash(&mut hasher);
+        }
+        for extension in extensions {
+            extension.encode_to_vec().hash(&mut hasher);
+        }
+        Ok(hasher.finish())
+    }
+
     fn try_as_rust_literal<T: LiteralInference>(&self) -> Result<T> {
         let literal = self.try_as_literal()?;
         T::try_from_substrait(literal.literal_type.as_ref().ok_or_else(|| {
@@ -59,31 +735,975 @@ impl ExpressionExt for Expression {
         }
     }
 
+    fn as_const_bool(&self) -> Option<bool> {
+        match self.try_as_literal().ok()?.literal_type {
+            Some(LiteralType::Boolean(value)) => Some(value),
+            _ => None,
+        }
+    }
+
     fn output_type(&self, schema: &SchemaInfo) -> Result<Type> {
         match self.try_rex_type()? {
             RexType::Literal(literal) => literal.data_type(),
             RexType::ScalarFunction(func) => func.output_type.required("output_type").cloned(),
+            RexType::WindowFunction(func) => func.output_type.required("output_type").cloned(),
+            RexType::Nested(nested) => nested_output_type(nested, schema),
+            RexType::SingularOrList(in_list) => {
+                let mut nullable = in_list
+                    .value
+                    .required("value")?
+                    .output_type(schema)?
+                    .is_nullable()?;
+                for option in &in_list.options {
+                    nullable |= option.output_type(schema)?.is_nullable()?;
+                }
+                Ok(types::bool(nullable))
+            }
             RexType::Selection(selection) => {
                 match selection.root_type.as_ref().required("root_type")? {
-                    substrait::proto::expression::field_reference::RootType::Expression(_) => {
-                        todo!()
+                    substrait::proto::expression::field_reference::RootType::Expression(root) => {
+                        let root_type = root.output_type(schema)?;
+                        if root_type.is_unknown(schema.extensions_registry()) {
+                            return Ok(root_type);
+                        }
+                        let output_type = match selection.reference_type.as_ref().required("reference_type")? {
+                            ReferenceType::DirectReference(root_segment) => {
+                                resolve_nested_type(&root_type, Some(root_segment))?
+                            }
+                            ReferenceType::MaskedReference(mask) => match &root_type.kind {
+                                Some(Kind::Struct(strct)) => {
+                                    resolve_masked_type(&strct.types, root_type.is_nullable()?, mask)?
+                                }
+                                _ => return Err(SubstraitExprError::invalid_input("Invalid mask: attempt to select struct fields of a non-struct type")),
+                            },
+                        };
+                        // A field of a null struct is null
+                        if root_type.is_nullable()? {
+                            Ok(output_type.with_nullability(true))
+                        } else {
+                            Ok(output_type)
+                        }
                     }
                     substrait::proto::expression::field_reference::RootType::RootReference(_) => {
-                        match selection.reference_type.as_ref().required("reference_type")? {
+                        match selection
+                            .reference_type
+                            .as_ref()
+                            .required("reference_type")?
+                        {
                             ReferenceType::DirectReference(root_segment) => {
                                 schema.resolve_type(root_segment)
-                            },
-                            ReferenceType::MaskedReference(_) => {
-                                Err(SubstraitExprError::invalid_substrait("A root reference did not have a reference type of direct reference"))
                             }
+                            ReferenceType::MaskedReference(mask) => schema.resolve_mask(mask),
                         }
                     }
                     substrait::proto::expression::field_reference::RootType::OuterReference(_) => {
-                        todo!()
+                        Err(SubstraitExprError::invalid_input("An outer reference can only be resolved with the enclosing schemas (see output_type_in_stack)"))
                     }
                 }
             }
-            _ => todo!(),
+            RexType::Cast(cast) => {
+                let output_type = cast.r#type.required("type")?;
+                // If a cast fails and the failure behavior is to return null then the
+                // output must be nullable, even if the target type is not
+                if cast.failure_behavior == FailureBehavior::ReturnNull as i32 {
+                    Ok(output_type.with_nullability(true))
+                } else {
+                    Ok(output_type.clone())
+                }
+            }
+            RexType::IfThen(if_then) => {
+                let branch_types = if_then
+                    .ifs
+                    .iter()
+                    .map(|clause| clause.then.required("then"))
+                    .chain(if_then.r#else.as_deref().map(Ok))
+                    .map(|branch| branch?.output_type(schema))
+                    .collect::<Result<Vec<_>>>()?;
+                types::branch_result_type(&branch_types, if_then.r#else.is_some())
+            }
+            RexType::SwitchExpression(switch) => {
+                let branch_types = switch
+                    .ifs
+                    .iter()
+                    .map(|case| case.then.required("then"))
+                    .chain(switch.r#else.as_deref().map(Ok))
+                    .map(|branch| branch?.output_type(schema))
+                    .collect::<Result<Vec<_>>>()?;
+                types::branch_result_type(&branch_types, switch.r#else.is_some())
+            }
+            RexType::MultiOrList(_) => Err(SubstraitExprError::not_implemented(
+                "output_type for MultiOrList",
+            )),
+            RexType::Subquery(_) => Err(SubstraitExprError::not_implemented(
+                "output_type for Subquery",
+            )),
+            RexType::Enum(_) => Err(SubstraitExprError::not_implemented("output_type for Enum")),
+        }
+    }
+
+    fn output_type_in_stack(&self, schemas: &SchemaStack) -> Result<Type> {
+        if let RexType::Selection(selection) = self.try_rex_type()? {
+            if let Some(RootType::OuterReference(outer)) = &selection.root_type {
+                let outer_schema = schemas.outer(outer.steps_out)?;
+                return match selection
+                    .reference_type
+                    .as_ref()
+                    .required("reference_type")?
+                {
+                    ReferenceType::DirectReference(root_segment) => {
+                        outer_schema.resolve_type(root_segment)
+                    }
+                    ReferenceType::MaskedReference(_) => {
+                        Err(SubstraitExprError::invalid_substrait(
+                            "An outer reference did not have a reference type of direct reference",
+                        ))
+                    }
+                };
+            }
+        }
+        self.output_type(schemas.current())
+    }
+
+    fn to_human_readable(&self, schema: &SchemaInfo) -> Result<String> {
+        render_expression(self, schema, schema.extensions_registry())
+    }
+
+    fn to_sql_string(&self, schema: &SchemaInfo, registry: &ExtensionsRegistry) -> Result<String> {
+        render_expression(self, schema, registry)
+    }
+
+    fn to_tree_string(&self, registry: &ExtensionsRegistry) -> String {
+        let mut lines = Vec::new();
+        push_tree_lines(self, registry, 0, &mut lines);
+        lines.join("\n")
+    }
+
+    fn contains_unknown(&self, registry: &ExtensionsRegistry) -> Result<bool> {
+        let mut finder = UnknownFinder {
+            registry,
+            found: false,
+            error: None,
+        };
+        walk(self, &mut finder);
+        match finder.error {
+            Some(err) => Err(err),
+            None => Ok(finder.found),
+        }
+    }
+
+    fn validate(&self, schema: &SchemaInfo) -> Result<()> {
+        type_check(self, schema)
+    }
+
+    fn to_dot(&self, registry: &ExtensionsRegistry) -> String {
+        let mut writer = DotWriter::default();
+        writer.add_expression(self, registry);
+        format!("digraph expression {{\n{}\n}}\n", writer.lines.join("\n"))
+    }
+}
+
+/// Describes a (possibly missing) type for [`ExpressionExt::to_tree_string`]
+fn tree_type(typ: Option<&Type>, registry: &ExtensionsRegistry) -> String {
+    match typ {
+        Some(typ) if typ.is_unknown(registry) => " [UNKNOWN]".to_string(),
+        Some(typ) => format!(" : {}", typ.to_human_readable(registry)),
+        None => " : <missing type>".to_string(),
+    }
+}
+
+fn tree_function_name(function_reference: u32, registry: &ExtensionsRegistry) -> String {
+    registry
+        .lookup_function(function_reference)
+        .map(|name| name.name)
+        .unwrap_or_else(|| format!("<unregistered anchor {}>", function_reference))
+}
+
+/// A child of a node rendered by [`ExpressionExt::to_tree_string`] or
+/// [`ExpressionExt::to_dot`]
+enum TreeChild<'a> {
+    /// A child expression
+    Expr(&'a Expression),
+    /// A child that is not an expression (e.g. an enum argument)
+    Leaf(String),
+}
+
+/// Describes a single node, returning its label and its children
+fn describe_node<'a>(
+    expr: &'a Expression,
+    registry: &ExtensionsRegistry,
+) -> (String, Vec<TreeChild<'a>>) {
+    let mut children = Vec::new();
+    let label = match &expr.rex_type {
+        None => "<missing rex_type>".to_string(),
+        Some(RexType::Literal(literal)) => format!(
+            "Literal {}{}",
+            render_literal(literal, registry).unwrap_or_else(|_| "<invalid>".to_string()),
+            tree_type(literal.data_type().ok().as_ref(), registry)
+        ),
+        Some(RexType::Selection(selection)) => {
+            let root = match &selection.root_type {
+                Some(RootType::OuterReference(outer)) => format!(" (outer {})", outer.steps_out),
+                Some(RootType::Expression(root)) => {
+                    children.push(TreeChild::Expr(root.as_ref()));
+                    " (of expression)".to_string()
+                }
+                _ => String::new(),
+            };
+            let segments = match &selection.reference_type {
+                Some(ReferenceType::DirectReference(segment)) => {
+                    render_reference(segment, &SchemaInfo::Empty(Default::default()), registry)
+                        .unwrap_or_else(|_| "<invalid>".to_string())
+                }
+                Some(ReferenceType::MaskedReference(_)) => "<mask>".to_string(),
+                None => "<missing>".to_string(),
+            };
+            format!("FieldReference {}{}", segments, root)
+        }
+        Some(RexType::ScalarFunction(func)) => {
+            children.extend(func.arguments.iter().map(|arg| match &arg.arg_type {
+                Some(ArgType::Value(value)) => TreeChild::Expr(value),
+                Some(ArgType::Enum(value)) => TreeChild::Leaf(format!("Enum {}", value)),
+                Some(ArgType::Type(typ)) => {
+                    TreeChild::Leaf(format!("Type {}", typ.to_human_readable(registry)))
+                }
+                None => TreeChild::Leaf("<missing argument>".to_string()),
+            }));
+            format!(
+                "ScalarFunction {}{}",
+                tree_function_name(func.function_reference, registry),
+                tree_type(func.output_type.as_ref(), registry)
+            )
+        }
+        Some(RexType::WindowFunction(func)) => {
+            children.extend(func.arguments.iter().filter_map(|arg| match &arg.arg_type {
+                Some(ArgType::Value(value)) => Some(TreeChild::Expr(value)),
+                _ => None,
+            }));
+            format!(
+                "WindowFunction {}{}",
+                tree_function_name(func.function_reference, registry),
+                tree_type(func.output_type.as_ref(), registry)
+            )
+        }
+        Some(RexType::Cast(cast)) => {
+            children.extend(cast.input.as_deref().map(TreeChild::Expr));
+            format!("Cast{}", tree_type(cast.r#type.as_ref(), registry))
         }
+        Some(RexType::SingularOrList(in_list)) => {
+            children.extend(in_list.value.as_deref().map(TreeChild::Expr));
+            children.extend(in_list.options.iter().map(TreeChild::Expr));
+            "InList".to_string()
+        }
+        Some(RexType::Nested(nested)) => {
+            let kind = match &nested.nested_type {
+                Some(NestedType::Struct(strct)) => {
+                    children.extend(strct.fields.iter().map(TreeChild::Expr));
+                    "struct"
+                }
+                Some(NestedType::List(list)) => {
+                    children.extend(list.values.iter().map(TreeChild::Expr));
+                    "list"
+                }
+                Some(NestedType::Map(map)) => {
+                    for key_value in &map.key_values {
+                        children.extend(
+                            key_value
+                                .key
+                                .iter()
+                                .chain(&key_value.value)
+                                .map(TreeChild::Expr),
+                        );
+                    }
+                    "map"
+                }
+                None => "<missing>",
+            };
+            format!("Nested {}", kind)
+        }
+        Some(_) => "<unsupported expression>".to_string(),
+    };
+    (label, children)
+}
+
+fn push_tree_lines(
+    expr: &Expression,
+    registry: &ExtensionsRegistry,
+    depth: usize,
+    lines: &mut Vec<String>,
+) {
+    let indent = "  ".repeat(depth);
+    let (label, children) = describe_node(expr, registry);
+    lines.push(format!("{}{}", indent, label));
+    for child in children {
+        match child {
+            TreeChild::Expr(child) => push_tree_lines(child, registry, depth + 1, lines),
+            TreeChild::Leaf(label) => lines.push(format!("{}  {}", indent, label)),
+        }
+    }
+}
+
+/// Builds the body of a Graphviz digraph for [`ExpressionExt::to_dot`]
+#[derive(Default)]
+struct DotWriter {
+    lines: Vec<String>,
+    num_nodes: usize,
+}
+
+impl DotWriter {
+    fn add_node(&mut self, label: &str, shape: &str) -> String {
+        let id = format!("n{}", self.num_nodes);
+        self.num_nodes += 1;
+        // Graphviz labels are quoted strings so quotes and backslashes must be escaped
+        let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+        self.lines
+            .push(format!("  {} [label=\"{}\", shape={}];", id, label, shape));
+        id
+    }
+
+    fn add_edge(&mut self, from: &str, to: &str) {
+        self.lines.push(format!("  {} -> {};", from, to));
+    }
+
+    /// Adds one node per segment of a field reference, chained from `parent`
+    fn add_segments(&mut self, parent: &str, segment: &ReferenceSegment) {
+        let mut parent = parent.to_string();
+        let mut segment = Some(segment);
+        while let Some(seg) = segment {
+            let (label, child) = match &seg.reference_type {
+                Some(reference_segment::ReferenceType::StructField(field)) => (
+                    format!("StructField {}", field.field),
+                    field.child.as_deref(),
+                ),
+                Some(reference_segment::ReferenceType::ListElement(element)) => (
+                    format!("ListElement {}", element.offset),
+                    element.child.as_deref(),
+                ),
+                Some(reference_segment::ReferenceType::MapKey(map_key)) => {
+                    ("MapKey".to_string(), map_key.child.as_deref())
+                }
+                None => ("<missing segment>".to_string(), None),
+            };
+            let id = self.add_node(&label, "ellipse");
+            self.add_edge(&parent, &id);
+            parent = id;
+            segment = child;
+        }
+    }
+
+    fn add_expression(&mut self, expr: &Expression, registry: &ExtensionsRegistry) -> String {
+        let (label, children) = describe_node(expr, registry);
+        let id = self.add_node(&label, "box");
+        if let Some(RexType::Selection(selection)) = &expr.rex_type {
+            if let Some(ReferenceType::DirectReference(segment)) = &selection.reference_type {
+                self.add_segments(&id, segment);
+            }
+        }
+        for child in children {
+            let child_id = match child {
+                TreeChild::Expr(child) => self.add_expression(child, registry),
+                TreeChild::Leaf(label) => self.add_node(&label, "plaintext"),
+            };
+            self.add_edge(&id, &child_id);
+        }
+        id
+    }
+}
+
+fn render_expression(
+    expr: &Expression,
+    schema: &SchemaInfo,
+    registry: &ExtensionsRegistry,
+) -> Result<String> {
+    match expr.try_rex_type()? {
+        RexType::Literal(literal) => render_literal(literal, registry),
+        RexType::ScalarFunction(func) => render_function(func, schema, registry),
+        RexType::Selection(selection) => {
+            let prefix = match selection.root_type.as_ref().required("root_type")? {
+                RootType::RootReference(_) => String::new(),
+                RootType::OuterReference(outer) => format!("outer({}).", outer.steps_out),
+                RootType::Expression(expr) => {
+                    format!("({}).", render_expression(expr, schema, registry)?)
+                }
+            };
+            match selection
+                .reference_type
+                .as_ref()
+                .required("reference_type")?
+            {
+                ReferenceType::DirectReference(root_segment) => {
+                    let schema = match selection.root_type {
+                        Some(RootType::RootReference(_)) => schema,
+                        // Outer and expression references do not refer to this schema
+                        _ => &SchemaInfo::Empty(Default::default()),
+                    };
+                    Ok(format!(
+                        "{}{}",
+                        prefix,
+                        render_reference(root_segment, schema, registry)?
+                    ))
+                }
+                ReferenceType::MaskedReference(_) => Ok(format!("{}<mask>", prefix)),
+            }
+        }
+        RexType::Cast(cast) => Ok(format!(
+            "cast({} AS {})",
+            render_expression(cast.input.as_ref().required("input")?, schema, registry)?,
+            cast.r#type
+                .as_ref()
+                .required("type")?
+                .to_human_readable(registry)
+        )),
+        RexType::SingularOrList(in_list) => Ok(format!(
+            "{} IN ({})",
+            render_expression(in_list.value.as_ref().required("value")?, schema, registry)?,
+            in_list
+                .options
+                .iter()
+                .map(|option| render_expression(option, schema, registry))
+                .collect::<Result<Vec<_>>>()?
+                .join(", ")
+        )),
+        _ => Err(SubstraitExprError::invalid_input(
+            "Rendering this kind of expression is not yet supported",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use substrait::proto::expression::Cast;
+
+    use crate::builder::functions::test_udfs::UDF_URI;
+    use crate::builder::functions::{CastFailureBehavior, FunctionsBuilder};
+    use crate::builder::schema::SchemaBuildersExt;
+    use crate::builder::{BuilderParams, ExpressionsBuilder};
+    use crate::functions::functions_arithmetic::{
+        FunctionsArithmeticExt, FunctionsArithmeticWindowExt,
+    };
+    use crate::functions::functions_comparison::FunctionsComparisonExt;
+    use crate::helpers::literals::{literal, literals, null_literal};
+    use crate::helpers::schema::EmptySchema;
+
+    use super::*;
+
+    fn cast(input: Expression, to: Type, failure_behavior: FailureBehavior) -> Expression {
+        Expression {
+            rex_type: Some(RexType::Cast(Box::new(Cast {
+                r#type: Some(to),
+                input: Some(Box::new(input)),
+                failure_behavior: failure_behavior as i32,
+            }))),
+        }
+    }
+
+    #[test]
+    fn test_cast_output_type() {
+        let schema = SchemaInfo::Empty(EmptySchema::default());
+        let unknown_input = FunctionsBuilder::new(&schema).lookup_field_by_name("x");
+
+        let throwing = cast(
+            unknown_input.clone(),
+            types::i64(false),
+            FailureBehavior::ThrowException,
+        );
+        assert_eq!(throwing.output_type(&schema).unwrap(), types::i64(false));
+
+        let returning_null = cast(
+            unknown_input,
+            types::i64(false),
+            FailureBehavior::ReturnNull,
+        );
+        assert_eq!(
+            returning_null.output_type(&schema).unwrap(),
+            types::i64(true)
+        );
+    }
+
+    #[test]
+    fn test_output_type_not_implemented() {
+        let schema = SchemaInfo::Empty(EmptySchema::default());
+        let subquery = Expression {
+            rex_type: Some(RexType::Subquery(Box::default())),
+        };
+        match subquery.output_type(&schema) {
+            Err(SubstraitExprError::NotImplemented(message)) => {
+                assert_eq!(message, "output_type for Subquery")
+            }
+            other => panic!("Expected a NotImplemented error but got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_as_comparison() {
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let registry = builder.schema().extensions_registry();
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let predicate = builder
+            .functions()
+            .lt(x.clone(), literal(10_i32))
+            .build()
+            .unwrap();
+        assert_eq!(
+            predicate.as_comparison(registry),
+            Some((ComparisonOp::Lt, &x, &literal(10_i32)))
+        );
+
+        let sum = builder
+            .functions()
+            .add(x.clone(), literal(10_i32))
+            .build()
+            .unwrap();
+        assert_eq!(sum.as_comparison(registry), None);
+        assert_eq!(x.as_comparison(registry), None);
+    }
+
+    #[test]
+    fn test_collect_types() {
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(true))
+            .field("y", types::list(false, types::fp64(false)))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let sum = builder.functions().add(x, literal(1_i32)).build().unwrap();
+        let predicate = builder
+            .functions()
+            .lt(sum, literal(10_i32))
+            .build()
+            .unwrap();
+        assert_eq!(
+            predicate.collect_types(builder.schema()).unwrap(),
+            vec![types::bool(false), types::i32(false)]
+        );
+
+        // The children of every kind of node are included
+        let y = builder.fields().resolve_by_name("y").unwrap();
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let in_list = builder
+            .functions()
+            .in_list(x, vec![literal(1_i32)])
+            .unwrap();
+        let strct = builder
+            .functions()
+            .make_struct(vec![literal("a"), y])
+            .unwrap();
+        let if_then = builder
+            .functions()
+            .if_then(vec![(in_list, strct)], None)
+            .unwrap();
+        assert_eq!(
+            if_then.collect_types(builder.schema()).unwrap(),
+            vec![
+                types::struct_(
+                    false,
+                    vec![types::string(false), types::list(false, types::fp64(false))]
+                ),
+                types::string(false),
+                types::list(false, types::fp64(false)),
+                types::fp64(false),
+                types::bool(false),
+                types::i32(false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_referenced_fields() {
+        let schema = SchemaInfo::new_full()
+            .field("a", types::i32(false))
+            .field("b", types::i32(false))
+            .nested("c", false, |builder| builder.field("x", types::i32(false)))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let a = builder.fields().resolve_by_name("a").unwrap();
+        let cx = builder.fields().resolve_by_name("c.x").unwrap();
+        let sum = builder.functions().add(a.clone(), cx).build().unwrap();
+        let predicate = builder.functions().lt(sum, a.clone()).build().unwrap();
+        assert_eq!(
+            predicate.referenced_fields().unwrap(),
+            BTreeSet::from([0, 2])
+        );
+        assert!(literal(3_i32).referenced_fields().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_remap_fields() {
+        let schema = SchemaInfo::new_full()
+            .field("a", types::i32(false))
+            .field("b", types::i32(false))
+            .nested("c", false, |builder| {
+                builder
+                    .field("x", types::i32(false))
+                    .field("y", types::i32(false))
+            })
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let a = builder.fields().resolve_by_name("a").unwrap();
+        let cy = builder.fields().resolve_by_name("c.y").unwrap();
+        let sum = builder.functions().add(a, cy).build().unwrap();
+
+        // b is pruned so c moves to index 1
+        let pruned_schema = SchemaInfo::new_full()
+            .field("a", types::i32(false))
+            .nested("c", false, |builder| {
+                builder
+                    .field("x", types::i32(false))
+                    .field("y", types::i32(false))
+            })
+            .build();
+        let pruned_builder = ExpressionsBuilder::new(pruned_schema, BuilderParams::default());
+        let a = pruned_builder.fields().resolve_by_name("a").unwrap();
+        let cy = pruned_builder.fields().resolve_by_name("c.y").unwrap();
+        let expected = pruned_builder.functions().add(a, cy).build().unwrap();
+
+        let mapping = HashMap::from([(0, 0), (2, 1)]);
+        assert_eq!(sum.remap_fields(&mapping).unwrap(), expected);
+        assert!(sum.remap_fields(&HashMap::from([(0, 0)])).is_err());
+    }
+
+    #[test]
+    fn test_semantic_eq() {
+        let schema = || SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let first = ExpressionsBuilder::new(schema(), BuilderParams::default());
+        let second = ExpressionsBuilder::new(schema(), BuilderParams::default());
+        // Register a function so the anchors in the second builder are different
+        second
+            .schema()
+            .extensions_registry()
+            .register_function_by_name(UDF_URI, "double");
+
+        let build = |builder: &ExpressionsBuilder, value: i32| {
+            let x = builder.fields().resolve_by_name("x").unwrap();
+            builder.functions().add(x, literal(value)).build().unwrap()
+        };
+        let lhs = build(&first, 1);
+        let rhs = build(&second, 1);
+        let first_registry = first.schema().extensions_registry();
+        let second_registry = second.schema().extensions_registry();
+        assert_ne!(lhs, rhs);
+        assert!(lhs.semantic_eq(&rhs, first_registry, second_registry));
+        assert!(!lhs.semantic_eq(&build(&second, 2), first_registry, second_registry));
+        // The anchors are interpreted with the wrong registry
+        assert!(!lhs.semantic_eq(&rhs, first_registry, first_registry));
+
+        // Functions inside other kinds of nodes are compared by name too
+        let branch = |builder: &ExpressionsBuilder, value: i32| {
+            let x = builder.fields().resolve_by_name("x").unwrap();
+            let condition = builder
+                .functions()
+                .lt(x.clone(), literal(value))
+                .build()
+                .unwrap();
+            let sum = build(builder, value);
+            let in_list = builder.functions().in_list(x, vec![sum]).unwrap();
+            builder
+                .functions()
+                .if_then(vec![(condition, in_list)], None)
+                .unwrap()
+        };
+        let lhs = branch(&first, 1);
+        let rhs = branch(&second, 1);
+        assert_ne!(lhs, rhs);
+        assert!(lhs.semantic_eq(&rhs, first_registry, second_registry));
+        assert!(!lhs.semantic_eq(&branch(&second, 2), first_registry, second_registry));
+    }
+
+    #[test]
+    fn test_semantic_hash() {
+        let schema = || {
+            SchemaInfo::new_full()
+                .field("x", types::fp64(false))
+                .build()
+        };
+        let first = ExpressionsBuilder::new(schema(), BuilderParams::default());
+        let second = ExpressionsBuilder::new(schema(), BuilderParams::default());
+        second
+            .schema()
+            .extensions_registry()
+            .register_function_by_name(UDF_URI, "double");
+
+        let build = |builder: &ExpressionsBuilder, value: f64| {
+            let x = builder.fields().resolve_by_name("x").unwrap();
+            let sum = builder
+                .functions()
+                .add(x.clone(), literal(value))
+                .build()
+                .unwrap();
+            builder
+                .functions()
+                .if_then(
+                    vec![(
+                        builder.functions().lt(x, sum).build().unwrap(),
+                        literal(true),
+                    )],
+                    None,
+                )
+                .unwrap()
+        };
+        let first_registry = first.schema().extensions_registry();
+        let second_registry = second.schema().extensions_registry();
+        let hash = |expr: &Expression, registry: &ExtensionsRegistry| {
+            expr.semantic_hash(registry).unwrap()
+        };
+        let lhs = build(&first, 1.0);
+        let rhs = build(&second, 1.0);
+        assert_ne!(lhs, rhs);
+        assert!(lhs.semantic_eq(&rhs, first_registry, second_registry));
+        assert_eq!(hash(&lhs, first_registry), hash(&rhs, second_registry));
+        assert_ne!(
+            hash(&lhs, first_registry),
+            hash(&build(&second, 2.0), second_registry)
+        );
+
+        // The two zeros are equal and so must have the same hash
+        let positive = build(&first, 0.0);
+        let negative = build(&second, -0.0);
+        assert!(positive.semantic_eq(&negative, first_registry, second_registry));
+        assert_eq!(
+            hash(&positive, first_registry),
+            hash(&negative, second_registry)
+        );
+
+        // Functions with different names can share an anchor in different registries
+        let third = ExpressionsBuilder::new(schema(), BuilderParams::default());
+        let x = third.fields().resolve_by_name("x").unwrap();
+        let difference = third.functions().subtract(x, literal(1.0)).build().unwrap();
+        let sum = first
+            .functions()
+            .add(first.fields().resolve_by_name("x").unwrap(), literal(1.0))
+            .build()
+            .unwrap();
+        let third_registry = third.schema().extensions_registry();
+        assert_eq!(difference, sum);
+        assert!(!difference.semantic_eq(&sum, third_registry, first_registry));
+        assert_ne!(
+            hash(&difference, third_registry),
+            hash(&sum, first_registry)
+        );
+
+        let empty = ExtensionsRegistry::default();
+        assert!(lhs.semantic_hash(&empty).is_err());
+    }
+
+    #[test]
+    fn test_as_const_bool() {
+        assert_eq!(literal(true).as_const_bool(), Some(true));
+        assert_eq!(literal(false).as_const_bool(), Some(false));
+        assert_eq!(null_literal(types::bool(true)).as_const_bool(), None);
+        assert_eq!(literal(1_i32).as_const_bool(), None);
+
+        // Predicates are only constant once they have been folded to a literal
+        let schema = SchemaInfo::Empty(EmptySchema::default());
+        let predicate = FunctionsBuilder::new(&schema)
+            .lt(literal(1_i32), literal(2_i32))
+            .build()
+            .unwrap();
+        assert_eq!(predicate.as_const_bool(), None);
+    }
+
+    #[test]
+    fn test_human_readable() {
+        let loose = ExpressionsBuilder::new(
+            SchemaInfo::Empty(EmptySchema::default()),
+            BuilderParams::new_loose(),
+        );
+        let x = loose.fields().resolve_by_name("x").unwrap();
+        let sum = loose.functions().add(x, literal(3_i32)).build().unwrap();
+        assert_eq!(sum.to_human_readable(loose.schema()).unwrap(), "x + 3");
+
+        let schema = SchemaInfo::new_full()
+            .field("score", types::fp64(false))
+            .nested("location", false, |builder| {
+                builder
+                    .field("x", types::fp64(false))
+                    .field("y", types::fp64(false))
+            })
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let location_x = builder.fields().resolve_by_name("location.x").unwrap();
+        let scaled = builder
+            .functions()
+            .multiply(
+                builder
+                    .functions()
+                    .add(location_x.clone(), literal(1.5_f64))
+                    .build()
+                    .unwrap(),
+                literal(2.0_f64),
+            )
+            .build()
+            .unwrap();
+        assert_eq!(
+            scaled.to_human_readable(builder.schema()).unwrap(),
+            "(location.x + 1.5) * 2.0"
+        );
+        let sqrt = builder.functions().sqrt(location_x).build().unwrap();
+        assert_eq!(
+            sqrt.to_human_readable(builder.schema()).unwrap(),
+            "sqrt(location.x)"
+        );
+
+        let types_only = SchemaInfo::new_types()
+            .field(types::fp64(false))
+            .nested(false, |builder| builder.field(types::fp64(false)))
+            .build();
+        let by_index = ExpressionsBuilder::new(types_only, BuilderParams::default());
+        let reference = by_index.fields().resolve_by_index(&[1, 0]).unwrap();
+        assert_eq!(
+            reference.to_human_readable(by_index.schema()).unwrap(),
+            "#1.#0"
+        );
+
+        let decimal = literals::decimal(-1205, 6, 3).unwrap();
+        assert_eq!(decimal.to_human_readable(loose.schema()).unwrap(), "-1.205");
+    }
+
+    #[test]
+    fn test_to_sql_string() {
+        let schema = || {
+            SchemaInfo::new_full()
+                .nested("location", false, |builder| {
+                    builder.field("x", types::fp64(false))
+                })
+                .build()
+        };
+        let builder = ExpressionsBuilder::new(schema(), BuilderParams::default());
+        let location_x = builder.fields().resolve_by_name("location.x").unwrap();
+        let sum = builder
+            .functions()
+            .add(location_x.clone(), literal(3.0_f64))
+            .build()
+            .unwrap();
+        let in_list = builder
+            .functions()
+            .in_list(location_x, vec![literal(1.0_f64), literal(2.0_f64)])
+            .unwrap();
+        builder
+            .add_expression("sum", sum.clone())
+            .unwrap()
+            .add_expression("in_list", in_list.clone())
+            .unwrap();
+        let msg = builder.build();
+
+        // The functions are only registered in the message's registry
+        let registry =
+            ExtensionsRegistry::from_substrait(&msg.extension_uris, &msg.extensions).unwrap();
+        let fresh_schema = schema();
+        assert!(sum.to_human_readable(&fresh_schema).is_err());
+        assert_eq!(
+            sum.to_sql_string(&fresh_schema, &registry).unwrap(),
+            "location.x + 3.0"
+        );
+        assert_eq!(
+            in_list.to_sql_string(&fresh_schema, &registry).unwrap(),
+            "location.x IN (1.0, 2.0)"
+        );
+    }
+
+    #[test]
+    fn test_to_tree_string() {
+        let schema = SchemaInfo::new_full()
+            .nested("location", false, |builder| {
+                builder.field("x", types::fp64(false))
+            })
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let location_x = builder.fields().resolve_by_name("location.x").unwrap();
+        let sum = builder
+            .functions()
+            .add(location_x, literal(3.0_f64))
+            .build()
+            .unwrap();
+        let registry = builder.schema().extensions_registry();
+        assert_eq!(
+            sum.to_tree_string(registry),
+            "ScalarFunction add : fp64\n  FieldReference #0.#0\n  Literal 3.0 : fp64"
+        );
+
+        let loose = ExpressionsBuilder::new(
+            SchemaInfo::Empty(EmptySchema::default()),
+            BuilderParams::new_loose(),
+        );
+        let y = loose.fields().resolve_by_name("y").unwrap();
+        let tree = y.to_tree_string(loose.schema().extensions_registry());
+        assert!(tree.starts_with("ScalarFunction lookup_by_name [UNKNOWN]"));
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let schema = SchemaInfo::new_full()
+            .nested("location", false, |builder| {
+                builder.field("x", types::i32(false))
+            })
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let location_x = builder.fields().resolve_by_name("location.x").unwrap();
+        let cast = builder
+            .functions()
+            .cast(
+                location_x,
+                types::i64(false),
+                CastFailureBehavior::ThrowException,
+            )
+            .unwrap();
+        let sum = builder
+            .functions()
+            .add(cast, literal(3_i64))
+            .build()
+            .unwrap();
+        let dot = sum.to_dot(builder.schema().extensions_registry());
+        let expected = [
+            "digraph expression {",
+            "  n0 [label=\"ScalarFunction add : i64\", shape=box];",
+            "  n1 [label=\"Cast : i64\", shape=box];",
+            "  n2 [label=\"FieldReference #0.#0\", shape=box];",
+            "  n3 [label=\"StructField 0\", shape=ellipse];",
+            "  n2 -> n3;",
+            "  n4 [label=\"StructField 0\", shape=ellipse];",
+            "  n3 -> n4;",
+            "  n1 -> n2;",
+            "  n0 -> n1;",
+            "  n5 [label=\"Literal 3 : i64\", shape=box];",
+            "  n0 -> n5;",
+            "}",
+            "",
+        ]
+        .join("\n");
+        assert_eq!(dot, expected);
+    }
+
+    #[test]
+    fn test_contains_unknown() {
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let sum = builder.functions().add(x, literal(3_i32)).build().unwrap();
+        let registry = builder.schema().extensions_registry();
+        assert!(!sum.contains_unknown(registry).unwrap());
+
+        let loose = ExpressionsBuilder::new(
+            SchemaInfo::Empty(EmptySchema::default()),
+            BuilderParams::new_loose(),
+        );
+        let y = loose.fields().resolve_by_name("y").unwrap();
+        let sum = loose.functions().add(y, literal(3_i32)).build().unwrap();
+        let registry = loose.schema().extensions_registry();
+        assert!(sum.contains_unknown(registry).unwrap());
+        // The partitions of a window function are checked too
+        let y = loose.fields().resolve_by_name("y").unwrap();
+        let ranked = loose
+            .functions()
+            .row_number()
+            .partition_by(y)
+            .build()
+            .unwrap();
+        assert!(ranked.contains_unknown(registry).unwrap());
+
+        // Unknown types nested inside other types are found too
+        let unknown = crate::builder::types::unknown(registry);
+        assert!(types::list(false, unknown.clone()).contains_unknown(registry));
+        assert!(types::struct_(false, vec![types::i32(false), unknown]).contains_unknown(registry));
+        assert!(!types::list(false, types::i32(false)).contains_unknown(registry));
     }
 }
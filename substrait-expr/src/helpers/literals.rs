@@ -1,21 +1,89 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+#[cfg(feature = "chrono")]
+use substrait::proto::expression::literal::PrecisionTimestamp;
 use substrait::proto::{
-    expression::{literal::LiteralType, Literal},
+    expression::{
+        literal::{
+            interval_day_to_second::PrecisionMode, map::KeyValue, List, LiteralType, Map, Struct,
+        },
+        Literal,
+    },
+    r#type::Kind,
     Expression, Type,
 };
 
 use crate::error::{Result, SubstraitExprError};
+use crate::util::HasRequiredPropertiesRef;
 
-use super::types;
+use super::types::{self, TypeExt, TypeInfer};
 
 /// Extends the protobuf Literal object with useful helper methods
 pub trait LiteralExt {
     /// Get the substrait type of a literal
     fn data_type(&self) -> Result<Type>;
+    /// Checks that the literal's stored value fits in its declared type
+    ///
+    /// Literals created by this crate are always valid.  However, literals decoded from
+    /// untrusted messages may, for example, store 300 in an i8 literal (i8 values are
+    /// stored as i32).  Struct literals are validated recursively.
+    fn validate(&self) -> Result<()>;
+}
+
+fn validate_range(value: i64, min: i64, max: i64, type_name: &str) -> Result<()> {
+    if value < min || value > max {
+        Err(SubstraitExprError::invalid_substrait(format!(
+            "The value {} is out of range for a {} literal",
+            value, type_name
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+const MICROS_PER_DAY: i64 = 86_400_000_000;
+const MAX_TIMESTAMP_PRECISION: i32 = 12;
+
+/// Returns the name of a literal's variant for error messages
+fn literal_type_name(literal_type: &LiteralType) -> &'static str {
+    match literal_type {
+        LiteralType::Boolean(_) => "Boolean",
+        LiteralType::I8(_) => "I8",
+        LiteralType::I16(_) => "I16",
+        LiteralType::I32(_) => "I32",
+        LiteralType::I64(_) => "I64",
+        LiteralType::Fp32(_) => "Fp32",
+        LiteralType::Fp64(_) => "Fp64",
+        LiteralType::String(_) => "String",
+        LiteralType::Binary(_) => "Binary",
+        LiteralType::Timestamp(_) => "Timestamp",
+        LiteralType::Date(_) => "Date",
+        LiteralType::Time(_) => "Time",
+        LiteralType::IntervalYearToMonth(_) => "IntervalYearToMonth",
+        LiteralType::IntervalDayToSecond(_) => "IntervalDayToSecond",
+        LiteralType::IntervalCompound(_) => "IntervalCompound",
+        LiteralType::FixedChar(_) => "FixedChar",
+        LiteralType::VarChar(_) => "VarChar",
+        LiteralType::FixedBinary(_) => "FixedBinary",
+        LiteralType::Decimal(_) => "Decimal",
+        LiteralType::PrecisionTimestamp(_) => "PrecisionTimestamp",
+        LiteralType::PrecisionTimestampTz(_) => "PrecisionTimestampTz",
+        LiteralType::Struct(_) => "Struct",
+        LiteralType::Map(_) => "Map",
+        LiteralType::TimestampTz(_) => "TimestampTz",
+        LiteralType::Uuid(_) => "Uuid",
+        LiteralType::Null(_) => "Null",
+        LiteralType::List(_) => "List",
+        LiteralType::EmptyList(_) => "EmptyList",
+        LiteralType::EmptyMap(_) => "EmptyMap",
+        LiteralType::UserDefined(_) => "UserDefined",
+    }
 }
 
 impl LiteralExt for Literal {
     fn data_type(&self) -> Result<Type> {
-        match &self.literal_type {
+        let mut data_type = match &self.literal_type {
             Some(LiteralType::Binary(_)) => Ok(types::binary(self.nullable)),
             Some(LiteralType::Boolean(_)) => Ok(types::bool(self.nullable)),
             Some(LiteralType::Fp32(_)) => Ok(types::fp32(self.nullable)),
@@ -25,11 +93,171 @@ impl LiteralExt for Literal {
             Some(LiteralType::I32(_)) => Ok(types::i32(self.nullable)),
             Some(LiteralType::I64(_)) => Ok(types::i64(self.nullable)),
             Some(LiteralType::Null(data_type)) => Ok(data_type.clone()),
+            Some(LiteralType::Decimal(decimal)) => {
+                let precision = u8::try_from(decimal.precision).map_err(|_| {
+                    SubstraitExprError::invalid_substrait(format!(
+                        "Invalid decimal literal precision {}",
+                        decimal.precision
+                    ))
+                })?;
+                let scale = u8::try_from(decimal.scale).map_err(|_| {
+                    SubstraitExprError::invalid_substrait(format!(
+                        "Invalid decimal literal scale {}",
+                        decimal.scale
+                    ))
+                })?;
+                types::decimal(precision, scale, self.nullable)
+            }
             Some(LiteralType::String(_)) => Ok(types::string(self.nullable)),
+            Some(LiteralType::FixedBinary(value)) => {
+                let length = u32::try_from(value.len()).map_err(|_| {
+                    SubstraitExprError::invalid_substrait(
+                        "A fixed binary literal was too long to describe with a type",
+                    )
+                })?;
+                Ok(types::fixed_binary(length, self.nullable))
+            }
+            Some(LiteralType::Uuid(value)) => {
+                if value.len() != 16 {
+                    return Err(SubstraitExprError::invalid_substrait(format!(
+                        "A uuid literal must have 16 bytes but had {}",
+                        value.len()
+                    )));
+                }
+                Ok(types::uuid(self.nullable))
+            }
+            Some(LiteralType::IntervalYearToMonth(_)) => Ok(types::interval_year(self.nullable)),
+            Some(LiteralType::IntervalDayToSecond(interval)) => {
+                let precision = match interval.precision_mode {
+                    Some(PrecisionMode::Precision(precision)) => {
+                        u8::try_from(precision).map_err(|_| {
+                            SubstraitExprError::invalid_substrait(format!(
+                                "Invalid interval literal precision {}",
+                                precision
+                            ))
+                        })?
+                    }
+                    // The deprecated microseconds field implies microsecond precision
+                    Some(PrecisionMode::Microseconds(_)) | None => types::MICROSECOND_PRECISION,
+                };
+                Ok(types::interval_day(precision, self.nullable))
+            }
+            Some(LiteralType::Date(_)) => Ok(types::date(self.nullable)),
+            Some(LiteralType::Time(_)) => Ok(types::time(self.nullable)),
+            Some(LiteralType::PrecisionTimestamp(timestamp)) => {
+                let precision = u8::try_from(timestamp.precision).map_err(|_| {
+                    SubstraitExprError::invalid_substrait(format!(
+                        "Invalid timestamp literal precision {}",
+                        timestamp.precision
+                    ))
+                })?;
+                Ok(types::precision_timestamp(precision, self.nullable))
+            }
+            Some(LiteralType::List(list)) => {
+                let item = list.values.first().ok_or_else(|| {
+                    SubstraitExprError::invalid_substrait(
+                        "A list literal had no values (empty lists should use EmptyList)",
+                    )
+                })?;
+                Ok(types::list(self.nullable, item.data_type()?))
+            }
+            Some(LiteralType::EmptyList(list)) => Ok(Type {
+                kind: Some(Kind::List(Box::new(list.clone()))),
+            }
+            .with_nullability(self.nullable)),
+            Some(LiteralType::Map(map)) => {
+                let key_value = map.key_values.first().ok_or_else(|| {
+                    SubstraitExprError::invalid_substrait(
+                        "A map literal had no entries (empty maps should use EmptyMap)",
+                    )
+                })?;
+                let key = key_value.key.required("key")?;
+                let value = key_value.value.required("value")?;
+                Ok(types::map(
+                    self.nullable,
+                    key.data_type()?,
+                    value.data_type()?,
+                ))
+            }
+            Some(LiteralType::EmptyMap(map)) => Ok(Type {
+                kind: Some(Kind::Map(Box::new(map.clone()))),
+            }
+            .with_nullability(self.nullable)),
+            Some(LiteralType::Struct(strct)) => Ok(types::struct_(
+                self.nullable,
+                strct
+                    .fields
+                    .iter()
+                    .map(|field| field.data_type())
+                    .collect::<Result<Vec<_>>>()?,
+            )),
+            None => Err(SubstraitExprError::invalid_substrait(
+                "Literal was missing required literal_type property",
+            )),
+            Some(other) => Err(SubstraitExprError::not_implemented(format!(
+                "data_type for {} literals",
+                literal_type_name(other)
+            ))),
+        }?;
+        // A null literal's type already carries its variation
+        if !matches!(self.literal_type, Some(LiteralType::Null(_))) {
+            types::set_variation(&mut data_type, self.type_variation_reference);
+        }
+        Ok(data_type)
+    }
+
+    fn validate(&self) -> Result<()> {
+        match &self.literal_type {
+            Some(LiteralType::I8(value)) => {
+                validate_range(*value as i64, i8::MIN as i64, i8::MAX as i64, "i8")
+            }
+            Some(LiteralType::I16(value)) => {
+                validate_range(*value as i64, i16::MIN as i64, i16::MAX as i64, "i16")
+            }
+            Some(LiteralType::Time(value)) => validate_range(*value, 0, MICROS_PER_DAY - 1, "time"),
+            Some(LiteralType::PrecisionTimestamp(timestamp))
+            | Some(LiteralType::PrecisionTimestampTz(timestamp)) => validate_range(
+                timestamp.precision as i64,
+                0,
+                MAX_TIMESTAMP_PRECISION as i64,
+                "timestamp precision",
+            ),
+            Some(LiteralType::VarChar(varchar)) => {
+                if varchar.value.len() > varchar.length as usize {
+                    Err(SubstraitExprError::invalid_substrait(format!(
+                        "A string of length {} does not fit in a varchar literal of length {}",
+                        varchar.value.len(),
+                        varchar.length
+                    )))
+                } else {
+                    Ok(())
+                }
+            }
+            Some(LiteralType::Decimal(decimal)) => {
+                // Validates the precision and scale
+                self.data_type()?;
+                let value: [u8; 16] = decimal.value.as_slice().try_into().map_err(|_| {
+                    SubstraitExprError::invalid_substrait(format!(
+                        "A decimal literal must have 16 bytes but had {}",
+                        decimal.value.len()
+                    ))
+                })?;
+                let max_value = 10_u128.pow(decimal.precision as u32);
+                if i128::from_le_bytes(value).unsigned_abs() >= max_value {
+                    Err(SubstraitExprError::invalid_substrait(format!(
+                        "The value {} does not fit in a decimal literal with precision {}",
+                        i128::from_le_bytes(value),
+                        decimal.precision
+                    )))
+                } else {
+                    Ok(())
+                }
+            }
+            Some(LiteralType::Struct(strct)) => strct.fields.iter().try_for_each(Literal::validate),
             None => Err(SubstraitExprError::invalid_substrait(
                 "Literal was missing required literal_type property",
             )),
-            _ => todo!(),
+            _ => Ok(()),
         }
     }
 }
@@ -156,47 +384,337 @@ impl LiteralInference for f64 {
     }
 }
 
+/// String slices can only be converted into literals
+///
+/// A literal cannot be borrowed as a `&str`, use `String` to decode string literals
 impl LiteralInference for &str {
     fn to_substrait(self) -> LiteralType {
         LiteralType::String(self.to_owned())
     }
     fn try_from_substrait(_: &LiteralType) -> Result<Self> {
-        todo!()
+        Err(SubstraitExprError::invalid_input(
+            "A literal cannot be decoded as a &str, use String instead",
+        ))
     }
 }
 
+/// String, fixed char, and varchar literals can all be decoded as a String
 impl LiteralInference for String {
     fn to_substrait(self) -> LiteralType {
         LiteralType::String(self)
     }
     fn try_from_substrait(lit: &LiteralType) -> Result<Self> {
         match lit {
-            LiteralType::String(value) => Ok(value.to_string()),
-            _ => Err(crate::error::SubstraitExprError::invalid_substrait(
-                format!("Expected a string literal but found {:?}", lit),
-            )),
+            LiteralType::String(value) | LiteralType::FixedChar(value) => Ok(value.clone()),
+            LiteralType::VarChar(varchar) => Ok(varchar.value.clone()),
+            _ => Err(SubstraitExprError::invalid_substrait(format!(
+                "Expected a string literal but found {:?}",
+                lit
+            ))),
         }
     }
 }
 
+/// Byte slices can only be converted into literals
+///
+/// A literal cannot be borrowed as a `&[u8]`, use `Vec<u8>` to decode binary literals
 impl LiteralInference for &[u8] {
     fn to_substrait(self) -> LiteralType {
         LiteralType::Binary(Vec::from(self))
     }
     fn try_from_substrait(_: &LiteralType) -> Result<Self> {
-        todo!()
+        Err(SubstraitExprError::invalid_input(
+            "A literal cannot be decoded as a &[u8], use Vec<u8> instead",
+        ))
     }
 }
 
+/// Binary and fixed binary literals can both be decoded as a Vec<u8>
 impl LiteralInference for Vec<u8> {
     fn to_substrait(self) -> LiteralType {
         LiteralType::Binary(self)
     }
     fn try_from_substrait(lit: &LiteralType) -> Result<Self> {
         match lit {
-            LiteralType::Binary(value) => Ok(value.clone()),
+            LiteralType::Binary(value) | LiteralType::FixedBinary(value) => Ok(value.clone()),
+            _ => Err(SubstraitExprError::invalid_substrait(format!(
+                "Expected a binary literal but found {:?}",
+                lit
+            ))),
+        }
+    }
+}
+
+/// Wraps a rust value as a literal that can be placed in a collection
+///
+/// The literal is nullable if `T` is an `Option`
+fn element_literal<T: LiteralInference + TypeInfer>(value: T) -> Literal {
+    Literal {
+        nullable: T::as_substrait(false).is_nullable().unwrap_or(false),
+        type_variation_reference: NO_TYPE_VARIATION,
+        literal_type: Some(value.to_substrait()),
+    }
+}
+
+/// Decodes a literal stored in a collection
+fn element_value<T: LiteralInference>(literal: Option<&Literal>) -> Result<T> {
+    let literal_type = literal
+        .and_then(|literal| literal.literal_type.as_ref())
+        .ok_or_else(|| {
+            SubstraitExprError::invalid_substrait("A nested literal was missing its literal_type")
+        })?;
+    T::try_from_substrait(literal_type)
+}
+
+/// A null literal decodes to None and any other literal is decoded as the inner type
+impl<T: LiteralInference + TypeInfer> LiteralInference for Option<T> {
+    fn to_substrait(self) -> LiteralType {
+        match self {
+            Some(value) => value.to_substrait(),
+            None => LiteralType::Null(T::as_substrait(true)),
+        }
+    }
+    fn try_from_substrait(lit: &LiteralType) -> Result<Self> {
+        match lit {
+            LiteralType::Null(_) => Ok(None),
+            _ => T::try_from_substrait(lit).map(Some),
+        }
+    }
+}
+
+/// Vectors are list literals
+///
+/// Use `Vec<Option<T>>` for lists that may contain nulls
+impl<T: LiteralInference + TypeInfer> LiteralInference for Vec<T> {
+    fn to_substrait(self) -> LiteralType {
+        if self.is_empty() {
+            match Self::as_substrait(false).kind {
+                Some(Kind::List(list)) => LiteralType::EmptyList(*list),
+                _ => unreachable!("The type of a vector is always a list"),
+            }
+        } else {
+            LiteralType::List(List {
+                values: self.into_iter().map(element_literal).collect(),
+            })
+        }
+    }
+    fn try_from_substrait(lit: &LiteralType) -> Result<Self> {
+        match lit {
+            LiteralType::List(list) => list
+                .values
+                .iter()
+                .map(|value| element_value(Some(value)))
+                .collect(),
+            LiteralType::EmptyList(_) => Ok(Vec::new()),
+            _ => Err(SubstraitExprError::invalid_substrait(format!(
+                "Expected a list literal but found {:?}",
+                lit
+            ))),
+        }
+    }
+}
+
+fn map_to_substrait<K, V>(
+    map_type: Type,
+    entries: impl ExactSizeIterator<Item = (K, V)>,
+) -> LiteralType
+where
+    K: LiteralInference + TypeInfer,
+    V: LiteralInference + TypeInfer,
+{
+    if entries.len() == 0 {
+        match map_type.kind {
+            Some(Kind::Map(map)) => LiteralType::EmptyMap(*map),
+            _ => unreachable!("The type of a map is always a map"),
+        }
+    } else {
+        LiteralType::Map(Map {
+            key_values: entries
+                .map(|(key, value)| KeyValue {
+                    key: Some(element_literal(key)),
+                    value: Some(element_literal(value)),
+                })
+                .collect(),
+        })
+    }
+}
+
+fn map_from_substrait<K, V, M>(lit: &LiteralType) -> Result<M>
+where
+    K: LiteralInference,
+    V: LiteralInference,
+    M: FromIterator<(K, V)>,
+{
+    match lit {
+        LiteralType::Map(map) => map
+            .key_values
+            .iter()
+            .map(|key_value| {
+                Ok((
+                    element_value(key_value.key.as_ref())?,
+                    element_value(key_value.value.as_ref())?,
+                ))
+            })
+            .collect(),
+        LiteralType::EmptyMap(_) => Ok(M::from_iter(std::iter::empty())),
+        _ => Err(SubstraitExprError::invalid_substrait(format!(
+            "Expected a map literal but found {:?}",
+            lit
+        ))),
+    }
+}
+
+impl<K, V> LiteralInference for BTreeMap<K, V>
+where
+    K: LiteralInference + TypeInfer + Ord,
+    V: LiteralInference + TypeInfer,
+{
+    fn to_substrait(self) -> LiteralType {
+        map_to_substrait(Self::as_substrait(false), self.into_iter())
+    }
+    fn try_from_substrait(lit: &LiteralType) -> Result<Self> {
+        map_from_substrait(lit)
+    }
+}
+
+impl<K, V> LiteralInference for HashMap<K, V>
+where
+    K: LiteralInference + TypeInfer + Eq + Hash,
+    V: LiteralInference + TypeInfer,
+{
+    fn to_substrait(self) -> LiteralType {
+        map_to_substrait(Self::as_substrait(false), self.into_iter())
+    }
+    fn try_from_substrait(lit: &LiteralType) -> Result<Self> {
+        map_from_substrait(lit)
+    }
+}
+
+macro_rules! impl_tuple_literal_inference {
+    ($len:literal; $($name:ident: $idx:tt),+) => {
+        /// Tuples are struct literals with one field per element
+        impl<$($name: LiteralInference + TypeInfer),+> LiteralInference for ($($name,)+) {
+            fn to_substrait(self) -> LiteralType {
+                LiteralType::Struct(Struct {
+                    fields: vec![$(element_literal(self.$idx)),+],
+                })
+            }
+            fn try_from_substrait(lit: &LiteralType) -> Result<Self> {
+                match lit {
+                    LiteralType::Struct(strct) if strct.fields.len() == $len => {
+                        Ok(($(element_value::<$name>(strct.fields.get($idx))?,)+))
+                    }
+                    _ => Err(SubstraitExprError::invalid_substrait(format!(
+                        "Expected a struct literal with {} fields but found {:?}",
+                        $len, lit
+                    ))),
+                }
+            }
+        }
+    };
+}
+
+impl_tuple_literal_inference!(1; A: 0);
+impl_tuple_literal_inference!(2; A: 0, B: 1);
+impl_tuple_literal_inference!(3; A: 0, B: 1, C: 2);
+impl_tuple_literal_inference!(4; A: 0, B: 1, C: 2, D: 3);
+
+#[cfg(feature = "chrono")]
+const MICROS_PER_SECOND: i64 = 1_000_000;
+
+#[cfg(feature = "chrono")]
+fn unix_epoch_date() -> chrono::NaiveDate {
+    chrono::NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()
+}
+
+/// Dates are encoded as the number of days since the UNIX epoch
+///
+/// Dates before the epoch are encoded as negative values
+#[cfg(feature = "chrono")]
+impl LiteralInference for chrono::NaiveDate {
+    fn to_substrait(self) -> LiteralType {
+        LiteralType::Date(self.signed_duration_since(unix_epoch_date()).num_days() as i32)
+    }
+    fn try_from_substrait(lit: &LiteralType) -> Result<Self> {
+        match lit {
+            LiteralType::Date(days) => unix_epoch_date()
+                .checked_add_signed(chrono::TimeDelta::days(*days as i64))
+                .ok_or_else(|| {
+                    SubstraitExprError::invalid_substrait(format!(
+                        "The date literal {} is outside the range supported by chrono",
+                        days
+                    ))
+                }),
             _ => Err(crate::error::SubstraitExprError::invalid_substrait(
-                format!("Expected a binary literal but found {:?}", lit),
+                format!("Expected a date literal but found {:?}", lit),
+            )),
+        }
+    }
+}
+
+/// Times are encoded as the number of microseconds past midnight
+///
+/// Any sub-microsecond component is truncated
+#[cfg(feature = "chrono")]
+impl LiteralInference for chrono::NaiveTime {
+    fn to_substrait(self) -> LiteralType {
+        use chrono::Timelike;
+        LiteralType::Time(
+            self.num_seconds_from_midnight() as i64 * MICROS_PER_SECOND
+                + (self.nanosecond() / 1000) as i64,
+        )
+    }
+    fn try_from_substrait(lit: &LiteralType) -> Result<Self> {
+        match lit {
+            LiteralType::Time(micros) => u32::try_from(micros.div_euclid(MICROS_PER_SECOND))
+                .ok()
+                .and_then(|seconds| {
+                    let nanos = micros.rem_euclid(MICROS_PER_SECOND) as u32 * 1000;
+                    chrono::NaiveTime::from_num_seconds_from_midnight_opt(seconds, nanos)
+                })
+                .ok_or_else(|| {
+                    SubstraitExprError::invalid_substrait(format!(
+                        "The time literal {} is not a valid time of day",
+                        micros
+                    ))
+                }),
+            _ => Err(crate::error::SubstraitExprError::invalid_substrait(
+                format!("Expected a time literal but found {:?}", lit),
+            )),
+        }
+    }
+}
+
+/// Timestamps are encoded as precision timestamps with microsecond precision
+///
+/// Any sub-microsecond component is truncated
+#[cfg(feature = "chrono")]
+impl LiteralInference for chrono::NaiveDateTime {
+    fn to_substrait(self) -> LiteralType {
+        LiteralType::PrecisionTimestamp(PrecisionTimestamp {
+            precision: types::MICROSECOND_PRECISION as i32,
+            value: self.and_utc().timestamp_micros(),
+        })
+    }
+    fn try_from_substrait(lit: &LiteralType) -> Result<Self> {
+        match lit {
+            LiteralType::PrecisionTimestamp(timestamp)
+                if timestamp.precision == types::MICROSECOND_PRECISION as i32 =>
+            {
+                chrono::DateTime::from_timestamp_micros(timestamp.value)
+                    .map(|datetime| datetime.naive_utc())
+                    .ok_or_else(|| {
+                        SubstraitExprError::invalid_substrait(format!(
+                            "The timestamp literal {} is outside the range supported by chrono",
+                            timestamp.value
+                        ))
+                    })
+            }
+            _ => Err(crate::error::SubstraitExprError::invalid_substrait(
+                format!(
+                    "Expected a microsecond precision timestamp literal but found {:?}",
+                    lit
+                ),
             )),
         }
     }
@@ -216,7 +734,9 @@ fn make_literal(lit_type: LiteralType, nullable: bool) -> Expression {
 
 /// Methods for creating literals from rust
 pub mod literals {
-    use substrait::proto::expression::literal::{Struct, VarChar};
+    use substrait::proto::expression::literal::{
+        Decimal, IntervalDayToSecond, IntervalYearToMonth, Struct, VarChar,
+    };
 
     use crate::{error::SubstraitExprError, helpers::expr::ExpressionExt};
 
@@ -242,6 +762,34 @@ pub mod literals {
         make_literal(LiteralType::FixedBinary(value), false)
     }
 
+    /// Create a uuid literal from its 16 bytes
+    pub fn uuid(value: [u8; 16]) -> Expression {
+        make_literal(LiteralType::Uuid(value.to_vec()), false)
+    }
+
+    /// Create an interval_year literal from a number of years and months
+    pub fn interval_year(years: i32, months: i32) -> Expression {
+        make_literal(
+            LiteralType::IntervalYearToMonth(IntervalYearToMonth { years, months }),
+            false,
+        )
+    }
+
+    /// Create an interval_day literal from a number of days and seconds
+    ///
+    /// The literal has no sub-second component (precision 0)
+    pub fn interval_day(days: i32, seconds: i32) -> Expression {
+        make_literal(
+            LiteralType::IntervalDayToSecond(IntervalDayToSecond {
+                days,
+                seconds,
+                subseconds: 0,
+                precision_mode: Some(PrecisionMode::Precision(0)),
+            }),
+            false,
+        )
+    }
+
     /// Create a var-char literal
     ///
     /// There are three primary string types in Substrait, string, fixed-char, and var-char
@@ -269,6 +817,53 @@ pub mod literals {
         }
     }
 
+    /// Create a decimal literal
+    ///
+    /// `value` is the unscaled value (e.g. 12345 with a scale of 2 is 123.45)
+    ///
+    /// Returns an error if the precision or scale are invalid (see [types::decimal]) or
+    /// if the value has more digits than the precision allows
+    pub fn decimal(value: i128, precision: u8, scale: u8) -> Result<Expression> {
+        types::decimal(precision, scale, false)?;
+        let max_value = 10_i128.pow(precision as u32);
+        if value.unsigned_abs() >= max_value as u128 {
+            return Err(SubstraitExprError::invalid_input(format!(
+                "The value {} does not fit in a decimal with precision {}",
+                value, precision
+            )));
+        }
+        Ok(make_literal(
+            LiteralType::Decimal(Decimal {
+                value: value.to_le_bytes().to_vec(),
+                precision: precision as i32,
+                scale: scale as i32,
+            }),
+            false,
+        ))
+    }
+
+    /// Create a timestamp literal with microsecond precision
+    ///
+    /// Any sub-microsecond component is truncated
+    #[cfg(feature = "chrono")]
+    pub fn timestamp(value: chrono::NaiveDateTime) -> Expression {
+        literal(value)
+    }
+
+    /// Create a date literal
+    #[cfg(feature = "chrono")]
+    pub fn date(value: chrono::NaiveDate) -> Expression {
+        literal(value)
+    }
+
+    /// Create a time literal with microsecond precision
+    ///
+    /// Any sub-microsecond component is truncated
+    #[cfg(feature = "chrono")]
+    pub fn time(value: chrono::NaiveTime) -> Expression {
+        literal(value)
+    }
+
     /// Create a struct literal
     ///
     /// `children` must all be literal expressions and will be the children of the struct
@@ -279,6 +874,25 @@ pub mod literals {
             .collect::<Result<Vec<_>>>()?;
         Ok(make_literal(LiteralType::Struct(Struct { fields }), false))
     }
+
+    /// Attaches a type variation to a literal
+    ///
+    /// `variation` should be an anchor obtained from
+    /// [`register_variation`](crate::helpers::registry::ExtensionsRegistry::register_variation)
+    /// so that the variation is declared when the expression is serialized.  For example, a
+    /// producer might use a "money" variation of decimal.
+    ///
+    /// `expr` must be a literal expression
+    pub fn with_variation(expr: Expression, variation: u32) -> Result<Expression> {
+        let mut literal = expr.try_as_literal()?.clone();
+        match literal.literal_type.as_mut() {
+            Some(LiteralType::Null(data_type)) => types::set_variation(data_type, variation),
+            _ => literal.type_variation_reference = variation,
+        }
+        Ok(Expression {
+            rex_type: Some(substrait::proto::expression::RexType::Literal(literal)),
+        })
+    }
 }
 
 /// Create a null literal of the given type
@@ -296,8 +910,29 @@ pub fn nullable_literal<T: LiteralInference>(value: T) -> Expression {
     make_literal(value.to_substrait(), true)
 }
 
+/// Create a nullable literal from an optional rust value
+///
+/// `None` becomes a null literal whose type is inferred from `T` so that both cases
+/// have the same (nullable) data type
+pub fn literal_opt<T: LiteralInference + TypeInfer>(value: Option<T>) -> Expression {
+    match value {
+        Some(value) => nullable_literal(value),
+        None => null_literal(T::as_substrait(true)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use substrait::proto::expression::literal::{Decimal, Struct, VarChar};
+    use substrait::proto::expression_reference::ExprType;
+
+    use crate::builder::schema::SchemaBuildersExt;
+    use crate::builder::{BuilderParams, ExpressionsBuilder};
+    use crate::helpers::expr::ExpressionExt;
+    use crate::helpers::registry::ExtensionsRegistry;
+    use crate::helpers::schema::SchemaInfo;
+    use crate::helpers::serde::{from_bytes, to_bytes};
+
     use super::*;
 
     #[test]
@@ -315,4 +950,287 @@ mod tests {
 
         assert!(literals::try_varchar("hello", 3).is_err());
     }
+
+    #[test]
+    fn test_string_and_binary_literals() {
+        let string = literal("hello");
+        assert_eq!(string.try_as_rust_literal::<String>().unwrap(), "hello");
+        assert!(string.try_as_rust_literal::<&str>().is_err());
+        assert!(string.try_as_rust_literal::<Vec<u8>>().is_err());
+        assert_eq!(
+            literals::fixed_char("abc")
+                .try_as_rust_literal::<String>()
+                .unwrap(),
+            "abc"
+        );
+        assert_eq!(
+            literals::try_varchar("abc", 5)
+                .unwrap()
+                .try_as_rust_literal::<String>()
+                .unwrap(),
+            "abc"
+        );
+
+        let binary = literal(vec![0_u8, 1, 2]);
+        assert_eq!(
+            binary.try_as_rust_literal::<Vec<u8>>().unwrap(),
+            vec![0, 1, 2]
+        );
+        assert!(binary.try_as_rust_literal::<&[u8]>().is_err());
+        assert!(binary.try_as_rust_literal::<String>().is_err());
+        assert_eq!(
+            literals::fixed_binary(vec![3, 4])
+                .try_as_rust_literal::<Vec<u8>>()
+                .unwrap(),
+            vec![3, 4]
+        );
+    }
+
+    #[test]
+    fn test_literal_opt() {
+        let some = literal_opt(Some(7_i32));
+        assert_eq!(some.try_as_rust_literal::<i32>().unwrap(), 7);
+        assert_eq!(
+            some.try_as_literal().unwrap().data_type().unwrap(),
+            types::i32(true)
+        );
+
+        let none = literal_opt::<i32>(None);
+        assert_eq!(none.try_as_rust_literal::<Option<i32>>().unwrap(), None);
+        assert_eq!(
+            none.try_as_literal().unwrap().data_type().unwrap(),
+            types::i32(true)
+        );
+
+        let none = literal_opt::<Vec<String>>(None);
+        assert_eq!(
+            none.try_as_literal().unwrap().data_type().unwrap(),
+            types::list(true, types::string(false))
+        );
+    }
+
+    #[test]
+    fn test_interval_and_binary_literals() {
+        let data_type = |expr: Expression| expr.try_as_literal().unwrap().data_type().unwrap();
+        assert_eq!(data_type(literals::uuid([7; 16])), types::uuid(false));
+        assert_eq!(
+            data_type(literals::fixed_binary(vec![0, 1, 2])),
+            types::fixed_binary(3, false)
+        );
+        assert_eq!(
+            data_type(literals::interval_year(1, 6)),
+            types::interval_year(false)
+        );
+        assert_eq!(
+            data_type(literals::interval_day(2, 30)),
+            types::interval_day(0, false)
+        );
+
+        let bad_uuid = make_literal(LiteralType::Uuid(vec![1, 2, 3]), false);
+        assert!(bad_uuid.try_as_literal().unwrap().data_type().is_err());
+    }
+
+    #[test]
+    fn test_decimal_literal() {
+        let lit = literals::decimal(-12345, 10, 2).unwrap();
+        let decimal = match &lit.try_as_literal().unwrap().literal_type {
+            Some(LiteralType::Decimal(decimal)) => decimal.clone(),
+            _ => panic!("Expected a decimal literal"),
+        };
+        assert_eq!(
+            i128::from_le_bytes(decimal.value.try_into().unwrap()),
+            -12345
+        );
+        assert_eq!(
+            lit.try_as_literal().unwrap().data_type().unwrap(),
+            types::decimal(10, 2, false).unwrap()
+        );
+
+        assert!(literals::decimal(1, 0, 0).is_err());
+        assert!(literals::decimal(1, 39, 0).is_err());
+        assert!(literals::decimal(1, 5, 6).is_err());
+        assert!(literals::decimal(100_000, 5, 0).is_err());
+        assert!(literals::decimal(99_999, 5, 0).is_ok());
+    }
+
+    #[test]
+    fn test_nested_literals() {
+        let list = literal(vec![1_i32, 2, 3]);
+        assert_eq!(
+            list.try_as_rust_literal::<Vec<i32>>().unwrap(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            list.try_as_literal().unwrap().data_type().unwrap(),
+            types::list(false, types::i32(false))
+        );
+        let empty = literal(Vec::<i32>::new());
+        assert!(empty.try_as_rust_literal::<Vec<i32>>().unwrap().is_empty());
+        assert_eq!(
+            empty.try_as_literal().unwrap().data_type().unwrap(),
+            types::list(false, types::i32(false))
+        );
+
+        // Nulls can only be decoded into optional elements
+        let with_nulls = literal(vec![Some(1_i32), None]);
+        assert_eq!(
+            with_nulls
+                .try_as_rust_literal::<Vec<Option<i32>>>()
+                .unwrap(),
+            vec![Some(1), None]
+        );
+        assert!(with_nulls.try_as_rust_literal::<Vec<i32>>().is_err());
+        assert_eq!(
+            with_nulls.try_as_literal().unwrap().data_type().unwrap(),
+            types::list(false, types::i32(true))
+        );
+
+        let scores = BTreeMap::from([("a".to_string(), 1_i32), ("b".to_string(), 2)]);
+        let map = literal(scores.clone());
+        assert_eq!(
+            map.try_as_rust_literal::<BTreeMap<String, i32>>().unwrap(),
+            scores
+        );
+        assert_eq!(
+            map.try_as_rust_literal::<HashMap<String, i32>>().unwrap(),
+            HashMap::from_iter(scores)
+        );
+        assert_eq!(
+            map.try_as_literal().unwrap().data_type().unwrap(),
+            types::map(false, types::string(false), types::i32(false))
+        );
+        assert!(map.try_as_rust_literal::<Vec<i32>>().is_err());
+
+        let strct = literal((1_i32, "x".to_string(), vec![1.5_f64]));
+        assert_eq!(
+            strct
+                .try_as_rust_literal::<(i32, String, Vec<f64>)>()
+                .unwrap(),
+            (1, "x".to_string(), vec![1.5])
+        );
+        assert!(strct.try_as_rust_literal::<(i32, String)>().is_err());
+    }
+
+    #[test]
+    fn test_literal_variation() {
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let registry = builder.schema().extensions_registry();
+        let money =
+            registry.register_variation("https://example.com/variations".to_string(), "money");
+        let price =
+            literals::with_variation(literals::decimal(1999, 10, 2).unwrap(), money).unwrap();
+
+        let mut expected_type = types::decimal(10, 2, false).unwrap();
+        types::set_variation(&mut expected_type, money);
+        assert_eq!(price.output_type(builder.schema()).unwrap(), expected_type);
+        assert!(
+            literals::with_variation(builder.fields().resolve_by_name("x").unwrap(), money)
+                .is_err()
+        );
+
+        builder.add_expression("price", price.clone()).unwrap();
+        let expression = from_bytes(&to_bytes(&builder.build())).unwrap();
+        let registry =
+            ExtensionsRegistry::from_substrait(&expression.extension_uris, &expression.extensions)
+                .unwrap();
+        assert_eq!(registry.lookup_variation(money).unwrap().name, "money");
+        let round_tripped = match &expression.referred_expr[0].expr_type {
+            Some(ExprType::Expression(expr)) => expr.clone(),
+            _ => panic!("Expected an expression"),
+        };
+        assert_eq!(round_tripped, price);
+    }
+
+    #[test]
+    fn test_data_type_not_implemented() {
+        let timestamp = Literal {
+            literal_type: Some(LiteralType::Timestamp(0)),
+            ..Default::default()
+        };
+        match timestamp.data_type() {
+            Err(SubstraitExprError::NotImplemented(message)) => {
+                assert_eq!(message, "data_type for Timestamp literals")
+            }
+            other => panic!("Expected a NotImplemented error but got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut i8_literal = literal(12_i8).try_as_literal().unwrap().clone();
+        assert!(i8_literal.validate().is_ok());
+        i8_literal.literal_type = Some(LiteralType::I8(300));
+        assert!(i8_literal.validate().is_err());
+
+        let mut varchar = literals::try_varchar("hello", 5)
+            .unwrap()
+            .try_as_literal()
+            .unwrap()
+            .clone();
+        assert!(varchar.validate().is_ok());
+        varchar.literal_type = Some(LiteralType::VarChar(VarChar {
+            value: "hello".to_string(),
+            length: 4,
+        }));
+        assert!(varchar.validate().is_err());
+
+        let decimal = literals::decimal(99_999, 5, 0).unwrap();
+        let mut decimal = decimal.try_as_literal().unwrap().clone();
+        assert!(decimal.validate().is_ok());
+        decimal.literal_type = Some(LiteralType::Decimal(Decimal {
+            value: 100_000_i128.to_le_bytes().to_vec(),
+            precision: 5,
+            scale: 0,
+        }));
+        assert!(decimal.validate().is_err());
+
+        let strct = Literal {
+            literal_type: Some(LiteralType::Struct(Struct {
+                fields: vec![literal(1_i32).try_as_literal().unwrap().clone(), i8_literal],
+            })),
+            ..Default::default()
+        };
+        assert!(strct.validate().is_err());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono_literals() {
+        use chrono::{NaiveDate, NaiveTime};
+
+        let before_epoch = NaiveDate::from_ymd_opt(1969, 12, 30).unwrap();
+        let lit = literals::date(before_epoch);
+        assert_eq!(
+            lit.try_as_literal().unwrap().literal_type,
+            Some(LiteralType::Date(-2))
+        );
+        assert_eq!(
+            lit.try_as_literal().unwrap().data_type().unwrap(),
+            types::date(false)
+        );
+        let round_tripped = literal(before_epoch)
+            .try_as_rust_literal::<NaiveDate>()
+            .unwrap();
+        assert_eq!(round_tripped, before_epoch);
+
+        let time = NaiveTime::from_hms_micro_opt(1, 2, 3, 456_789).unwrap();
+        let lit = literals::time(time);
+        assert_eq!(
+            lit.try_as_literal().unwrap().literal_type,
+            Some(LiteralType::Time(3_723_456_789))
+        );
+        assert_eq!(lit.try_as_rust_literal::<NaiveTime>().unwrap(), time);
+
+        let timestamp = before_epoch.and_time(time);
+        let lit = literals::timestamp(timestamp);
+        assert_eq!(
+            lit.try_as_literal().unwrap().data_type().unwrap(),
+            types::precision_timestamp(types::MICROSECOND_PRECISION, false)
+        );
+        assert_eq!(
+            lit.try_as_rust_literal::<chrono::NaiveDateTime>().unwrap(),
+            timestamp
+        );
+    }
 }
@@ -1,11 +1,14 @@
 use std::{collections::BTreeMap, sync::RwLock};
 
 use substrait::proto::extensions::{
-    simple_extension_declaration::{ExtensionFunction, ExtensionType, MappingType},
+    simple_extension_declaration::{
+        ExtensionFunction, ExtensionType, ExtensionTypeVariation, MappingType,
+    },
     SimpleExtensionDeclaration, SimpleExtensionUri,
 };
 
 use crate::builder::functions::FunctionDefinition;
+use crate::error::{Result, SubstraitExprError};
 
 /// A qualified name has both a uri and a name
 #[derive(PartialEq, Debug)]
@@ -28,6 +31,13 @@ struct TypeRecord {
     anchor: u32,
 }
 
+#[derive(PartialEq, Clone, Debug)]
+struct VariationRecord {
+    uri: String,
+    name: String,
+    anchor: u32,
+}
+
 #[derive(PartialEq, Clone, Debug)]
 struct FunctionRecord {
     uri: String,
@@ -67,12 +77,14 @@ impl UriLookup {
     }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Clone, Debug)]
 struct RegistryInternal {
     functions: BTreeMap<String, FunctionRecord>,
     functions_inverse: BTreeMap<u32, FunctionRecord>,
     types: BTreeMap<String, TypeRecord>,
     types_inverse: BTreeMap<u32, TypeRecord>,
+    variations: BTreeMap<String, VariationRecord>,
+    variations_inverse: BTreeMap<u32, VariationRecord>,
     counter: u32,
 }
 
@@ -84,6 +96,23 @@ impl RegistryInternal {
         })
     }
 
+    pub fn lookup_type_anchors(&self, name: &str) -> Vec<u32> {
+        self.types_inverse
+            .values()
+            .filter(|record| record.name == name)
+            .map(|record| record.anchor)
+            .collect()
+    }
+
+    pub fn lookup_variation(&self, anchor: u32) -> Option<QualifiedName> {
+        self.variations_inverse
+            .get(&anchor)
+            .map(|record| QualifiedName {
+                uri: record.uri.clone(),
+                name: record.name.clone(),
+            })
+    }
+
     pub fn lookup_function(&self, anchor: u32) -> Option<QualifiedName> {
         self.functions_inverse
             .get(&anchor)
@@ -111,6 +140,46 @@ impl RegistryInternal {
             .anchor
     }
 
+    fn register_variation(&mut self, uri: String, name: &str) -> u32 {
+        let key = uri.clone() + name;
+        let entry = self.variations.entry(key);
+        entry
+            .or_insert_with(|| {
+                let anchor = self.counter;
+                self.counter += 1;
+                let variation_record = VariationRecord {
+                    uri,
+                    name: name.to_string(),
+                    anchor,
+                };
+                self.variations_inverse
+                    .insert(anchor, variation_record.clone());
+                variation_record
+            })
+            .anchor
+    }
+
+    fn insert_type(&mut self, record: TypeRecord) {
+        self.counter = self.counter.max(record.anchor + 1);
+        self.types_inverse.insert(record.anchor, record.clone());
+        self.types.insert(record.uri.clone() + &record.name, record);
+    }
+
+    fn insert_variation(&mut self, record: VariationRecord) {
+        self.counter = self.counter.max(record.anchor + 1);
+        self.variations_inverse
+            .insert(record.anchor, record.clone());
+        self.variations
+            .insert(record.uri.clone() + &record.name, record);
+    }
+
+    fn insert_function(&mut self, record: FunctionRecord) {
+        self.counter = self.counter.max(record.anchor + 1);
+        self.functions_inverse.insert(record.anchor, record.clone());
+        self.functions
+            .insert(record.uri.clone() + &record.name, record);
+    }
+
     fn register_function(&mut self, uri: &str, name: &str) -> u32 {
         let key = uri.to_string() + name;
         let entry = self.functions.entry(key);
@@ -156,12 +225,25 @@ impl Default for ExtensionsRegistry {
                 types: BTreeMap::new(),
                 functions_inverse: BTreeMap::new(),
                 types_inverse: BTreeMap::new(),
+                variations: BTreeMap::new(),
+                variations_inverse: BTreeMap::new(),
                 counter: 1,
             }),
         }
     }
 }
 
+/// Cloning a registry takes a snapshot of its contents
+///
+/// Registrations made after the clone are not shared between the two registries.
+impl Clone for ExtensionsRegistry {
+    fn clone(&self) -> Self {
+        Self {
+            internal: RwLock::new(self.internal.read().unwrap().clone()),
+        }
+    }
+}
+
 impl PartialEq for ExtensionsRegistry {
     fn eq(&self, other: &Self) -> bool {
         *self.internal.read().unwrap() == *other.internal.read().unwrap()
@@ -169,6 +251,64 @@ impl PartialEq for ExtensionsRegistry {
 }
 
 impl ExtensionsRegistry {
+    /// Recreates a registry from the extensions of a substrait message (e.g. ExtendedExpression)
+    ///
+    /// The anchors in the message are preserved so that the registry can be used to look up
+    /// the anchors in the message's expressions.  Returns an error if a declaration refers
+    /// to a URI that is not in `uris`.
+    pub fn from_substrait(
+        uris: &[SimpleExtensionUri],
+        decls: &[SimpleExtensionDeclaration],
+    ) -> Result<Self> {
+        let uris = uris
+            .iter()
+            .map(|uri| (uri.extension_uri_anchor, uri.uri.as_str()))
+            .collect::<BTreeMap<_, _>>();
+        let lookup_uri = |uri_ref: u32| {
+            uris.get(&uri_ref)
+                .map(|uri| uri.to_string())
+                .ok_or_else(|| {
+                    SubstraitExprError::invalid_substrait(format!(
+                        "The extension URI anchor {} is not declared",
+                        uri_ref
+                    ))
+                })
+        };
+        let registry = Self::default();
+        {
+            let mut internal = registry.internal.write().unwrap();
+            for decl in decls {
+                match &decl.mapping_type {
+                    Some(MappingType::ExtensionType(typ)) => internal.insert_type(TypeRecord {
+                        uri: lookup_uri(typ.extension_uri_reference)?,
+                        name: typ.name.clone(),
+                        anchor: typ.type_anchor,
+                    }),
+                    Some(MappingType::ExtensionTypeVariation(variation)) => {
+                        internal.insert_variation(VariationRecord {
+                            uri: lookup_uri(variation.extension_uri_reference)?,
+                            name: variation.name.clone(),
+                            anchor: variation.type_variation_anchor,
+                        })
+                    }
+                    Some(MappingType::ExtensionFunction(func)) => {
+                        internal.insert_function(FunctionRecord {
+                            uri: lookup_uri(func.extension_uri_reference)?,
+                            name: func.name.clone(),
+                            anchor: func.function_anchor,
+                        })
+                    }
+                    None => {
+                        return Err(SubstraitExprError::invalid_substrait(
+                            "The required property mapping_type was missing from an extension declaration",
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(registry)
+    }
+
     /// Registers a new type with the extensions registry and returns an anchor to use
     ///
     /// If this is called multiple times with the same uri/name it will return the same anchor
@@ -177,6 +317,14 @@ impl ExtensionsRegistry {
         internal.register_type(uri, name)
     }
 
+    /// Registers a new type variation with the extensions registry and returns an anchor to use
+    ///
+    /// If this is called multiple times with the same uri/name it will return the same anchor
+    pub fn register_variation(&self, uri: String, name: &str) -> u32 {
+        let mut internal = self.internal.write().unwrap();
+        internal.register_variation(uri, name)
+    }
+
     /// Registers a new function with the extensions registry and returns an anchor to use
     ///
     /// If this is called multiple times with the same uri/name it will return the same anchor
@@ -199,6 +347,20 @@ impl ExtensionsRegistry {
         internal.lookup_type(anchor)
     }
 
+    /// Looks up the anchors of all registered types with the given name
+    ///
+    /// Types from different URIs may share a name so there can be more than one match
+    pub fn lookup_type_anchors(&self, name: &str) -> Vec<u32> {
+        let internal = self.internal.read().unwrap();
+        internal.lookup_type_anchors(name)
+    }
+
+    /// Looks up the qualified name that corresponds to a type variation anchor
+    pub fn lookup_variation(&self, anchor: u32) -> Option<QualifiedName> {
+        let internal = self.internal.read().unwrap();
+        internal.lookup_variation(anchor)
+    }
+
     /// Looks up the qualified name that corresponds to a function anchor
     pub fn lookup_function(&self, anchor: u32) -> Option<QualifiedName> {
         let internal = self.internal.read().unwrap();
@@ -224,6 +386,27 @@ impl ExtensionsRegistry {
         }
     }
 
+    fn add_variations(
+        &self,
+        internal: &RegistryInternal,
+        uris: &mut UriLookup,
+        extensions: &mut Vec<SimpleExtensionDeclaration>,
+    ) {
+        for record in internal.variations.values() {
+            let uri_ref = uris.register(record.uri.clone());
+            let declaration = SimpleExtensionDeclaration {
+                mapping_type: Some(MappingType::ExtensionTypeVariation(
+                    ExtensionTypeVariation {
+                        extension_uri_reference: uri_ref,
+                        type_variation_anchor: record.anchor,
+                        name: record.name.clone(),
+                    },
+                )),
+            };
+            extensions.push(declaration);
+        }
+    }
+
     fn add_functions(
         &self,
         internal: &RegistryInternal,
@@ -252,6 +435,7 @@ impl ExtensionsRegistry {
         let internal = self.internal.read().unwrap();
 
         self.add_types(&internal, &mut uris, &mut extensions);
+        self.add_variations(&internal, &mut uris, &mut extensions);
         self.add_functions(&internal, &mut uris, &mut extensions);
 
         let uris = uris.to_substrait();
@@ -259,3 +443,88 @@ impl ExtensionsRegistry {
         (uris, extensions)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let registry = ExtensionsRegistry::default();
+        registry.register_type("https://example.com/types".to_string(), "point");
+        registry.register_function_by_name("https://example.com/functions", "distance");
+        registry.register_variation("https://example.com/types".to_string(), "projected");
+        registry.register_function_by_name("https://example.com/functions", "area");
+
+        let (uris, extensions) = registry.to_substrait();
+        let restored = ExtensionsRegistry::from_substrait(&uris, &extensions).unwrap();
+        assert_eq!(restored, registry);
+        assert_eq!(
+            restored.lookup_function(4).unwrap().to_string(),
+            "https://example.com/functions#area"
+        );
+        // New registrations do not collide with the restored anchors
+        assert_eq!(
+            restored.register_function_by_name("https://example.com/functions", "perimeter"),
+            5
+        );
+
+        assert!(ExtensionsRegistry::from_substrait(&[], &extensions).is_err());
+    }
+
+    #[test]
+    fn test_clone() {
+        let registry = ExtensionsRegistry::default();
+        registry.register_type("https://example.com/types".to_string(), "point");
+        let snapshot = registry.clone();
+        assert_eq!(snapshot, registry);
+
+        // The clone does not see later registrations
+        registry.register_function_by_name("https://example.com/functions", "distance");
+        assert_ne!(snapshot, registry);
+        assert_eq!(
+            snapshot.register_function_by_name("https://example.com/functions", "area"),
+            2
+        );
+    }
+
+    #[test]
+    fn test_variations() {
+        let registry = ExtensionsRegistry::default();
+        let uri = "https://example.com/variations".to_string();
+        let anchor = registry.register_variation(uri.clone(), "dict_encoded");
+        assert_eq!(
+            registry.register_variation(uri.clone(), "dict_encoded"),
+            anchor
+        );
+        assert_eq!(
+            registry.lookup_variation(anchor),
+            Some(QualifiedName {
+                uri: uri.clone(),
+                name: "dict_encoded".to_string(),
+            })
+        );
+        assert_eq!(registry.lookup_type(anchor), None);
+
+        let (uris, extensions) = registry.to_substrait();
+        assert_eq!(
+            uris,
+            vec![SimpleExtensionUri {
+                extension_uri_anchor: 1,
+                uri,
+            }]
+        );
+        assert_eq!(
+            extensions,
+            vec![SimpleExtensionDeclaration {
+                mapping_type: Some(MappingType::ExtensionTypeVariation(
+                    ExtensionTypeVariation {
+                        extension_uri_reference: 1,
+                        type_variation_anchor: anchor,
+                        name: "dict_encoded".to_string(),
+                    }
+                )),
+            }]
+        );
+    }
+}
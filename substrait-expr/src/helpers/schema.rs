@@ -1,6 +1,10 @@
 use substrait::proto::{
-    expression::{reference_segment::ReferenceType, ReferenceSegment},
-    r#type::Struct,
+    expression::{
+        mask_expression::{select, StructSelect},
+        reference_segment::ReferenceType,
+        MaskExpression, ReferenceSegment,
+    },
+    r#type::{Kind, Struct},
     NamedStruct, Type,
 };
 
@@ -18,13 +22,13 @@ use super::{
 ///
 /// This is also the only schema type that does not know
 /// how many fields there are.
-#[derive(Debug, Default, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct EmptySchema {
     registry: ExtensionsRegistry,
 }
 
 /// A field in a names-only schema
-#[derive(PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct NamesOnlySchemaNode {
     /// The name of this node
     ///
@@ -39,7 +43,7 @@ pub struct NamesOnlySchemaNode {
 ///
 /// The root of the schema is `Vec<NamesOnlySchemaNode>` where each node represents
 /// a single (possibly nested) column
-#[derive(PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct NamesOnlySchema {
     registry: ExtensionsRegistry,
     /// The root node of the schema
@@ -112,7 +116,7 @@ impl<'a> Iterator for NamesOnlySchemaNodeNamesDfsIter<'a> {
 }
 
 /// A schema that knows the types (but not names) of its fields
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TypesOnlySchema {
     registry: ExtensionsRegistry,
     /// The root node of the schema
@@ -135,7 +139,7 @@ impl TypesOnlySchema {
 }
 
 /// A field in a schema that knows both types and names
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FullSchemaNode {
     /// The name of the field
     ///
@@ -148,7 +152,7 @@ pub struct FullSchemaNode {
 }
 
 /// A schema that knows both the types and names of its fields
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FullSchema {
     registry: ExtensionsRegistry,
     /// The root node of the schema
@@ -170,10 +174,158 @@ impl FullSchema {
     }
 }
 
+/// Describes the number of fields in a struct for error messages
+fn describe_field_count(num_fields: usize) -> String {
+    if num_fields == 1 {
+        "1 field".to_string()
+    } else {
+        format!("{} fields", num_fields)
+    }
+}
+
+/// Resolves a struct field segment against the given struct field types
+///
+/// Returns the type of the referenced field and the segment's child (if any)
+fn struct_field_type<'a>(
+    types: &'a [Type],
+    ref_seg: &'a ReferenceSegment,
+) -> Result<(&'a Type, Option<&'a ReferenceSegment>)> {
+    match ref_seg.reference_type.required("reference_type")? {
+        ReferenceType::StructField(struct_field) => {
+            let field = types.get(struct_field.field as usize).ok_or_else(|| {
+                SubstraitExprError::invalid_input(format!(
+                    "Invalid reference: field {} does not exist (the struct has {})",
+                    struct_field.field,
+                    describe_field_count(types.len())
+                ))
+            })?;
+            Ok((field, struct_field.child.as_deref()))
+        }
+        ReferenceType::ListElement(_) | ReferenceType::MapKey(_) => {
+            Err(SubstraitExprError::invalid_input(
+                "Invalid reference: attempt to reference a list element or map key of a struct",
+            ))
+        }
+    }
+}
+
+/// Creates the struct type containing the fields of `types` selected by `select`
+fn struct_select_type(types: &[Type], select: &StructSelect, nullable: bool) -> Result<Type> {
+    let selected = select
+        .struct_items
+        .iter()
+        .map(|item| {
+            let field = types.get(item.field as usize).ok_or_else(|| {
+                SubstraitExprError::invalid_input(format!(
+                    "Invalid mask: field {} does not exist (the struct has {})",
+                    item.field,
+                    describe_field_count(types.len())
+                ))
+            })?;
+            match item.child.as_ref().and_then(|child| child.r#type.as_ref()) {
+                None => Ok(field.clone()),
+                Some(select::Type::Struct(child_select)) => match &field.kind {
+                    Some(Kind::Struct(strct)) => {
+                        struct_select_type(&strct.types, child_select, field.is_nullable()?)
+                    }
+                    _ => Err(SubstraitExprError::invalid_input(
+                        "Invalid mask: attempt to select struct fields of a non-struct type",
+                    )),
+                },
+                Some(select::Type::List(_)) | Some(select::Type::Map(_)) => Err(
+                    SubstraitExprError::not_implemented("output_type for list and map masks"),
+                ),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Type {
+        kind: Some(Kind::Struct(Struct {
+            types: selected,
+            nullability: nullability(nullable),
+            ..Default::default()
+        })),
+    })
+}
+
+/// Resolves the type selected by `mask` from a struct with the given field types
+///
+/// Unless `maintain_singular_struct` is set, a mask that selects a single field returns
+/// that field's type instead of a struct with one field.
+pub(crate) fn resolve_masked_type(
+    types: &[Type],
+    nullable: bool,
+    mask: &MaskExpression,
+) -> Result<Type> {
+    let select = mask.select.required("select")?;
+    let selected = struct_select_type(types, select, nullable)?;
+    match &selected.kind {
+        Some(Kind::Struct(strct)) if strct.types.len() == 1 && !mask.maintain_singular_struct => {
+            let field = &strct.types[0];
+            if nullable {
+                Ok(field.with_nullability(true))
+            } else {
+                Ok(field.clone())
+            }
+        }
+        _ => Ok(selected),
+    }
+}
+
+/// Resolves the type referenced by `ref_seg` when it is applied to a field of type `typ`
+///
+/// If `ref_seg` is None then `typ` itself is returned.  The result is nullable if the
+/// reference passes through a list element or map key because a missing item is NULL.
+pub(crate) fn resolve_nested_type(typ: &Type, ref_seg: Option<&ReferenceSegment>) -> Result<Type> {
+    let mut cur_type = typ;
+    let mut cur_seg = ref_seg;
+    let mut may_be_missing = false;
+    while let Some(seg) = cur_seg {
+        let (next_type, next_seg) = match (
+            seg.reference_type.required("reference_type")?,
+            &cur_type.kind,
+        ) {
+            (ReferenceType::StructField(_), Some(Kind::Struct(strct))) => {
+                struct_field_type(&strct.types, seg)?
+            }
+            (ReferenceType::ListElement(list_element), Some(Kind::List(list))) => (
+                list.r#type.required("type")?.as_ref(),
+                list_element.child.as_deref(),
+            ),
+            (ReferenceType::MapKey(map_key), Some(Kind::Map(map))) => (
+                map.value.required("value")?.as_ref(),
+                map_key.child.as_deref(),
+            ),
+            (ReferenceType::StructField(_), _) => {
+                return Err(SubstraitExprError::invalid_input(
+                    "Invalid reference: attempt to reference a struct field of a non-struct type",
+                ))
+            }
+            (ReferenceType::ListElement(_), _) => {
+                return Err(SubstraitExprError::invalid_input(
+                    "Invalid reference: attempt to reference a list element of a non-list type",
+                ))
+            }
+            (ReferenceType::MapKey(_), _) => {
+                return Err(SubstraitExprError::invalid_input(
+                    "Invalid reference: attempt to reference a map key of a non-map type",
+                ))
+            }
+        };
+        may_be_missing |= !matches!(seg.reference_type, Some(ReferenceType::StructField(_)));
+        cur_type = next_type;
+        cur_seg = next_seg;
+    }
+    if may_be_missing {
+        Ok(cur_type.with_nullability(true))
+    } else {
+        Ok(cur_type.clone())
+    }
+}
+
 /// A schema represents what we know about the input to an expression
 ///
 /// TODO: Expand, copy over content from crate docs
-#[derive(PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum SchemaInfo {
     Empty(EmptySchema),
     Names(NamesOnlySchema),
@@ -181,6 +333,68 @@ pub enum SchemaInfo {
     Full(FullSchema),
 }
 
+/// The schemas of a query and the queries that enclose it
+///
+/// Correlated subqueries (and window functions evaluated within them) can reference
+/// fields from an enclosing query using an outer reference.  An outer reference with
+/// `steps_out` of 1 refers to the query immediately enclosing the current one.
+#[derive(Debug)]
+pub struct SchemaStack<'a> {
+    // The outermost schema is first and the current schema is last
+    schemas: Vec<&'a SchemaInfo>,
+}
+
+impl<'a> SchemaStack<'a> {
+    /// Create a stack with a single (outermost) schema
+    pub fn new(schema: &'a SchemaInfo) -> Self {
+        Self {
+            schemas: vec![schema],
+        }
+    }
+
+    /// Enter a subquery with the given schema
+    pub fn push(&mut self, schema: &'a SchemaInfo) -> &mut Self {
+        self.schemas.push(schema);
+        self
+    }
+
+    /// Leave the current subquery
+    ///
+    /// Returns None (and leaves the stack unchanged) if this is the outermost schema
+    pub fn pop(&mut self) -> Option<&'a SchemaInfo> {
+        if self.schemas.len() > 1 {
+            self.schemas.pop()
+        } else {
+            None
+        }
+    }
+
+    /// The schema of the current (innermost) query
+    pub fn current(&self) -> &'a SchemaInfo {
+        self.schemas[self.schemas.len() - 1]
+    }
+
+    /// The schema of the query `steps_out` levels above the current query
+    ///
+    /// Returns an error if `steps_out` is 0 or if there are not enough enclosing queries
+    pub fn outer(&self, steps_out: u32) -> Result<&'a SchemaInfo> {
+        if steps_out == 0 {
+            return Err(SubstraitExprError::invalid_substrait(
+                "An outer reference must have a steps_out of at least 1",
+            ));
+        }
+        let steps_out = steps_out as usize;
+        if steps_out >= self.schemas.len() {
+            return Err(SubstraitExprError::invalid_input(format!(
+                "An outer reference steps out {} levels but there are only {} enclosing queries",
+                steps_out,
+                self.schemas.len() - 1
+            )));
+        }
+        Ok(self.schemas[self.schemas.len() - 1 - steps_out])
+    }
+}
+
 struct TypesOnlySchemaTypesDfsIter<'a> {
     stack: Vec<&'a Type>,
     include_inner: bool,
@@ -229,7 +443,129 @@ impl<'a> Iterator for FullSchemaFieldsDfsIter<'a> {
     }
 }
 
+fn complete_path<N>(
+    root: &N,
+    prefix: &str,
+    name_and_children: impl Fn(&N) -> (&str, &[N]),
+) -> Vec<String> {
+    let (parent_path, partial) = match prefix.rsplit_once('.') {
+        Some((parent_path, partial)) => (Some(parent_path), partial),
+        None => (None, prefix),
+    };
+    let mut node = root;
+    if let Some(parent_path) = parent_path {
+        for segment in parent_path.split('.') {
+            let child = name_and_children(node)
+                .1
+                .iter()
+                .find(|child| name_and_children(child).0 == segment);
+            match child {
+                Some(child) => node = child,
+                None => return Vec::new(),
+            }
+        }
+    }
+    name_and_children(node)
+        .1
+        .iter()
+        .map(|child| name_and_children(child).0)
+        .filter(|name| name.starts_with(partial))
+        .map(|name| match parent_path {
+            Some(parent_path) => format!("{}.{}", parent_path, name),
+            None => name.to_string(),
+        })
+        .collect()
+}
+
+/// The name given to fields of schemas that are not aware of names
+fn synthetic_name(idx: usize) -> String {
+    format!("field_{}", idx)
+}
+
+/// Creates schema nodes for `types`, taking names from `names` in DFS order
+fn nodes_from_names(
+    types: &[Type],
+    names: &mut impl Iterator<Item = String>,
+) -> Result<Vec<FullSchemaNode>> {
+    types
+        .iter()
+        .map(|typ| {
+            let name = names.next().ok_or_else(|| {
+                SubstraitExprError::invalid_substrait("A named struct has fewer names than fields")
+            })?;
+            let children = match &typ.kind {
+                Some(Kind::Struct(strct)) => nodes_from_names(&strct.types, names)?,
+                _ => Vec::new(),
+            };
+            Ok(FullSchemaNode {
+                name,
+                r#type: typ.clone(),
+                children,
+            })
+        })
+        .collect()
+}
+
 impl SchemaInfo {
+    /// Creates a schema from a NamedStruct (e.g. the base schema of an ExtendedExpression)
+    ///
+    /// This is the inverse of [`to_substrait`](Self::to_substrait).  The names are assigned
+    /// to the fields in DFS order.  If the names are the synthetic names generated for a
+    /// schema that does not know names (`field_0`, `field_1`, ...) then a types-only schema
+    /// is created.  Otherwise a full schema is created.
+    pub fn from_substrait(named: NamedStruct) -> Result<SchemaInfo> {
+        Self::from_substrait_with_registry(named, ExtensionsRegistry::default())
+    }
+
+    /// Creates a schema from a NamedStruct, using `registry` to interpret user defined types
+    ///
+    /// See [`from_substrait`](Self::from_substrait)
+    pub fn from_substrait_with_registry(
+        named: NamedStruct,
+        registry: ExtensionsRegistry,
+    ) -> Result<SchemaInfo> {
+        let root = named.r#struct.ok_or_else(|| {
+            SubstraitExprError::invalid_substrait(
+                "The required property struct was missing from a named struct",
+            )
+        })?;
+        let synthetic = named
+            .names
+            .iter()
+            .enumerate()
+            .all(|(idx, name)| *name == synthetic_name(idx));
+        if synthetic && !named.names.is_empty() {
+            let num_fields = root.types.iter().map(|typ| typ.num_types()).sum::<u32>();
+            if named.names.len() != num_fields as usize {
+                return Err(SubstraitExprError::invalid_substrait(format!(
+                    "A named struct has {} names but {} fields",
+                    named.names.len(),
+                    num_fields
+                )));
+            }
+            return Ok(SchemaInfo::Types(TypesOnlySchema::new_with_registry(
+                root, registry,
+            )));
+        }
+        let mut names = named.names.into_iter();
+        let children = nodes_from_names(&root.types, &mut names)?;
+        if names.next().is_some() {
+            return Err(SubstraitExprError::invalid_substrait(
+                "A named struct has more names than fields",
+            ));
+        }
+        Ok(SchemaInfo::Full(FullSchema::new_with_registry(
+            FullSchemaNode {
+                name: String::new(),
+                r#type: Type {
+                    kind: Some(Kind::Struct(root)),
+                },
+                children,
+            },
+            registry,
+        )))
+    }
+
     /// Return a reference to the schema's extensions registry
     ///
     /// This registry keeps track of the user defined types
@@ -334,20 +670,58 @@ impl SchemaInfo {
         }
     }
 
+    /// Returns every distinct type used by the schema, including nested types
+    ///
+    /// See [`types::collect_types`] for details
+    pub fn collect_types(&self) -> Vec<Type> {
+        types::collect_types(self.types_dfs(true))
+    }
+
+    /// Returns the field paths that could complete a partially typed path
+    ///
+    /// The prefix is split on the last `.`.  Everything before the dot must name an
+    /// existing (nested) field and everything after it is matched against the names of
+    /// that field's children.  For example, `loc` might complete to `location` and
+    /// `location.` would complete to all of the children of `location` (e.g. `location.x`
+    /// and `location.y`).
+    ///
+    /// Returns an empty vector if there are no matches or if the schema does not know
+    /// the names of its fields
+    pub fn completions(&self, prefix: &str) -> Vec<String> {
+        match self {
+            SchemaInfo::Empty(_) | SchemaInfo::Types(_) => Vec::new(),
+            SchemaInfo::Names(names) => complete_path(&names.root, prefix, |node| {
+                (node.name.as_str(), node.children.as_slice())
+            }),
+            SchemaInfo::Full(full) => complete_path(&full.root, prefix, |node| {
+                (node.name.as_str(), node.children.as_slice())
+            }),
+        }
+    }
+
     /// Converts to a NamedStruct which is the closest equivalent SubstraitMessage
     pub fn to_substrait(self) -> NamedStruct {
-        // TODO: Should include_inner be true here?
-        let types = self.types_dfs(false).collect::<Vec<_>>();
+        // The struct holds the top-level types (nested structs carry their own children)
+        // while the names list every field, including nested fields, in DFS order
+        let types = match &self {
+            SchemaInfo::Empty(_) => Vec::new(),
+            SchemaInfo::Types(type_info) => type_info.root.types.clone(),
+            SchemaInfo::Full(full) => full
+                .root
+                .children
+                .iter()
+                .map(|child| child.r#type.clone())
+                .collect(),
+            SchemaInfo::Names(_) => self.types_dfs(false).collect(),
+        };
         let names = if self.names_aware() {
             self.names_dfs()
                 .unwrap()
                 .map(|name| name.to_string())
                 .collect::<Vec<_>>()
         } else {
-            types
-                .iter()
-                .enumerate()
-                .map(|(idx, _)| format!("field_{}", idx))
+            (0..self.types_dfs(true).count())
+                .map(synthetic_name)
                 .collect::<Vec<_>>()
         };
         NamedStruct {
@@ -360,6 +734,38 @@ impl SchemaInfo {
         }
     }
 
+    /// Return the type selected from the schema by `mask`
+    ///
+    /// Returns an error if the mask selects a field that is not in the schema.  If types
+    /// are not known then the returned type will be the unknown type.
+    pub fn resolve_mask(&self, mask: &MaskExpression) -> Result<Type> {
+        match self {
+            SchemaInfo::Empty(empty) => Ok(crate::builder::types::unknown(&empty.registry)),
+            SchemaInfo::Names(names) => Ok(crate::builder::types::unknown(&names.registry)),
+            SchemaInfo::Types(type_info) => resolve_masked_type(&type_info.root.types, false, mask),
+            SchemaInfo::Full(full) => {
+                let types = full
+                    .root
+                    .children
+                    .iter()
+                    .map(|child| child.r#type.clone())
+                    .collect::<Vec<_>>();
+                resolve_masked_type(&types, false, mask)
+            }
+        }
+    }
+
+    /// Return the key type of the map referenced by `ref_seg`
+    ///
+    /// Returns None if the referenced field is not a map or if types are not known.  Returns
+    /// an error if the reference does not refer to a field in the schema.
+    pub fn resolve_map_key_type(&self, ref_seg: &ReferenceSegment) -> Result<Option<Type>> {
+        match self.resolve_type(ref_seg)?.kind {
+            Some(Kind::Map(map)) => Ok(map.key.map(|key| *key)),
+            _ => Ok(None),
+        }
+    }
+
     /// Return the type of the field referenced by `ref_seg`
     ///
     /// Returns an error if the reference does not refer to a field in the schema
@@ -371,30 +777,8 @@ impl SchemaInfo {
             // TODO: Make sure a field exists before returning unknown
             SchemaInfo::Names(names) => Ok(crate::builder::types::unknown(&names.registry)),
             SchemaInfo::Types(type_info) => {
-                let mut cur = &type_info.root.types;
-                let mut _owned_cur = Vec::new();
-                loop {
-                    match ref_seg.reference_type.required("reference_type")? {
-                        ReferenceType::StructField(struct_field) => {
-                            let field = &cur[struct_field.field as usize];
-                            if let Some(_child) = &struct_field.child {
-                                let children = field.children();
-                                if children.is_empty() {
-                                    // TODO: fix error message to explain what happened
-                                    return Err(SubstraitExprError::invalid_input(
-                                        "Invalid reference",
-                                    ));
-                                }
-                                _owned_cur = children.into_iter().cloned().collect::<Vec<_>>();
-                                cur = &_owned_cur;
-                            } else {
-                                return Ok(field.clone());
-                            }
-                        }
-                        ReferenceType::ListElement(_list_element) => todo!(),
-                        ReferenceType::MapKey(_map_key) => todo!(),
-                    }
-                }
+                let (field, child) = struct_field_type(&type_info.root.types, ref_seg)?;
+                resolve_nested_type(field, child)
             }
             SchemaInfo::Full(full) => {
                 let mut cur_seg = ref_seg;
@@ -402,24 +786,34 @@ impl SchemaInfo {
                 loop {
                     match cur_seg.reference_type.required("reference_type")? {
                         ReferenceType::StructField(struct_field) => {
-                            // TODO: Bounds checking?
-                            let field = &cur_children[struct_field.field as usize];
-                            if let Some(child) = &struct_field.child {
-                                let children = &field.children;
-                                if children.is_empty() {
-                                    // TODO: fix error message to explain what happened
-                                    return Err(SubstraitExprError::invalid_input(
-                                        "Invalid reference",
-                                    ));
+                            let field =
+                                cur_children
+                                    .get(struct_field.field as usize)
+                                    .ok_or_else(|| {
+                                        SubstraitExprError::invalid_input(format!(
+                                            "Invalid reference: field {} does not exist (the struct has {})",
+                                            struct_field.field,
+                                            describe_field_count(cur_children.len())
+                                        ))
+                                    })?;
+                            match &struct_field.child {
+                                Some(child) => {
+                                    if field.children.is_empty() {
+                                        // Not a struct, the remaining segments are resolved
+                                        // against the field's type (e.g. list / map fields)
+                                        return resolve_nested_type(&field.r#type, Some(child));
+                                    }
+                                    cur_children = &field.children;
+                                    cur_seg = child.as_ref();
                                 }
-                                cur_children = children;
-                                cur_seg = child.as_ref();
-                            } else {
-                                return Ok(field.r#type.clone());
+                                None => return Ok(field.r#type.clone()),
                             }
                         }
-                        ReferenceType::ListElement(_list_element) => todo!(),
-                        ReferenceType::MapKey(_map_key) => todo!(),
+                        ReferenceType::ListElement(_) | ReferenceType::MapKey(_) => {
+                            return Err(SubstraitExprError::invalid_input(
+                                "Invalid reference: attempt to reference a list element or map key of a struct",
+                            ));
+                        }
                     }
                 }
             }
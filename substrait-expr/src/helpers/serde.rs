@@ -0,0 +1,51 @@
+//! # Converting expressions to and from protobuf bytes
+//!
+//! These are thin wrappers around prost so that users sending expressions over the wire
+//! don't need to depend on prost directly.
+
+use prost::Message;
+use substrait::proto::ExtendedExpression;
+
+use crate::error::Result;
+
+/// Encodes an extended expression as protobuf bytes
+pub fn to_bytes(msg: &ExtendedExpression) -> Vec<u8> {
+    msg.encode_to_vec()
+}
+
+/// Decodes an extended expression from protobuf bytes
+///
+/// Returns a serialization error if the bytes are not a valid protobuf message
+pub fn from_bytes(bytes: &[u8]) -> Result<ExtendedExpression> {
+    Ok(ExtendedExpression::decode(bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::schema::SchemaBuildersExt;
+    use crate::builder::{BuilderParams, ExpressionsBuilder};
+    use crate::error::SubstraitExprError;
+    use crate::functions::functions_arithmetic::FunctionsArithmeticExt;
+    use crate::helpers::literals::literal;
+    use crate::helpers::schema::SchemaInfo;
+    use crate::helpers::types;
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let sum = builder.functions().add(x, literal(3_i32)).build().unwrap();
+        builder.add_expression("sum", sum).unwrap();
+        let expression = builder.build();
+
+        let bytes = to_bytes(&expression);
+        assert_eq!(from_bytes(&bytes).unwrap(), expression);
+        assert!(matches!(
+            from_bytes(&[0xff, 0xff]),
+            Err(SubstraitExprError::SerializationError(_))
+        ));
+    }
+}
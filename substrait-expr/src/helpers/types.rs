@@ -1,12 +1,16 @@
+use std::collections::{BTreeMap, HashMap};
+
 use substrait::proto::{
     r#type::{
-        Binary, Boolean, Fp32, Fp64, Kind, Nullability, String as SubstraitString, Struct, I16,
-        I32, I64, I8,
+        Binary, Boolean, Date, Decimal, FixedBinary, FixedChar, Fp32, Fp64, IntervalCompound,
+        IntervalDay, IntervalYear, Kind, List, Map, Nullability, PrecisionTimestamp,
+        PrecisionTimestampTz, String as SubstraitString, Struct, Time, Timestamp, TimestampTz,
+        UserDefined, Uuid, VarChar, I16, I32, I64, I8,
     },
     Type,
 };
 
-use crate::error::Result;
+use crate::error::{Result, SubstraitExprError};
 use crate::util::HasRequiredPropertiesRef;
 
 use super::registry::ExtensionsRegistry;
@@ -17,15 +21,151 @@ pub trait TypeExt {
     fn same_kind(&self, other: &Type) -> Result<bool>;
     /// Returns true if this is the unknown type
     fn is_unknown(&self, registry: &ExtensionsRegistry) -> bool;
+    /// Returns true if this is, or (recursively) contains, the unknown type
+    ///
+    /// For example, a list of unknown or a struct with an unknown field
+    fn contains_unknown(&self, registry: &ExtensionsRegistry) -> bool;
     /// Returns the total number of types (including this one) represented by this type
     ///
     /// Will return 1 if this is not a struct type
     fn num_types(&self) -> u32;
     /// Returns the child types
     fn children(&self) -> Vec<&Type>;
+    /// Renders the type as a short human readable string (e.g. `decimal?<38,6>`)
+    ///
+    /// A trailing `?` marks a nullable type and type parameters are listed in `<>`.
+    /// User defined types are rendered as `u!name`, using the registry to find the name.
+    fn to_human_readable(&self, registry: &ExtensionsRegistry) -> String;
+    /// Returns true if the type is nullable
+    ///
+    /// Returns an error if the type has no kind.  Legacy user defined type references
+    /// have no nullability and are reported as non-nullable.
+    fn is_nullable(&self) -> Result<bool>;
+    /// Returns a copy of this type with its (top-level) nullability changed
+    ///
+    /// Legacy user defined type references are converted to the modern user defined
+    /// type.  The nullability of child types (e.g. list items) is not changed.
+    fn with_nullability(&self, nullable: bool) -> Type;
+    /// Returns true if the two types are the same, ignoring nullability
+    ///
+    /// Unlike [`same_kind`](Self::same_kind) this compares type parameters (e.g. decimal
+    /// precision and scale) and nested types.  Nullability is ignored at every level.
+    fn structurally_eq(&self, other: &Type) -> bool;
+    /// Returns true if the two types are the same, ignoring type variations
+    ///
+    /// Variations are ignored at every level but nullability must match.
+    fn eq_ignoring_variation(&self, other: &Type) -> bool;
+}
+
+fn format_type(name: &str, nullability: i32, params: &[String]) -> String {
+    let mut formatted = name.to_string();
+    if nullability == Nullability::Nullable as i32 {
+        formatted.push('?');
+    }
+    if !params.is_empty() {
+        formatted.push('<');
+        formatted.push_str(&params.join(","));
+        formatted.push('>');
+    }
+    formatted
+}
+
+fn format_user_defined(anchor: u32, nullability: i32, registry: &ExtensionsRegistry) -> String {
+    let name = match registry.lookup_type(anchor) {
+        Some(qualified_name) => format!("u!{}", qualified_name.name),
+        None => format!("u!anchor_{}", anchor),
+    };
+    format_type(&name, nullability, &[])
 }
 
 impl TypeExt for Type {
+    fn is_nullable(&self) -> Result<bool> {
+        let nullability = match self.kind.required("kind")? {
+            Kind::Bool(t) => t.nullability,
+            Kind::I8(t) => t.nullability,
+            Kind::I16(t) => t.nullability,
+            Kind::I32(t) => t.nullability,
+            Kind::I64(t) => t.nullability,
+            Kind::Fp32(t) => t.nullability,
+            Kind::Fp64(t) => t.nullability,
+            Kind::String(t) => t.nullability,
+            Kind::Binary(t) => t.nullability,
+            Kind::Timestamp(t) => t.nullability,
+            Kind::Date(t) => t.nullability,
+            Kind::Time(t) => t.nullability,
+            Kind::IntervalYear(t) => t.nullability,
+            Kind::IntervalDay(t) => t.nullability,
+            Kind::IntervalCompound(t) => t.nullability,
+            Kind::TimestampTz(t) => t.nullability,
+            Kind::Uuid(t) => t.nullability,
+            Kind::FixedChar(t) => t.nullability,
+            Kind::Varchar(t) => t.nullability,
+            Kind::FixedBinary(t) => t.nullability,
+            Kind::Decimal(t) => t.nullability,
+            Kind::PrecisionTimestamp(t) => t.nullability,
+            Kind::PrecisionTimestampTz(t) => t.nullability,
+            Kind::Struct(t) => t.nullability,
+            Kind::List(t) => t.nullability,
+            Kind::Map(t) => t.nullability,
+            Kind::UserDefined(t) => t.nullability,
+            Kind::UserDefinedTypeReference(_) => return Ok(false),
+        };
+        Ok(nullability == Nullability::Nullable as i32)
+    }
+
+    fn with_nullability(&self, nullable: bool) -> Type {
+        let nullability = nullability(nullable);
+        let mut typ = self.clone();
+        if let Some(Kind::UserDefinedTypeReference(anchor)) = typ.kind {
+            typ.kind = Some(Kind::UserDefined(UserDefined {
+                type_reference: anchor,
+                type_variation_reference: NO_VARIATION,
+                nullability,
+                type_parameters: vec![],
+            }));
+            return typ;
+        }
+        match typ.kind.as_mut() {
+            Some(Kind::Bool(t)) => t.nullability = nullability,
+            Some(Kind::I8(t)) => t.nullability = nullability,
+            Some(Kind::I16(t)) => t.nullability = nullability,
+            Some(Kind::I32(t)) => t.nullability = nullability,
+            Some(Kind::I64(t)) => t.nullability = nullability,
+            Some(Kind::Fp32(t)) => t.nullability = nullability,
+            Some(Kind::Fp64(t)) => t.nullability = nullability,
+            Some(Kind::String(t)) => t.nullability = nullability,
+            Some(Kind::Binary(t)) => t.nullability = nullability,
+            Some(Kind::Timestamp(t)) => t.nullability = nullability,
+            Some(Kind::Date(t)) => t.nullability = nullability,
+            Some(Kind::Time(t)) => t.nullability = nullability,
+            Some(Kind::IntervalYear(t)) => t.nullability = nullability,
+            Some(Kind::IntervalDay(t)) => t.nullability = nullability,
+            Some(Kind::IntervalCompound(t)) => t.nullability = nullability,
+            Some(Kind::TimestampTz(t)) => t.nullability = nullability,
+            Some(Kind::Uuid(t)) => t.nullability = nullability,
+            Some(Kind::FixedChar(t)) => t.nullability = nullability,
+            Some(Kind::Varchar(t)) => t.nullability = nullability,
+            Some(Kind::FixedBinary(t)) => t.nullability = nullability,
+            Some(Kind::Decimal(t)) => t.nullability = nullability,
+            Some(Kind::PrecisionTimestamp(t)) => t.nullability = nullability,
+            Some(Kind::PrecisionTimestampTz(t)) => t.nullability = nullability,
+            Some(Kind::Struct(t)) => t.nullability = nullability,
+            Some(Kind::List(t)) => t.nullability = nullability,
+            Some(Kind::Map(t)) => t.nullability = nullability,
+            Some(Kind::UserDefined(t)) => t.nullability = nullability,
+            Some(Kind::UserDefinedTypeReference(_)) | None => {}
+        }
+        typ
+    }
+
+    fn structurally_eq(&self, other: &Type) -> bool {
+        without_nullability(self) == without_nullability(other)
+    }
+
+    fn eq_ignoring_variation(&self, other: &Type) -> bool {
+        without_variation(self) == without_variation(other)
+    }
+
     fn same_kind(&self, other: &Type) -> Result<bool> {
         let self_kind = self.kind.required("kind")?;
         let other_kind = other.kind.required("kind")?;
@@ -33,9 +173,14 @@ impl TypeExt for Type {
     }
 
     fn is_unknown(&self, registry: &ExtensionsRegistry) -> bool {
-        match &self.kind {
-            Some(Kind::UserDefined(user_defined)) => {
-                let type_name = registry.lookup_type(user_defined.type_reference);
+        let type_reference = match &self.kind {
+            Some(Kind::UserDefined(user_defined)) => Some(user_defined.type_reference),
+            Some(Kind::UserDefinedTypeReference(anchor)) => Some(*anchor),
+            _ => None,
+        };
+        match type_reference {
+            Some(type_reference) => {
+                let type_name = registry.lookup_type(type_reference);
                 match type_name {
                     Some(type_name) => {
                         type_name.uri == UNKNOWN_TYPE_URI && type_name.name == UNKNOWN_TYPE_NAME
@@ -47,6 +192,26 @@ impl TypeExt for Type {
         }
     }
 
+    fn contains_unknown(&self, registry: &ExtensionsRegistry) -> bool {
+        if self.is_unknown(registry) {
+            return true;
+        }
+        match &self.kind {
+            Some(Kind::Struct(strct)) => {
+                strct.types.iter().any(|typ| typ.contains_unknown(registry))
+            }
+            Some(Kind::List(list)) => list
+                .r#type
+                .as_ref()
+                .is_some_and(|typ| typ.contains_unknown(registry)),
+            Some(Kind::Map(map)) => [&map.key, &map.value]
+                .into_iter()
+                .flatten()
+                .any(|typ| typ.contains_unknown(registry)),
+            _ => false,
+        }
+    }
+
     fn num_types(&self) -> u32 {
         match &self.kind {
             Some(Kind::Struct(strct)) => {
@@ -62,6 +227,544 @@ impl TypeExt for Type {
             _ => vec![],
         }
     }
+
+    fn to_human_readable(&self, registry: &ExtensionsRegistry) -> String {
+        let render_child = |child: &Option<Box<Type>>| match child {
+            Some(child) => child.to_human_readable(registry),
+            None => "<missing>".to_string(),
+        };
+        match &self.kind {
+            None => "<missing>".to_string(),
+            Some(kind) => match kind {
+                Kind::Bool(t) => format_type("boolean", t.nullability, &[]),
+                Kind::I8(t) => format_type("i8", t.nullability, &[]),
+                Kind::I16(t) => format_type("i16", t.nullability, &[]),
+                Kind::I32(t) => format_type("i32", t.nullability, &[]),
+                Kind::I64(t) => format_type("i64", t.nullability, &[]),
+                Kind::Fp32(t) => format_type("fp32", t.nullability, &[]),
+                Kind::Fp64(t) => format_type("fp64", t.nullability, &[]),
+                Kind::String(t) => format_type("string", t.nullability, &[]),
+                Kind::Binary(t) => format_type("binary", t.nullability, &[]),
+                Kind::Timestamp(t) => format_type("timestamp", t.nullability, &[]),
+                Kind::Date(t) => format_type("date", t.nullability, &[]),
+                Kind::Time(t) => format_type("time", t.nullability, &[]),
+                Kind::IntervalYear(t) => format_type("interval_year", t.nullability, &[]),
+                Kind::IntervalDay(t) => {
+                    let params = t
+                        .precision
+                        .map(|precision| vec![precision.to_string()])
+                        .unwrap_or_default();
+                    format_type("interval_day", t.nullability, &params)
+                }
+                Kind::IntervalCompound(t) => format_type(
+                    "interval_compound",
+                    t.nullability,
+                    &[t.precision.to_string()],
+                ),
+                Kind::TimestampTz(t) => format_type("timestamp_tz", t.nullability, &[]),
+                Kind::Uuid(t) => format_type("uuid", t.nullability, &[]),
+                Kind::FixedChar(t) => {
+                    format_type("fixedchar", t.nullability, &[t.length.to_string()])
+                }
+                Kind::Varchar(t) => format_type("varchar", t.nullability, &[t.length.to_string()]),
+                Kind::FixedBinary(t) => {
+                    format_type("fixedbinary", t.nullability, &[t.length.to_string()])
+                }
+                Kind::Decimal(t) => format_type(
+                    "decimal",
+                    t.nullability,
+                    &[t.precision.to_string(), t.scale.to_string()],
+                ),
+                Kind::PrecisionTimestamp(t) => format_type(
+                    "precision_timestamp",
+                    t.nullability,
+                    &[t.precision.to_string()],
+                ),
+                Kind::PrecisionTimestampTz(t) => format_type(
+                    "precision_timestamp_tz",
+                    t.nullability,
+                    &[t.precision.to_string()],
+                ),
+                Kind::Struct(t) => format_type(
+                    "struct",
+                    t.nullability,
+                    &t.types
+                        .iter()
+                        .map(|child| child.to_human_readable(registry))
+                        .collect::<Vec<_>>(),
+                ),
+                Kind::List(t) => format_type("list", t.nullability, &[render_child(&t.r#type)]),
+                Kind::Map(t) => format_type(
+                    "map",
+                    t.nullability,
+                    &[render_child(&t.key), render_child(&t.value)],
+                ),
+                Kind::UserDefined(t) => {
+                    format_user_defined(t.type_reference, t.nullability, registry)
+                }
+                Kind::UserDefinedTypeReference(anchor) => {
+                    format_user_defined(*anchor, nullability(false), registry)
+                }
+            },
+        }
+    }
+}
+
+/// A type parameter parsed from a human readable type string
+#[derive(Clone)]
+enum TypeParam {
+    Integer(i32),
+    Type(Type),
+}
+
+/// A small recursive descent parser for the human readable type syntax
+struct TypeParser<'a> {
+    text: &'a str,
+    pos: usize,
+    registry: &'a ExtensionsRegistry,
+}
+
+impl<'a> TypeParser<'a> {
+    fn error(&self, message: impl AsRef<str>) -> SubstraitExprError {
+        SubstraitExprError::invalid_input(format!(
+            "Could not parse the type {} at position {}: {}",
+            self.text,
+            self.pos,
+            message.as_ref()
+        ))
+    }
+
+    fn skip_whitespace(&mut self) {
+        let remaining = &self.text[self.pos..];
+        self.pos += remaining.len() - remaining.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.text[self.pos..].chars().next()
+    }
+
+    fn consume(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.pos += expected.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> &'a str {
+        self.skip_whitespace();
+        let remaining = &self.text[self.pos..];
+        let len = remaining
+            .find(|c: char| !predicate(c))
+            .unwrap_or(remaining.len());
+        self.pos += len;
+        &remaining[..len]
+    }
+
+    fn parse_param(&mut self) -> Result<TypeParam> {
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() => {
+                let digits = self.take_while(|c| c.is_ascii_digit());
+                digits
+                    .parse::<i32>()
+                    .map(TypeParam::Integer)
+                    .map_err(|_| self.error(format!("{} is not a valid parameter", digits)))
+            }
+            _ => Ok(TypeParam::Type(self.parse_type()?)),
+        }
+    }
+
+    fn parse_params(&mut self) -> Result<Vec<TypeParam>> {
+        let mut params = Vec::new();
+        if !self.consume('<') || self.consume('>') {
+            return Ok(params);
+        }
+        loop {
+            params.push(self.parse_param()?);
+            if self.consume('>') {
+                return Ok(params);
+            }
+            if !self.consume(',') {
+                return Err(self.error("expected , or >"));
+            }
+        }
+    }
+
+    fn user_defined_anchor(&self, name: &str) -> Result<u32> {
+        let anchors = self.registry.lookup_type_anchors(name);
+        match anchors.as_slice() {
+            [anchor] => Ok(*anchor),
+            [] => name
+                .strip_prefix("anchor_")
+                .and_then(|anchor| anchor.parse::<u32>().ok())
+                .ok_or_else(|| self.error(format!("no type named {} is registered", name))),
+            _ => Err(self.error(format!("the type name {} is ambiguous", name))),
+        }
+    }
+
+    fn parse_type(&mut self) -> Result<Type> {
+        let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_' || c == '!');
+        if name.is_empty() {
+            return Err(self.error("expected a type name"));
+        }
+        let nullable = self.consume('?');
+        let params = self.parse_params()?;
+        let nullability = nullability(nullable);
+        let type_variation_reference = NO_VARIATION;
+
+        if let Some(udt_name) = name.strip_prefix("u!") {
+            if !params.is_empty() {
+                return Err(self.error("user defined type parameters are not supported"));
+            }
+            return Ok(Type {
+                kind: Some(Kind::UserDefined(UserDefined {
+                    type_reference: self.user_defined_anchor(udt_name)?,
+                    type_variation_reference,
+                    nullability,
+                    type_parameters: vec![],
+                })),
+            });
+        }
+
+        let lowercase = name.to_ascii_lowercase();
+        let kind = match (lowercase.as_str(), params.as_slice()) {
+            ("boolean" | "bool", []) => Kind::Bool(Boolean {
+                type_variation_reference,
+                nullability,
+            }),
+            ("i8", []) => Kind::I8(I8 {
+                type_variation_reference,
+                nullability,
+            }),
+            ("i16", []) => Kind::I16(I16 {
+                type_variation_reference,
+                nullability,
+            }),
+            ("i32", []) => Kind::I32(I32 {
+                type_variation_reference,
+                nullability,
+            }),
+            ("i64", []) => Kind::I64(I64 {
+                type_variation_reference,
+                nullability,
+            }),
+            ("fp32", []) => Kind::Fp32(Fp32 {
+                type_variation_reference,
+                nullability,
+            }),
+            ("fp64", []) => Kind::Fp64(Fp64 {
+                type_variation_reference,
+                nullability,
+            }),
+            ("string" | "str", []) => Kind::String(SubstraitString {
+                type_variation_reference,
+                nullability,
+            }),
+            ("binary" | "vbin", []) => Kind::Binary(Binary {
+                type_variation_reference,
+                nullability,
+            }),
+            ("timestamp" | "ts", []) => Kind::Timestamp(Timestamp {
+                type_variation_reference,
+                nullability,
+            }),
+            ("timestamp_tz" | "tstz", []) => Kind::TimestampTz(TimestampTz {
+                type_variation_reference,
+                nullability,
+            }),
+            ("date", []) => Kind::Date(Date {
+                type_variation_reference,
+                nullability,
+            }),
+            ("time", []) => Kind::Time(Time {
+                type_variation_reference,
+                nullability,
+            }),
+            ("interval_year" | "iyear", []) => Kind::IntervalYear(IntervalYear {
+                type_variation_reference,
+                nullability,
+            }),
+            ("interval_day" | "iday", []) => Kind::IntervalDay(IntervalDay {
+                type_variation_reference,
+                nullability,
+                precision: None,
+            }),
+            ("interval_day" | "iday", [TypeParam::Integer(precision)]) => {
+                Kind::IntervalDay(IntervalDay {
+                    type_variation_reference,
+                    nullability,
+                    precision: Some(*precision),
+                })
+            }
+            ("interval_compound" | "icompound", [TypeParam::Integer(precision)]) => {
+                Kind::IntervalCompound(IntervalCompound {
+                    type_variation_reference,
+                    nullability,
+                    precision: *precision,
+                })
+            }
+            ("uuid", []) => Kind::Uuid(Uuid {
+                type_variation_reference,
+                nullability,
+            }),
+            ("fixedchar" | "fchar", [TypeParam::Integer(length)]) => Kind::FixedChar(FixedChar {
+                length: *length,
+                type_variation_reference,
+                nullability,
+            }),
+            ("varchar" | "vchar", [TypeParam::Integer(length)]) => Kind::Varchar(VarChar {
+                length: *length,
+                type_variation_reference,
+                nullability,
+            }),
+            ("fixedbinary" | "fbin", [TypeParam::Integer(length)]) => {
+                Kind::FixedBinary(FixedBinary {
+                    length: *length,
+                    type_variation_reference,
+                    nullability,
+                })
+            }
+            ("decimal" | "dec", [TypeParam::Integer(precision), TypeParam::Integer(scale)]) => {
+                let precision = u8::try_from(*precision)
+                    .map_err(|_| self.error("the decimal precision is too large"))?;
+                let scale = u8::try_from(*scale)
+                    .map_err(|_| self.error("the decimal scale is too large"))?;
+                return decimal(precision, scale, nullable);
+            }
+            ("precision_timestamp" | "pts", [TypeParam::Integer(precision)]) => {
+                Kind::PrecisionTimestamp(PrecisionTimestamp {
+                    precision: *precision,
+                    type_variation_reference,
+                    nullability,
+                })
+            }
+            ("precision_timestamp_tz" | "ptstz", [TypeParam::Integer(precision)]) => {
+                Kind::PrecisionTimestampTz(PrecisionTimestampTz {
+                    precision: *precision,
+                    type_variation_reference,
+                    nullability,
+                })
+            }
+            ("list", [TypeParam::Type(item)]) => return Ok(list(nullable, item.clone())),
+            ("map", [TypeParam::Type(key), TypeParam::Type(value)]) => {
+                return Ok(map(nullable, key.clone(), value.clone()))
+            }
+            ("struct", children) => {
+                let children = children
+                    .iter()
+                    .map(|child| match child {
+                        TypeParam::Type(child) => Ok(child.clone()),
+                        TypeParam::Integer(_) => Err(self.error("struct parameters must be types")),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                return Ok(struct_(nullable, children));
+            }
+            _ => {
+                return Err(self.error(format!(
+                    "{} is not a known type or has the wrong parameters",
+                    name
+                )))
+            }
+        };
+        Ok(Type { kind: Some(kind) })
+    }
+}
+
+/// Parses a type from its human readable form (e.g. `decimal?<38,6>` or `list<i32?>`)
+///
+/// This is the inverse of [`TypeExt::to_human_readable`].  Both the long and short type
+/// names from the Substrait documentation are accepted (e.g. `boolean` or `bool`).  User
+/// defined types (e.g. `u!point`) are looked up by name in the registry.
+pub fn parse(text: &str, registry: &ExtensionsRegistry) -> Result<Type> {
+    let mut parser = TypeParser {
+        text,
+        pos: 0,
+        registry,
+    };
+    let parsed = parser.parse_type()?;
+    if parser.peek().is_some() {
+        return Err(parser.error("unexpected trailing characters"));
+    }
+    Ok(parsed)
+}
+
+/// Changes the type variation of a type in place
+///
+/// Legacy user defined type references have no variation and are converted to the
+/// modern user defined type first.  Types without a kind are left alone.
+pub(crate) fn set_variation(typ: &mut Type, variation: u32) {
+    if let Some(Kind::UserDefinedTypeReference(anchor)) = typ.kind {
+        typ.kind = Some(Kind::UserDefined(UserDefined {
+            type_reference: anchor,
+            type_variation_reference: variation,
+            nullability: nullability(false),
+            type_parameters: vec![],
+        }));
+        return;
+    }
+    match typ.kind.as_mut() {
+        Some(Kind::Bool(t)) => t.type_variation_reference = variation,
+        Some(Kind::I8(t)) => t.type_variation_reference = variation,
+        Some(Kind::I16(t)) => t.type_variation_reference = variation,
+        Some(Kind::I32(t)) => t.type_variation_reference = variation,
+        Some(Kind::I64(t)) => t.type_variation_reference = variation,
+        Some(Kind::Fp32(t)) => t.type_variation_reference = variation,
+        Some(Kind::Fp64(t)) => t.type_variation_reference = variation,
+        Some(Kind::String(t)) => t.type_variation_reference = variation,
+        Some(Kind::Binary(t)) => t.type_variation_reference = variation,
+        Some(Kind::Timestamp(t)) => t.type_variation_reference = variation,
+        Some(Kind::Date(t)) => t.type_variation_reference = variation,
+        Some(Kind::Time(t)) => t.type_variation_reference = variation,
+        Some(Kind::IntervalYear(t)) => t.type_variation_reference = variation,
+        Some(Kind::IntervalDay(t)) => t.type_variation_reference = variation,
+        Some(Kind::IntervalCompound(t)) => t.type_variation_reference = variation,
+        Some(Kind::TimestampTz(t)) => t.type_variation_reference = variation,
+        Some(Kind::Uuid(t)) => t.type_variation_reference = variation,
+        Some(Kind::FixedChar(t)) => t.type_variation_reference = variation,
+        Some(Kind::Varchar(t)) => t.type_variation_reference = variation,
+        Some(Kind::FixedBinary(t)) => t.type_variation_reference = variation,
+        Some(Kind::Decimal(t)) => t.type_variation_reference = variation,
+        Some(Kind::PrecisionTimestamp(t)) => t.type_variation_reference = variation,
+        Some(Kind::PrecisionTimestampTz(t)) => t.type_variation_reference = variation,
+        Some(Kind::Struct(t)) => t.type_variation_reference = variation,
+        Some(Kind::List(t)) => t.type_variation_reference = variation,
+        Some(Kind::Map(t)) => t.type_variation_reference = variation,
+        Some(Kind::UserDefined(t)) => t.type_variation_reference = variation,
+        Some(Kind::UserDefinedTypeReference(_)) | None => {}
+    }
+}
+
+/// Determines the result type of an expression that picks one of several branches
+///
+/// This is used for expressions like coalesce, if-then, and switch.  The result has the
+/// type of the first branch.  It is non-nullable only if there is a default branch (the
+/// else / default case, or the last argument of coalesce) and none of the branches are
+/// nullable.  Callers should pass branches that can only be taken when they are non-null
+/// (e.g. the leading arguments of coalesce) as non-nullable types.
+///
+/// Returns an error if there are no branches
+pub fn branch_result_type(branch_types: &[Type], has_default: bool) -> Result<Type> {
+    let first = branch_types.first().ok_or_else(|| {
+        SubstraitExprError::invalid_input("Cannot determine the result type of zero branches")
+    })?;
+    let mut nullable = !has_default;
+    for branch_type in branch_types {
+        nullable |= branch_type.is_nullable()?;
+    }
+    Ok(first.with_nullability(nullable))
+}
+
+/// Returns a copy of the type where `modify` has been applied to the type and any nested types
+fn modify_recursive(typ: &Type, modify: &impl Fn(&mut Type)) -> Type {
+    let mut typ = typ.clone();
+    modify(&mut typ);
+    match typ.kind.as_mut() {
+        Some(Kind::Struct(strct)) => {
+            for child in strct.types.iter_mut() {
+                *child = modify_recursive(child, modify);
+            }
+        }
+        Some(Kind::List(list)) => {
+            if let Some(item) = list.r#type.as_mut() {
+                **item = modify_recursive(item, modify);
+            }
+        }
+        Some(Kind::Map(map)) => {
+            if let Some(key) = map.key.as_mut() {
+                **key = modify_recursive(key, modify);
+            }
+            if let Some(value) = map.value.as_mut() {
+                **value = modify_recursive(value, modify);
+            }
+        }
+        _ => {}
+    }
+    typ
+}
+
+/// Returns a copy of the type where the type, and any nested types, are non-nullable
+fn without_nullability(typ: &Type) -> Type {
+    modify_recursive(typ, &|typ| *typ = typ.with_nullability(false))
+}
+
+/// Returns a copy of the type where the type, and any nested types, have no variation
+pub(crate) fn without_variation(typ: &Type) -> Type {
+    modify_recursive(typ, &|typ| set_variation(typ, NO_VARIATION))
+}
+
+fn collect_type(typ: &Type, collected: &mut Vec<Type>) {
+    let non_nullable = without_nullability(typ);
+    if !collected.contains(&non_nullable) {
+        collected.push(non_nullable);
+    }
+    match &typ.kind {
+        Some(Kind::Struct(strct)) => strct
+            .types
+            .iter()
+            .for_each(|child| collect_type(child, collected)),
+        Some(Kind::List(list)) => list
+            .r#type
+            .iter()
+            .for_each(|item| collect_type(item, collected)),
+        Some(Kind::Map(map)) => map
+            .key
+            .iter()
+            .chain(map.value.iter())
+            .for_each(|child| collect_type(child, collected)),
+        _ => {}
+    }
+}
+
+/// Returns every distinct type that appears in `types`, including nested types
+///
+/// Types are compared ignoring nullability and are returned as non-nullable types in the
+/// order they are first encountered (a parent type comes before its children).  This is
+/// useful for code that needs to map every Substrait type to some other type system.
+pub fn collect_types(types: impl IntoIterator<Item = Type>) -> Vec<Type> {
+    let mut collected = Vec::new();
+    for typ in types {
+        collect_type(&typ, &mut collected);
+    }
+    collected
+}
+
+/// Replaces any legacy user defined type references with the modern equivalent
+///
+/// Older producers may use `Kind::UserDefinedTypeReference`, which only carries an anchor.
+/// The spec says these should be treated as non-nullable with no type variation so they are
+/// converted to `Kind::UserDefined` with those properties.  Nested types are normalized as well.
+pub fn normalize_legacy_types(typ: Type) -> Type {
+    let normalize_child =
+        |child: Option<Box<Type>>| child.map(|child| Box::new(normalize_legacy_types(*child)));
+    let kind = typ.kind.map(|kind| match kind {
+        Kind::UserDefinedTypeReference(anchor) => Kind::UserDefined(UserDefined {
+            type_reference: anchor,
+            type_variation_reference: NO_VARIATION,
+            nullability: nullability(false),
+            type_parameters: vec![],
+        }),
+        Kind::Struct(strct) => Kind::Struct(Struct {
+            types: strct
+                .types
+                .into_iter()
+                .map(normalize_legacy_types)
+                .collect(),
+            ..strct
+        }),
+        Kind::List(mut list) => {
+            list.r#type = normalize_child(list.r#type);
+            Kind::List(list)
+        }
+        Kind::Map(mut map) => {
+            map.key = normalize_child(map.key);
+            map.value = normalize_child(map.value);
+            Kind::Map(map)
+        }
+        other => other,
+    });
+    Type { kind }
 }
 
 pub(crate) const fn nullability(nullable: bool) -> i32 {
@@ -204,6 +907,68 @@ impl TypeInfer for Vec<u8> {
     }
 }
 
+#[cfg(feature = "chrono")]
+impl TypeInfer for chrono::NaiveDate {
+    fn as_substrait(nullable: bool) -> Type {
+        date(nullable)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TypeInfer for chrono::NaiveTime {
+    fn as_substrait(nullable: bool) -> Type {
+        time(nullable)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TypeInfer for chrono::NaiveDateTime {
+    fn as_substrait(nullable: bool) -> Type {
+        precision_timestamp(MICROSECOND_PRECISION, nullable)
+    }
+}
+
+/// An optional value is a nullable instance of the inner type
+impl<T: TypeInfer> TypeInfer for Option<T> {
+    fn as_substrait(_nullable: bool) -> Type {
+        T::as_substrait(true)
+    }
+}
+
+impl<T: TypeInfer> TypeInfer for Vec<T> {
+    fn as_substrait(nullable: bool) -> Type {
+        list(nullable, T::as_substrait(false))
+    }
+}
+
+impl<K: TypeInfer, V: TypeInfer> TypeInfer for BTreeMap<K, V> {
+    fn as_substrait(nullable: bool) -> Type {
+        map(nullable, K::as_substrait(false), V::as_substrait(false))
+    }
+}
+
+impl<K: TypeInfer, V: TypeInfer> TypeInfer for HashMap<K, V> {
+    fn as_substrait(nullable: bool) -> Type {
+        map(nullable, K::as_substrait(false), V::as_substrait(false))
+    }
+}
+
+macro_rules! impl_tuple_type_infer {
+    ($($name:ident),+) => {
+        /// Tuples are structs with one field per element
+        impl<$($name: TypeInfer),+> TypeInfer for ($($name,)+) {
+            fn as_substrait(nullable: bool) -> Type {
+                struct_(nullable, vec![$($name::as_substrait(false)),+])
+            }
+        }
+    };
+}
+
+impl_tuple_type_infer!(A);
+impl_tuple_type_infer!(A, B);
+impl_tuple_type_infer!(A, B, C);
+impl_tuple_type_infer!(A, B, C, D);
+
 /// Create a substrait type from a rust type
 pub fn from_rust<T: TypeInfer>(nullable: bool) -> Type {
     <T as TypeInfer>::as_substrait(nullable)
@@ -254,9 +1019,324 @@ pub fn struct_(nullable: bool, children: Vec<Type>) -> Type {
         })),
     }
 }
+/// The maximum precision of a decimal type
+pub const MAX_DECIMAL_PRECISION: u8 = 38;
+/// Create an instance of the decimal type
+///
+/// Returns an error if precision is not between 1 and 38 or if scale is larger than precision
+pub fn decimal(precision: u8, scale: u8, nullable: bool) -> Result<Type> {
+    if precision == 0 || precision > MAX_DECIMAL_PRECISION {
+        return Err(SubstraitExprError::invalid_input(format!(
+            "Decimal precision must be between 1 and {} but was {}",
+            MAX_DECIMAL_PRECISION, precision
+        )));
+    }
+    if scale > precision {
+        return Err(SubstraitExprError::invalid_input(format!(
+            "Decimal scale ({}) cannot be larger than the precision ({})",
+            scale, precision
+        )));
+    }
+    Ok(Type {
+        kind: Some(Kind::Decimal(Decimal {
+            precision: precision as i32,
+            scale: scale as i32,
+            nullability: nullability(nullable),
+            type_variation_reference: NO_VARIATION,
+        })),
+    })
+}
+/// Create an instance of the date type
+pub fn date(nullable: bool) -> Type {
+    Type {
+        kind: Some(Kind::Date(Date {
+            nullability: nullability(nullable),
+            type_variation_reference: NO_VARIATION,
+        })),
+    }
+}
+/// Create an instance of the time type (microseconds past midnight)
+pub fn time(nullable: bool) -> Type {
+    Type {
+        kind: Some(Kind::Time(Time {
+            nullability: nullability(nullable),
+            type_variation_reference: NO_VARIATION,
+        })),
+    }
+}
+/// Create an instance of the (deprecated) timestamp type (microseconds since the epoch)
+pub fn timestamp(nullable: bool) -> Type {
+    Type {
+        kind: Some(Kind::Timestamp(Timestamp {
+            nullability: nullability(nullable),
+            type_variation_reference: NO_VARIATION,
+        })),
+    }
+}
+/// Create an instance of the (deprecated) timestamp_tz type (microseconds since the epoch, UTC)
+pub fn timestamp_tz(nullable: bool) -> Type {
+    Type {
+        kind: Some(Kind::TimestampTz(TimestampTz {
+            nullability: nullability(nullable),
+            type_variation_reference: NO_VARIATION,
+        })),
+    }
+}
+/// The precision of a timestamp measured in microseconds
+pub const MICROSECOND_PRECISION: u8 = 6;
+/// Create an instance of the precision_timestamp type
+///
+/// `precision` is the number of sub-second digits (e.g. 3 for milliseconds, 6 for microseconds)
+pub fn precision_timestamp(precision: u8, nullable: bool) -> Type {
+    Type {
+        kind: Some(Kind::PrecisionTimestamp(PrecisionTimestamp {
+            precision: precision as i32,
+            nullability: nullability(nullable),
+            type_variation_reference: NO_VARIATION,
+        })),
+    }
+}
+/// Create an instance of the uuid type
+pub fn uuid(nullable: bool) -> Type {
+    Type {
+        kind: Some(Kind::Uuid(Uuid {
+            nullability: nullability(nullable),
+            type_variation_reference: NO_VARIATION,
+        })),
+    }
+}
+/// Create an instance of the fixed_binary type
+pub fn fixed_binary(length: u32, nullable: bool) -> Type {
+    Type {
+        kind: Some(Kind::FixedBinary(FixedBinary {
+            length: length as i32,
+            nullability: nullability(nullable),
+            type_variation_reference: NO_VARIATION,
+        })),
+    }
+}
+/// Create an instance of the interval_year type (a number of years and months)
+pub fn interval_year(nullable: bool) -> Type {
+    Type {
+        kind: Some(Kind::IntervalYear(IntervalYear {
+            nullability: nullability(nullable),
+            type_variation_reference: NO_VARIATION,
+        })),
+    }
+}
+/// Create an instance of the interval_day type (a number of days, seconds, and subseconds)
+///
+/// `precision` is the number of sub-second digits, as with [`precision_timestamp`]
+pub fn interval_day(precision: u8, nullable: bool) -> Type {
+    Type {
+        kind: Some(Kind::IntervalDay(IntervalDay {
+            precision: Some(precision as i32),
+            nullability: nullability(nullable),
+            type_variation_reference: NO_VARIATION,
+        })),
+    }
+}
+/// Create an instance of the list type
+pub fn list(nullable: bool, item_type: Type) -> Type {
+    Type {
+        kind: Some(Kind::List(Box::new(List {
+            r#type: Some(Box::new(item_type)),
+            nullability: nullability(nullable),
+            ..Default::default()
+        }))),
+    }
+}
+/// Create an instance of the map type
+pub fn map(nullable: bool, key_type: Type, value_type: Type) -> Type {
+    Type {
+        kind: Some(Kind::Map(Box::new(Map {
+            key: Some(Box::new(key_type)),
+            value: Some(Box::new(value_type)),
+            nullability: nullability(nullable),
+            ..Default::default()
+        }))),
+    }
+}
 /// The URI of the unknown type
 pub const UNKNOWN_TYPE_URI: &'static str = "https://substrait.io/types";
 /// The name of the unknown type
 pub const UNKNOWN_TYPE_NAME: &'static str = "unknown";
 /// A friendly name that indicates there is no type variation being used
 pub const NO_VARIATION: u32 = 0;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_human_readable() {
+        let registry = ExtensionsRegistry::default();
+        assert_eq!(i32(false).to_human_readable(&registry), "i32");
+        assert_eq!(
+            list(true, map(false, string(false), fp64(true))).to_human_readable(&registry),
+            "list?<map<string,fp64?>>"
+        );
+        assert_eq!(
+            struct_(false, vec![bool(true), binary(false)]).to_human_readable(&registry),
+            "struct<boolean?,binary>"
+        );
+    }
+
+    #[test]
+    fn test_nullability() {
+        let nested = list(false, map(true, string(false), fp64(true)));
+        assert!(!nested.is_nullable().unwrap());
+        let flipped = nested.with_nullability(true);
+        assert!(flipped.is_nullable().unwrap());
+        // Only the top-level nullability changes
+        assert_eq!(flipped.children(), nested.children());
+        assert_eq!(flipped.with_nullability(false), nested);
+
+        let legacy = Type {
+            kind: Some(Kind::UserDefinedTypeReference(3)),
+        };
+        assert!(!legacy.is_nullable().unwrap());
+        assert!(legacy.with_nullability(true).is_nullable().unwrap());
+
+        assert!(Type::default().is_nullable().is_err());
+    }
+
+    #[test]
+    fn test_structural_equality() {
+        let dec = |precision, scale, nullable| decimal(precision, scale, nullable).unwrap();
+        assert!(dec(38, 6, false).same_kind(&dec(10, 2, false)).unwrap());
+        assert!(!dec(38, 6, false).structurally_eq(&dec(10, 2, false)));
+        assert!(dec(38, 6, false).structurally_eq(&dec(38, 6, true)));
+        assert!(list(true, i32(true)).structurally_eq(&list(false, i32(false))));
+        assert!(!list(true, i32(true)).structurally_eq(&list(true, i64(true))));
+        assert!(map(false, string(false), fp64(true)).structurally_eq(&map(
+            true,
+            string(true),
+            fp64(false)
+        )));
+
+        let mut variant = i32(false);
+        set_variation(&mut variant, 7);
+        let nested_variant = struct_(false, vec![variant.clone()]);
+        assert!(!variant.structurally_eq(&i32(false)));
+        assert!(variant.eq_ignoring_variation(&i32(false)));
+        assert!(nested_variant.eq_ignoring_variation(&struct_(false, vec![i32(false)])));
+        assert!(!variant.eq_ignoring_variation(&i32(true)));
+    }
+
+    #[test]
+    fn test_parse() {
+        let registry = ExtensionsRegistry::default();
+        let simple = [
+            "boolean",
+            "i8",
+            "i16",
+            "i32",
+            "i64",
+            "fp32",
+            "fp64",
+            "string",
+            "binary",
+            "timestamp",
+            "timestamp_tz",
+            "date",
+            "time",
+            "interval_year",
+            "interval_day",
+            "interval_day<6>",
+            "interval_compound<3>",
+            "uuid",
+            "fixedchar<8>",
+            "varchar<32>",
+            "fixedbinary<16>",
+            "decimal<38,6>",
+            "precision_timestamp<6>",
+            "precision_timestamp_tz<9>",
+        ];
+        for name in simple {
+            let nullable = match name.find('<') {
+                Some(pos) => format!("{}?{}", &name[..pos], &name[pos..]),
+                None => format!("{}?", name),
+            };
+            for text in [name.to_string(), nullable] {
+                let parsed = parse(&text, &registry).unwrap();
+                assert_eq!(parsed.to_human_readable(&registry), text);
+                assert_eq!(
+                    parse(&parsed.to_human_readable(&registry), &registry).unwrap(),
+                    parsed
+                );
+            }
+        }
+
+        assert_eq!(
+            parse("list?<map<string, fp64?>>", &registry).unwrap(),
+            list(true, map(false, string(false), fp64(true)))
+        );
+        assert_eq!(
+            parse("struct<bool?,vbin>", &registry).unwrap(),
+            struct_(false, vec![bool(true), binary(false)])
+        );
+        assert_eq!(
+            parse("struct<>", &registry).unwrap(),
+            struct_(false, vec![])
+        );
+        assert_eq!(
+            parse("DEC<10,2>", &registry).unwrap(),
+            decimal(10, 2, false).unwrap()
+        );
+
+        let anchor = registry.register_type("https://imaginary.com/types".to_string(), "point");
+        let point = parse("list<u!point?>", &registry).unwrap();
+        assert_eq!(point.to_human_readable(&registry), "list<u!point?>");
+        assert!(point.children().is_empty());
+        assert_eq!(
+            parse("u!point", &registry).unwrap().kind,
+            Some(Kind::UserDefined(UserDefined {
+                type_reference: anchor,
+                type_variation_reference: NO_VARIATION,
+                nullability: nullability(false),
+                type_parameters: vec![],
+            }))
+        );
+
+        for invalid in [
+            "",
+            "i33",
+            "i32<4>",
+            "decimal<10>",
+            "decimal<10,11>",
+            "list<i32",
+            "list<i32>>",
+            "map<i32>",
+            "struct<1>",
+            "u!circle",
+        ] {
+            assert!(parse(invalid, &registry).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_legacy_user_defined_type() {
+        let registry = ExtensionsRegistry::default();
+        let anchor = registry.register_type("https://imaginary.com/types".to_string(), "point");
+        let legacy = Type {
+            kind: Some(Kind::UserDefinedTypeReference(anchor)),
+        };
+        assert_eq!(legacy.to_human_readable(&registry), "u!point");
+
+        let normalized = normalize_legacy_types(list(false, legacy));
+        let expected = list(
+            false,
+            Type {
+                kind: Some(Kind::UserDefined(UserDefined {
+                    type_reference: anchor,
+                    type_variation_reference: NO_VARIATION,
+                    nullability: nullability(false),
+                    type_parameters: vec![],
+                })),
+            },
+        );
+        assert_eq!(normalized, expected);
+        assert_eq!(normalized.to_human_readable(&registry), "list<u!point>");
+    }
+}
@@ -0,0 +1,486 @@
+//! # Traversing and rewriting expressions
+//!
+//! Implement [`ExpressionVisitor`] to collect information from an expression tree and then
+//! call [`walk`] to visit every node.  Each visit method has an empty default so a visitor
+//! only needs to implement the methods for the nodes it cares about.
+//!
+//! Implement [`ExpressionRewriter`] to transform an expression tree.  The rewriter is given
+//! each node after its children have been rewritten and returns a replacement.
+//!
+//! For example, this visitor collects the (top-level) fields referenced by an expression:
+//!
+//! ```
+//! use substrait::proto::expression::field_reference::ReferenceType;
+//! use substrait::proto::expression::reference_segment;
+//! use substrait::proto::expression::FieldReference;
+//! use substrait_expr::builder::schema::SchemaBuildersExt;
+//! use substrait_expr::builder::{BuilderParams, ExpressionsBuilder};
+//! use substrait_expr::functions::functions_arithmetic::FunctionsArithmeticExt;
+//! use substrait_expr::helpers::schema::SchemaInfo;
+//! use substrait_expr::helpers::types;
+//! use substrait_expr::helpers::visitor::{walk, ExpressionVisitor};
+//!
+//! #[derive(Default)]
+//! struct FieldIndexCollector {
+//!     indices: Vec<i32>,
+//! }
+//!
+//! impl ExpressionVisitor for FieldIndexCollector {
+//!     fn visit_field_reference(&mut self, reference: &FieldReference) {
+//!         if let Some(ReferenceType::DirectReference(segment)) = &reference.reference_type {
+//!             if let Some(reference_segment::ReferenceType::StructField(field)) =
+//!                 &segment.reference_type
+//!             {
+//!                 self.indices.push(field.field);
+//!             }
+//!         }
+//!     }
+//! }
+//!
+//! let schema = SchemaInfo::new_full()
+//!     .field("x", types::i32(false))
+//!     .field("y", types::i32(false))
+//!     .build();
+//! let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+//! let x = builder.fields().resolve_by_name("x").unwrap();
+//! let y = builder.fields().resolve_by_name("y").unwrap();
+//! let sum = builder.functions().add(y, x).build().unwrap();
+//!
+//! let mut collector = FieldIndexCollector::default();
+//! walk(&sum, &mut collector);
+//! assert_eq!(collector.indices, vec![1, 0]);
+//! ```
+
+use substrait::proto::expression::field_reference::RootType;
+use substrait::proto::expression::nested::NestedType;
+use substrait::proto::expression::{
+    Cast, FieldReference, Literal, RexType, ScalarFunction, WindowFunction,
+};
+use substrait::proto::function_argument::ArgType;
+use substrait::proto::Expression;
+
+use crate::error::Result;
+
+/// A read-only visitor of expression nodes
+///
+/// See [`walk`] for the order in which nodes are visited
+pub trait ExpressionVisitor {
+    /// Called for every node, before the method for the kind of node (if any)
+    fn visit_expression(&mut self, _expr: &Expression) {}
+    /// Called for each literal
+    fn visit_literal(&mut self, _literal: &Literal) {}
+    /// Called for each field reference, before its root expression (if any) is visited
+    fn visit_field_reference(&mut self, _reference: &FieldReference) {}
+    /// Called for each scalar function, before its arguments are visited
+    fn visit_scalar_function(&mut self, _func: &ScalarFunction) {}
+    /// Called for each window function, before its arguments, partitions, and sorts are
+    /// visited
+    fn visit_window_function(&mut self, _func: &WindowFunction) {}
+    /// Called for each cast, before its input is visited
+    fn visit_cast(&mut self, _cast: &Cast) {}
+}
+
+/// Visits every node in an expression, parents before children
+///
+/// The walk descends into the value arguments of scalar functions, the value arguments,
+/// partitions, and sort expressions of window functions, the inputs of casts,
+/// the value and options of IN-lists, the children of nested (struct, list, and map)
+/// expressions, the conditions and results of if-then expressions, the value and results
+/// of switch expressions, and the root expressions of field references.
+pub fn walk(expr: &Expression, visitor: &mut impl ExpressionVisitor) {
+    visitor.visit_expression(expr);
+    match &expr.rex_type {
+        Some(RexType::Literal(literal)) => visitor.visit_literal(literal),
+        Some(RexType::Selection(reference)) => {
+            visitor.visit_field_reference(reference);
+            if let Some(RootType::Expression(root)) = &reference.root_type {
+                walk(root, visitor);
+            }
+        }
+        Some(RexType::ScalarFunction(func)) => {
+            visitor.visit_scalar_function(func);
+            for arg in &func.arguments {
+                if let Some(ArgType::Value(value)) = &arg.arg_type {
+                    walk(value, visitor);
+                }
+            }
+        }
+        Some(RexType::WindowFunction(func)) => {
+            visitor.visit_window_function(func);
+            for arg in &func.arguments {
+                if let Some(ArgType::Value(value)) = &arg.arg_type {
+                    walk(value, visitor);
+                }
+            }
+            for partition in &func.partitions {
+                walk(partition, visitor);
+            }
+            for sort in &func.sorts {
+                if let Some(sort_expr) = &sort.expr {
+                    walk(sort_expr, visitor);
+                }
+            }
+        }
+        Some(RexType::Cast(cast)) => {
+            visitor.visit_cast(cast);
+            if let Some(input) = &cast.input {
+                walk(input, visitor);
+            }
+        }
+        Some(RexType::SingularOrList(in_list)) => {
+            if let Some(value) = &in_list.value {
+                walk(value, visitor);
+            }
+            for option in &in_list.options {
+                walk(option, visitor);
+            }
+        }
+        Some(RexType::Nested(nested)) => {
+            let children: Vec<&Expression> = match &nested.nested_type {
+                Some(NestedType::Struct(strct)) => strct.fields.iter().collect(),
+                Some(NestedType::List(list)) => list.values.iter().collect(),
+                Some(NestedType::Map(map)) => map
+                    .key_values
+                    .iter()
+                    .flat_map(|key_value| key_value.key.iter().chain(&key_value.value))
+                    .collect(),
+                None => vec![],
+            };
+            for child in children {
+                walk(child, visitor);
+            }
+        }
+        Some(RexType::IfThen(if_then)) => {
+            for clause in &if_then.ifs {
+                for child in clause.r#if.iter().chain(&clause.then) {
+                    walk(child, visitor);
+                }
+            }
+            if let Some(else_result) = &if_then.r#else {
+                walk(else_result, visitor);
+            }
+        }
+        Some(RexType::SwitchExpression(switch)) => {
+            if let Some(value) = &switch.r#match {
+                walk(value, visitor);
+            }
+            for case in &switch.ifs {
+                if let Some(result) = &case.then {
+                    walk(result, visitor);
+                }
+            }
+            if let Some(else_result) = &switch.r#else {
+                walk(else_result, visitor);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Rewrites an expression from the leaves up
+///
+/// Only [`rewrite_node`](Self::rewrite_node) needs to be implemented.  The same nodes that
+/// are visited by [`walk`] are rewritten.
+pub trait ExpressionRewriter {
+    /// Returns the replacement for a node whose children have already been rewritten
+    fn rewrite_node(&mut self, expr: Expression) -> Result<Expression>;
+
+    /// Rewrites the children of `expr` and then `expr` itself
+    fn rewrite(&mut self, mut expr: Expression) -> Result<Expression> {
+        match &mut expr.rex_type {
+            Some(RexType::Selection(reference)) => {
+                if let Some(RootType::Expression(root)) = &mut reference.root_type {
+                    **root = self.rewrite(std::mem::take(root.as_mut()))?;
+                }
+            }
+            Some(RexType::ScalarFunction(func)) => {
+                for arg in &mut func.arguments {
+                    if let Some(ArgType::Value(value)) = &mut arg.arg_type {
+                        *value = self.rewrite(std::mem::take(value))?;
+                    }
+                }
+            }
+            Some(RexType::WindowFunction(func)) => {
+                for arg in &mut func.arguments {
+                    if let Some(ArgType::Value(value)) = &mut arg.arg_type {
+                        *value = self.rewrite(std::mem::take(value))?;
+                    }
+                }
+                for partition in &mut func.partitions {
+                    *partition = self.rewrite(std::mem::take(partition))?;
+                }
+                for sort in &mut func.sorts {
+                    if let Some(sort_expr) = &mut sort.expr {
+                        *sort_expr = self.rewrite(std::mem::take(sort_expr))?;
+                    }
+                }
+            }
+            Some(RexType::Cast(cast)) => {
+                if let Some(input) = &mut cast.input {
+                    **input = self.rewrite(std::mem::take(input.as_mut()))?;
+                }
+            }
+            Some(RexType::SingularOrList(in_list)) => {
+                if let Some(value) = &mut in_list.value {
+                    **value = self.rewrite(std::mem::take(value.as_mut()))?;
+                }
+                for option in &mut in_list.options {
+                    *option = self.rewrite(std::mem::take(option))?;
+                }
+            }
+            Some(RexType::Nested(nested)) => {
+                let children: Vec<&mut Expression> = match &mut nested.nested_type {
+                    Some(NestedType::Struct(strct)) => strct.fields.iter_mut().collect(),
+                    Some(NestedType::List(list)) => list.values.iter_mut().collect(),
+                    Some(NestedType::Map(map)) => map
+                        .key_values
+                        .iter_mut()
+                        .flat_map(|key_value| key_value.key.iter_mut().chain(&mut key_value.value))
+                        .collect(),
+                    None => vec![],
+                };
+                for child in children {
+                    *child = self.rewrite(std::mem::take(child))?;
+                }
+            }
+            Some(RexType::IfThen(if_then)) => {
+                for clause in &mut if_then.ifs {
+                    for child in clause.r#if.iter_mut().chain(&mut clause.then) {
+                        *child = self.rewrite(std::mem::take(child))?;
+                    }
+                }
+                if let Some(else_result) = &mut if_then.r#else {
+                    **else_result = self.rewrite(std::mem::take(else_result.as_mut()))?;
+                }
+            }
+            Some(RexType::SwitchExpression(switch)) => {
+                if let Some(value) = &mut switch.r#match {
+                    **value = self.rewrite(std::mem::take(value.as_mut()))?;
+                }
+                for case in &mut switch.ifs {
+                    if let Some(result) = &mut case.then {
+                        *result = self.rewrite(std::mem::take(result))?;
+                    }
+                }
+                if let Some(else_result) = &mut switch.r#else {
+                    **else_result = self.rewrite(std::mem::take(else_result.as_mut()))?;
+                }
+            }
+            _ => {}
+        }
+        self.rewrite_node(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use substrait::proto::expression::literal::LiteralType;
+    use substrait::proto::sort_field::SortDirection;
+
+    use crate::builder::functions::CastFailureBehavior;
+    use crate::builder::schema::SchemaBuildersExt;
+    use crate::builder::{BuilderParams, ExpressionsBuilder};
+    use crate::functions::functions_arithmetic::{
+        FunctionsArithmeticExt, FunctionsArithmeticWindowExt,
+    };
+    use crate::functions::functions_comparison::FunctionsComparisonExt;
+    use crate::helpers::literals::literal;
+    use crate::helpers::schema::SchemaInfo;
+    use crate::helpers::types;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Counter {
+        literals: usize,
+        references: usize,
+        function_anchors: Vec<u32>,
+    }
+
+    impl ExpressionVisitor for Counter {
+        fn visit_literal(&mut self, _literal: &Literal) {
+            self.literals += 1;
+        }
+
+        fn visit_field_reference(&mut self, _reference: &FieldReference) {
+            self.references += 1;
+        }
+
+        fn visit_scalar_function(&mut self, func: &ScalarFunction) {
+            self.function_anchors.push(func.function_reference);
+        }
+    }
+
+    #[test]
+    fn test_walk() {
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(false))
+            .nested("location", false, |builder| {
+                builder.field("y", types::i32(false))
+            })
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let y = builder.fields().resolve_by_name("location.y").unwrap();
+        // x + 1 < location.y
+        let sum = builder.functions().add(x, literal(1_i32)).build().unwrap();
+        let predicate = builder.functions().lt(sum, y).build().unwrap();
+
+        let mut counter = Counter::default();
+        walk(&predicate, &mut counter);
+        assert_eq!(counter.literals, 1);
+        assert_eq!(counter.references, 2);
+        let registry = builder.schema().extensions_registry();
+        let names = counter
+            .function_anchors
+            .iter()
+            .map(|anchor| registry.lookup_function(*anchor).unwrap().name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["lt", "add"]);
+    }
+
+    #[test]
+    fn test_walk_window_function() {
+        let schema = SchemaInfo::new_full()
+            .field("x", types::i32(false))
+            .field("y", types::i32(false))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let y = builder.fields().resolve_by_name("y").unwrap();
+        // first_value(x) OVER (PARTITION BY x + 1 ORDER BY y)
+        let sum = builder
+            .functions()
+            .add(x.clone(), literal(1_i32))
+            .build()
+            .unwrap();
+        let window = builder
+            .functions()
+            .first_value(x)
+            .partition_by(sum)
+            .order_by(y, SortDirection::AscNullsFirst)
+            .build()
+            .unwrap();
+
+        let mut counter = Counter::default();
+        walk(&window, &mut counter);
+        assert_eq!(counter.literals, 1);
+        assert_eq!(counter.references, 3);
+        assert_eq!(counter.function_anchors.len(), 1);
+
+        // The partition is rewritten too
+        let Some(RexType::WindowFunction(func)) = Negate.rewrite(window).unwrap().rex_type else {
+            panic!("Expected a window function");
+        };
+        let Some(RexType::ScalarFunction(sum)) = &func.partitions[0].rex_type else {
+            panic!("Expected a scalar function");
+        };
+        assert_eq!(
+            sum.arguments[1].arg_type,
+            Some(ArgType::Value(literal(-1_i32)))
+        );
+    }
+
+    #[test]
+    fn test_walk_if_then_and_switch() {
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        // if x < 1 then x + 2 else 3
+        let condition = builder
+            .functions()
+            .lt(x.clone(), literal(1_i32))
+            .build()
+            .unwrap();
+        let sum = builder
+            .functions()
+            .add(x.clone(), literal(2_i32))
+            .build()
+            .unwrap();
+        let if_then = builder
+            .functions()
+            .if_then(vec![(condition, sum)], Some(literal(3_i32)))
+            .unwrap();
+
+        let mut counter = Counter::default();
+        walk(&if_then, &mut counter);
+        assert_eq!(counter.literals, 3);
+        assert_eq!(counter.references, 2);
+        assert_eq!(counter.function_anchors.len(), 2);
+
+        let negated = Negate.rewrite(if_then).unwrap();
+        let Some(RexType::IfThen(negated)) = negated.rex_type else {
+            panic!("Expected an if-then expression");
+        };
+        assert_eq!(negated.r#else.as_deref(), Some(&literal(-3_i32)));
+
+        // switch x + 2 { 1 => 4 } else 5 (the case literals are not expressions)
+        let sum = builder.functions().add(x, literal(2_i32)).build().unwrap();
+        let switch = builder
+            .functions()
+            .switch(
+                sum,
+                vec![(literal(1_i32), literal(4_i32))],
+                Some(literal(5_i32)),
+            )
+            .unwrap();
+
+        let mut counter = Counter::default();
+        walk(&switch, &mut counter);
+        assert_eq!(counter.literals, 3);
+        assert_eq!(counter.references, 1);
+        assert_eq!(counter.function_anchors.len(), 1);
+
+        let negated = Negate.rewrite(switch).unwrap();
+        let Some(RexType::SwitchExpression(negated)) = negated.rex_type else {
+            panic!("Expected a switch expression");
+        };
+        assert_eq!(negated.ifs[0].then, Some(literal(-4_i32)));
+        assert_eq!(negated.r#else.as_deref(), Some(&literal(-5_i32)));
+    }
+
+    struct Negate;
+
+    impl ExpressionRewriter for Negate {
+        fn rewrite_node(&mut self, mut expr: Expression) -> Result<Expression> {
+            if let Some(RexType::Literal(literal)) = &mut expr.rex_type {
+                match &mut literal.literal_type {
+                    Some(LiteralType::I8(value)) | Some(LiteralType::I16(value)) => {
+                        *value = -*value
+                    }
+                    Some(LiteralType::I32(value)) => *value = -*value,
+                    Some(LiteralType::I64(value)) => *value = -*value,
+                    _ => {}
+                }
+            }
+            Ok(expr)
+        }
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let build = |a: i32, b: i32| {
+            // cast(x + a as i64) < b
+            let sum = builder
+                .functions()
+                .add(x.clone(), literal(a))
+                .build()
+                .unwrap();
+            let cast = builder
+                .functions()
+                .cast(sum, types::i64(false), CastFailureBehavior::ThrowException)
+                .unwrap();
+            builder
+                .functions()
+                .lt(cast, literal(b as i64))
+                .build()
+                .unwrap()
+        };
+
+        let negated = Negate.rewrite(build(1, 5)).unwrap();
+        assert_eq!(negated, build(-1, -5));
+    }
+}
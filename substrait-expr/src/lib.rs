@@ -10,6 +10,7 @@
 //! * An [expression builder](crate::builder) is provided which makes it easy to programmatically
 //!   create expressions
 //! * Helper functions make it easy to get information about parts of an expression
+//! * Expressions received from other systems can be [validated](crate::validation)
 //! * (TODO) Utilities for converting to/from other Rust libraries
 //! * (TODO) An SQL parser allows you to create expressions from SQL strings
 //!
@@ -117,7 +118,8 @@
 //! to fields `x` and `y`.  If our schema is not aware of names then we cannot perform this
 //! lookup.  If name lookups are enabled then these field references will turn into a special
 //! "name lookup" AST node.  This behaves just like a field reference whose field is the
-//! unknown type.
+//! unknown type.  Once the schema is known these nodes can be replaced with real field
+//! references using [`bind`](crate::binding::bind).
 //!
 //! #### Name Annotations (TODO)
 //!
@@ -126,6 +128,7 @@
 //! a function call node.  We aim to be able to round trip this plan without losing that
 //! name information.  We do this by attaching a name annotation to the AST node.
 
+pub mod binding;
 pub mod builder;
 pub mod error;
 /// # Function definitions for common functions
@@ -155,6 +158,8 @@ pub mod functions {
     include!(concat!(env!("OUT_DIR"), "/src/functions.rs"));
 }
 pub mod helpers;
+pub mod optimize;
 pub(crate) mod util;
+pub mod validation;
 
 pub use substrait_expr_macros as macros;
@@ -0,0 +1,585 @@
+//! # Simplifying expressions
+//!
+//! Expressions that are built programmatically (e.g. by translating a user's query) are often
+//! more complicated than they need to be.  The passes in this module rewrite an expression
+//! into a simpler, equivalent expression.
+//!
+//! Each pass needs the extensions registry that the expression was built with in order to
+//! interpret the function anchors in the expression.
+
+use substrait::proto::expression::literal::LiteralType;
+use substrait::proto::expression::{Literal, RexType, ScalarFunction};
+use substrait::proto::function_argument::ArgType;
+use substrait::proto::{Expression, FunctionArgument};
+
+use crate::builder::functions::FunctionDefinition;
+use crate::error::{Result, SubstraitExprError};
+use crate::functions::{functions_arithmetic, functions_boolean, functions_string};
+use crate::helpers::expr::ExpressionExt;
+use crate::helpers::literals::literal;
+use crate::helpers::registry::ExtensionsRegistry;
+use crate::helpers::visitor::ExpressionRewriter;
+
+struct FnRewriter<F>(F);
+
+impl<F: Fn(Expression) -> Result<Expression>> ExpressionRewriter for FnRewriter<F> {
+    fn rewrite_node(&mut self, expr: Expression) -> Result<Expression> {
+        (self.0)(expr)
+    }
+}
+
+/// Rewrites an expression from the leaves up
+///
+/// The children of a node are rewritten before `rewrite` is applied to the node itself
+fn rewrite_bottom_up(
+    expr: Expression,
+    rewrite: &impl Fn(Expression) -> Result<Expression>,
+) -> Result<Expression> {
+    FnRewriter(rewrite).rewrite(expr)
+}
+
+/// Returns true if `func` is a call to the function with the given uri and name
+fn is_function(
+    func: &ScalarFunction,
+    registry: &ExtensionsRegistry,
+    uri: &str,
+    name: &str,
+) -> bool {
+    registry
+        .lookup_function(func.function_reference)
+        .map(|qualified_name| qualified_name.uri == uri && qualified_name.name == name)
+        .unwrap_or(false)
+}
+
+/// Collapses nested calls to the standard `concat` function into a single call
+///
+/// For example, `concat(concat(a, b), c)` becomes `concat(a, b, c)`.  An inner call is only
+/// merged if it has the same options (e.g. `null_handling`) as the outer call.  Calls to
+/// other functions named concat (e.g. a UDF) are left alone.
+pub fn flatten_concat(expr: Expression, registry: &ExtensionsRegistry) -> Result<Expression> {
+    let concat = &functions_string::CONCAT;
+    let is_concat = |func: &ScalarFunction| is_function(func, registry, &concat.uri, &concat.name);
+    rewrite_bottom_up(expr, &|expr| {
+        let Some(RexType::ScalarFunction(func)) = &expr.rex_type else {
+            return Ok(expr);
+        };
+        if !is_concat(func) {
+            return Ok(expr);
+        }
+        let mut arguments = Vec::with_capacity(func.arguments.len());
+        for arg in &func.arguments {
+            match &arg.arg_type {
+                Some(ArgType::Value(Expression {
+                    rex_type: Some(RexType::ScalarFunction(inner)),
+                })) if is_concat(inner) && inner.options == func.options => {
+                    arguments.extend(inner.arguments.iter().cloned())
+                }
+                _ => arguments.push(arg.clone()),
+            }
+        }
+        Ok(Expression {
+            rex_type: Some(RexType::ScalarFunction(ScalarFunction {
+                arguments,
+                ..func.clone()
+            })),
+        })
+    })
+}
+
+/// The arithmetic functions that can be folded
+#[derive(Clone, Copy)]
+enum ArithmeticOp {
+    Add,
+    Subtract,
+    Multiply,
+}
+
+impl ArithmeticOp {
+    fn function(&self) -> &'static FunctionDefinition {
+        match self {
+            ArithmeticOp::Add => &functions_arithmetic::ADD,
+            ArithmeticOp::Subtract => &functions_arithmetic::SUBTRACT,
+            ArithmeticOp::Multiply => &functions_arithmetic::MULTIPLY,
+        }
+    }
+
+    fn from_function(func: &ScalarFunction, registry: &ExtensionsRegistry) -> Option<Self> {
+        [
+            ArithmeticOp::Add,
+            ArithmeticOp::Subtract,
+            ArithmeticOp::Multiply,
+        ]
+        .into_iter()
+        .find(|op| {
+            let definition = op.function();
+            is_function(func, registry, &definition.uri, &definition.name)
+        })
+    }
+
+    fn apply_int(&self, lhs: i64, rhs: i64) -> i128 {
+        let (lhs, rhs) = (lhs as i128, rhs as i128);
+        match self {
+            ArithmeticOp::Add => lhs + rhs,
+            ArithmeticOp::Subtract => lhs - rhs,
+            ArithmeticOp::Multiply => lhs * rhs,
+        }
+    }
+
+    fn apply_float(&self, lhs: f64, rhs: f64) -> f64 {
+        match self {
+            ArithmeticOp::Add => lhs + rhs,
+            ArithmeticOp::Subtract => lhs - rhs,
+            ArithmeticOp::Multiply => lhs * rhs,
+        }
+    }
+}
+
+/// Returns the most preferred value of an option, if the option was specified
+fn preferred_option<'a>(func: &'a ScalarFunction, name: &str) -> Option<&'a str> {
+    func.options
+        .iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.preference.first())
+        .map(|preference| preference.as_str())
+}
+
+/// Fits the result of an integer operation into the range [min, max]
+///
+/// Returns None if the result overflows and the overflow behavior is not known
+fn fit_int(
+    value: i128,
+    min: i64,
+    max: i64,
+    bits: u32,
+    overflow: Option<&str>,
+    func_name: &str,
+) -> Result<Option<i64>> {
+    if value >= min as i128 && value <= max as i128 {
+        return Ok(Some(value as i64));
+    }
+    match overflow {
+        Some("SILENT") => {
+            // Keep the low bits and sign extend
+            let shift = 128 - bits;
+            Ok(Some(((value << shift) >> shift) as i64))
+        }
+        Some("SATURATE") => Ok(Some(if value < min as i128 { min } else { max })),
+        Some("ERROR") => Err(SubstraitExprError::invalid_input(format!(
+            "The constant expression {} overflows",
+            func_name
+        ))),
+        _ => Ok(None),
+    }
+}
+
+fn fold_arithmetic(
+    op: ArithmeticOp,
+    func: &ScalarFunction,
+    lhs: &Literal,
+    rhs: &Literal,
+) -> Result<Option<LiteralType>> {
+    if lhs.type_variation_reference != rhs.type_variation_reference {
+        return Ok(None);
+    }
+    let overflow = preferred_option(func, "overflow");
+    let name = &op.function().name;
+    let folded = match (&lhs.literal_type, &rhs.literal_type) {
+        (Some(LiteralType::I8(l)), Some(LiteralType::I8(r))) => {
+            let value = op.apply_int(*l as i64, *r as i64);
+            fit_int(value, i8::MIN as i64, i8::MAX as i64, 8, overflow, name)?
+                .map(|value| LiteralType::I8(value as i32))
+        }
+        (Some(LiteralType::I16(l)), Some(LiteralType::I16(r))) => {
+            let value = op.apply_int(*l as i64, *r as i64);
+            fit_int(value, i16::MIN as i64, i16::MAX as i64, 16, overflow, name)?
+                .map(|value| LiteralType::I16(value as i32))
+        }
+        (Some(LiteralType::I32(l)), Some(LiteralType::I32(r))) => {
+            let value = op.apply_int(*l as i64, *r as i64);
+            fit_int(value, i32::MIN as i64, i32::MAX as i64, 32, overflow, name)?
+                .map(|value| LiteralType::I32(value as i32))
+        }
+        (Some(LiteralType::I64(l)), Some(LiteralType::I64(r))) => {
+            let value = op.apply_int(*l, *r);
+            fit_int(value, i64::MIN, i64::MAX, 64, overflow, name)?.map(LiteralType::I64)
+        }
+        // Rust rounds floating point results to the nearest value, ties to even, so other
+        // rounding modes are not folded
+        (Some(LiteralType::Fp32(l)), Some(LiteralType::Fp32(r)))
+            if matches!(
+                preferred_option(func, "rounding"),
+                None | Some("TIE_TO_EVEN")
+            ) =>
+        {
+            Some(LiteralType::Fp32(
+                op.apply_float(*l as f64, *r as f64) as f32
+            ))
+        }
+        (Some(LiteralType::Fp64(l)), Some(LiteralType::Fp64(r)))
+            if matches!(
+                preferred_option(func, "rounding"),
+                None | Some("TIE_TO_EVEN")
+            ) =>
+        {
+            Some(LiteralType::Fp64(op.apply_float(*l, *r)))
+        }
+        _ => None,
+    };
+    Ok(folded)
+}
+
+/// Evaluates arithmetic over literals
+///
+/// Calls to the standard `add`, `subtract`, and `multiply` functions are replaced by a
+/// literal if both arguments are non-null literals of the same numeric type.  Folding
+/// happens bottom-up so `(1 + 2) * 3` becomes `9`.
+///
+/// Integer overflow follows the `overflow` option.  If the option is `ERROR` and the
+/// result overflows then an error is returned.  If there is no `overflow` option and the
+/// result overflows then the call is left alone.  Floating point calls are only folded if
+/// the `rounding` option is not specified or is `TIE_TO_EVEN`.
+///
+/// ```
+/// use substrait_expr::builder::{BuilderParams, ExpressionsBuilder};
+/// use substrait_expr::functions::functions_arithmetic::FunctionsArithmeticExt;
+/// use substrait_expr::helpers::literals::literal;
+/// use substrait_expr::helpers::schema::{EmptySchema, SchemaInfo};
+/// use substrait_expr::optimize::fold_constants;
+///
+/// let schema = SchemaInfo::Empty(EmptySchema::default());
+/// let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+/// let sum = builder
+///     .functions()
+///     .add(literal(3_i32), literal(5_i32))
+///     .build()
+///     .unwrap();
+/// let folded = fold_constants(sum, builder.schema().extensions_registry()).unwrap();
+/// assert_eq!(folded, literal(8_i32));
+/// ```
+pub fn fold_constants(expr: Expression, registry: &ExtensionsRegistry) -> Result<Expression> {
+    rewrite_bottom_up(expr, &|expr| {
+        let Some(RexType::ScalarFunction(func)) = &expr.rex_type else {
+            return Ok(expr);
+        };
+        let Some(op) = ArithmeticOp::from_function(func, registry) else {
+            return Ok(expr);
+        };
+        let literals = func
+            .arguments
+            .iter()
+            .map(|arg| match &arg.arg_type {
+                Some(ArgType::Value(Expression {
+                    rex_type: Some(RexType::Literal(literal)),
+                })) => Some(literal),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>();
+        let Some([lhs, rhs]) = literals.as_deref() else {
+            return Ok(expr);
+        };
+        match fold_arithmetic(op, func, lhs, rhs)? {
+            Some(literal_type) => Ok(Expression {
+                rex_type: Some(RexType::Literal(Literal {
+                    nullable: lhs.nullable || rhs.nullable,
+                    type_variation_reference: lhs.type_variation_reference,
+                    literal_type: Some(literal_type),
+                })),
+            }),
+            None => Ok(expr),
+        }
+    })
+}
+
+/// Returns the value arguments of a function or None if any argument is not a value
+fn value_args(func: &ScalarFunction) -> Option<Vec<&Expression>> {
+    func.arguments
+        .iter()
+        .map(|arg| match &arg.arg_type {
+            Some(ArgType::Value(value)) => Some(value),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Simplifies a call to `and` (if `absorbing` is false) or `or` (if `absorbing` is true)
+///
+/// An argument equal to `absorbing` decides the result and arguments equal to `!absorbing`
+/// can be dropped
+fn simplify_junction(expr: Expression, func: &ScalarFunction, absorbing: bool) -> Expression {
+    let Some(args) = value_args(func) else {
+        return expr;
+    };
+    if args
+        .iter()
+        .any(|arg| arg.as_const_bool() == Some(absorbing))
+    {
+        return literal(absorbing);
+    }
+    let remaining = func
+        .arguments
+        .iter()
+        .zip(args)
+        .filter(|(_, arg)| arg.as_const_bool() != Some(!absorbing))
+        .map(|(func_arg, _)| func_arg.clone())
+        .collect::<Vec<_>>();
+    if remaining.len() == func.arguments.len() {
+        return expr;
+    }
+    match remaining.as_slice() {
+        [] => literal(!absorbing),
+        [FunctionArgument {
+            arg_type: Some(ArgType::Value(value)),
+        }] => value.clone(),
+        _ => Expression {
+            rex_type: Some(RexType::ScalarFunction(ScalarFunction {
+                arguments: remaining,
+                ..func.clone()
+            })),
+        },
+    }
+}
+
+/// Removes redundant boolean logic
+///
+/// The standard `and`, `or`, and `not` functions are simplified with these identities:
+///
+/// * `and(x, true)` becomes `x` and `and(x, false)` becomes `false`
+/// * `or(x, false)` becomes `x` and `or(x, true)` becomes `true`
+/// * `not(not(x))` becomes `x`
+///
+/// Only non-null boolean literals are considered constant.  The rules are applied bottom-up
+/// so nested redundancy collapses (e.g. `or(and(x, false), y)` becomes `y`).
+pub fn simplify_boolean(expr: Expression, registry: &ExtensionsRegistry) -> Result<Expression> {
+    let is = |func: &ScalarFunction, definition: &FunctionDefinition| {
+        is_function(func, registry, &definition.uri, &definition.name)
+    };
+    rewrite_bottom_up(expr, &|expr| {
+        let Some(RexType::ScalarFunction(func)) = &expr.rex_type else {
+            return Ok(expr);
+        };
+        if is(func, &functions_boolean::AND) {
+            Ok(simplify_junction(expr.clone(), func, false))
+        } else if is(func, &functions_boolean::OR) {
+            Ok(simplify_junction(expr.clone(), func, true))
+        } else if is(func, &functions_boolean::NOT) {
+            match value_args(func).as_deref() {
+                Some(
+                    [Expression {
+                        rex_type: Some(RexType::ScalarFunction(inner)),
+                    }],
+                ) if is(inner, &functions_boolean::NOT) => match value_args(inner).as_deref() {
+                    Some([value]) => Ok((*value).clone()),
+                    _ => Ok(expr.clone()),
+                },
+                _ => Ok(expr.clone()),
+            }
+        } else {
+            Ok(expr.clone())
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use substrait::proto::{FunctionOption, Type};
+
+    use crate::builder::functions::test_udfs::UDF_URI;
+    use crate::builder::schema::SchemaBuildersExt;
+    use crate::builder::{BuilderParams, ExpressionsBuilder};
+    use crate::functions::functions_arithmetic::FunctionsArithmeticExt;
+    use crate::helpers::literals::literal;
+    use crate::helpers::schema::SchemaInfo;
+    use crate::helpers::types;
+
+    use super::*;
+
+    fn call(function_reference: u32, args: Vec<Expression>) -> Expression {
+        call_returning(function_reference, args, types::string(false))
+    }
+
+    fn call_returning(
+        function_reference: u32,
+        args: Vec<Expression>,
+        output_type: Type,
+    ) -> Expression {
+        Expression {
+            rex_type: Some(RexType::ScalarFunction(ScalarFunction {
+                function_reference,
+                arguments: args
+                    .into_iter()
+                    .map(|arg| FunctionArgument {
+                        arg_type: Some(ArgType::Value(arg)),
+                    })
+                    .collect(),
+                output_type: Some(output_type),
+                ..Default::default()
+            })),
+        }
+    }
+
+    fn num_args(expr: &Expression) -> usize {
+        match &expr.rex_type {
+            Some(RexType::ScalarFunction(func)) => func.arguments.len(),
+            _ => panic!("Expected a scalar function"),
+        }
+    }
+
+    #[test]
+    fn test_flatten_concat() {
+        let schema = SchemaInfo::new_full()
+            .field("a", types::string(false))
+            .field("b", types::string(false))
+            .field("c", types::string(false))
+            .field("d", types::string(false))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let field = |name: &str| builder.fields().resolve_by_name(name).unwrap();
+        let registry = builder.schema().extensions_registry();
+        let concat = registry.register_function(&functions_string::CONCAT);
+
+        // concat(concat(concat(a, b), c), d)
+        let nested = call(
+            concat,
+            vec![
+                call(
+                    concat,
+                    vec![call(concat, vec![field("a"), field("b")]), field("c")],
+                ),
+                field("d"),
+            ],
+        );
+        let flattened = flatten_concat(nested, registry).unwrap();
+        assert_eq!(
+            flattened,
+            call(concat, vec![field("a"), field("b"), field("c"), field("d")])
+        );
+
+        // Calls with different options are not merged
+        let mut ignore_nulls = call(concat, vec![field("a"), field("b")]);
+        if let Some(RexType::ScalarFunction(func)) = ignore_nulls.rex_type.as_mut() {
+            func.options = vec![FunctionOption {
+                name: "null_handling".to_string(),
+                preference: vec!["IGNORE_NULLS".to_string()],
+            }];
+        }
+        let mixed = call(concat, vec![ignore_nulls, literal("c")]);
+        assert_eq!(num_args(&flatten_concat(mixed, registry).unwrap()), 2);
+
+        // Other functions named concat are not merged
+        let udf = registry.register_function_by_name(UDF_URI, "concat");
+        let not_standard = call(
+            udf,
+            vec![call(udf, vec![field("a"), field("b")]), field("c")],
+        );
+        assert_eq!(
+            flatten_concat(not_standard.clone(), registry).unwrap(),
+            not_standard
+        );
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let registry = builder.schema().extensions_registry();
+        let functions = builder.functions();
+
+        // (3 + 5) * 2
+        let sum = functions
+            .add(literal(3_i32), literal(5_i32))
+            .build()
+            .unwrap();
+        let product = functions.multiply(sum, literal(2_i32)).build().unwrap();
+        assert_eq!(fold_constants(product, registry).unwrap(), literal(16_i32));
+
+        let difference = functions
+            .subtract(literal(1.5_f64), literal(0.25_f64))
+            .build()
+            .unwrap();
+        assert_eq!(
+            fold_constants(difference, registry).unwrap(),
+            literal(1.25_f64)
+        );
+
+        // Non-literal arguments and mismatched types are not folded
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let with_field = functions.add(x, literal(1_i32)).build().unwrap();
+        assert_eq!(
+            fold_constants(with_field.clone(), registry).unwrap(),
+            with_field
+        );
+
+        // Overflow follows the overflow option
+        let overflowing = |overflow: &str| {
+            functions
+                .add(literal(i8::MAX), literal(1_i8))
+                .with_option("overflow", overflow)
+                .build()
+                .unwrap()
+        };
+        assert_eq!(
+            fold_constants(overflowing("SILENT"), registry).unwrap(),
+            literal(i8::MIN)
+        );
+        assert_eq!(
+            fold_constants(overflowing("SATURATE"), registry).unwrap(),
+            literal(i8::MAX)
+        );
+        assert!(fold_constants(overflowing("ERROR"), registry).is_err());
+        let unspecified = functions
+            .add(literal(i64::MAX), literal(1_i64))
+            .build()
+            .unwrap();
+        assert_eq!(
+            fold_constants(unspecified.clone(), registry).unwrap(),
+            unspecified
+        );
+    }
+
+    #[test]
+    fn test_simplify_boolean() {
+        let schema = SchemaInfo::new_full()
+            .field("x", types::bool(true))
+            .field("y", types::bool(false))
+            .build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let registry = builder.schema().extensions_registry();
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let y = builder.fields().resolve_by_name("y").unwrap();
+        let boolean = |func: &FunctionDefinition, args: Vec<Expression>| {
+            call_returning(registry.register_function(func), args, types::bool(true))
+        };
+        let and = |args| boolean(&functions_boolean::AND, args);
+        let or = |args| boolean(&functions_boolean::OR, args);
+        let not = |arg| boolean(&functions_boolean::NOT, vec![arg]);
+        let simplify = |expr| simplify_boolean(expr, registry).unwrap();
+
+        assert_eq!(simplify(and(vec![x.clone(), literal(true)])), x);
+        assert_eq!(
+            simplify(and(vec![x.clone(), literal(false)])),
+            literal(false)
+        );
+        assert_eq!(simplify(or(vec![x.clone(), literal(false)])), x);
+        assert_eq!(simplify(or(vec![x.clone(), literal(true)])), literal(true));
+        assert_eq!(simplify(not(not(x.clone()))), x);
+
+        // Only the redundant arguments are removed
+        assert_eq!(
+            simplify(and(vec![x.clone(), literal(true), y.clone()])),
+            and(vec![x.clone(), y.clone()])
+        );
+        // Nothing to simplify
+        assert_eq!(simplify(not(x.clone())), not(x.clone()));
+        assert_eq!(
+            simplify(or(vec![x.clone(), y.clone()])),
+            or(vec![x.clone(), y.clone()])
+        );
+
+        // or(and(x, false), not(not(and(y, true)))) -> y
+        let nested = or(vec![
+            and(vec![x.clone(), literal(false)]),
+            not(not(and(vec![y.clone(), literal(true)]))),
+        ]);
+        assert_eq!(simplify(nested), y);
+    }
+}
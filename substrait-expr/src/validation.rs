@@ -0,0 +1,819 @@
+//! # Validating expressions received from elsewhere
+//!
+//! Expressions built with the [builder](crate::builder) are valid by construction.  Expressions
+//! received from other systems (e.g. sent to a server by a client) should be validated before
+//! they are used.
+//!
+//! By default any function is accepted, since it may be a user defined function that the
+//! consumer knows how to execute.  Servers that only want to execute certain functions can
+//! supply an allow-list.
+//!
+//! ```
+//! use substrait_expr::builder::schema::SchemaBuildersExt;
+//! use substrait_expr::builder::{BuilderParams, ExpressionsBuilder};
+//! use substrait_expr::functions::functions_arithmetic::{FunctionsArithmeticExt, ADD};
+//! use substrait_expr::helpers::literals::literal;
+//! use substrait_expr::helpers::schema::SchemaInfo;
+//! use substrait_expr::helpers::types;
+//! use substrait_expr::validation::{validate, ValidateParams};
+//!
+//! let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+//! let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+//! let x = builder.fields().resolve_by_name("x").unwrap();
+//! let sum = builder.functions().add(x, literal(3_i32)).build().unwrap();
+//!
+//! let params = ValidateParams::strict([&*ADD]);
+//! validate(&sum, builder.schema(), &params).unwrap();
+//! ```
+//!
+//! An entire message can be checked with [`validate_extended_expression`], which also
+//! checks the output names of each expression.
+
+use std::collections::HashSet;
+
+use substrait::proto::expression::field_reference::RootType;
+use substrait::proto::expression::nested::NestedType;
+use substrait::proto::expression::{IfThen, Nested, RexType, SwitchExpression, WindowFunction};
+use substrait::proto::expression_reference::ExprType;
+use substrait::proto::function_argument::ArgType;
+use substrait::proto::{Expression, ExtendedExpression, FunctionArgument};
+
+use crate::builder::functions::{
+    FunctionDefinition, FunctionsBuilder, ImplementationArgType, LOOKUP_BY_NAME_FUNC_NAME,
+    LOOKUP_BY_NAME_FUNC_URI,
+};
+use crate::error::{Result, SubstraitExprError};
+use crate::functions;
+use crate::helpers::expr::ExpressionExt;
+use crate::helpers::literals::{literal, LiteralExt};
+use crate::helpers::registry::ExtensionsRegistry;
+use crate::helpers::schema::SchemaInfo;
+use crate::helpers::types::TypeExt;
+
+/// Controls how strict validation is
+#[derive(Clone, Debug, Default)]
+pub struct ValidateParams<'a> {
+    /// If set then every function must be one of these functions
+    ///
+    /// Functions are compared by URI and name.  If not set then any function is accepted.
+    pub allowed_functions: Option<Vec<&'a FunctionDefinition>>,
+    /// If true then two expressions in a message may have the same output name
+    ///
+    /// This only affects [`validate_extended_expression`]
+    pub allow_duplicate_names: bool,
+}
+
+impl<'a> ValidateParams<'a> {
+    /// Create parameters that only accept the given functions
+    pub fn strict(allowed_functions: impl IntoIterator<Item = &'a FunctionDefinition>) -> Self {
+        Self {
+            allowed_functions: Some(allowed_functions.into_iter().collect()),
+            ..Default::default()
+        }
+    }
+}
+
+struct Validator<'a> {
+    schema: &'a SchemaInfo,
+    params: &'a ValidateParams<'a>,
+}
+
+impl<'a> Validator<'a> {
+    fn validate_function_reference(&self, function_reference: u32) -> Result<()> {
+        let Some(allowed_functions) = &self.params.allowed_functions else {
+            return Ok(());
+        };
+        let name = self
+            .schema
+            .extensions_registry()
+            .lookup_function(function_reference)
+            .ok_or_else(|| {
+                SubstraitExprError::invalid_substrait(format!(
+                    "The function anchor {} is not registered",
+                    function_reference
+                ))
+            })?;
+        // Name lookups are placeholders for field references and not real functions
+        let is_placeholder =
+            name.uri == LOOKUP_BY_NAME_FUNC_URI && name.name == LOOKUP_BY_NAME_FUNC_NAME;
+        let is_allowed = allowed_functions
+            .iter()
+            .any(|func| func.uri == name.uri && func.name == name.name);
+        if is_placeholder || is_allowed {
+            Ok(())
+        } else {
+            Err(SubstraitExprError::invalid_input(format!(
+                "The function {} is not allowed",
+                name
+            )))
+        }
+    }
+
+    fn validate_arguments(&self, arguments: &[FunctionArgument]) -> Result<()> {
+        arguments.iter().try_for_each(|arg| match &arg.arg_type {
+            Some(ArgType::Value(value)) => self.validate(value),
+            _ => Ok(()),
+        })
+    }
+
+    fn validate(&self, expr: &Expression) -> Result<()> {
+        match expr.try_rex_type()? {
+            RexType::Literal(literal) => literal.validate(),
+            RexType::ScalarFunction(func) => {
+                self.validate_function_reference(func.function_reference)?;
+                self.validate_arguments(&func.arguments)
+            }
+            RexType::WindowFunction(func) => {
+                self.validate_function_reference(func.function_reference)?;
+                self.validate_arguments(&func.arguments)?;
+                func.partitions
+                    .iter()
+                    .chain(func.sorts.iter().filter_map(|sort| sort.expr.as_ref()))
+                    .try_for_each(|child| self.validate(child))
+            }
+            RexType::Cast(cast) => match &cast.input {
+                Some(input) => self.validate(input),
+                None => Err(SubstraitExprError::invalid_substrait(
+                    "The required property input was missing from a cast",
+                )),
+            },
+            RexType::Nested(nested) => match &nested.nested_type {
+                Some(NestedType::Struct(strct)) => strct
+                    .fields
+                    .iter()
+                    .try_for_each(|field| self.validate(field)),
+                Some(NestedType::List(list)) => list
+                    .values
+                    .iter()
+                    .try_for_each(|value| self.validate(value)),
+                Some(NestedType::Map(map)) => map
+                    .key_values
+                    .iter()
+                    .flat_map(|key_value| [key_value.key.as_ref(), key_value.value.as_ref()])
+                    .flatten()
+                    .try_for_each(|child| self.validate(child)),
+                None => Ok(()),
+            },
+            RexType::SingularOrList(in_list) => in_list
+                .value
+                .as_deref()
+                .into_iter()
+                .chain(&in_list.options)
+                .try_for_each(|child| self.validate(child)),
+            RexType::IfThen(if_then) => if_then
+                .ifs
+                .iter()
+                .flat_map(|clause| clause.r#if.iter().chain(&clause.then))
+                .chain(if_then.r#else.as_deref())
+                .try_for_each(|child| self.validate(child)),
+            RexType::SwitchExpression(switch) => {
+                for case in &switch.ifs {
+                    if let Some(case_literal) = &case.r#if {
+                        case_literal.validate()?;
+                    }
+                }
+                switch
+                    .r#match
+                    .as_deref()
+                    .into_iter()
+                    .chain(switch.ifs.iter().filter_map(|case| case.then.as_ref()))
+                    .chain(switch.r#else.as_deref())
+                    .try_for_each(|child| self.validate(child))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Validates an expression against a schema
+///
+/// Literals are checked to ensure their values fit their types and, if the params contain
+/// an allow-list, every function must be in the allow-list.
+pub fn validate(expr: &Expression, schema: &SchemaInfo, params: &ValidateParams) -> Result<()> {
+    Validator { schema, params }.validate(expr)
+}
+
+/// Type checks expressions for [`ExpressionExt::validate`]
+///
+/// `context` describes where the current node is (e.g. `argument 1 of add`), innermost
+/// first, so errors can point to the offending node.
+struct TypeChecker<'a> {
+    schema: &'a SchemaInfo,
+    definitions: Vec<&'static FunctionDefinition>,
+    context: Vec<String>,
+}
+
+impl<'a> TypeChecker<'a> {
+    fn error(&self, message: impl AsRef<str>) -> SubstraitExprError {
+        let location = if self.context.is_empty() {
+            "the root of the expression".to_string()
+        } else {
+            self.context
+                .iter()
+                .rev()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" in ")
+        };
+        SubstraitExprError::invalid_input(format!("{} at {}", message.as_ref(), location))
+    }
+
+    fn check_child(&mut self, child: &Expression, context: String) -> Result<()> {
+        self.context.push(context);
+        let result = self.check(child);
+        self.context.pop();
+        result
+    }
+
+    fn check_function(
+        &mut self,
+        function_reference: u32,
+        arguments: &[FunctionArgument],
+    ) -> Result<()> {
+        let registry = self.schema.extensions_registry();
+        let name = registry
+            .lookup_function(function_reference)
+            .ok_or_else(|| {
+                self.error(format!(
+                    "The function anchor {} is not registered",
+                    function_reference
+                ))
+            })?;
+        for (idx, arg) in arguments.iter().enumerate() {
+            if let Some(ArgType::Value(value)) = &arg.arg_type {
+                self.check_child(value, format!("argument {} of {}", idx, name.name))?;
+            }
+        }
+        // Placeholders and functions we don't know (e.g. UDFs) can't be checked further
+        let Some(definition) = self
+            .definitions
+            .iter()
+            .find(|def| def.uri == name.uri && def.name == name.name)
+        else {
+            return Ok(());
+        };
+        // Enum arguments are matched against implementations as string literals
+        let args = arguments
+            .iter()
+            .enumerate()
+            .map(|(idx, arg)| match &arg.arg_type {
+                Some(ArgType::Value(value)) => Ok(value.clone()),
+                Some(ArgType::Enum(value)) => Ok(literal(value.as_str())),
+                _ => Err(self.error(format!(
+                    "Argument {} of {} is not a value or an enum",
+                    idx, name.name
+                ))),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let Some(implementation) = definition.pick_implementation_from_args(&args, self.schema)?
+        else {
+            let arg_types = args
+                .iter()
+                .map(|arg| arg.output_type(self.schema))
+                .collect::<Result<Vec<_>>>()?;
+            return Err(self.error(definition.no_match_message(&arg_types, registry)));
+        };
+        for (idx, (arg, imp_arg)) in arguments.iter().zip(&implementation.args).enumerate() {
+            if let (Some(ArgType::Enum(value)), ImplementationArgType::Enum(allowed)) =
+                (&arg.arg_type, &imp_arg.arg_type)
+            {
+                if !allowed.contains(value) {
+                    return Err(self.error(format!(
+                        "The value {} is not valid for argument {} of {} (accepted values: {:?})",
+                        value, idx, name.name, allowed
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_window_function(&mut self, func: &WindowFunction) -> Result<()> {
+        self.check_function(func.function_reference, &func.arguments)?;
+        for (idx, partition) in func.partitions.iter().enumerate() {
+            self.check_child(partition, format!("partition {} of a window function", idx))?;
+        }
+        for (idx, sort) in func.sorts.iter().enumerate() {
+            if let Some(sort_expr) = &sort.expr {
+                self.check_child(sort_expr, format!("sort {} of a window function", idx))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_nested(&mut self, nested: &Nested) -> Result<()> {
+        match &nested.nested_type {
+            Some(NestedType::Struct(strct)) => {
+                for (idx, field) in strct.fields.iter().enumerate() {
+                    self.check_child(field, format!("field {} of a struct", idx))?;
+                }
+            }
+            Some(NestedType::List(list)) => {
+                for (idx, value) in list.values.iter().enumerate() {
+                    self.check_child(value, format!("item {} of a list", idx))?;
+                }
+            }
+            Some(NestedType::Map(map)) => {
+                for (idx, key_value) in map.key_values.iter().enumerate() {
+                    if let Some(key) = &key_value.key {
+                        self.check_child(key, format!("key {} of a map", idx))?;
+                    }
+                    if let Some(value) = &key_value.value {
+                        self.check_child(value, format!("value {} of a map", idx))?;
+                    }
+                }
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn check_if_then(&mut self, if_then: &IfThen) -> Result<()> {
+        let mut clauses = Vec::with_capacity(if_then.ifs.len());
+        for (idx, clause) in if_then.ifs.iter().enumerate() {
+            let (Some(condition), Some(result)) = (&clause.r#if, &clause.then) else {
+                return Err(self.error(format!(
+                    "Clause {} of an if-then expression is missing its condition or result",
+                    idx
+                )));
+            };
+            self.check_child(condition, format!("condition {} of an if-then", idx))?;
+            self.check_child(result, format!("result {} of an if-then", idx))?;
+            clauses.push((condition.clone(), result.clone()));
+        }
+        if let Some(else_result) = &if_then.r#else {
+            self.check_child(else_result, "the else result of an if-then".to_string())?;
+        }
+        // The builder checks that the conditions are boolean and the results agree
+        FunctionsBuilder::new(self.schema)
+            .if_then(clauses, if_then.r#else.as_deref().cloned())
+            .map(|_| ())
+            .map_err(|err| self.error(err.to_string()))
+    }
+
+    fn check_switch(&mut self, switch: &SwitchExpression) -> Result<()> {
+        let value = switch
+            .r#match
+            .as_deref()
+            .ok_or_else(|| self.error("A switch expression is missing its value"))?;
+        self.check_child(value, "the value of a switch".to_string())?;
+        let mut cases = Vec::with_capacity(switch.ifs.len());
+        for (idx, case) in switch.ifs.iter().enumerate() {
+            let (Some(case_literal), Some(result)) = (&case.r#if, &case.then) else {
+                return Err(self.error(format!(
+                    "Case {} of a switch expression is missing its literal or result",
+                    idx
+                )));
+            };
+            case_literal
+                .validate()
+                .map_err(|err| self.error(err.to_string()))?;
+            self.check_child(result, format!("result {} of a switch", idx))?;
+            let case_expr = Expression {
+                rex_type: Some(RexType::Literal(case_literal.clone())),
+            };
+            cases.push((case_expr, result.clone()));
+        }
+        if let Some(else_result) = &switch.r#else {
+            self.check_child(else_result, "the else result of a switch".to_string())?;
+        }
+        // The builder checks that the cases match the value and the results agree
+        FunctionsBuilder::new(self.schema)
+            .switch(value.clone(), cases, switch.r#else.as_deref().cloned())
+            .map(|_| ())
+            .map_err(|err| self.error(err.to_string()))
+    }
+
+    fn check(&mut self, expr: &Expression) -> Result<()> {
+        match expr.try_rex_type()? {
+            RexType::Literal(literal) => literal
+                .validate()
+                .map_err(|err| self.error(err.to_string())),
+            RexType::Selection(reference) => match &reference.root_type {
+                Some(RootType::Expression(root)) => {
+                    self.check_child(root, "the root of a field reference".to_string())
+                }
+                // Outer references need the enclosing schemas
+                Some(RootType::OuterReference(_)) => Ok(()),
+                _ => expr
+                    .output_type(self.schema)
+                    .map(|_| ())
+                    .map_err(|err| self.error(err.to_string())),
+            },
+            RexType::ScalarFunction(func) => {
+                self.check_function(func.function_reference, &func.arguments)
+            }
+            RexType::WindowFunction(func) => self.check_window_function(func),
+            RexType::Nested(nested) => self.check_nested(nested),
+            RexType::Cast(cast) => match &cast.input {
+                Some(input) => self.check_child(input, "the input of a cast".to_string()),
+                None => Err(self.error("A cast is missing its input")),
+            },
+            RexType::SingularOrList(in_list) => {
+                if let Some(value) = &in_list.value {
+                    self.check_child(value, "the value of an IN-list".to_string())?;
+                }
+                for (idx, option) in in_list.options.iter().enumerate() {
+                    self.check_child(option, format!("option {} of an IN-list", idx))?;
+                }
+                Ok(())
+            }
+            RexType::IfThen(if_then) => self.check_if_then(if_then),
+            RexType::SwitchExpression(switch) => self.check_switch(switch),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Type checks an expression against a schema
+///
+/// See [`ExpressionExt::validate`]
+pub(crate) fn type_check(expr: &Expression, schema: &SchemaInfo) -> Result<()> {
+    TypeChecker {
+        schema,
+        definitions: functions::definitions(),
+        context: Vec::new(),
+    }
+    .check(expr)
+}
+
+/// Validates an extended expression message
+///
+/// Every expression is validated (see [`validate`]) against the message's base schema.  In
+/// addition, each expression must have one non-empty output name for every type it returns
+/// (e.g. a struct with two fields needs three names) and, unless
+/// [`allow_duplicate_names`](ValidateParams::allow_duplicate_names) is set, the (top-level)
+/// output names must be unique across the message.
+///
+/// Errors include the index of the offending expression
+pub fn validate_extended_expression(
+    msg: &ExtendedExpression,
+    params: &ValidateParams,
+) -> Result<()> {
+    let registry = ExtensionsRegistry::from_substrait(&msg.extension_uris, &msg.extensions)?;
+    let base_schema = msg.base_schema.clone().ok_or_else(|| {
+        SubstraitExprError::invalid_substrait(
+            "The required property base_schema was missing from an extended expression",
+        )
+    })?;
+    let schema = SchemaInfo::from_substrait_with_registry(base_schema, registry)?;
+    let mut seen_names = HashSet::new();
+    for (index, expr_ref) in msg.referred_expr.iter().enumerate() {
+        if let Some(empty_pos) = expr_ref
+            .output_names
+            .iter()
+            .position(|name| name.is_empty())
+        {
+            return Err(SubstraitExprError::invalid_substrait(format!(
+                "Expression {} has an empty output name at position {}",
+                index, empty_pos
+            )));
+        }
+        let expected_names = match &expr_ref.expr_type {
+            Some(ExprType::Expression(expr)) => {
+                validate(expr, &schema, params).map_err(|err| {
+                    SubstraitExprError::invalid_substrait(format!(
+                        "Expression {} is invalid: {}",
+                        index, err
+                    ))
+                })?;
+                expr.output_type(&schema)?.num_types() as usize
+            }
+            // Measures always return a single value
+            Some(ExprType::Measure(_)) => 1,
+            None => {
+                return Err(SubstraitExprError::invalid_substrait(format!(
+                    "Expression {} is missing its expression",
+                    index
+                )))
+            }
+        };
+        if expr_ref.output_names.len() != expected_names {
+            return Err(SubstraitExprError::invalid_substrait(format!(
+                "Expression {} has {} output names but needs {}",
+                index,
+                expr_ref.output_names.len(),
+                expected_names
+            )));
+        }
+        let name = &expr_ref.output_names[0];
+        if !params.allow_duplicate_names && !seen_names.insert(name) {
+            return Err(SubstraitExprError::invalid_substrait(format!(
+                "Expression {} has the output name {} which is used by an earlier expression",
+                index, name
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::builder::functions::test_udfs::{typed_implementation, udf};
+    use crate::builder::schema::SchemaBuildersExt;
+    use crate::builder::{BuilderParams, ExpressionsBuilder};
+    use crate::functions::functions_arithmetic::{
+        FunctionsArithmeticExt, FunctionsArithmeticWindowExt, ADD,
+    };
+    use crate::helpers::literals::literal;
+    use crate::helpers::types;
+
+    use super::*;
+
+    #[test]
+    fn test_allowed_functions() {
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let double = udf(
+            "double",
+            vec![typed_implementation(
+                vec![types::i32(false)],
+                types::i32(false),
+            )],
+        );
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let doubled = builder
+            .functions()
+            .new_builder_owned(double.clone(), vec![x])
+            .build()
+            .unwrap();
+        let expr = builder
+            .functions()
+            .add(doubled, literal(1_i32))
+            .build()
+            .unwrap();
+
+        assert!(validate(&expr, builder.schema(), &ValidateParams::default()).is_ok());
+        assert!(validate(&expr, builder.schema(), &ValidateParams::strict([&*ADD])).is_err());
+        assert!(validate(
+            &expr,
+            builder.schema(),
+            &ValidateParams::strict([&*ADD, &*double])
+        )
+        .is_ok());
+
+        // Functions nested in structs and window partitions are also checked
+        let strct = builder.functions().make_struct(vec![expr.clone()]).unwrap();
+        assert!(validate(&strct, builder.schema(), &ValidateParams::strict([&*ADD])).is_err());
+        let window = builder
+            .functions()
+            .row_number()
+            .partition_by(expr)
+            .build()
+            .unwrap();
+        let params = ValidateParams::strict([&*ADD, &*double]);
+        assert!(validate(&window, builder.schema(), &params).is_err());
+    }
+
+    #[test]
+    fn test_allowed_functions_in_branches() {
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let sum = builder
+            .functions()
+            .add(x.clone(), literal(1_i32))
+            .build()
+            .unwrap();
+        let product = builder
+            .functions()
+            .multiply(x.clone(), literal(2_i32))
+            .build()
+            .unwrap();
+        let params = ValidateParams::strict([&*ADD]);
+
+        // if true then x + 1 else x * 2
+        let if_then = builder
+            .functions()
+            .if_then(vec![(literal(true), sum.clone())], Some(product.clone()))
+            .unwrap();
+        match validate(&if_then, builder.schema(), &params) {
+            Err(SubstraitExprError::InvalidInput(message)) => {
+                assert!(message.contains("multiply"))
+            }
+            _ => panic!("Expected the forbidden function to be rejected"),
+        }
+        let allowed = builder
+            .functions()
+            .if_then(vec![(literal(true), sum.clone())], Some(x.clone()))
+            .unwrap();
+        assert!(validate(&allowed, builder.schema(), &params).is_ok());
+
+        // switch x * 2 { 1 => x + 1 }
+        let switch = builder
+            .functions()
+            .switch(product, vec![(literal(1_i32), sum)], None)
+            .unwrap();
+        assert!(validate(&switch, builder.schema(), &params).is_err());
+    }
+
+    #[test]
+    fn test_output_names() {
+        let build = || {
+            let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+            let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+            let x = builder.fields().resolve_by_name("x").unwrap();
+            let sum = builder.functions().add(x, literal(1_i32)).build().unwrap();
+            builder
+                .add_expression("sum", sum)
+                .unwrap()
+                .add_expression("one", literal(1_i32))
+                .unwrap();
+            builder.build()
+        };
+        let params = ValidateParams::default();
+        assert!(validate_extended_expression(&build(), &params).is_ok());
+
+        let mut empty_name = build();
+        empty_name.referred_expr[1].output_names[0] = String::new();
+        match validate_extended_expression(&empty_name, &params) {
+            Err(SubstraitExprError::InvalidSubstrait(message)) => {
+                assert!(message.contains("Expression 1"))
+            }
+            _ => panic!("Expected an empty name to be rejected"),
+        }
+
+        let mut duplicate_name = build();
+        duplicate_name.referred_expr[1].output_names[0] = "sum".to_string();
+        match validate_extended_expression(&duplicate_name, &params) {
+            Err(SubstraitExprError::InvalidSubstrait(message)) => {
+                assert!(message.contains("Expression 1") && message.contains("sum"))
+            }
+            _ => panic!("Expected a duplicate name to be rejected"),
+        }
+        let lenient = ValidateParams {
+            allow_duplicate_names: true,
+            ..Default::default()
+        };
+        assert!(validate_extended_expression(&duplicate_name, &lenient).is_ok());
+
+        let mut extra_name = build();
+        extra_name.referred_expr[0]
+            .output_names
+            .push("extra".to_string());
+        assert!(validate_extended_expression(&extra_name, &params).is_err());
+    }
+
+    #[test]
+    fn test_type_check() {
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let sum = builder
+            .functions()
+            .add(x.clone(), literal(3_i32))
+            .build()
+            .unwrap();
+        let product = builder
+            .functions()
+            .multiply(sum, literal(2_i32))
+            .build()
+            .unwrap();
+        assert!(product.validate(builder.schema()).is_ok());
+
+        // Replace the 3 in (x + 3) * 2 with a string
+        let mut bad_arg = product.clone();
+        let Some(RexType::ScalarFunction(multiply)) = &mut bad_arg.rex_type else {
+            unreachable!()
+        };
+        let Some(ArgType::Value(sum)) = &mut multiply.arguments[0].arg_type else {
+            unreachable!()
+        };
+        let Some(RexType::ScalarFunction(add)) = &mut sum.rex_type else {
+            unreachable!()
+        };
+        add.arguments[1].arg_type = Some(ArgType::Value(literal("hello")));
+        let bad_add = sum.clone();
+        match bad_arg.validate(builder.schema()) {
+            Err(SubstraitExprError::InvalidInput(message)) => {
+                assert!(message.contains("No implementation of add"));
+                assert!(message.contains("Candidates are: add(i8, i8)"));
+                assert!(message.contains("at argument 0 of multiply"));
+            }
+            _ => panic!("Expected the mismatched argument to be reported"),
+        }
+
+        // Invalid calls inside nested expressions and window functions are found
+        let strct = builder
+            .functions()
+            .make_struct(vec![literal(1_i32), bad_add.clone()])
+            .unwrap();
+        match strct.validate(builder.schema()) {
+            Err(SubstraitExprError::InvalidInput(message)) => {
+                assert!(message.contains("at field 1 of a struct"))
+            }
+            _ => panic!("Expected the struct field to be reported"),
+        }
+        let window = builder
+            .functions()
+            .row_number()
+            .partition_by(bad_add)
+            .build()
+            .unwrap();
+        match window.validate(builder.schema()) {
+            Err(SubstraitExprError::InvalidInput(message)) => {
+                assert!(message.contains("at partition 0 of a window function"))
+            }
+            _ => panic!("Expected the partition to be reported"),
+        }
+
+        // A reference to a field that does not exist
+        let sum = builder.functions().add(x, literal(3_i32)).build().unwrap();
+        let out_of_bounds = sum.remap_fields(&HashMap::from([(0, 5)])).unwrap();
+        match out_of_bounds.validate(builder.schema()) {
+            Err(SubstraitExprError::InvalidInput(message)) => {
+                assert!(message.contains("at argument 0 of add"))
+            }
+            _ => panic!("Expected the invalid reference to be reported"),
+        }
+    }
+
+    #[test]
+    fn test_type_check_branches() {
+        let schema = SchemaInfo::new_full().field("x", types::i32(false)).build();
+        let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+        let x = builder.fields().resolve_by_name("x").unwrap();
+        let if_then = builder
+            .functions()
+            .if_then(vec![(literal(true), x.clone())], Some(literal(0_i32)))
+            .unwrap();
+        assert!(if_then.validate(builder.schema()).is_ok());
+
+        // The results of the branches must agree
+        let mut mismatched = if_then.clone();
+        let Some(RexType::IfThen(clauses)) = &mut mismatched.rex_type else {
+            unreachable!()
+        };
+        clauses.r#else = Some(Box::new(literal("zero")));
+        match mismatched.validate(builder.schema()) {
+            Err(SubstraitExprError::InvalidInput(message)) => {
+                assert!(message.contains("results of an if-then expression"))
+            }
+            _ => panic!("Expected the mismatched results to be reported"),
+        }
+
+        // The conditions must be boolean
+        let mut bad_condition = if_then.clone();
+        let Some(RexType::IfThen(clauses)) = &mut bad_condition.rex_type else {
+            unreachable!()
+        };
+        clauses.ifs[0].r#if = Some(literal(1_i32));
+        assert!(bad_condition.validate(builder.schema()).is_err());
+
+        // Invalid calls in a branch are found
+        let mut bad_branch = if_then;
+        let Some(RexType::IfThen(clauses)) = &mut bad_branch.rex_type else {
+            unreachable!()
+        };
+        clauses.ifs[0].then = Some(
+            builder
+                .functions()
+                .add(x.clone(), literal(1_i32))
+                .build()
+                .unwrap(),
+        );
+        let Some(RexType::ScalarFunction(add)) = clauses.ifs[0]
+            .then
+            .as_mut()
+            .and_then(|then| then.rex_type.as_mut())
+        else {
+            unreachable!()
+        };
+        add.arguments[1].arg_type = Some(ArgType::Value(literal("hello")));
+        match bad_branch.validate(builder.schema()) {
+            Err(SubstraitExprError::InvalidInput(message)) => {
+                assert!(message.contains("at result 0 of an if-then"))
+            }
+            _ => panic!("Expected the invalid branch to be reported"),
+        }
+
+        let switch = builder
+            .functions()
+            .switch(x, vec![(literal(1_i32), literal(2_i32))], None)
+            .unwrap();
+        assert!(switch.validate(builder.schema()).is_ok());
+
+        // The cases must have the same type as the value
+        let mut bad_case = switch.clone();
+        let Some(RexType::SwitchExpression(cases)) = &mut bad_case.rex_type else {
+            unreachable!()
+        };
+        cases.ifs[0].r#if = Some(literal("one").try_as_literal().unwrap().clone());
+        assert!(bad_case.validate(builder.schema()).is_err());
+
+        // The results of the cases must agree
+        let mut mismatched = switch;
+        let Some(RexType::SwitchExpression(cases)) = &mut mismatched.rex_type else {
+            unreachable!()
+        };
+        cases.r#else = Some(Box::new(literal(true)));
+        match mismatched.validate(builder.schema()) {
+            Err(SubstraitExprError::InvalidInput(message)) => {
+                assert!(message.contains("results of a switch expression"))
+            }
+            _ => panic!("Expected the mismatched results to be reported"),
+        }
+    }
+}
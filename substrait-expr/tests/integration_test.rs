@@ -1,5 +1,7 @@
 use substrait_expr::builder::schema::SchemaBuildersExt;
 use substrait_expr::functions::functions_comparison::FunctionsComparisonExt;
+use substrait_expr::functions::functions_string::FunctionsStringExt;
+use substrait_expr::helpers::expr::ExpressionExt;
 use substrait_expr::helpers::schema::{EmptySchema, SchemaInfo};
 use substrait_expr::helpers::types;
 use substrait_expr::{
@@ -7,7 +9,7 @@ use substrait_expr::{
     functions::functions_arithmetic::FunctionsArithmeticExt,
     helpers::literals::literal,
 };
-use substrait_expr_macros::names_schema;
+use substrait_expr_macros::{full_schema, names_schema, types_schema};
 
 #[test]
 pub fn test_schema_macros() {
@@ -23,6 +25,75 @@ pub fn test_schema_macros() {
         .nested("location", |builder| builder.field("x").field("y"))
         .build();
     assert_eq!(schema, expected);
+
+    let schema = types_schema!({
+        score: i32,
+        location: {
+            x: fp32,
+            y: fp64?
+        },
+        tags: {
+            label: string
+        }?
+    });
+    let expected = SchemaInfo::new_types()
+        .field(types::i32(false))
+        .nested(false, |builder| {
+            builder.field(types::fp32(false)).field(types::fp64(true))
+        })
+        .nested(true, |builder| builder.field(types::string(false)))
+        .build();
+    assert_eq!(schema, expected);
+
+    let schema = full_schema!({
+        score: i32,
+        location?: {
+            x: fp32,
+            y: fp64?
+        }
+    });
+    let expected = SchemaInfo::new_full()
+        .field("score", types::i32(false))
+        .nested("location", true, |builder| {
+            builder
+                .field("x", types::fp32(false))
+                .field("y", types::fp64(true))
+        })
+        .build();
+    assert_eq!(schema, expected);
+
+    let schema = full_schema!({
+        tags: list<string>,
+        scores?: list<fp64?>,
+        attrs: map<string, list<i32>>?,
+        points: list<{ x: fp32, y: fp32? }>
+    });
+    let expected = SchemaInfo::new_full()
+        .field("tags", types::list(false, types::string(false)))
+        .field("scores", types::list(true, types::fp64(true)))
+        .field(
+            "attrs",
+            types::map(
+                true,
+                types::string(false),
+                types::list(false, types::i32(false)),
+            ),
+        )
+        .field(
+            "points",
+            types::list(
+                false,
+                types::struct_(false, vec![types::fp32(false), types::fp32(true)]),
+            ),
+        )
+        .build();
+    assert_eq!(schema, expected);
+
+    let schema = types_schema!({ tags: list<string> });
+    let expected = SchemaInfo::new_types()
+        .field(types::list(false, types::string(false)))
+        .build();
+    assert_eq!(schema, expected);
 }
 
 #[test]
@@ -89,3 +160,46 @@ pub fn test_expression_with_template_params() {
     let expressions = builder.build();
     dbg!(expressions);
 }
+
+#[test]
+pub fn test_string_functions() {
+    let schema = SchemaInfo::new_full()
+        .field("name", types::string(false))
+        .build();
+    let builder = ExpressionsBuilder::new(schema, BuilderParams::default());
+    let name = builder.fields().resolve_by_name("name").unwrap();
+
+    let prefix = builder
+        .functions()
+        .substring3(name.clone(), literal(1_i32), literal(3_i32))
+        .build()
+        .unwrap();
+    assert_eq!(
+        prefix.output_type(builder.schema()).unwrap(),
+        types::string(false)
+    );
+    let greeting = builder
+        .functions()
+        .concat(vec![literal("Hello, ".to_string()), name.clone()])
+        .build()
+        .unwrap();
+    assert_eq!(
+        greeting.output_type(builder.schema()).unwrap(),
+        types::string(false)
+    );
+    let matches = builder
+        .functions()
+        .like(name, literal("J%".to_string()))
+        .build()
+        .unwrap();
+    assert_eq!(
+        matches.output_type(builder.schema()).unwrap(),
+        types::bool(false)
+    );
+
+    builder.add_expression("prefix", prefix).unwrap();
+    builder.add_expression("greeting", greeting).unwrap();
+    builder.add_expression("matches", matches).unwrap();
+    let expressions = builder.build();
+    assert_eq!(expressions.referred_expr.len(), 3);
+}